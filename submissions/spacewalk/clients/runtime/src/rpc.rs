@@ -1440,6 +1440,10 @@ impl ReplacePallet for SpacewalkParachain {
 #[async_trait]
 pub trait StellarRelayPallet {
 	async fn is_public_network(&self) -> bool;
+
+	/// Returns the validators that the proofs of Stellar transactions are checked against:
+	/// the new ones once they are enacted, otherwise the old ones.
+	async fn get_stellar_validators(&self) -> Result<Vec<StellarRelayValidator>, Error>;
 }
 
 #[async_trait]
@@ -1460,6 +1464,23 @@ impl StellarRelayPallet for SpacewalkParachain {
 			},
 		}
 	}
+
+	async fn get_stellar_validators(&self) -> Result<Vec<StellarRelayValidator>, Error> {
+		let enactment_block_height = self
+			.query_finalized_or_default(
+				metadata::storage().stellar_relay().new_validators_enactment_block_height(),
+			)
+			.await?;
+
+		let validators = if self.get_current_chain_height().await? >= enactment_block_height {
+			self.query_finalized_or_default(metadata::storage().stellar_relay().validators())
+				.await?
+		} else {
+			self.query_finalized_or_default(metadata::storage().stellar_relay().old_validators())
+				.await?
+		};
+		Ok(validators.0)
+	}
 }
 
 #[cfg(any(feature = "standalone-metadata", feature = "parachain-metadata-foucoco"))]
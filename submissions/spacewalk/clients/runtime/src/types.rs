@@ -54,6 +54,7 @@ mod metadata_aliases {
 			vault_registry::{pallet::Error as VaultRegistryPalletError, types::VaultStatus},
 		},
 		security::events::UpdateActiveBlock as UpdateActiveBlockEvent,
+		stellar_relay::events::UpdateTier1ValidatorSet as UpdateTier1ValidatorSetEvent,
 		tokens::events::Endowed as EndowedEvent,
 		vault_registry::events::{
 			DepositCollateral as DepositCollateralEvent, LiquidateVault as LiquidateVaultEvent,
@@ -102,6 +103,9 @@ mod metadata_aliases {
 	pub type VaultCurrencyPair =
 		metadata::runtime_types::spacewalk_primitives::VaultCurrencyPair<Static<CurrencyId>>;
 
+	pub type StellarRelayValidator =
+		metadata::runtime_types::stellar_relay::traits::Validator<u128>;
+
 	pub type IssueRequestsMap = HashMap<IssueId, SpacewalkIssueRequest>;
 	pub type IssueIdLookup = HashMap<TextMemo, IssueId>;
 
@@ -0,0 +1,344 @@
+use clap::Parser;
+use serde_json::{json, Value};
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	str::FromStr,
+	time::Duration,
+};
+use stellar_relay_lib::{
+	connect_to_stellar_overlay_network,
+	sdk::{
+		types::{Hello, ScpEnvelope, ScpStatementPledges, StellarMessage},
+		SecretKey, TransactionSetType, XdrCodec,
+	},
+	ConnectionEndpoint, Error, StellarOverlayConfig, StellarOverlayConnection,
+};
+
+/// How long to wait for the `Hello` of the Stellar Node
+const HELLO_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How the SCP messages are printed
+#[derive(Clone, Copy, Debug)]
+enum Format {
+	/// the decoded statement
+	Json,
+	/// the base64 XDR of the envelope
+	Xdr,
+}
+
+impl FromStr for Format {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"json" => Ok(Format::Json),
+			"xdr" => Ok(Format::Xdr),
+			_ => Err(format!("unknown format {s}, expected json or xdr")),
+		}
+	}
+}
+
+/// Connects to a Stellar Node to debug the overlay, without running a vault.
+/// The output goes to stdout, one JSON object or base64 XDR per line; the logs go to stderr.
+#[derive(Parser)]
+#[clap(name = "stellar-overlay-cli", version)]
+struct Opts {
+	/// Path to the config of the overlay, like the ones in `resources/config`.
+	#[clap(long)]
+	config: String,
+
+	/// The Stellar Node to connect to, as `address:port`. Defaults to the endpoints of the config.
+	#[clap(long)]
+	peer: Option<String>,
+
+	/// Secret key to connect with. A random one is used if not given.
+	#[clap(long, env = "STELLAR_OVERLAY_SECRET_KEY", hide_env_values = true)]
+	secret_key: Option<String>,
+
+	#[clap(subcommand)]
+	command: Command,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+	/// Prints the `Hello` of the Stellar Node.
+	Hello,
+
+	/// Streams the SCP messages of the Stellar Node.
+	Scp {
+		/// `json` for the decoded statement, `xdr` for the base64 XDR of the envelope.
+		#[clap(long, default_value = "json")]
+		format: Format,
+
+		/// Stops after this many messages.
+		#[clap(long)]
+		count: Option<usize>,
+	},
+
+	/// Fetches the tx set of the given hex encoded hash.
+	TxSet { hash: String },
+
+	/// Prints how many nodes externalized each slot, once the network moved past it.
+	ExternalizeCounts {
+		/// Stops after this many slots.
+		#[clap(long, default_value = "10")]
+		slots: usize,
+	},
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+	env_logger::init_from_env(
+		env_logger::Env::default().filter_or(env_logger::DEFAULT_FILTER_ENV, "warn"),
+	);
+	let opts = Opts::parse();
+
+	let mut cfg = StellarOverlayConfig::try_from_path(&opts.config)?;
+	if let Some(peer) = &opts.peer {
+		cfg.connection_info.endpoints = vec![parse_endpoint(peer)?];
+	}
+	cfg.connection_info.recv_scp_msgs =
+		matches!(opts.command, Command::Scp { .. } | Command::ExternalizeCounts { .. });
+
+	let secret_key = match opts.secret_key {
+		Some(secret_key) => secret_key,
+		None => String::from_utf8(SecretKey::from_binary(rand::random()).to_encoding())?,
+	};
+
+	let mut connection = connect_to_stellar_overlay_network(cfg, secret_key).await?;
+	let hello = tokio::time::timeout(HELLO_TIMEOUT, wait_for_hello(&mut connection))
+		.await
+		.map_err(|_| Error::Timeout)??;
+
+	match opts.command {
+		Command::Hello => println!("{}", hello_json(&hello)),
+		Command::Scp { format, count } => stream_scp(&mut connection, format, count).await?,
+		Command::TxSet { hash } => {
+			let tx_set_hash = <[u8; 32]>::try_from(hex::decode(&hash)?)
+				.map_err(|_| format!("the tx set hash {hash} is not 32 bytes"))?;
+			let tx_set = connection.get_tx_set(tx_set_hash).await?;
+			println!("{}", tx_set_json(&hash, &tx_set));
+		},
+		Command::ExternalizeCounts { slots } =>
+			report_externalize_counts(&mut connection, slots).await?,
+	}
+
+	connection.stop();
+	Ok(())
+}
+
+fn parse_endpoint(peer: &str) -> Result<ConnectionEndpoint, String> {
+	let (address, port) =
+		peer.rsplit_once(':').ok_or_else(|| format!("the peer {peer} is not address:port"))?;
+	let port = port.parse().map_err(|e| format!("the port of the peer {peer}: {e}"))?;
+
+	Ok(ConnectionEndpoint { address: address.as_bytes().to_vec(), port })
+}
+
+/// Returns the `Hello` that the Stellar Node sent once the handshake is done
+async fn wait_for_hello(connection: &mut StellarOverlayConnection) -> Result<Hello, Error> {
+	loop {
+		match connection.listen().await? {
+			Some(StellarMessage::Hello(hello)) => return Ok(hello),
+			Some(_) => continue,
+			None => return Err(Error::Disconnected),
+		}
+	}
+}
+
+async fn stream_scp(
+	connection: &mut StellarOverlayConnection,
+	format: Format,
+	count: Option<usize>,
+) -> Result<(), Error> {
+	let mut printed = 0;
+	while count.map_or(true, |count| printed < count) {
+		let Some(msg) = connection.listen().await? else { break };
+		let StellarMessage::ScpMessage(env) = msg else { continue };
+
+		match format {
+			Format::Json => println!("{}", envelope_json(&env)),
+			Format::Xdr => println!("{}", String::from_utf8_lossy(&env.to_base64_xdr())),
+		}
+		printed += 1;
+	}
+	Ok(())
+}
+
+async fn report_externalize_counts(
+	connection: &mut StellarOverlayConnection,
+	slots: usize,
+) -> Result<(), Error> {
+	let mut counts = ExternalizeCounts::default();
+	let mut reported = 0;
+	while reported < slots {
+		let Some(msg) = connection.listen().await? else { break };
+		let StellarMessage::ScpMessage(env) = msg else { continue };
+
+		for (slot, values) in counts.on_envelope(&env) {
+			// the quorum sets were requested by the connection as the envelopes arrived
+			let is_valid = connection.validate_externalize(slot).is_ok();
+			println!("{}", slot_counts_json(slot, &values, is_valid));
+			reported += 1;
+		}
+	}
+	Ok(())
+}
+
+/// The nodes that externalized each value, by slot
+type ValueNodes = BTreeMap<Vec<u8>, BTreeSet<Vec<u8>>>;
+
+/// Collects the externalize envelopes of a slot, until the network moved past it
+#[derive(Default)]
+struct ExternalizeCounts {
+	slots: BTreeMap<u64, ValueNodes>,
+	/// the latest slot that was returned already
+	last_done: Option<u64>,
+}
+
+impl ExternalizeCounts {
+	/// Keeps the envelope if it is an externalize, and returns the slots that are done: the ones
+	/// more than a slot behind the envelope, since late envelopes of the previous slot still count.
+	fn on_envelope(&mut self, env: &ScpEnvelope) -> Vec<(u64, ValueNodes)> {
+		let slot = env.statement.slot_index;
+
+		if let ScpStatementPledges::ScpStExternalize(externalize) = &env.statement.pledges {
+			if self.last_done.map_or(true, |last_done| slot > last_done) {
+				self.slots
+					.entry(slot)
+					.or_default()
+					.entry(externalize.commit.value.get_vec().clone())
+					.or_default()
+					.insert(env.statement.node_id.to_encoding());
+			}
+		}
+
+		let done: Vec<u64> =
+			self.slots.keys().copied().take_while(|known| known + 1 < slot).collect();
+		if let Some(last) = done.last() {
+			self.last_done = Some(*last);
+		}
+		done.into_iter().filter_map(|slot| self.slots.remove_entry(&slot)).collect()
+	}
+}
+
+/// The tx set hash is the start of the `StellarValue` that the nodes agree on
+fn tx_set_hash_of_value(value: &[u8]) -> Option<String> {
+	value.get(..32).map(hex::encode)
+}
+
+fn hello_json(hello: &Hello) -> Value {
+	json!({
+		"peer_id": String::from_utf8_lossy(&hello.peer_id.to_encoding()),
+		"version_str": String::from_utf8_lossy(hello.version_str.get_vec()),
+		"ledger_version": hello.ledger_version,
+		"overlay_version": hello.overlay_version,
+		"overlay_min_version": hello.overlay_min_version,
+		"network_id": hex::encode(hello.network_id),
+		"listening_port": hello.listening_port,
+	})
+}
+
+fn envelope_json(env: &ScpEnvelope) -> Value {
+	let statement = &env.statement;
+	let (statement_type, ballot) = match &statement.pledges {
+		ScpStatementPledges::ScpStPrepare(prepare) => ("prepare", Some(&prepare.ballot)),
+		ScpStatementPledges::ScpStConfirm(confirm) => ("confirm", Some(&confirm.ballot)),
+		ScpStatementPledges::ScpStExternalize(externalize) =>
+			("externalize", Some(&externalize.commit)),
+		ScpStatementPledges::ScpStNominate(_) => ("nominate", None),
+	};
+
+	json!({
+		"slot": statement.slot_index,
+		"node_id": String::from_utf8_lossy(&statement.node_id.to_encoding()),
+		"type": statement_type,
+		"ballot_counter": ballot.map(|ballot| ballot.counter),
+		"tx_set_hash": ballot.and_then(|ballot| tx_set_hash_of_value(ballot.value.get_vec())),
+	})
+}
+
+fn tx_set_json(hash: &str, tx_set: &TransactionSetType) -> Value {
+	let (tx_set_type, xdr) = match tx_set {
+		TransactionSetType::TransactionSet(set) => ("tx_set", set.to_base64_xdr()),
+		TransactionSetType::GeneralizedTransactionSet(set) =>
+			("generalized_tx_set", set.to_base64_xdr()),
+	};
+
+	json!({
+		"hash": hash,
+		"type": tx_set_type,
+		"xdr": String::from_utf8_lossy(&xdr),
+	})
+}
+
+fn slot_counts_json(slot: u64, values: &ValueNodes, is_valid: bool) -> Value {
+	let values: Vec<Value> = values
+		.iter()
+		.map(|(value, nodes)| {
+			json!({
+				"tx_set_hash": tx_set_hash_of_value(value),
+				"nodes": nodes.len(),
+			})
+		})
+		.collect();
+
+	json!({
+		"slot": slot,
+		"values": values,
+		"validated": is_valid,
+	})
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use stellar_relay_lib::sdk::{
+		compound_types::UnlimitedVarOpaque,
+		types::{ScpBallot, ScpStatement, ScpStatementExternalize, Signature},
+	};
+
+	fn externalize(node: u8, slot: u64, value: u8) -> ScpEnvelope {
+		let statement = ScpStatement {
+			node_id: SecretKey::from_binary([node; 32]).get_public().clone(),
+			slot_index: slot,
+			pledges: ScpStatementPledges::ScpStExternalize(ScpStatementExternalize {
+				commit: ScpBallot {
+					counter: 1,
+					value: UnlimitedVarOpaque::new(vec![value; 32]).expect("should create a value"),
+				},
+				n_h: 1,
+				commit_quorum_set_hash: [0; 32],
+			}),
+		};
+		ScpEnvelope {
+			statement,
+			signature: Signature::new(vec![]).expect("should create a signature"),
+		}
+	}
+
+	#[test]
+	fn slots_are_counted_once_the_network_moved_past_them() {
+		let mut counts = ExternalizeCounts::default();
+
+		assert!(counts.on_envelope(&externalize(1, 10, 7)).is_empty());
+		assert!(counts.on_envelope(&externalize(2, 10, 7)).is_empty());
+		// a late envelope of the previous slot
+		assert!(counts.on_envelope(&externalize(1, 11, 7)).is_empty());
+		assert!(counts.on_envelope(&externalize(3, 10, 8)).is_empty());
+		// the same node again
+		assert!(counts.on_envelope(&externalize(3, 10, 8)).is_empty());
+
+		let done = counts.on_envelope(&externalize(1, 12, 7));
+		assert_eq!(done.len(), 1);
+		let (slot, values) = &done[0];
+		assert_eq!(*slot, 10);
+		assert_eq!(values.get(&vec![7; 32]).map(BTreeSet::len), Some(2));
+		assert_eq!(values.get(&vec![8; 32]).map(BTreeSet::len), Some(1));
+
+		// envelopes of a slot that was reported already are ignored
+		assert!(counts.on_envelope(&externalize(4, 10, 7)).is_empty());
+		let done = counts.on_envelope(&externalize(1, 13, 7));
+		assert_eq!(done.iter().map(|(slot, _)| *slot).collect::<Vec<_>>(), vec![11]);
+	}
+}
@@ -1,7 +1,10 @@
 use crate::{
-	connection::{ConnectionInfo, Error},
+	connection::{
+		read_recording, ConnectionInfo, Error, FlowControlConfig, ReconnectPolicy, Transport,
+	},
 	node::NodeInfo,
-	StellarOverlayConnection,
+	peer_book::PeerBook,
+	StellarOverlayConnection, StellarOverlayListener, StellarOverlayPool,
 };
 use rand::seq::SliceRandom;
 use serde::{Deserialize, Serialize};
@@ -44,35 +47,63 @@ impl StellarOverlayConfig {
 		&self,
 		secret_key_as_string: String,
 	) -> Result<ConnectionInfo, Error> {
-		let cfg = &self.connection_info;
 		let secret_key = SecretKey::from_encoding(secret_key_as_string)?;
+		log_public_key(&secret_key);
 
-		let public_key = secret_key.get_public().to_encoding();
-		let public_key = std::str::from_utf8(&public_key).unwrap();
-		tracing::info!(
-			"connection_info(): Connecting to Stellar overlay network using public key: {public_key}"
-		);
-		let endpoint = cfg.endpoints.choose(&mut rand::thread_rng()).ok_or(Error::ConfigError(
-			"No endpoints found in config for connecting to overlay".to_string(),
-		))?;
+		let endpoint = self.connection_info.endpoints.choose(&mut rand::thread_rng()).ok_or(
+			Error::ConfigError(
+				"No endpoints found in config for connecting to overlay".to_string(),
+			),
+		)?;
 
-		let address = std::str::from_utf8(&endpoint.address)
-			.map_err(|e| Error::ConfigError(format!("Address: {:?}", e)))?;
-		let port = endpoint.port;
+		self.connection_info.to_connection_info(endpoint, secret_key)
+	}
 
-		Ok(ConnectionInfo::new_with_timeout(
-			address,
-			port,
-			secret_key,
-			cfg.auth_cert_expiration,
-			cfg.recv_tx_msgs,
-			cfg.recv_scp_msgs,
-			cfg.remote_called_us,
-			cfg.timeout_in_secs,
-		))
+	/// Returns a `ConnectionInfo` for every endpoint found in the config.
+	pub(crate) fn connection_infos(
+		&self,
+		secret_key_as_string: String,
+	) -> Result<Vec<ConnectionInfo>, Error> {
+		let secret_key = SecretKey::from_encoding(secret_key_as_string)?;
+		log_public_key(&secret_key);
+
+		if self.connection_info.endpoints.is_empty() {
+			return Err(Error::ConfigError(
+				"No endpoints found in config for connecting to overlay".to_string(),
+			))
+		}
+
+		self.connection_info
+			.endpoints
+			.iter()
+			.map(|endpoint| self.connection_info.to_connection_info(endpoint, secret_key.clone()))
+			.collect()
+	}
+
+	/// Returns the `ConnectionInfo` for listening to connections opened by Stellar Nodes.
+	pub(crate) fn listener_info(
+		&self,
+		secret_key_as_string: String,
+	) -> Result<ConnectionInfo, Error> {
+		let secret_key = SecretKey::from_encoding(secret_key_as_string)?;
+		log_public_key(&secret_key);
+
+		let endpoint = self.connection_info.listen_endpoint.as_ref().ok_or(Error::ConfigError(
+			"No listen endpoint found in config for accepting overlay connections".to_string(),
+		))?;
+
+		let mut conn_info = self.connection_info.to_connection_info(endpoint, secret_key)?;
+		conn_info.remote_called_us = true;
+		Ok(conn_info)
 	}
 }
 
+fn log_public_key(secret_key: &SecretKey) {
+	let public_key = secret_key.get_public().to_encoding();
+	let public_key = std::str::from_utf8(&public_key).unwrap();
+	tracing::info!("Connecting to Stellar overlay network using public key: {public_key}");
+}
+
 /// The config structure for the NodeInfo
 #[serde_as]
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
@@ -114,6 +145,30 @@ pub struct ConnectionInfoCfg {
 	/// how long to wait for the Stellar Node's messages.
 	#[serde(default = "ConnectionInfoCfg::default_timeout")]
 	pub timeout_in_secs: u64,
+
+	/// where to listen for connections opened by Stellar Nodes.
+	#[serde(default)]
+	pub listen_endpoint: Option<ConnectionEndpoint>,
+
+	/// the file to record the frames of the connection to, for replaying them later. The
+	/// connections of a pool are recorded to files of their own, named after the endpoint.
+	#[serde(default)]
+	pub record_frames_to: Option<String>,
+
+	/// the file to keep the peers discovered by the overlay pool in. Without it, the pool only
+	/// connects to the `endpoints`.
+	#[serde(default)]
+	pub peer_book_path: Option<String>,
+
+	/// how to reach the Stellar Nodes, e.g. through a SOCKS5 or HTTP proxy. Connects directly by
+	/// default.
+	#[serde(default)]
+	pub transport: Transport,
+
+	/// how many messages the Stellar Nodes may send, and when to hold them back because the user
+	/// is too slow.
+	#[serde(default)]
+	pub flow_control: FlowControlConfig,
 }
 
 impl ConnectionInfoCfg {
@@ -136,6 +191,33 @@ impl ConnectionInfoCfg {
 	fn default_timeout() -> u64 {
 		10
 	}
+
+	fn to_connection_info(
+		&self,
+		endpoint: &ConnectionEndpoint,
+		secret_key: SecretKey,
+	) -> Result<ConnectionInfo, Error> {
+		let address = std::str::from_utf8(&endpoint.address)
+			.map_err(|e| Error::ConfigError(format!("Address: {:?}", e)))?;
+
+		let conn_info = ConnectionInfo::new_with_timeout(
+			address,
+			endpoint.port,
+			secret_key,
+			self.auth_cert_expiration,
+			self.recv_tx_msgs,
+			self.recv_scp_msgs,
+			self.remote_called_us,
+			self.timeout_in_secs,
+		)
+		.with_transport(self.transport.clone())
+		.with_flow_control(self.flow_control.clone())?;
+
+		Ok(match &self.record_frames_to {
+			Some(path) => conn_info.with_recording(path),
+			None => conn_info,
+		})
+	}
 }
 
 /// Triggers connection to the Stellar Node.
@@ -150,9 +232,83 @@ pub async fn connect_to_stellar_overlay_network(
 	StellarOverlayConnection::connect(local_node.into(), conn_info).await
 }
 
+/// Triggers connection to the Stellar Node, which is restarted based on the `policy` whenever
+/// it gets lost.
+/// Returns the `StellarOverlayConnection` if connection is a success, otherwise an Error
+pub async fn connect_to_stellar_overlay_network_with_reconnect(
+	cfg: StellarOverlayConfig,
+	secret_key_as_string: String,
+	policy: ReconnectPolicy,
+) -> Result<StellarOverlayConnection, Error> {
+	let conn_info = cfg.connection_info(secret_key_as_string)?;
+	let local_node = cfg.node_info;
+
+	StellarOverlayConnection::connect_with_reconnect_policy(local_node.into(), conn_info, policy)
+		.await
+}
+
+/// Triggers connections to multiple Stellar Nodes at once.
+/// Returns the `StellarOverlayPool` if at least one connection is a success, otherwise an Error
+///
+/// If the config has a `peer_book_path`, the endpoints are only the seeds: the pool asks its peers
+/// for more, and prefers the ones that were the fastest and most stable so far.
+///
+/// # Arguments
+/// * `pool_size` - the number of peers to keep connected simultaneously. This is capped by the
+///   number of known peers.
+pub async fn connect_to_stellar_overlay_pool(
+	cfg: StellarOverlayConfig,
+	secret_key_as_string: String,
+	pool_size: usize,
+) -> Result<StellarOverlayPool, Error> {
+	let conn_infos = cfg.connection_infos(secret_key_as_string)?;
+	let local_node = cfg.node_info;
+
+	match &cfg.connection_info.peer_book_path {
+		Some(path) => {
+			let peer_book = PeerBook::load(path)?;
+			StellarOverlayPool::connect_with_peer_book(
+				local_node.into(),
+				conn_infos,
+				pool_size,
+				peer_book,
+			)
+			.await
+		},
+		None => StellarOverlayPool::connect(local_node.into(), conn_infos, pool_size).await,
+	}
+}
+
+/// Binds to the `listen_endpoint` of the config, to accept connections opened by Stellar Nodes.
+/// Returns the `StellarOverlayListener` if binding is a success, otherwise an Error
+pub async fn listen_for_stellar_overlay_peers(
+	cfg: StellarOverlayConfig,
+	secret_key_as_string: String,
+) -> Result<StellarOverlayListener, Error> {
+	let conn_info = cfg.listener_info(secret_key_as_string)?;
+	let local_node = cfg.node_info;
+
+	StellarOverlayListener::bind(local_node.into(), conn_info).await
+}
+
+/// Replays the frames recorded at `recording_path`, instead of connecting to a Stellar Node.
+/// Returns the `StellarOverlayConnection` if the recording can be read, otherwise an Error
+pub async fn replay_stellar_overlay_recording(
+	cfg: StellarOverlayConfig,
+	secret_key_as_string: String,
+	recording_path: &str,
+) -> Result<StellarOverlayConnection, Error> {
+	let conn_info = cfg.connection_info(secret_key_as_string)?;
+	let local_node = cfg.node_info;
+	let frames = read_recording(recording_path)?;
+
+	StellarOverlayConnection::replay(local_node.into(), conn_info, frames)
+}
+
 #[cfg(test)]
 mod test {
 	use super::*;
+	use crate::ProxyConfig;
 
 	#[test]
 	fn connection_info_conversion_successful() {
@@ -167,6 +323,91 @@ mod test {
 			serde_json::from_str(json).expect("should return a ConnectionInfoCfg");
 	}
 
+	#[test]
+	fn listen_endpoint_in_connection_info_config() {
+		let json = r#"
+			{
+			  "endpoints": [],
+			  "listen_endpoint": { "address": "0.0.0.0", "port": 11625 }
+			}
+			"#;
+
+		let cfg: ConnectionInfoCfg =
+			serde_json::from_str(json).expect("should return a ConnectionInfoCfg");
+		let listen_endpoint = cfg.listen_endpoint.expect("should have a listen endpoint");
+		assert_eq!(listen_endpoint.address, b"0.0.0.0".to_vec());
+		assert_eq!(listen_endpoint.port, 11625);
+
+		// the listen endpoint is optional
+		let cfg: ConnectionInfoCfg = serde_json::from_str(r#"{ "endpoints": [] }"#)
+			.expect("should return a ConnectionInfoCfg");
+		assert!(cfg.listen_endpoint.is_none());
+	}
+
+	#[test]
+	fn peer_book_path_in_connection_info_config() {
+		let json = r#"
+			{
+			  "endpoints": [],
+			  "peer_book_path": "peers.json"
+			}
+			"#;
+
+		let cfg: ConnectionInfoCfg =
+			serde_json::from_str(json).expect("should return a ConnectionInfoCfg");
+		assert_eq!(cfg.peer_book_path.as_deref(), Some("peers.json"));
+
+		// the peer book is optional
+		let cfg: ConnectionInfoCfg = serde_json::from_str(r#"{ "endpoints": [] }"#)
+			.expect("should return a ConnectionInfoCfg");
+		assert!(cfg.peer_book_path.is_none());
+	}
+
+	#[test]
+	fn transport_in_connection_info_config() {
+		let json = r#"
+			{
+			  "endpoints": [],
+			  "transport": { "type": "socks5", "address": "10.0.0.1", "port": 1080 }
+			}
+			"#;
+
+		let cfg: ConnectionInfoCfg =
+			serde_json::from_str(json).expect("should return a ConnectionInfoCfg");
+		assert_eq!(cfg.transport, Transport::Socks5(ProxyConfig::new("10.0.0.1", 1080)));
+
+		// the transport is direct by default
+		let cfg: ConnectionInfoCfg = serde_json::from_str(r#"{ "endpoints": [] }"#)
+			.expect("should return a ConnectionInfoCfg");
+		assert_eq!(cfg.transport, Transport::Direct);
+	}
+
+	#[test]
+	fn flow_control_in_connection_info_config() {
+		let json = r#"
+			{
+			  "endpoints": [],
+			  "flow_control": { "send_more_batch_size": 20, "user_channel_capacity": 4096 }
+			}
+			"#;
+
+		let cfg: ConnectionInfoCfg =
+			serde_json::from_str(json).expect("should return a ConnectionInfoCfg");
+		// the missing budgets are the defaults
+		assert_eq!(
+			cfg.flow_control,
+			FlowControlConfig {
+				send_more_batch_size: 20,
+				user_channel_capacity: 4096,
+				..Default::default()
+			}
+		);
+
+		let cfg: ConnectionInfoCfg = serde_json::from_str(r#"{ "endpoints": [] }"#)
+			.expect("should return a ConnectionInfoCfg");
+		assert_eq!(cfg.flow_control, FlowControlConfig::default());
+	}
+
 	#[test]
 	fn missing_fields_in_connection_info_config() {
 		// missing port
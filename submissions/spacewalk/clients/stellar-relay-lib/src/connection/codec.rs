@@ -0,0 +1,137 @@
+use bytes::{Buf, BufMut, BytesMut};
+use std::io;
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::connection::xdr_converter::get_xdr_message_length;
+
+/// The 4 bytes in front of every frame, holding its length
+const FRAME_HEADER_LEN: usize = 4;
+
+/// The smallest frame of an authenticated message: its version, sequence, message type and mac
+const MIN_FRAME_LEN: usize = 4 + 8 + 4 + 32;
+
+/// The largest frame accepted from the Stellar Node, like stellar-core's `MAX_MESSAGE_SIZE`
+const MAX_FRAME_LEN: usize = 0x100_0000;
+
+/// Splits the stream of the Stellar Node into the record-marked frames of XDR messages,
+/// and writes messages as such frames.
+/// The frames are without the 4 bytes of their length, and are split off the read buffer
+/// without copying.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct XdrFrameCodec;
+
+impl Decoder for XdrFrameCodec {
+	type Item = BytesMut;
+	type Error = io::Error;
+
+	fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+		loop {
+			if src.len() < FRAME_HEADER_LEN {
+				return Ok(None)
+			}
+
+			let frame_len = get_xdr_message_length(&src[..FRAME_HEADER_LEN]);
+			if frame_len > MAX_FRAME_LEN {
+				return Err(io::Error::new(
+					io::ErrorKind::InvalidData,
+					format!("frame of {frame_len} bytes exceeds the max of {MAX_FRAME_LEN}"),
+				))
+			}
+
+			// there's nothing to read; skip it
+			if frame_len == 0 {
+				src.advance(FRAME_HEADER_LEN);
+				continue
+			}
+
+			if frame_len < MIN_FRAME_LEN {
+				return Err(io::Error::new(
+					io::ErrorKind::InvalidData,
+					format!("frame of {frame_len} bytes is below the min of {MIN_FRAME_LEN}"),
+				))
+			}
+
+			if src.len() < FRAME_HEADER_LEN + frame_len {
+				// make room for the rest of the frame, to read it at once
+				src.reserve(FRAME_HEADER_LEN + frame_len - src.len());
+				return Ok(None)
+			}
+
+			src.advance(FRAME_HEADER_LEN);
+			return Ok(Some(src.split_to(frame_len)))
+		}
+	}
+}
+
+impl Encoder<&[u8]> for XdrFrameCodec {
+	type Error = io::Error;
+
+	fn encode(&mut self, frame: &[u8], dst: &mut BytesMut) -> Result<(), Self::Error> {
+		let frame_len = u32::try_from(frame.len()).map_err(|_| {
+			io::Error::new(io::ErrorKind::InvalidInput, "frame exceeds the max of u32")
+		})?;
+
+		dst.reserve(FRAME_HEADER_LEN + frame.len());
+		dst.put_u32(frame_len);
+		dst.put_slice(frame);
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn frames_are_decoded_once_complete() {
+		let mut codec = XdrFrameCodec;
+		let mut buf = BytesMut::new();
+		let (first, second) = ([1; MIN_FRAME_LEN], [2; MIN_FRAME_LEN + 1]);
+		codec.encode(&first[..], &mut buf).expect("should encode");
+		codec.encode(&second[..], &mut buf).expect("should encode");
+
+		// only half of the first frame arrived so far
+		let mut partial = buf.split_to(MIN_FRAME_LEN / 2);
+		assert_eq!(codec.decode(&mut partial).expect("should decode"), None);
+
+		partial.unsplit(buf);
+		let mut buf = partial;
+		assert_eq!(codec.decode(&mut buf).expect("should decode").as_deref(), Some(&first[..]));
+		assert_eq!(codec.decode(&mut buf).expect("should decode").as_deref(), Some(&second[..]));
+		assert_eq!(codec.decode(&mut buf).expect("should decode"), None);
+	}
+
+	#[test]
+	fn record_mark_is_ignored_and_empty_frames_are_skipped() {
+		let mut codec = XdrFrameCodec;
+		// an empty frame, and a frame with the record mark set
+		let mut buf = BytesMut::from(&[0, 0, 0, 0, 0x80, 0, 0, MIN_FRAME_LEN as u8][..]);
+		buf.extend_from_slice(&[7; MIN_FRAME_LEN]);
+
+		assert_eq!(
+			codec.decode(&mut buf).expect("should decode").as_deref(),
+			Some(&[7; MIN_FRAME_LEN][..])
+		);
+		assert!(buf.is_empty());
+	}
+
+	#[test]
+	fn oversized_frames_are_rejected() {
+		let mut codec = XdrFrameCodec;
+		let mut buf = BytesMut::from(&[0x7f, 0xff, 0xff, 0xff][..]);
+
+		assert!(codec.decode(&mut buf).is_err());
+	}
+
+	#[test]
+	fn frames_too_short_for_an_authenticated_message_are_rejected() {
+		let mut codec = XdrFrameCodec;
+		for frame_len in [1, 15, MIN_FRAME_LEN - 1] {
+			let mut buf = BytesMut::new();
+			codec.encode(&vec![0; frame_len][..], &mut buf).expect("should encode");
+
+			let error = codec.decode(&mut buf).expect_err("should reject the frame");
+			assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+		}
+	}
+}
@@ -1,7 +1,8 @@
-use async_std::net::TcpStream;
+use bytes::BytesMut;
+use futures::StreamExt;
 use std::{
 	fmt::{Debug, Formatter},
-	net::Shutdown,
+	time::{Duration, Instant},
 };
 use substrate_stellar_sdk::{
 	types::{AuthenticatedMessageV0, Curve25519Public, HmacSha256Mac},
@@ -9,20 +10,37 @@ use substrate_stellar_sdk::{
 };
 
 use substrate_stellar_sdk::types::StellarMessage;
+use tokio::{
+	net::{
+		tcp::{OwnedReadHalf, OwnedWriteHalf},
+		TcpStream,
+	},
+	sync::mpsc,
+};
+use tokio_util::codec::{FramedRead, FramedWrite};
 use tracing::{error, trace};
 
 use crate::{
 	connection::{
 		authentication::{gen_shared_key, ConnectionAuth},
+		codec::XdrFrameCodec,
+		events::send_event,
 		flow_controller::FlowController,
 		handshake::HandshakeState,
 		hmac::{verify_hmac, HMacKeys},
-		ConnectionInfo, Error,
+		ConnectionEvent, ConnectionInfo, Error, FrameDirection, FrameRecorder, ScpTracker,
+		TxFlooding,
 	},
+	metrics,
 	node::{LocalInfo, NodeInfo, RemoteInfo},
 };
 
 pub struct Connector {
+	/// the address of the Stellar Node, which labels the metrics of the connection
+	peer_address: String,
+	/// when the connection was created, to measure how long the handshake takes
+	started_at: Instant,
+
 	local: LocalInfo,
 
 	remote_info: Option<RemoteInfo>,
@@ -38,8 +56,25 @@ pub struct Connector {
 	handshake_state: HandshakeState,
 	pub(crate) flow_controller: FlowController,
 
-	/// for writing/reading xdr messages to/from Stellar Node.
-	pub(crate) tcp_stream: TcpStream,
+	/// for reading xdr messages from Stellar Node.
+	/// None when replaying a recording, where there is no Stellar Node to talk to.
+	reader: Option<FramedRead<OwnedReadHalf, XdrFrameCodec>>,
+	/// for writing xdr messages to Stellar Node.
+	writer: Option<FramedWrite<OwnedWriteHalf, XdrFrameCodec>>,
+	/// true when replaying a recording
+	is_replaying: bool,
+
+	/// for notifying the user of what happens with the connection.
+	event_sender: Option<mpsc::Sender<ConnectionEvent>>,
+
+	/// for recording the frames of the connection.
+	recorder: Option<FrameRecorder>,
+
+	/// for demanding advertised transactions, and serving our own.
+	tx_flooding: TxFlooding,
+
+	/// for keeping the quorum sets and the externalized slots of the verified envelopes.
+	scp_tracker: ScpTracker,
 }
 
 impl Debug for Connector {
@@ -58,19 +93,14 @@ impl Debug for Connector {
 			.field(
 				"local_addr",
 				&self
-					.tcp_stream
-					.local_addr()
-					.map(|addr| addr.to_string())
-					.unwrap_or("cannot provide".to_string()),
-			)
-			.field(
-				"peer_addr",
-				&self
-					.tcp_stream
-					.peer_addr()
+					.reader
+					.as_ref()
+					.and_then(|reader| reader.get_ref().local_addr().ok())
 					.map(|addr| addr.to_string())
 					.unwrap_or("cannot provide".to_string()),
 			)
+			.field("peer_addr", &self.peer_address)
+			.field("is_recording", &self.recorder.is_some())
 			.finish()
 	}
 }
@@ -96,13 +126,15 @@ impl Connector {
 		);
 
 		if remote_info.sequence() != auth_msg.sequence {
+			metrics::record_sequence_mismatch(&self.peer_address);
 			// must be handled on main thread because workers could mix up order of messages.
 			return Err(Error::InvalidSequenceNumber);
 		}
 
 		let keys = self.hmac_keys.as_ref().ok_or(Error::MissingHmacKeys)?;
 
-		verify_hmac(body, &keys.receiving().mac, &auth_msg.mac.to_xdr())?;
+		verify_hmac(body, &keys.receiving().mac, &auth_msg.mac.to_xdr())
+			.inspect_err(|_| metrics::record_hmac_failure(&self.peer_address))?;
 
 		Ok(())
 	}
@@ -134,9 +166,47 @@ impl Connector {
 	/// returns a Connector and starts creating a connection to Stellar
 	pub async fn start(local_node: NodeInfo, conn_info: ConnectionInfo) -> Result<Self, Error> {
 		// Create the stream
-		let tcp_stream = TcpStream::connect(conn_info.address())
-			.await
-			.map_err(|e| Error::ConnectionFailed(e.to_string()))?;
+		let tcp_stream = conn_info
+			.transport
+			.connect(
+				&conn_info.address,
+				conn_info.port,
+				Duration::from_secs(conn_info.timeout_in_secs),
+			)
+			.await?;
+
+		let mut connector = Connector::new(local_node, conn_info, Some(tcp_stream))?;
+
+		// To start the handshake, send a hello message to Stellar
+		connector.send_hello_message().await?;
+
+		Ok(connector)
+	}
+
+	/// returns a Connector for a stream that the Stellar Node opened to us.
+	/// The handshake starts once the Stellar Node sends its hello message.
+	pub fn accept(
+		local_node: NodeInfo,
+		conn_info: ConnectionInfo,
+		tcp_stream: TcpStream,
+	) -> Result<Self, Error> {
+		let conn_info = ConnectionInfo { remote_called_us: true, ..conn_info };
+		Connector::new(local_node, conn_info, Some(tcp_stream))
+	}
+
+	/// returns a Connector without a stream, for feeding the frames of a recording to it.
+	/// Messages meant for the Stellar Node are dropped.
+	pub fn replay(local_node: NodeInfo, conn_info: ConnectionInfo) -> Result<Self, Error> {
+		let conn_info = ConnectionInfo { record_frames_to: None, ..conn_info };
+		Connector::new(local_node, conn_info, None)
+	}
+
+	fn new(
+		local_node: NodeInfo,
+		conn_info: ConnectionInfo,
+		tcp_stream: Option<TcpStream>,
+	) -> Result<Self, Error> {
+		let recorder = conn_info.record_frames_to.as_ref().map(FrameRecorder::open).transpose()?;
 
 		let connection_auth = ConnectionAuth::new(
 			&local_node.network_id,
@@ -144,8 +214,38 @@ impl Connector {
 			conn_info.auth_cert_expiration,
 		);
 
-		let mut connector = Connector {
-			local: LocalInfo::new(local_node),
+		let mut local = LocalInfo::new(local_node);
+		if conn_info.remote_called_us {
+			// let the Stellar Node know where we are listening
+			local = local.with_port(conn_info.port);
+		}
+
+		// a Stellar Node that connected to us is not at the address of the `conn_info`.
+		// a stream through a proxy is connected to the proxy instead.
+		let peer_address = tcp_stream
+			.as_ref()
+			.filter(|_| conn_info.remote_called_us || !conn_info.transport.is_proxy())
+			.and_then(|stream| stream.peer_addr().ok())
+			.map(|addr| addr.to_string())
+			.unwrap_or_else(|| conn_info.address());
+
+		let is_replaying = tcp_stream.is_none();
+		let (reader, writer) = match tcp_stream {
+			Some(tcp_stream) => {
+				// reading and writing do not wait for each other
+				let (read_half, write_half) = tcp_stream.into_split();
+				(
+					Some(FramedRead::new(read_half, XdrFrameCodec)),
+					Some(FramedWrite::new(write_half, XdrFrameCodec)),
+				)
+			},
+			None => (None, None),
+		};
+
+		Ok(Connector {
+			peer_address,
+			started_at: Instant::now(),
+			local,
 			remote_info: None,
 			hmac_keys: None,
 			connection_auth,
@@ -154,19 +254,55 @@ impl Connector {
 			receive_tx_messages: conn_info.recv_tx_msgs,
 			receive_scp_messages: conn_info.recv_scp_msgs,
 			handshake_state: HandshakeState::Connecting,
-			flow_controller: FlowController::default(),
-			tcp_stream,
-		};
+			flow_controller: FlowController::new(conn_info.flow_control.clone()),
+			reader,
+			writer,
+			is_replaying,
+			event_sender: None,
+			recorder,
+			tx_flooding: TxFlooding::default(),
+			scp_tracker: ScpTracker::default(),
+		})
+	}
 
-		// To start the handshake, send a hello message to Stellar
-		connector.send_hello_message().await?;
+	/// Closes the stream to the Stellar Node
+	pub fn stop(&mut self) {
+		// dropping both halves closes the stream
+		self.reader = None;
+		self.writer = None;
+	}
 
-		Ok(connector)
+	/// Returns the next frame from the Stellar Node, without the 4 bytes of its length.
+	/// This is cancel safe: a frame that is partly read stays in the buffer.
+	pub(crate) async fn read_frame(&mut self) -> Result<BytesMut, Error> {
+		let reader = self.reader.as_mut().ok_or(Error::Disconnected)?;
+
+		match reader.next().await {
+			Some(Ok(frame)) => Ok(frame),
+			Some(Err(e)) => Err(Error::ReadFailed(e.to_string())),
+			None => Err(Error::ReadFailed("stream closed by Stellar Node".to_string())),
+		}
 	}
 
-	pub fn stop(&mut self) {
-		if let Err(e) = self.tcp_stream.shutdown(Shutdown::Both) {
-			error!("stop(): failed to shutdown tcp stream: {}", e);
+	/// Returns the writer to the Stellar Node, which is missing when replaying a recording.
+	pub(crate) fn writer(
+		&mut self,
+	) -> Result<&mut FramedWrite<OwnedWriteHalf, XdrFrameCodec>, Error> {
+		self.writer.as_mut().ok_or(Error::Disconnected)
+	}
+
+	pub fn is_replaying(&self) -> bool {
+		self.is_replaying
+	}
+
+	/// Writes the frame to the recording, if there is one.
+	/// Recording stops at the first failure, so that the connection is not disturbed by it.
+	pub(crate) fn record(&mut self, direction: FrameDirection, xdr: &[u8]) {
+		let Some(recorder) = self.recorder.as_mut() else { return };
+
+		if let Err(e) = recorder.record(direction, xdr) {
+			error!("record(): failed to record frame; recording stops: {e:?}");
+			self.recorder = None;
 		}
 	}
 }
@@ -226,6 +362,10 @@ impl Connector {
 		self.handshake_state >= HandshakeState::GotHello
 	}
 
+	pub fn is_handshake_completed(&self) -> bool {
+		self.handshake_state == HandshakeState::Completed
+	}
+
 	pub fn got_hello(&mut self) {
 		self.handshake_state = HandshakeState::GotHello;
 	}
@@ -239,6 +379,38 @@ impl Connector {
 
 	pub fn handshake_completed(&mut self) {
 		self.handshake_state = HandshakeState::Completed;
+		metrics::record_handshake_duration(&self.peer_address, self.started_at.elapsed());
+	}
+
+	pub(crate) fn peer_address(&self) -> &str {
+		&self.peer_address
+	}
+
+	pub fn set_event_sender(&mut self, value: mpsc::Sender<ConnectionEvent>) {
+		self.event_sender = Some(value);
+	}
+
+	pub(crate) fn set_tx_flooding(&mut self, value: TxFlooding) {
+		self.tx_flooding = value;
+	}
+
+	pub(crate) fn tx_flooding(&self) -> &TxFlooding {
+		&self.tx_flooding
+	}
+
+	pub(crate) fn set_scp_tracker(&mut self, value: ScpTracker) {
+		self.scp_tracker = value;
+	}
+
+	pub(crate) fn scp_tracker(&self) -> &ScpTracker {
+		&self.scp_tracker
+	}
+
+	/// Sends the event to the user, if anyone is listening
+	pub(super) fn notify(&self, event: ConnectionEvent) {
+		if let Some(event_sender) = self.event_sender.as_ref() {
+			send_event(event_sender, event);
+		}
 	}
 }
 
@@ -1,12 +1,8 @@
 use crate::connection::{
-	authentication::create_auth_cert, handshake, hmac::create_sha256_hmac, xdr_converter,
-	Connector, Error,
+	authentication::create_auth_cert, handshake, hmac::create_sha256_hmac, Connector, Error,
 };
 use substrate_stellar_sdk::{
-	compound_types::LimitedString,
-	types::{
-		AuthenticatedMessage, AuthenticatedMessageV0, ErrorCode, HmacSha256Mac, StellarMessage,
-	},
+	types::{AuthenticatedMessage, AuthenticatedMessageV0, HmacSha256Mac, StellarMessage},
 	XdrCodec,
 };
 
@@ -28,9 +24,10 @@ impl Connector {
 		AuthenticatedMessage::V0(auth_message_v0)
 	}
 
+	/// Returns the xdr of the authenticated message, without the 4 bytes of its length
 	pub fn create_xdr_message(&mut self, msg: StellarMessage) -> Result<Vec<u8>, Error> {
 		let auth_msg = self.authenticate_message(msg);
-		xdr_converter::from_authenticated_message(&auth_msg).map_err(Error::from)
+		Ok(auth_msg.to_xdr())
 	}
 
 	/// Returns HmacSha256Mac for the AuthenticatedMessage
@@ -78,14 +75,3 @@ impl Connector {
 		)
 	}
 }
-
-/// Create our own error to send over to the user/outsider.
-pub(crate) fn crate_specific_error() -> StellarMessage {
-	let error = "Stellar Relay Error".as_bytes().to_vec();
-	let error = substrate_stellar_sdk::types::Error {
-		code: ErrorCode::ErrMisc,
-		msg: LimitedString::new(error).expect("should return a valid LimitedString"),
-	};
-
-	StellarMessage::ErrorMsg(error)
-}
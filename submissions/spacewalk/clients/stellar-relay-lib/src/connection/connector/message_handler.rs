@@ -1,11 +1,14 @@
 use crate::{
 	connection::{
 		authentication::verify_remote_auth_cert,
+		flooding::{flood_demands, tx_flood_hash},
 		helper::{error_to_string, time_now},
 		hmac::HMacKeys,
+		verify_scp_envelope,
 		xdr_converter::parse_authenticated_message,
-		Connector, Error, Xdr,
+		ConnectionEvent, Connector, Error,
 	},
+	metrics,
 	node::RemoteInfo,
 };
 use substrate_stellar_sdk::{
@@ -16,11 +19,12 @@ use tracing::{error, info, trace, warn};
 
 impl Connector {
 	/// Processes the raw bytes from the stream
-	pub(super) async fn process_raw_message(
+	pub(crate) async fn process_raw_message(
 		&mut self,
-		data: Xdr,
+		data: &[u8],
 	) -> Result<Option<StellarMessage>, Error> {
-		let (auth_msg, msg_type) = parse_authenticated_message(&data)?;
+		let (auth_msg, msg_type) = parse_authenticated_message(data)?;
+		metrics::record_message_received(self.peer_address(), msg_type);
 
 		match msg_type {
 			MessageType::Transaction | MessageType::FloodAdvert if !self.receive_tx_messages() => {
@@ -32,12 +36,18 @@ impl Connector {
 				self.increment_remote_sequence()?;
 			},
 
+			// stellar-core neither MACs nor sequences an `ErrorMsg`
+			MessageType::ErrorMsg if self.is_handshake_completed() => {
+				return self.process_stellar_message(auth_msg.message, msg_type, data.len()).await;
+			},
+
 			MessageType::ErrorMsg => match auth_msg.message {
 				StellarMessage::ErrorMsg(e) => {
 					error!(
 						"process_raw_message(): Received ErrorMsg during authentication: {}",
 						error_to_string(e.clone())
 					);
+					self.notify(peer_error_event(&e));
 					return Err(Error::from(e));
 				},
 				other => error!(
@@ -48,8 +58,11 @@ impl Connector {
 
 			_ => {
 				// we only verify the authenticated message when a handshake has been done.
+				// a recording was already verified when it was captured.
 				if self.is_handshake_created() {
-					self.verify_auth(&auth_msg, &data[4..(data.len() - 32)])?;
+					if !self.is_replaying() {
+						self.verify_auth(&auth_msg, &data[4..(data.len() - 32)])?;
+					}
 					self.increment_remote_sequence()?;
 					trace!("process_raw_message(): Processing {msg_type:?} message: auth verified");
 				}
@@ -89,7 +102,11 @@ impl Connector {
 			},
 
 			StellarMessage::ErrorMsg(e) => {
-				error!("process_stellar_message(): Received ErrorMsg during authentication: {e:?}");
+				error!(
+					"process_stellar_message(): Received ErrorMsg: {}",
+					error_to_string(e.clone())
+				);
+				self.notify(peer_error_event(&e));
 				if e.code == ErrorCode::ErrConf || e.code == ErrorCode::ErrAuth {
 					return Err(Error::from(e));
 				}
@@ -97,6 +114,67 @@ impl Connector {
 			},
 			StellarMessage::SendMore(_) => {},
 			StellarMessage::SendMoreExtended(_) => {},
+
+			StellarMessage::FloodAdvert(advert) => {
+				self.maybe_reclaim_capacity(msg_type, data_len).await?;
+
+				let demanded = self.tx_flooding().on_advert(advert.tx_hashes.get_vec());
+				if !demanded.is_empty() {
+					trace!("process_stellar_message(): demanding {} transactions", demanded.len());
+					for demand in flood_demands(&demanded)? {
+						self.send_to_node(demand).await?;
+					}
+				}
+				return Ok(Some(StellarMessage::FloodAdvert(advert)));
+			},
+			StellarMessage::FloodDemand(demand) => {
+				self.maybe_reclaim_capacity(msg_type, data_len).await?;
+
+				for env in self.tx_flooding().on_demand(demand.tx_hashes.get_vec()) {
+					info!(
+						"process_stellar_message(): serving demanded transaction {}",
+						hex::encode(tx_flood_hash(&env))
+					);
+					self.send_to_node(StellarMessage::Transaction(env)).await?;
+				}
+				return Ok(Some(StellarMessage::FloodDemand(demand)));
+			},
+			StellarMessage::Transaction(env) => {
+				self.maybe_reclaim_capacity(msg_type, data_len).await?;
+
+				if self.tx_flooding().on_transaction(&env) {
+					return Ok(Some(StellarMessage::Transaction(env)));
+				}
+				trace!(
+					"process_stellar_message(): dropping demanded transaction that is not watched"
+				);
+			},
+			StellarMessage::ScpMessage(env) => {
+				self.maybe_reclaim_capacity(msg_type, data_len).await?;
+
+				if !verify_scp_envelope(self.connection_auth.network_id(), &env) {
+					warn!(
+						"process_stellar_message(): dropping envelope of slot {} with an invalid signature",
+						env.statement.slot_index
+					);
+					return Ok(None);
+				}
+
+				if let Some(qset_hash) = self.scp_tracker().on_envelope(&env) {
+					trace!(
+						"process_stellar_message(): requesting quorum set {}",
+						hex::encode(qset_hash)
+					);
+					self.send_to_node(StellarMessage::GetScpQuorumset(qset_hash)).await?;
+				}
+				return Ok(Some(StellarMessage::ScpMessage(env)));
+			},
+			StellarMessage::ScpQuorumset(qset) => {
+				self.maybe_reclaim_capacity(msg_type, data_len).await?;
+
+				self.scp_tracker().on_quorum_set(&qset);
+				return Ok(Some(StellarMessage::ScpQuorumset(qset)));
+			},
 			// we do not handle other messages. Return to caller
 			other => {
 				self.maybe_reclaim_capacity(msg_type, data_len).await?;
@@ -115,10 +193,13 @@ impl Connector {
 		self.handshake_completed();
 
 		if let Some(remote) = self.remote() {
+			self.notify(ConnectionEvent::HandshakeCompleted { remote: remote.node().clone() });
+
 			let msg = self.maybe_start_flow_control_bytes(
 				self.local().node().overlay_version,
 				remote.node().overlay_version,
 			);
+			metrics::record_send_more(self.peer_address(), &msg);
 			self.send_to_node(msg).await?;
 		} else {
 			warn!("process_auth_message(): No remote overlay version after handshake.");
@@ -130,7 +211,10 @@ impl Connector {
 	fn process_hello_message(&mut self, hello: Hello) -> Result<(), Error> {
 		let mut network_id = self.connection_auth.network_id().to_xdr();
 
-		if !verify_remote_auth_cert(time_now(), &hello.peer_id, &hello.cert, &mut network_id) {
+		// the auth cert of a recording might have expired since it was captured.
+		if !self.is_replaying() &&
+			!verify_remote_auth_cert(time_now(), &hello.peer_id, &hello.cert, &mut network_id)
+		{
 			return Err(Error::AuthCertInvalid);
 		}
 
@@ -149,3 +233,10 @@ impl Connector {
 		Ok(())
 	}
 }
+
+fn peer_error_event(e: &substrate_stellar_sdk::types::Error) -> ConnectionEvent {
+	ConnectionEvent::PeerError {
+		code: e.code,
+		msg: String::from_utf8_lossy(e.msg.get_vec()).to_string(),
+	}
+}
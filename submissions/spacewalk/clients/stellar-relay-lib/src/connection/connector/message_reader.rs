@@ -1,19 +1,32 @@
-use crate::connection::{
-	connector::message_creation::crate_specific_error, xdr_converter::get_xdr_message_length,
-	Connector, Error, Xdr,
+use crate::{
+	connection::{
+		events::send_event, reconnect::Reconnector, ConnectionEvent, Connector, Error,
+		FrameDirection, RecordedFrame, ScpTracker, Subscriptions, TxFlooding,
+	},
+	metrics,
 };
-use async_std::io::ReadExt;
 use std::time::Duration;
 use substrate_stellar_sdk::{types::StellarMessage, XdrCodec};
 use tokio::{
-	sync::{mpsc, mpsc::error::TryRecvError},
-	time::timeout,
+	sync::mpsc,
+	time::{sleep, timeout_at, Instant},
 };
 use tracing::{error, info, trace, warn};
 
 /// The waiting time for reading messages from stream.
 static READ_TIMEOUT_IN_SECS: u64 = 60;
 
+/// How often to check whether the user caught up, while messages are held back
+const BACKPRESSURE_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Why polling the messages of a single connection has stopped
+enum PollStop {
+	/// the user has closed its end of the channels
+	UserClosed,
+	/// the connection to the Stellar Node was lost
+	ConnectionLost(String),
+}
+
 /// Polls for messages coming from the Stellar Node and communicates it back to the user
 ///
 /// # Arguments
@@ -21,79 +34,55 @@ static READ_TIMEOUT_IN_SECS: u64 = 60;
 /// * `send_to_user_sender` - sends message from Stellar to the user
 /// * `send_to_node_receiver` - receives message from user and writes it to the write half of the
 ///   stream.
+/// * `event_sender` - sends lifecycle events of the connection to the user
+/// * `reconnector` - if provided, restarts the connection when it was lost
+/// * `subscriptions` - the consumers that receive a filtered copy of the messages
+/// * `tx_flooding` - the transactions to demand and to serve, shared with the user
+/// * `scp_tracker` - the quorum sets and externalized slots, shared with the user
 pub(crate) async fn poll_messages_from_stellar(
 	mut connector: Connector,
 	send_to_user_sender: mpsc::Sender<StellarMessage>,
 	mut send_to_node_receiver: mpsc::Receiver<StellarMessage>,
+	event_sender: mpsc::Sender<ConnectionEvent>,
+	reconnector: Option<Reconnector>,
+	subscriptions: Subscriptions,
+	tx_flooding: TxFlooding,
+	scp_tracker: ScpTracker,
 ) {
 	info!("poll_messages_from_stellar(): started.");
+	connector.set_event_sender(event_sender.clone());
+	connector.set_tx_flooding(tx_flooding.clone());
+	connector.set_scp_tracker(scp_tracker.clone());
 
-	loop {
-		if send_to_user_sender.is_closed() {
-			info!("poll_messages_from_stellar(): closing receiver during disconnection");
-			// close this channel as communication to user was closed.
-			break;
-		}
-
-		// check for messages from user.
-		match send_to_node_receiver.try_recv() {
-			Ok(msg) =>
-				if let Err(e) = connector.send_to_node(msg).await {
-					error!("poll_messages_from_stellar(): Error occurred during sending message to node: {e:?}");
-				},
-			Err(TryRecvError::Disconnected) => break,
-			Err(TryRecvError::Empty) => {
-				// there's no message from user; wait for the next iteration
-				tokio::task::yield_now().await;
-			},
-		}
-
-		// check for messages from Stellar Node.
-		// if reading took too much time, flag it as "disconnected"
-		let xdr = match timeout(
-			Duration::from_secs(READ_TIMEOUT_IN_SECS),
-			read_message_from_stellar(&mut connector),
+	let close_reason = loop {
+		let reason = match poll_connection(
+			&mut connector,
+			&send_to_user_sender,
+			&mut send_to_node_receiver,
+			&subscriptions,
 		)
 		.await
 		{
-			Ok(Ok(xdr)) => xdr,
-			Ok(Err(e)) => {
-				error!("poll_messages_from_stellar(): {e:?}");
-				break
-			},
-			Err(_) => {
-				error!("poll_messages_from_stellar(): timed out");
-				break
-			},
+			PollStop::UserClosed => break "closed by user".to_string(),
+			PollStop::ConnectionLost(reason) => reason,
 		};
 
-		match connector.process_raw_message(xdr).await {
-			Ok(Some(stellar_msg)) => {
-				// push message to user
-				let stellar_msg_as_base64_xdr = stellar_msg.to_base64_xdr();
-				if let Err(e) = send_to_user_sender.send(stellar_msg).await {
-					warn!("poll_messages_from_stellar(): Error occurred during sending message {} to user: {e:?}",
-					String::from_utf8(stellar_msg_as_base64_xdr.clone())
-					.unwrap_or_else(|_| format!("{stellar_msg_as_base64_xdr:?}"))
-				);
-				}
-				tokio::task::yield_now().await;
-			},
-			Ok(None) => tokio::task::yield_now().await,
-			Err(e) => {
-				error!("poll_messages_from_stellar(): Error occurred during processing xdr message: {e:?}");
-				break;
+		let Some(reconnector) = reconnector.as_ref() else { break reason };
+
+		connector.stop();
+		match reconnector.reconnect(reason.clone(), &send_to_user_sender, &event_sender).await {
+			Some(new_connector) => {
+				connector = new_connector;
+				connector.set_event_sender(event_sender.clone());
+				connector.set_tx_flooding(tx_flooding.clone());
+				connector.set_scp_tracker(scp_tracker.clone());
 			},
+			None => break format!("gave up reconnecting: {reason}"),
 		}
-	}
+	};
 
-	// push error to user
-	if let Err(e) = send_to_user_sender.send(crate_specific_error()).await {
-		warn!(
-			"poll_messages_from_stellar(): Error occurred during sending message {} to user: {e:?}",
-			e
-		);
-	}
+	// let the user know that the connection is gone
+	send_event(&event_sender, ConnectionEvent::Closed { reason: close_reason });
 
 	// make sure to shutdown the connector
 	connector.stop();
@@ -103,158 +92,170 @@ pub(crate) async fn poll_messages_from_stellar(
 	info!("poll_messages_from_stellar(): stopped.");
 }
 
-/// Returns Xdr format of the `StellarMessage` sent from the Stellar Node
-async fn read_message_from_stellar(connector: &mut Connector) -> Result<Xdr, Error> {
-	// holds the number of bytes that were missing from the previous stellar message.
-	let mut lack_bytes_from_prev = 0;
-	let mut readbuf: Vec<u8> = vec![];
-	let mut buff_for_reading = vec![0; 4];
+/// Feeds the inbound frames of a recorded session to the `Connector`, as if they were read from
+/// the Stellar Node, and communicates the resulting messages back to the user.
+/// Messages from the user are dropped.
+///
+/// # Arguments
+/// * `connector` - a connector without a stream, see `Connector::replay`
+/// * `frames` - the recorded session
+/// * `send_to_user_sender` - sends message from the recording to the user
+/// * `send_to_node_receiver` - receives message from user, which are dropped
+/// * `event_sender` - sends lifecycle events of the connection to the user
+/// * `subscriptions` - the consumers that receive a filtered copy of the messages
+/// * `scp_tracker` - the quorum sets and externalized slots, shared with the user
+pub(crate) async fn replay_messages_from_recording(
+	mut connector: Connector,
+	frames: Vec<RecordedFrame>,
+	send_to_user_sender: mpsc::Sender<StellarMessage>,
+	mut send_to_node_receiver: mpsc::Receiver<StellarMessage>,
+	event_sender: mpsc::Sender<ConnectionEvent>,
+	subscriptions: Subscriptions,
+	scp_tracker: ScpTracker,
+) {
+	info!("replay_messages_from_recording(): started with {} frames.", frames.len());
+	connector.set_event_sender(event_sender.clone());
+	connector.set_scp_tracker(scp_tracker);
 
-	loop {
-		// identify bytes as:
-		//  1. the length of the next stellar message
-		//  2. the remaining bytes of the previous stellar message
-		// return Timeout error if reading time has elapsed.
-		match connector.tcp_stream.read(&mut buff_for_reading).await {
-			Ok(0) => continue,
-			Ok(_) if lack_bytes_from_prev == 0 => {
-				// if there are no more bytes lacking from the previous message,
-				// then check the size of next stellar message.
-				let expect_msg_len = get_xdr_message_length(&buff_for_reading);
+	let close_reason = replay_frames(
+		&mut connector,
+		frames,
+		&send_to_user_sender,
+		&mut send_to_node_receiver,
+		&subscriptions,
+	)
+	.await;
 
-				// If it's not enough, skip it.
-				if expect_msg_len == 0 {
-					// there's nothing to read; wait for the next iteration
-					trace!("read_message_from_stellar(): expect_msg_len == 0");
-					continue;
-				}
+	send_event(&event_sender, ConnectionEvent::Closed { reason: close_reason });
+	// the user receives the remaining messages, and then `None`
+	drop(send_to_user_sender);
 
-				// let's start reading the actual stellar message.
-				readbuf = vec![0; expect_msg_len];
+	// keep the connection alive for the user, until the user is gone
+	while let Some(msg) = send_to_node_receiver.recv().await {
+		trace!("replay_messages_from_recording(): dropping message to node: {msg:?}");
+	}
 
-				match is_reading_complete(
-					connector,
-					&mut lack_bytes_from_prev,
-					&mut readbuf,
-					expect_msg_len,
-				)
-				.await
-				{
-					Ok(false) => continue,
-					Ok(true) => return Ok(readbuf),
-					Err(e) => {
-						trace!("read_message_from_stellar(): ERROR: {e:?}");
-						return Err(e)
-					},
-				}
-			},
-			Ok(size) => {
-				// The next few bytes was read. Add it to the readbuf.
-				lack_bytes_from_prev = lack_bytes_from_prev.saturating_sub(size);
-				readbuf.append(&mut buff_for_reading);
-				// make sure to cleanup the buffer
-				buff_for_reading = vec![0; 4];
+	info!("replay_messages_from_recording(): stopped.");
+}
 
-				// let's read the continuation number of bytes from the previous message.
-				match is_reading_unfinished_message_complete(
-					connector,
-					&mut lack_bytes_from_prev,
-					&mut readbuf,
-				)
-				.await
-				{
-					Ok(false) => continue,
-					Ok(true) => return Ok(readbuf),
-					Err(e) => {
-						trace!("read_message_from_stellar(): ERROR: {e:?}");
-						return Err(e)
-					},
+/// Returns why the replay has stopped
+async fn replay_frames(
+	connector: &mut Connector,
+	frames: Vec<RecordedFrame>,
+	send_to_user_sender: &mpsc::Sender<StellarMessage>,
+	send_to_node_receiver: &mut mpsc::Receiver<StellarMessage>,
+	subscriptions: &Subscriptions,
+) -> String {
+	let inbound_frames =
+		frames.into_iter().filter(|frame| frame.direction == FrameDirection::Inbound);
+
+	for frame in inbound_frames {
+		while let Ok(msg) = send_to_node_receiver.try_recv() {
+			trace!("replay_messages_from_recording(): dropping message to node: {msg:?}");
+		}
+
+		match connector.process_raw_message(&frame.xdr).await {
+			Ok(Some(stellar_msg)) => {
+				subscriptions.publish(&stellar_msg);
+				if send_to_user_sender.send(stellar_msg).await.is_err() {
+					return "closed by user".to_string()
 				}
 			},
+			Ok(None) => {},
 			Err(e) => {
-				trace!("read_message_from_stellar(): ERROR reading messages: {e:?}");
-				return Err(Error::ReadFailed(e.to_string()))
+				error!("replay_messages_from_recording(): Error occurred during processing xdr message recorded at {}: {e:?}", frame.timestamp);
+				return e.to_string()
 			},
 		}
 	}
-}
-
-/// Returns true when all bytes from the stream have successfully been converted; else false.
-/// This reads a number of bytes based on the expected message length.
-///
-/// # Arguments
-/// * `connector` - a ref struct that contains the config and necessary info for connecting to
-///   Stellar Node
-/// * `lack_bytes_from_prev` - the number of bytes remaining, to complete the previous message
-/// * `readbuf` - the buffer that holds the bytes of the previous and incomplete message
-/// * `xpect_msg_len` - the expected # of bytes of the Stellar message
-async fn is_reading_complete(
-	connector: &mut Connector,
-	lack_bytes_from_prev: &mut usize,
-	readbuf: &mut Vec<u8>,
-	xpect_msg_len: usize,
-) -> Result<bool, Error> {
-	let actual_msg_len = connector
-		.tcp_stream
-		.read(readbuf)
-		.await
-		.map_err(|e| Error::ReadFailed(e.to_string()))?;
-
-	// only when the message has the exact expected size bytes, should we send to user.
-	if actual_msg_len == xpect_msg_len {
-		return Ok(true)
-	}
 
-	// The next bytes are remnants from the previous stellar message.
-	// save it and read it on the next loop.
-	*lack_bytes_from_prev = xpect_msg_len - actual_msg_len;
-	*readbuf = readbuf[0..actual_msg_len].to_owned();
-	trace!(
-		"read_message(): received only partial message. Need {lack_bytes_from_prev} bytes to complete."
-	);
-
-	Ok(false)
+	"end of recording".to_string()
 }
 
-/// Returns true when all bytes from the stream have successfully been converted; else false.
-/// Reads a continuation of bytes that belong to the previous message
-///
-/// # Arguments
-/// * `connector` - a ref struct that contains the config and necessary info for connecting to
-///   Stellar Node
-/// * `lack_bytes_from_prev` - the number of bytes remaining, to complete the previous message
-/// * `readbuf` - the buffer that holds the bytes of the previous and incomplete message
-async fn is_reading_unfinished_message_complete(
+/// Polls the messages of the connection, until the connection is lost or the user is gone.
+/// Messages from the user are written as soon as they arrive, without waiting for the Stellar
+/// Node to send something.
+async fn poll_connection(
 	connector: &mut Connector,
-	lack_bytes_from_prev: &mut usize,
-	readbuf: &mut Vec<u8>,
-) -> Result<bool, Error> {
-	// let's read the continuation number of bytes from the previous message.
-	let mut cont_buf = vec![0; *lack_bytes_from_prev];
+	send_to_user_sender: &mpsc::Sender<StellarMessage>,
+	send_to_node_receiver: &mut mpsc::Receiver<StellarMessage>,
+	subscriptions: &Subscriptions,
+) -> PollStop {
+	// if reading took too much time, flag it as "disconnected"
+	let mut read_deadline = Instant::now() + Duration::from_secs(READ_TIMEOUT_IN_SECS);
 
-	let actual_msg_len = connector
-		.tcp_stream
-		.read(&mut cont_buf)
-		.await
-		.map_err(|e| Error::ReadFailed(e.to_string()))?;
+	loop {
+		tokio::select! {
+			_ = send_to_user_sender.closed() => {
+				info!("poll_messages_from_stellar(): closing receiver during disconnection");
+				// close this channel as communication to user was closed.
+				return PollStop::UserClosed
+			},
+			// check for messages from user.
+			msg = send_to_node_receiver.recv() => match msg {
+				Some(msg) =>
+					if let Err(e) = connector.send_to_node(msg).await {
+						error!("poll_messages_from_stellar(): Error occurred during sending message to node: {e:?}");
+					},
+				None => return PollStop::UserClosed,
+			},
+			// grant the Stellar Node more messages, once the user caught up.
+			_ = sleep(BACKPRESSURE_CHECK_INTERVAL), if connector.flow_controller.is_withholding() => {
+				match connector.update_backpressure(send_to_user_sender.capacity()).await {
+					// the Stellar Node was quiet because it was held back; it may send again now
+					Ok(true) =>
+						read_deadline = Instant::now() + Duration::from_secs(READ_TIMEOUT_IN_SECS),
+					// still held back. A peer that died meanwhile times out as usual.
+					Ok(false) => {},
+					Err(e) => {
+						error!("poll_messages_from_stellar(): Error occurred during granting more messages: {e:?}");
+						return PollStop::ConnectionLost(e.to_string())
+					},
+				}
+			},
+			// check for messages from Stellar Node.
+			result = timeout_at(read_deadline, connector.read_frame()) => {
+				let xdr = match result {
+					Ok(Ok(xdr)) => xdr,
+					Ok(Err(e)) => {
+						error!("poll_messages_from_stellar(): {e:?}");
+						return PollStop::ConnectionLost(e.to_string())
+					},
+					Err(_) => {
+						error!("poll_messages_from_stellar(): timed out");
+						connector.notify(ConnectionEvent::Timeout);
+						return PollStop::ConnectionLost(Error::Timeout.to_string())
+					},
+				};
+				read_deadline = Instant::now() + Duration::from_secs(READ_TIMEOUT_IN_SECS);
 
-	// this partial message completes the previous message.
-	if actual_msg_len == *lack_bytes_from_prev {
-		trace!("read_unfinished_message(): received continuation from the previous message.");
-		readbuf.append(&mut cont_buf);
+				connector.record(FrameDirection::Inbound, &xdr);
+				metrics::record_bytes_read(connector.peer_address(), xdr.len());
 
-		return Ok(true)
-	}
+				// a slow user holds back the `SendMore`s
+				connector.flow_controller.set_user_channel_free_slots(send_to_user_sender.capacity());
 
-	// this partial message is not enough to complete the previous message.
-	if actual_msg_len > 0 {
-		*lack_bytes_from_prev -= actual_msg_len;
-		cont_buf = cont_buf[0..actual_msg_len].to_owned();
-		readbuf.append(&mut cont_buf);
-		trace!(
-            "read_unfinished_message(): not enough bytes to complete the previous message. Need {lack_bytes_from_prev} bytes to complete."
-        );
-	}
+				match connector.process_raw_message(&xdr).await {
+					Ok(Some(stellar_msg)) => {
+						subscriptions.publish(&stellar_msg);
 
-	Ok(false)
+						// push message to user
+						if let Err(e) = send_to_user_sender.send(stellar_msg).await {
+							// the message is only encoded when it could not be sent
+							let stellar_msg_as_base64_xdr = e.0.to_base64_xdr();
+							warn!("poll_messages_from_stellar(): Error occurred during sending message {} to user: {e:?}",
+							String::from_utf8(stellar_msg_as_base64_xdr.clone())
+							.unwrap_or_else(|_| format!("{stellar_msg_as_base64_xdr:?}"))
+						);
+						}
+					},
+					Ok(None) => {},
+					Err(e) => {
+						error!("poll_messages_from_stellar(): Error occurred during processing xdr message: {e:?}");
+						return PollStop::ConnectionLost(e.to_string())
+					},
+				}
+			},
+		}
+	}
 }
@@ -1,22 +1,34 @@
-use async_std::io::WriteExt;
+use futures::SinkExt;
 use std::time::Duration;
 use substrate_stellar_sdk::{
 	types::{MessageType, StellarMessage},
 	StellarTypeToBase64String,
 };
 use tokio::time::timeout;
-use tracing::debug;
+use tracing::{debug, trace};
 
-use crate::connection::{handshake::create_auth_message, helper::time_now, Connector, Error};
+use crate::{
+	connection::{
+		handshake::create_auth_message, helper::time_now, Connector, Error, FrameDirection,
+	},
+	metrics,
+};
 
 impl Connector {
 	pub async fn send_to_node(&mut self, msg: StellarMessage) -> Result<(), Error> {
 		// Create the XDR message outside the closure
 		let xdr_msg = self.create_xdr_message(msg)?;
 
+		if self.is_replaying() {
+			// there is no Stellar Node to send the message to
+			trace!("send_to_node(): replaying; dropping message");
+			return Ok(())
+		}
+		self.record(FrameDirection::Outbound, &xdr_msg);
+
 		match timeout(
 			Duration::from_secs(self.timeout_in_secs),
-			self.tcp_stream.write_all(&xdr_msg),
+			self.writer()?.send(xdr_msg.as_slice()),
 		)
 		.await
 		{
@@ -49,8 +61,22 @@ impl Connector {
 	) -> Result<(), Error> {
 		let msg = self.flow_controller.create_reclaim_capacity_message(message_type, data_len);
 		if let Some(inner_msg) = msg {
+			metrics::record_send_more(self.peer_address(), &inner_msg);
 			return self.send_to_node(inner_msg).await;
 		};
 		Ok(())
 	}
+
+	/// Sends the `SendMore` that was withheld while the user was too slow, once it caught up.
+	/// Returns true if it was sent.
+	pub(super) async fn update_backpressure(&mut self, free_slots: usize) -> Result<bool, Error> {
+		self.flow_controller.set_user_channel_free_slots(free_slots);
+		if let Some(msg) = self.flow_controller.release_withheld_capacity() {
+			trace!("update_backpressure(): user caught up; granting more messages");
+			metrics::record_send_more(self.peer_address(), &msg);
+			self.send_to_node(msg).await?;
+			return Ok(true)
+		}
+		Ok(false)
+	}
 }
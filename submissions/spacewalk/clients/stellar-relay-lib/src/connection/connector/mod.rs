@@ -5,4 +5,4 @@ mod message_reader;
 mod message_sender;
 
 pub(crate) use connector::*;
-pub(crate) use message_reader::poll_messages_from_stellar;
+pub(crate) use message_reader::{poll_messages_from_stellar, replay_messages_from_recording};
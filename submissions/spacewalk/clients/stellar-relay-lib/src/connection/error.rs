@@ -1,5 +1,8 @@
 use crate::{connection::xdr_converter::Error as XDRError, helper::error_to_string};
-use substrate_stellar_sdk::{types::ErrorCode, StellarSdkError};
+use substrate_stellar_sdk::{
+	types::{ErrorCode, MessageType},
+	StellarSdkError,
+};
 use tokio::sync;
 
 #[derive(Debug, err_derive::Error)]
@@ -66,6 +69,15 @@ pub enum Error {
 
 	#[error(display = "Config Error: Version String too long")]
 	VersionStrTooLong,
+
+	#[error(display = "Recording: {}", _0)]
+	RecordingFailed(String),
+
+	#[error(display = "Stellar Node does not have the {:?} of hash {}", _0, _1)]
+	DontHave(MessageType, String),
+
+	#[error(display = "Externalize of slot {}: {}", _0, _1)]
+	InvalidExternalize(u64, String),
 }
 
 impl From<XDRError> for Error {
@@ -0,0 +1,36 @@
+use std::time::Duration;
+use substrate_stellar_sdk::types::ErrorCode;
+use tokio::sync::{mpsc, mpsc::error::TrySendError};
+use tracing::trace;
+
+use crate::node::NodeInfo;
+
+/// Lifecycle events of a `StellarOverlayConnection`.
+/// These are sent on their own channel, apart from the messages of the Stellar Node.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConnectionEvent {
+	/// The handshake with the Stellar Node is done
+	HandshakeCompleted { remote: NodeInfo },
+	/// The Stellar Node sent an `ErrorMsg`
+	PeerError { code: ErrorCode, msg: String },
+	/// The Stellar Node did not send any message in time
+	Timeout,
+	/// The connection was lost, and will be restarted after `delay`
+	Reconnecting { attempt: u32, delay: Duration, reason: String },
+	/// A new connection to the Stellar Node was created
+	Reconnected { attempt: u32 },
+	/// The connection is closed for good; no more messages will be received
+	Closed { reason: String },
+}
+
+/// Sends the event to the user without waiting.
+/// Events are dropped if the user does not keep up with them.
+pub(crate) fn send_event(event_sender: &mpsc::Sender<ConnectionEvent>, event: ConnectionEvent) {
+	match event_sender.try_send(event) {
+		Ok(()) => {},
+		Err(TrySendError::Full(event)) =>
+			trace!("send_event(): channel is full; dropping {event:?}"),
+		Err(TrySendError::Closed(event)) =>
+			trace!("send_event(): channel is closed; dropping {event:?}"),
+	}
+}
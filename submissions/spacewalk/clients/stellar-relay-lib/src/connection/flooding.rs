@@ -0,0 +1,263 @@
+use crate::connection::{helper::RecentHashes, subscription::tx_source_accounts, Error};
+use sha2::{Digest, Sha256};
+use std::{
+	collections::VecDeque,
+	sync::{Arc, Mutex},
+};
+use substrate_stellar_sdk::{
+	compound_types::LimitedVarArray,
+	types::{
+		FeeBumpTransactionInnerTx, FloodAdvert, FloodDemand, Memo, StellarMessage,
+		TransactionEnvelope, Uint256,
+	},
+	PublicKey, XdrCodec,
+};
+
+/// The maximum number of hashes in a single `FloodAdvert` or `FloodDemand`
+const TX_HASHES_PER_MESSAGE: usize = 1000;
+
+/// The number of hashes remembered, so that an advertised transaction is demanded only once
+const ADVERTISED_HASHES_CACHE_SIZE: usize = 4096;
+
+/// The number of our own transactions that are kept, to serve the demands for them
+const OWN_TRANSACTIONS_LIMIT: usize = 100;
+
+/// Selects the transactions to demand, when the Stellar Node advertises them.
+/// A transaction matches when its source is one of the accounts, or its memo is one of the memos.
+#[derive(Clone, Debug, Default)]
+pub struct TxWatch {
+	accounts: Vec<PublicKey>,
+	memos: Vec<Memo>,
+}
+
+impl TxWatch {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Watches transactions whose source is one of the given accounts.
+	/// For a fee bump transaction, both the fee source and the source of the inner transaction
+	/// count.
+	pub fn accounts(mut self, accounts: impl IntoIterator<Item = PublicKey>) -> Self {
+		self.accounts.extend(accounts);
+		self
+	}
+
+	/// Watches transactions with one of the given memos
+	pub fn memos(mut self, memos: impl IntoIterator<Item = Memo>) -> Self {
+		self.memos.extend(memos);
+		self
+	}
+
+	/// Returns true if the transaction is one of the watched
+	pub fn matches(&self, env: &TransactionEnvelope) -> bool {
+		tx_source_accounts(env).iter().any(|source| self.accounts.contains(source)) ||
+			self.memos.contains(tx_memo(env))
+	}
+}
+
+fn tx_memo(env: &TransactionEnvelope) -> &Memo {
+	match env {
+		TransactionEnvelope::EnvelopeTypeTxV0(env) => &env.tx.memo,
+		TransactionEnvelope::EnvelopeTypeTx(env) => &env.tx.memo,
+		TransactionEnvelope::EnvelopeTypeTxFeeBump(env) => {
+			let FeeBumpTransactionInnerTx::EnvelopeTypeTx(inner_env) = &env.tx.inner_tx;
+			&inner_env.tx.memo
+		},
+	}
+}
+
+/// Returns the hash that a transaction is advertised and demanded with:
+/// the hash of the whole envelope, like stellar-core's full hash.
+pub fn tx_flood_hash(env: &TransactionEnvelope) -> Uint256 {
+	Sha256::digest(env.to_xdr()).into()
+}
+
+/// Returns the `FloodAdvert`s of the hashes
+pub(crate) fn flood_adverts(hashes: &[Uint256]) -> Result<Vec<StellarMessage>, Error> {
+	hashes
+		.chunks(TX_HASHES_PER_MESSAGE)
+		.map(|chunk| {
+			let tx_hashes = LimitedVarArray::new(chunk.to_vec())
+				.map_err(|e| Error::SendFailed(format!("{e:?}")))?;
+			Ok(StellarMessage::FloodAdvert(FloodAdvert { tx_hashes }))
+		})
+		.collect()
+}
+
+/// Returns the `FloodDemand`s of the hashes
+pub(crate) fn flood_demands(hashes: &[Uint256]) -> Result<Vec<StellarMessage>, Error> {
+	hashes
+		.chunks(TX_HASHES_PER_MESSAGE)
+		.map(|chunk| {
+			let tx_hashes = LimitedVarArray::new(chunk.to_vec())
+				.map_err(|e| Error::SendFailed(format!("{e:?}")))?;
+			Ok(StellarMessage::FloodDemand(FloodDemand { tx_hashes }))
+		})
+		.collect()
+}
+
+struct FloodState {
+	watch: Option<TxWatch>,
+	/// the hashes advertised by the Stellar Node, or that we already know of
+	advertised: RecentHashes,
+	/// the hashes that we demanded
+	demanded: RecentHashes,
+	/// our own transactions, oldest first
+	own_transactions: VecDeque<(Uint256, TransactionEnvelope)>,
+}
+
+/// The pull-mode flooding of transactions, shared by the user and the `Connector`:
+/// the transactions advertised by the Stellar Node that match the `TxWatch` are demanded,
+/// and the demands for our own transactions are served.
+#[derive(Clone)]
+pub(crate) struct TxFlooding {
+	state: Arc<Mutex<FloodState>>,
+}
+
+impl Default for TxFlooding {
+	fn default() -> Self {
+		TxFlooding {
+			state: Arc::new(Mutex::new(FloodState {
+				watch: None,
+				advertised: RecentHashes::new(ADVERTISED_HASHES_CACHE_SIZE),
+				demanded: RecentHashes::new(ADVERTISED_HASHES_CACHE_SIZE),
+				own_transactions: VecDeque::new(),
+			})),
+		}
+	}
+}
+
+impl TxFlooding {
+	fn state(&self) -> std::sync::MutexGuard<'_, FloodState> {
+		self.state.lock().expect("should not be poisoned")
+	}
+
+	/// Starts demanding the advertised transactions that match the watch.
+	/// Without a watch, nothing is demanded.
+	pub fn watch(&self, watch: Option<TxWatch>) {
+		self.state().watch = watch;
+	}
+
+	/// Keeps our own transaction to serve the demands for it, and returns its hash to advertise
+	pub fn submit(&self, env: TransactionEnvelope) -> Uint256 {
+		let hash = tx_flood_hash(&env);
+
+		let mut state = self.state();
+		// the Stellar Node advertising it back to us should not trigger a demand
+		state.advertised.insert(hash);
+		if !state.own_transactions.iter().any(|(own_hash, _)| *own_hash == hash) {
+			state.own_transactions.push_back((hash, env));
+			if state.own_transactions.len() > OWN_TRANSACTIONS_LIMIT {
+				state.own_transactions.pop_front();
+			}
+		}
+
+		hash
+	}
+
+	/// Returns the advertised hashes to demand: the ones not seen before, if there is a watch.
+	/// Without a watch, the hashes are not remembered, so that they can be demanded once a watch
+	/// is set.
+	pub fn on_advert(&self, hashes: &[Uint256]) -> Vec<Uint256> {
+		let mut state = self.state();
+		if state.watch.is_none() {
+			return vec![]
+		}
+
+		let new_hashes: Vec<_> =
+			hashes.iter().filter(|hash| state.advertised.insert(**hash)).copied().collect();
+		for hash in new_hashes.iter() {
+			state.demanded.insert(*hash);
+		}
+		new_hashes
+	}
+
+	/// Returns our own transactions of the demanded hashes
+	pub fn on_demand(&self, hashes: &[Uint256]) -> Vec<TransactionEnvelope> {
+		let state = self.state();
+		state
+			.own_transactions
+			.iter()
+			.filter(|(hash, _)| hashes.contains(hash))
+			.map(|(_, env)| env.clone())
+			.collect()
+	}
+
+	/// Returns false if the transaction was demanded, but does not match the watch.
+	/// Since a hash does not tell whose the transaction is, more is demanded than is watched.
+	pub fn on_transaction(&self, env: &TransactionEnvelope) -> bool {
+		let state = self.state();
+		if !state.demanded.contains(&tx_flood_hash(env)) {
+			return true
+		}
+
+		state.watch.as_ref().map_or(true, |watch| watch.matches(env))
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use substrate_stellar_sdk::{types::Preconditions, Transaction};
+
+	fn transaction(source: PublicKey, memo: Memo) -> TransactionEnvelope {
+		Transaction::new(source, 1, None, Preconditions::PrecondNone, Some(memo))
+			.expect("should create a transaction")
+			.into_transaction_envelope()
+	}
+
+	fn account(byte: u8) -> PublicKey {
+		PublicKey::PublicKeyTypeEd25519([byte; 32])
+	}
+
+	#[test]
+	fn watch_matches_account_or_memo() {
+		let watch = TxWatch::new().accounts([account(1)]).memos([Memo::MemoId(7)]);
+
+		assert!(watch.matches(&transaction(account(1), Memo::MemoNone)));
+		assert!(watch.matches(&transaction(account(2), Memo::MemoId(7))));
+		assert!(!watch.matches(&transaction(account(2), Memo::MemoId(8))));
+	}
+
+	#[test]
+	fn advertised_hashes_are_demanded_once_while_watching() {
+		let flooding = TxFlooding::default();
+		assert!(flooding.on_advert(&[[1; 32]]).is_empty());
+
+		flooding.watch(Some(TxWatch::new().accounts([account(1)])));
+		// a hash advertised without a watch is demanded once there is one
+		assert_eq!(flooding.on_advert(&[[1; 32], [2; 32]]), vec![[1; 32], [2; 32]]);
+		// a hash that was demanded before is not demanded anymore
+		assert_eq!(flooding.on_advert(&[[2; 32], [3; 32]]), vec![[3; 32]]);
+	}
+
+	#[test]
+	fn demanded_transactions_pass_only_if_watched() {
+		let flooding = TxFlooding::default();
+		flooding.watch(Some(TxWatch::new().accounts([account(1)])));
+
+		let watched = transaction(account(1), Memo::MemoNone);
+		let unwatched = transaction(account(2), Memo::MemoNone);
+		let pushed = transaction(account(3), Memo::MemoNone);
+
+		flooding.on_advert(&[tx_flood_hash(&watched), tx_flood_hash(&unwatched)]);
+
+		assert!(flooding.on_transaction(&watched));
+		assert!(!flooding.on_transaction(&unwatched));
+		// a transaction that was not demanded passes as before
+		assert!(flooding.on_transaction(&pushed));
+	}
+
+	#[test]
+	fn own_transactions_are_served_and_not_demanded() {
+		let flooding = TxFlooding::default();
+		flooding.watch(Some(TxWatch::new().accounts([account(1)])));
+
+		let env = transaction(account(1), Memo::MemoNone);
+		let hash = flooding.submit(env.clone());
+
+		assert!(flooding.on_advert(&[hash]).is_empty());
+		assert_eq!(flooding.on_demand(&[hash, [9; 32]]), vec![env]);
+	}
+}
@@ -1,18 +1,96 @@
+use crate::connection::Error;
+use serde::{Deserialize, Serialize};
 use substrate_stellar_sdk::types::{MessageType, SendMore, SendMoreExtended, StellarMessage};
 
 pub const PER_FLOOD_READING_CAPACITY: u32 = 200;
 pub const FLOW_CONTROL_SEND_MORE_BATCH_SIZE: u32 = 40;
 pub const PER_FLOOD_READING_CAPACITY_BYTES: u32 = 300000;
 pub const FLOW_CONTROL_SEND_MORE_BATCH_SIZE_BYTES: u32 = 100000;
+pub const USER_CHANNEL_CAPACITY: usize = 1024;
+
+/// The budgets of the flow control with the Stellar Node.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FlowControlConfig {
+	/// the flood messages the Stellar Node may send right after the handshake
+	pub reading_capacity: u32,
+	/// the flood messages to receive, before granting them back with a `SendMore`
+	pub send_more_batch_size: u32,
+	/// the bytes of flood messages the Stellar Node may send right after the handshake
+	pub reading_capacity_bytes: u32,
+	/// the bytes of flood messages to receive, before granting them back
+	pub send_more_batch_size_bytes: u32,
+	/// the number of messages that wait in the channel to the user
+	pub user_channel_capacity: usize,
+	/// while the channel to the user has fewer free slots than this, no more messages are granted
+	/// to the Stellar Node. This way, a slow user slows down the Stellar Node.
+	pub backpressure_free_slots: usize,
+}
+
+impl Default for FlowControlConfig {
+	fn default() -> Self {
+		FlowControlConfig {
+			reading_capacity: PER_FLOOD_READING_CAPACITY,
+			send_more_batch_size: FLOW_CONTROL_SEND_MORE_BATCH_SIZE,
+			reading_capacity_bytes: PER_FLOOD_READING_CAPACITY_BYTES,
+			send_more_batch_size_bytes: FLOW_CONTROL_SEND_MORE_BATCH_SIZE_BYTES,
+			user_channel_capacity: USER_CHANNEL_CAPACITY,
+			// enough room for the messages that were granted already
+			backpressure_free_slots: PER_FLOOD_READING_CAPACITY as usize,
+		}
+	}
+}
+
+impl FlowControlConfig {
+	pub fn validate(&self) -> Result<(), Error> {
+		if self.send_more_batch_size == 0 || self.send_more_batch_size > self.reading_capacity {
+			return Err(Error::ConfigError(format!(
+				"Flow control: send_more_batch_size {} must be between 1 and reading_capacity {}",
+				self.send_more_batch_size, self.reading_capacity
+			)))
+		}
+
+		if self.send_more_batch_size_bytes == 0 ||
+			self.send_more_batch_size_bytes > self.reading_capacity_bytes
+		{
+			return Err(Error::ConfigError(format!(
+				"Flow control: send_more_batch_size_bytes {} must be between 1 and reading_capacity_bytes {}",
+				self.send_more_batch_size_bytes, self.reading_capacity_bytes
+			)))
+		}
+
+		if self.user_channel_capacity == 0 ||
+			self.backpressure_free_slots > self.user_channel_capacity
+		{
+			return Err(Error::ConfigError(format!(
+				"Flow control: backpressure_free_slots {} must not exceed user_channel_capacity {}, which must not be 0",
+				self.backpressure_free_slots, self.user_channel_capacity
+			)))
+		}
+
+		Ok(())
+	}
+}
 
 #[derive(Debug, Default)]
 pub struct FlowController {
+	config: FlowControlConfig,
 	flow_control_bytes_enabled: bool,
 	messages_received_in_current_batch: u32,
 	bytes_received_in_current_batch: u32,
+	/// true while the channel to the user is too full to grant more messages
+	is_backpressured: bool,
 }
 
 impl FlowController {
+	pub fn new(config: FlowControlConfig) -> Self {
+		FlowController { config, ..Default::default() }
+	}
+
+	pub fn config(&self) -> &FlowControlConfig {
+		&self.config
+	}
+
 	pub fn enable_bytes(&mut self, local_overlay_version: u32, remote_overlay_version: u32) {
 		self.flow_control_bytes_enabled =
 			remote_overlay_version >= 28 && local_overlay_version >= 28;
@@ -27,20 +105,41 @@ impl FlowController {
 
 		if self.flow_control_bytes_enabled {
 			let msg = StellarMessage::SendMoreExtended(SendMoreExtended {
-				num_messages: PER_FLOOD_READING_CAPACITY,
-				num_bytes: PER_FLOOD_READING_CAPACITY_BYTES,
+				num_messages: self.config.reading_capacity,
+				num_bytes: self.config.reading_capacity_bytes,
 			});
 			return msg;
 		}
-		let msg = StellarMessage::SendMore(SendMore { num_messages: PER_FLOOD_READING_CAPACITY });
+		let msg = StellarMessage::SendMore(SendMore { num_messages: self.config.reading_capacity });
 		return msg;
 	}
 
+	/// Updates the backpressure with the free slots of the channel to the user
+	pub fn set_user_channel_free_slots(&mut self, free_slots: usize) {
+		self.is_backpressured = free_slots < self.config.backpressure_free_slots;
+	}
+
+	/// Returns true if a `SendMore` is due, but withheld because of the backpressure
+	pub fn is_withholding(&self) -> bool {
+		self.is_backpressured && self.is_batch_complete()
+	}
+
 	fn reset_batch_counters(&mut self) {
 		self.messages_received_in_current_batch = 0;
 		self.bytes_received_in_current_batch = 0;
 	}
 
+	fn is_batch_complete(&self) -> bool {
+		let mut is_complete =
+			self.messages_received_in_current_batch >= self.config.send_more_batch_size;
+
+		if self.flow_control_bytes_enabled {
+			is_complete = is_complete ||
+				self.bytes_received_in_current_batch >= self.config.send_more_batch_size_bytes;
+		}
+		is_complete
+	}
+
 	pub fn create_reclaim_capacity_message(
 		&mut self,
 		message_type: MessageType,
@@ -55,38 +154,34 @@ impl FlowController {
 			self.bytes_received_in_current_batch += actual_message_size;
 		}
 
-		let mut should_send_more =
-			self.messages_received_in_current_batch == FLOW_CONTROL_SEND_MORE_BATCH_SIZE;
+		self.release_withheld_capacity()
+	}
 
-		if self.flow_control_bytes_enabled {
-			should_send_more = should_send_more ||
-				self.bytes_received_in_current_batch >= FLOW_CONTROL_SEND_MORE_BATCH_SIZE_BYTES;
+	/// Returns the `SendMore` for the messages received so far, once the batch is complete and
+	/// the user can take more messages.
+	pub fn release_withheld_capacity(&mut self) -> Option<StellarMessage> {
+		if self.is_backpressured || !self.is_batch_complete() {
+			return None;
 		}
 
 		//reclaim the capacity
-		if should_send_more {
-			if self.flow_control_bytes_enabled {
-				let send_more_message = StellarMessage::SendMoreExtended(SendMoreExtended {
-					num_messages: self.messages_received_in_current_batch, /* request back the
-					                                                        * number of messages
-					                                                        * we received, not
-					                                                        * the total capacity
-					                                                        * like when starting! */
-					num_bytes: self.bytes_received_in_current_batch,
-				});
-				self.reset_batch_counters();
-
-				return Some(send_more_message);
-			} else {
-				let send_more_message = StellarMessage::SendMore(SendMore {
-					num_messages: self.messages_received_in_current_batch,
-				});
-				self.reset_batch_counters();
-
-				return Some(send_more_message);
-			}
-		}
-		return None;
+		let send_more_message = if self.flow_control_bytes_enabled {
+			StellarMessage::SendMoreExtended(SendMoreExtended {
+				num_messages: self.messages_received_in_current_batch, /* request back the
+				                                                        * number of messages
+				                                                        * we received, not
+				                                                        * the total capacity
+				                                                        * like when starting! */
+				num_bytes: self.bytes_received_in_current_batch,
+			})
+		} else {
+			StellarMessage::SendMore(SendMore {
+				num_messages: self.messages_received_in_current_batch,
+			})
+		};
+		self.reset_batch_counters();
+
+		Some(send_more_message)
 	}
 }
 
@@ -99,3 +194,108 @@ pub fn is_flood_message(message_type: MessageType) -> bool {
 		_ => false,
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	/// the size of a frame whose message is `message_size` bytes
+	fn frame_len(message_size: u32) -> usize {
+		message_size as usize + 32 + 12
+	}
+
+	fn flow_controller_with_batch(send_more_batch_size: u32) -> FlowController {
+		let mut flow_controller = FlowController::new(FlowControlConfig {
+			reading_capacity: 10,
+			send_more_batch_size,
+			reading_capacity_bytes: 5000,
+			send_more_batch_size_bytes: 1000,
+			..Default::default()
+		});
+		flow_controller.start(35, 35);
+		flow_controller
+	}
+
+	#[test]
+	fn budgets_are_taken_from_the_config() {
+		let mut flow_controller = FlowController::new(FlowControlConfig {
+			reading_capacity: 10,
+			reading_capacity_bytes: 5000,
+			..Default::default()
+		});
+		assert_eq!(
+			flow_controller.start(35, 35),
+			StellarMessage::SendMoreExtended(SendMoreExtended {
+				num_messages: 10,
+				num_bytes: 5000
+			})
+		);
+
+		let mut flow_controller = flow_controller_with_batch(2);
+		assert_eq!(
+			flow_controller
+				.create_reclaim_capacity_message(MessageType::ScpMessage, frame_len(100)),
+			None
+		);
+		assert_eq!(
+			flow_controller
+				.create_reclaim_capacity_message(MessageType::ScpMessage, frame_len(100)),
+			Some(StellarMessage::SendMoreExtended(SendMoreExtended {
+				num_messages: 2,
+				num_bytes: 200
+			}))
+		);
+
+		// a big message completes the batch of bytes on its own
+		assert!(flow_controller
+			.create_reclaim_capacity_message(MessageType::Transaction, frame_len(1000))
+			.is_some());
+	}
+
+	#[test]
+	fn credits_are_withheld_while_the_user_channel_is_full() {
+		let mut flow_controller = flow_controller_with_batch(2);
+		let free_slots = flow_controller.config().backpressure_free_slots;
+
+		flow_controller.set_user_channel_free_slots(free_slots - 1);
+		for _ in 0..3 {
+			assert_eq!(
+				flow_controller
+					.create_reclaim_capacity_message(MessageType::ScpMessage, frame_len(100)),
+				None
+			);
+		}
+		assert!(flow_controller.is_withholding());
+
+		// once the user catches up, every message received so far is granted back
+		flow_controller.set_user_channel_free_slots(free_slots);
+		assert!(!flow_controller.is_withholding());
+		assert_eq!(
+			flow_controller.release_withheld_capacity(),
+			Some(StellarMessage::SendMoreExtended(SendMoreExtended {
+				num_messages: 3,
+				num_bytes: 300
+			}))
+		);
+		assert_eq!(flow_controller.release_withheld_capacity(), None);
+	}
+
+	#[test]
+	fn invalid_budgets_are_rejected() {
+		assert!(FlowControlConfig::default().validate().is_ok());
+
+		let config = FlowControlConfig { send_more_batch_size: 300, ..Default::default() };
+		assert!(config.validate().is_err());
+
+		let config = FlowControlConfig { user_channel_capacity: 100, ..Default::default() };
+		assert!(config.validate().is_err());
+
+		// a channel without capacity cannot be created
+		let config = FlowControlConfig {
+			user_channel_capacity: 0,
+			backpressure_free_slots: 0,
+			..Default::default()
+		};
+		assert!(crate::mock_peer::test_conn_info(11625).with_flow_control(config).is_err());
+	}
+}
@@ -1,6 +1,9 @@
 use rand::Rng;
 use sha2::{Digest, Sha256};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::{
+	collections::{HashSet, VecDeque},
+	time::{SystemTime, UNIX_EPOCH},
+};
 use substrate_stellar_sdk::{
 	types::{Error, Uint256},
 	SecretKey, XdrCodec,
@@ -35,3 +38,57 @@ pub fn error_to_string(e: Error) -> String {
 
 	format!("Error{{ code:{:?} message:{msg} }}", e.code)
 }
+
+/// A set of hashes that forgets the oldest entry once it reaches its limit
+pub(crate) struct RecentHashes {
+	limit: usize,
+	set: HashSet<Uint256>,
+	queue: VecDeque<Uint256>,
+}
+
+impl RecentHashes {
+	pub(crate) fn new(limit: usize) -> Self {
+		RecentHashes { limit, set: HashSet::new(), queue: VecDeque::new() }
+	}
+
+	/// Returns true if the hash was not seen before
+	pub(crate) fn insert(&mut self, hash: Uint256) -> bool {
+		if !self.set.insert(hash) {
+			return false
+		}
+
+		self.queue.push_back(hash);
+		if self.queue.len() > self.limit {
+			if let Some(oldest) = self.queue.pop_front() {
+				self.set.remove(&oldest);
+			}
+		}
+
+		true
+	}
+
+	pub(crate) fn contains(&self, hash: &Uint256) -> bool {
+		self.set.contains(hash)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn recent_hashes_filters_duplicates() {
+		let mut hashes = RecentHashes::new(2);
+
+		assert!(hashes.insert([0; 32]));
+		assert!(!hashes.insert([0; 32]));
+		assert!(hashes.insert([1; 32]));
+
+		// the oldest entry is forgotten
+		assert!(hashes.insert([2; 32]));
+		assert!(hashes.insert([0; 32]));
+		assert!(!hashes.insert([2; 32]));
+		assert!(hashes.contains(&[0; 32]));
+		assert!(!hashes.contains(&[1; 32]));
+	}
+}
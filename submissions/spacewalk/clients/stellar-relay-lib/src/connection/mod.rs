@@ -1,7 +1,15 @@
+mod codec;
 mod error;
+mod events;
+mod flooding;
 mod flow_controller;
 pub(crate) mod handshake;
 mod hmac;
+mod reconnect;
+mod recording;
+mod scp;
+mod subscription;
+mod transport;
 
 mod authentication;
 mod connector;
@@ -10,11 +18,25 @@ pub mod xdr_converter;
 
 pub(crate) use connector::*;
 pub use error::Error;
+pub use events::ConnectionEvent;
+pub(crate) use flooding::{flood_adverts, TxFlooding};
+pub use flooding::{tx_flood_hash, TxWatch};
+pub use flow_controller::FlowControlConfig;
+pub use reconnect::ReconnectPolicy;
+pub(crate) use reconnect::Reconnector;
+pub use recording::{read_recording, FrameDirection, FrameRecorder, RecordedFrame};
+pub(crate) use scp::ScpTracker;
+pub use scp::{quorum_set_hash, verify_scp_envelope, ExternalizedSlot};
 use serde::Serialize;
-use std::fmt::{Debug, Formatter};
+use std::{
+	fmt::{Debug, Formatter},
+	path::PathBuf,
+	time::Duration,
+};
+pub use subscription::MessageFilter;
+pub(crate) use subscription::Subscriptions;
 use substrate_stellar_sdk::SecretKey;
-
-type Xdr = Vec<u8>;
+pub use transport::{ProxyConfig, ProxyCredentials, Transport};
 
 /// Config for connecting to Stellar Node
 #[derive(Clone, Serialize, PartialEq, Eq)]
@@ -29,6 +51,12 @@ pub struct ConnectionInfo {
 	pub remote_called_us: bool,
 	/// how long to wait for the Stellar Node's messages.
 	timeout_in_secs: u64,
+	/// where to record the frames of the connection, if at all.
+	record_frames_to: Option<PathBuf>,
+	/// how to reach the Stellar Node.
+	transport: Transport,
+	/// the flow control budgets of the connection.
+	flow_control: FlowControlConfig,
 }
 
 impl Debug for ConnectionInfo {
@@ -43,6 +71,9 @@ impl Debug for ConnectionInfo {
 			.field("receive_scp_messages", &self.recv_scp_msgs)
 			.field("remote_called_us", &self.remote_called_us)
 			.field("timeout_in_seconds", &self.timeout_in_secs)
+			.field("record_frames_to", &self.record_frames_to)
+			.field("transport", &self.transport)
+			.field("flow_control", &self.flow_control)
 			.finish()
 	}
 }
@@ -68,13 +99,64 @@ impl ConnectionInfo {
 			recv_scp_msgs,
 			remote_called_us,
 			timeout_in_secs,
+			record_frames_to: None,
+			transport: Transport::Direct,
+			flow_control: FlowControlConfig::default(),
+		}
+	}
+
+	/// Records every frame of the connection to the given file.
+	pub fn with_recording(mut self, path: impl Into<PathBuf>) -> Self {
+		self.record_frames_to = Some(path.into());
+		self
+	}
+
+	/// Reaches the Stellar Node through the given transport, e.g. a proxy.
+	pub fn with_transport(mut self, transport: Transport) -> Self {
+		self.transport = transport;
+		self
+	}
+
+	/// Grants the Stellar Node messages with the given budgets, if they are valid.
+	pub fn with_flow_control(mut self, flow_control: FlowControlConfig) -> Result<Self, Error> {
+		flow_control.validate()?;
+		self.flow_control = flow_control;
+		Ok(self)
+	}
+
+	/// Returns the same info, but recording to a file of its own next to the configured one,
+	/// e.g. `session.jsonl` becomes `session.127.0.0.1_11625.jsonl`. This way, the connections of
+	/// a pool do not interleave their frames.
+	pub(crate) fn with_recording_of_endpoint(&self) -> Self {
+		let Some(path) = self.record_frames_to.as_ref() else { return self.clone() };
+
+		let stem = path.file_stem().map(|stem| stem.to_string_lossy()).unwrap_or_default();
+		let mut file_name = format!("{stem}.{}_{}", self.address, self.port);
+		if let Some(extension) = path.extension() {
+			file_name = format!("{file_name}.{}", extension.to_string_lossy());
 		}
+
+		ConnectionInfo { record_frames_to: Some(path.with_file_name(file_name)), ..self.clone() }
+	}
+
+	/// Returns the same info, for connecting to another endpoint.
+	pub(crate) fn with_endpoint(&self, address: &str, port: u32) -> Self {
+		ConnectionInfo { address: address.to_string(), port, ..self.clone() }
 	}
 
 	pub fn address(&self) -> String {
 		format!("{}:{}", self.address, self.port)
 	}
 
+	pub(crate) fn endpoint(&self) -> (&str, u32) {
+		(&self.address, self.port)
+	}
+
+	/// How long to wait for the Stellar Node's messages.
+	pub(crate) fn timeout(&self) -> Duration {
+		Duration::from_secs(self.timeout_in_secs)
+	}
+
 	pub fn keypair(&self) -> SecretKey {
 		self.secret_key.clone()
 	}
@@ -0,0 +1,153 @@
+use backoff::{backoff::Backoff, ExponentialBackoff};
+use std::time::Duration;
+use substrate_stellar_sdk::types::StellarMessage;
+use tokio::{sync::mpsc, time::sleep};
+use tracing::{info, warn};
+
+use crate::{
+	connection::{events::send_event, ConnectionEvent, ConnectionInfo, Connector},
+	node::NodeInfo,
+};
+
+/// Decides how a `StellarOverlayConnection` reconnects to the Stellar Node
+/// after the connection was lost.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReconnectPolicy {
+	/// the delay before the first reconnection attempt
+	pub initial_interval: Duration,
+	/// the delay doubles every attempt, but never goes beyond this
+	pub max_interval: Duration,
+	/// stop reconnecting after this much time has passed. Retries forever if `None`
+	pub max_elapsed_time: Option<Duration>,
+}
+
+impl Default for ReconnectPolicy {
+	fn default() -> Self {
+		ReconnectPolicy {
+			initial_interval: Duration::from_secs(1),
+			max_interval: Duration::from_secs(60),
+			max_elapsed_time: Some(Duration::from_secs(60 * 10)),
+		}
+	}
+}
+
+impl ReconnectPolicy {
+	fn exponential_backoff(&self) -> ExponentialBackoff {
+		ExponentialBackoff {
+			max_elapsed_time: self.max_elapsed_time,
+			max_interval: self.max_interval,
+			initial_interval: self.initial_interval,
+			current_interval: self.initial_interval,
+			multiplier: 2.0,
+			randomization_factor: 0.25,
+			..Default::default()
+		}
+	}
+}
+
+/// Holds everything needed to start a new `Connector` to the same Stellar Node
+pub(crate) struct Reconnector {
+	local_node: NodeInfo,
+	conn_info: ConnectionInfo,
+	policy: ReconnectPolicy,
+}
+
+impl Reconnector {
+	pub(crate) fn new(
+		local_node: NodeInfo,
+		conn_info: ConnectionInfo,
+		policy: ReconnectPolicy,
+	) -> Self {
+		Reconnector { local_node, conn_info, policy }
+	}
+
+	/// Returns a new `Connector` once reconnecting succeeds,
+	/// or None if the policy gave up or the user is gone.
+	///
+	/// The flow control is resumed by the new `Connector` itself, once its handshake is completed.
+	///
+	/// # Arguments
+	/// * `reason` - why the previous connection was lost
+	/// * `send_to_user_sender` - to check whether the user is still listening
+	/// * `event_sender` - to notify the user of the reconnection attempts
+	pub(crate) async fn reconnect(
+		&self,
+		reason: String,
+		send_to_user_sender: &mpsc::Sender<StellarMessage>,
+		event_sender: &mpsc::Sender<ConnectionEvent>,
+	) -> Option<Connector> {
+		let mut backoff = self.policy.exponential_backoff();
+		let mut reason = reason;
+		let mut attempt = 0;
+
+		loop {
+			let Some(delay) = backoff.next_backoff() else {
+				warn!(
+					"reconnect(): giving up reconnecting to {} after {attempt} attempts",
+					self.conn_info.address()
+				);
+				return None
+			};
+			attempt += 1;
+
+			warn!(
+				"reconnect(): attempt #{attempt} to reconnect to {} in {:.3} s; reason: {reason}",
+				self.conn_info.address(),
+				delay.as_secs_f64()
+			);
+			send_event(event_sender, ConnectionEvent::Reconnecting { attempt, delay, reason });
+
+			sleep(delay).await;
+			if send_to_user_sender.is_closed() {
+				info!("reconnect(): user is gone; stop reconnecting");
+				return None
+			}
+
+			match Connector::start(self.local_node.clone(), self.conn_info.clone()).await {
+				Ok(connector) => {
+					info!("reconnect(): reconnected to {}", self.conn_info.address());
+					send_event(event_sender, ConnectionEvent::Reconnected { attempt });
+					return Some(connector)
+				},
+				Err(e) => reason = e.to_string(),
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn reconnect_policy_backoff_grows_until_max_interval() {
+		let policy = ReconnectPolicy {
+			initial_interval: Duration::from_secs(1),
+			max_interval: Duration::from_secs(4),
+			max_elapsed_time: None,
+		};
+		let mut backoff = policy.exponential_backoff();
+
+		// allow for the randomization factor of 25%
+		let first = backoff.next_backoff().expect("should retry forever");
+		assert!(first <= Duration::from_millis(1250));
+
+		for _ in 0..10 {
+			let delay = backoff.next_backoff().expect("should retry forever");
+			assert!(delay <= Duration::from_secs(5));
+		}
+	}
+
+	#[test]
+	fn reconnect_policy_gives_up_after_max_elapsed_time() {
+		let policy = ReconnectPolicy {
+			initial_interval: Duration::from_millis(1),
+			max_interval: Duration::from_millis(1),
+			max_elapsed_time: Some(Duration::ZERO),
+		};
+		let mut backoff = policy.exponential_backoff();
+
+		std::thread::sleep(Duration::from_millis(5));
+		assert!(backoff.next_backoff().is_none());
+	}
+}
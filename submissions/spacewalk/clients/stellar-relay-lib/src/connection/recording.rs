@@ -0,0 +1,183 @@
+use crate::connection::{helper::time_now, xdr_converter::parse_message_type, Error};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+	fs::{File, OpenOptions},
+	io::{BufRead, BufReader, Write},
+	path::Path,
+};
+
+/// Who sent a recorded frame
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FrameDirection {
+	/// sent by the Stellar Node
+	Inbound,
+	/// sent by us
+	Outbound,
+}
+
+/// A raw authenticated XDR frame of an overlay session
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedFrame {
+	pub direction: FrameDirection,
+	/// when the frame was read or written, in milliseconds since the unix epoch
+	pub timestamp: u64,
+	/// the `MessageType` of the frame; only for reading the recording
+	pub message_type: String,
+	/// the frame, without the 4 bytes of its length
+	#[serde(serialize_with = "to_base64", deserialize_with = "from_base64")]
+	pub xdr: Vec<u8>,
+}
+
+impl RecordedFrame {
+	pub fn new(direction: FrameDirection, timestamp: u64, xdr: Vec<u8>) -> Self {
+		// the message type comes after the version and the sequence of the frame
+		let message_type = match xdr.get(12..16).map(parse_message_type) {
+			Some(Ok(msg_type)) => format!("{msg_type:?}"),
+			_ => "Unknown".to_string(),
+		};
+
+		RecordedFrame { direction, timestamp, message_type, xdr }
+	}
+}
+
+fn to_base64<S: Serializer>(xdr: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+	serializer.serialize_str(&base64::encode(xdr))
+}
+
+fn from_base64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+	let encoded = String::deserialize(deserializer)?;
+	base64::decode(encoded).map_err(serde::de::Error::custom)
+}
+
+/// Writes every frame of an overlay session to a file, one json line per frame.
+/// The file is appended to, so a reconnected session continues the same recording.
+pub struct FrameRecorder {
+	file: File,
+}
+
+impl FrameRecorder {
+	pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+		let path = path.as_ref();
+		let file = OpenOptions::new()
+			.create(true)
+			.append(true)
+			.open(path)
+			.map_err(|e| Error::RecordingFailed(format!("{}: {e:?}", path.display())))?;
+
+		Ok(FrameRecorder { file })
+	}
+
+	pub fn record(&mut self, direction: FrameDirection, xdr: &[u8]) -> Result<(), Error> {
+		let frame = RecordedFrame::new(direction, time_now(), xdr.to_vec());
+
+		let mut line =
+			serde_json::to_vec(&frame).map_err(|e| Error::RecordingFailed(e.to_string()))?;
+		line.push(b'\n');
+
+		// a single write per frame, so that frames of different connections do not interleave
+		self.file.write_all(&line).map_err(|e| Error::RecordingFailed(e.to_string()))
+	}
+}
+
+/// Returns the frames of a recording made by the `FrameRecorder`
+pub fn read_recording(path: impl AsRef<Path>) -> Result<Vec<RecordedFrame>, Error> {
+	let path = path.as_ref();
+	let file = File::open(path)
+		.map_err(|e| Error::RecordingFailed(format!("{}: {e:?}", path.display())))?;
+
+	BufReader::new(file)
+		.lines()
+		.filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+		.map(|line| {
+			let line = line.map_err(|e| Error::RecordingFailed(e.to_string()))?;
+			serde_json::from_str(&line).map_err(|e| Error::RecordingFailed(e.to_string()))
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::{
+		connection::Connector,
+		mock_peer::{test_conn_info, test_node_info},
+		StellarOverlayConnection,
+	};
+	use substrate_stellar_sdk::types::StellarMessage;
+	use tempdir::TempDir;
+
+	/// Returns the frames that a Stellar Node sends, without the 4 bytes of their length
+	fn frames_of_stellar_node(messages: Vec<StellarMessage>) -> Vec<Vec<u8>> {
+		let mut stellar_node = Connector::replay(test_node_info(), test_conn_info(11625))
+			.expect("should create a connector");
+
+		let hello = stellar_node.create_hello_message(time_now()).expect("should create hello");
+		std::iter::once(hello)
+			.chain(messages)
+			.map(|msg| stellar_node.create_xdr_message(msg).expect("should create xdr"))
+			.collect()
+	}
+
+	#[test]
+	fn recording_can_be_read_back() {
+		let dir = TempDir::new("recording").expect("should create a temp dir");
+		let path = dir.path().join("session.jsonl");
+
+		let frames = frames_of_stellar_node(vec![StellarMessage::GetScpState(42)]);
+
+		let mut recorder = FrameRecorder::open(&path).expect("should open the file");
+		recorder.record(FrameDirection::Inbound, &frames[0]).expect("should record");
+		recorder.record(FrameDirection::Outbound, &frames[1]).expect("should record");
+
+		let recording = read_recording(&path).expect("should read the recording");
+		assert_eq!(recording.len(), 2);
+
+		assert_eq!(recording[0].direction, FrameDirection::Inbound);
+		assert_eq!(recording[0].message_type, "Hello");
+		assert_eq!(recording[0].xdr, frames[0]);
+
+		assert_eq!(recording[1].direction, FrameDirection::Outbound);
+		assert_eq!(recording[1].message_type, "GetScpState");
+		assert_eq!(recording[1].xdr, frames[1]);
+	}
+
+	#[test]
+	fn every_endpoint_is_recorded_to_a_file_of_its_own() {
+		let recorded = test_conn_info(11625).with_recording("/tmp/session.jsonl");
+
+		let first = recorded.with_recording_of_endpoint();
+		let second = recorded.with_endpoint("127.0.0.2", 11626).with_recording_of_endpoint();
+
+		assert_eq!(first.record_frames_to, Some("/tmp/session.127.0.0.1_11625.jsonl".into()));
+		assert_eq!(second.record_frames_to, Some("/tmp/session.127.0.0.2_11626.jsonl".into()));
+
+		// without a recording, there is nothing to name
+		assert_eq!(test_conn_info(11625).with_recording_of_endpoint().record_frames_to, None);
+	}
+
+	#[tokio::test]
+	async fn replay_passes_recorded_messages_to_user() {
+		let frames = frames_of_stellar_node(vec![StellarMessage::GetScpState(42)]);
+		let mut recording: Vec<_> = frames
+			.into_iter()
+			.map(|xdr| RecordedFrame::new(FrameDirection::Inbound, time_now(), xdr))
+			.collect();
+		// frames that we sent are not replayed
+		recording.insert(1, recording[1].clone());
+		recording[1].direction = FrameDirection::Outbound;
+
+		let mut overlay_conn =
+			StellarOverlayConnection::replay(test_node_info(), test_conn_info(11625), recording)
+				.expect("should start the replay");
+
+		let msg = overlay_conn.listen().await.expect("should be alive");
+		assert!(matches!(msg, Some(StellarMessage::Hello(_))), "expected Hello, got {msg:?}");
+
+		let msg = overlay_conn.listen().await.expect("should be alive");
+		assert_eq!(msg, Some(StellarMessage::GetScpState(42)));
+
+		// the end of the recording
+		assert_eq!(overlay_conn.listen().await.expect("should be alive"), None);
+	}
+}
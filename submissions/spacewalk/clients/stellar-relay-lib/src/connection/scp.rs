@@ -0,0 +1,378 @@
+use crate::{connection::Error, node::NetworkId};
+use sha2::{Digest, Sha256};
+use std::{
+	collections::{BTreeMap, HashMap, VecDeque},
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+use substrate_stellar_sdk::{
+	types::{
+		EnvelopeType, NodeId, ScpEnvelope, ScpQuorumSet, ScpStatement, ScpStatementPledges, Uint256,
+	},
+	XdrCodec,
+};
+
+/// The number of most recent slots whose externalize envelopes are kept
+const TRACKED_SLOTS: usize = 64;
+
+/// The number of quorum sets kept; the oldest one is dropped first
+const QUORUM_SETS_CACHE_SIZE: usize = 1024;
+
+/// The number of pending quorum set requests remembered, so that each is requested only once
+const REQUESTED_QUORUM_SETS_CACHE_SIZE: usize = 1024;
+
+/// How long to wait for a requested quorum set, before requesting it again
+const QUORUM_SET_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Returns true if the envelope was signed by the node of its statement.
+/// The signature is over the network id, the envelope type and the statement, like stellar-core's
+/// `SCPEnvelope` signature.
+pub fn verify_scp_envelope(network_id: &NetworkId, env: &ScpEnvelope) -> bool {
+	let Ok(signature) = <[u8; 64]>::try_from(env.signature.get_vec().as_slice()) else {
+		return false
+	};
+
+	let mut raw_data = network_id.to_vec();
+	raw_data.append(&mut EnvelopeType::EnvelopeTypeScp.to_xdr());
+	raw_data.append(&mut env.statement.to_xdr());
+
+	env.statement.node_id.verify_signature(raw_data, &signature)
+}
+
+/// Returns the hash that a quorum set is referred to with in statements and `GetScpQuorumset`
+pub fn quorum_set_hash(qset: &ScpQuorumSet) -> Uint256 {
+	Sha256::digest(qset.to_xdr()).into()
+}
+
+/// Returns the hash of the quorum set of the node that made the statement
+fn statement_quorum_set_hash(statement: &ScpStatement) -> Uint256 {
+	match &statement.pledges {
+		ScpStatementPledges::ScpStPrepare(prepare) => prepare.quorum_set_hash,
+		ScpStatementPledges::ScpStConfirm(confirm) => confirm.quorum_set_hash,
+		ScpStatementPledges::ScpStExternalize(externalize) => externalize.commit_quorum_set_hash,
+		ScpStatementPledges::ScpStNominate(nominate) => nominate.quorum_set_hash,
+	}
+}
+
+/// Returns true if enough of the validators and inner sets of the quorum set are among the nodes
+fn is_slice_satisfied(qset: &ScpQuorumSet, nodes: &[&NodeId]) -> bool {
+	let validators = qset.validators.get_vec().iter().filter(|node| nodes.contains(node)).count();
+	let inner_sets = qset
+		.inner_sets
+		.get_vec()
+		.iter()
+		.filter(|inner_set| is_slice_satisfied(inner_set, nodes))
+		.count();
+
+	qset.threshold > 0 && validators + inner_sets >= qset.threshold as usize
+}
+
+/// The value that a slot externalized, with the envelopes of the nodes that agreed on it
+#[derive(Clone, Debug)]
+pub struct ExternalizedSlot {
+	pub slot: u64,
+	/// the value of the commit ballot
+	pub value: Vec<u8>,
+	/// the externalize envelopes of the nodes that form the quorum
+	pub envelopes: Vec<ScpEnvelope>,
+}
+
+struct ScpState {
+	/// the quorum sets received from the Stellar Node, by their hash
+	quorum_sets: HashMap<Uint256, ScpQuorumSet>,
+	/// the hashes of `quorum_sets`, the oldest first
+	quorum_set_order: VecDeque<Uint256>,
+	/// the hashes of the quorum sets that were requested but not received yet, with when
+	requested: HashMap<Uint256, Instant>,
+	/// the externalize envelopes of the most recent slots, one per node
+	externalized: BTreeMap<u64, Vec<ScpEnvelope>>,
+}
+
+/// Keeps the quorum sets and the externalize envelopes that passed the signature check, shared by
+/// the user and the `Connector`.
+#[derive(Clone)]
+pub(crate) struct ScpTracker {
+	state: Arc<Mutex<ScpState>>,
+	/// how long to wait for a requested quorum set, before requesting it again
+	request_timeout: Duration,
+}
+
+impl Default for ScpTracker {
+	fn default() -> Self {
+		ScpTracker::with_request_timeout(QUORUM_SET_REQUEST_TIMEOUT)
+	}
+}
+
+impl ScpTracker {
+	pub fn with_request_timeout(request_timeout: Duration) -> Self {
+		ScpTracker {
+			state: Arc::new(Mutex::new(ScpState {
+				quorum_sets: HashMap::new(),
+				quorum_set_order: VecDeque::new(),
+				requested: HashMap::new(),
+				externalized: BTreeMap::new(),
+			})),
+			request_timeout,
+		}
+	}
+
+	fn state(&self) -> std::sync::MutexGuard<'_, ScpState> {
+		self.state.lock().expect("should not be poisoned")
+	}
+
+	/// Keeps the envelope if it is an externalize.
+	/// Returns the hash of the quorum set to request, if it is neither known nor requested yet,
+	/// or if the previous request went unanswered for the request timeout.
+	pub fn on_envelope(&self, env: &ScpEnvelope) -> Option<Uint256> {
+		let hash = statement_quorum_set_hash(&env.statement);
+
+		let mut state = self.state();
+		if let ScpStatementPledges::ScpStExternalize(_) = &env.statement.pledges {
+			let envelopes = state.externalized.entry(env.statement.slot_index).or_default();
+			// the latest envelope of a node replaces the previous one
+			envelopes.retain(|known| known.statement.node_id != env.statement.node_id);
+			envelopes.push(env.clone());
+
+			while state.externalized.len() > TRACKED_SLOTS {
+				state.externalized.pop_first();
+			}
+		}
+
+		if state.quorum_sets.contains_key(&hash) {
+			return None
+		}
+		if let Some(requested_at) = state.requested.get(&hash) {
+			if requested_at.elapsed() < self.request_timeout {
+				return None
+			}
+		}
+
+		if state.requested.len() >= REQUESTED_QUORUM_SETS_CACHE_SIZE {
+			let request_timeout = self.request_timeout;
+			state.requested.retain(|_, requested_at| requested_at.elapsed() < request_timeout);
+		}
+		if state.requested.len() >= REQUESTED_QUORUM_SETS_CACHE_SIZE {
+			return None
+		}
+		state.requested.insert(hash, Instant::now());
+		Some(hash)
+	}
+
+	/// Keeps the quorum set if it was requested. Returns true if it was kept.
+	pub fn on_quorum_set(&self, qset: &ScpQuorumSet) -> bool {
+		let hash = quorum_set_hash(qset);
+
+		let mut state = self.state();
+		if state.requested.remove(&hash).is_none() {
+			return false
+		}
+		state.quorum_sets.insert(hash, qset.clone());
+		state.quorum_set_order.push_back(hash);
+
+		while state.quorum_set_order.len() > QUORUM_SETS_CACHE_SIZE {
+			if let Some(oldest) = state.quorum_set_order.pop_front() {
+				state.quorum_sets.remove(&oldest);
+			}
+		}
+		true
+	}
+
+	pub fn quorum_set(&self, hash: &Uint256) -> Option<ScpQuorumSet> {
+		self.state().quorum_sets.get(hash).cloned()
+	}
+
+	/// Returns the value that the slot externalized, if the nodes that externalized it form a
+	/// quorum: each of them has its quorum set satisfied by the others.
+	/// The quorum sets are the ones the nodes declared; whether to trust these nodes is up to the
+	/// caller.
+	pub fn validate_externalize(&self, slot: u64) -> Result<ExternalizedSlot, Error> {
+		let state = self.state();
+		let envelopes = state.externalized.get(&slot).ok_or_else(|| {
+			Error::InvalidExternalize(slot, "no externalize envelopes".to_string())
+		})?;
+
+		// the envelopes of each value, since a faulty node might externalize a different one
+		let mut values: Vec<(Vec<u8>, Vec<&ScpEnvelope>)> = vec![];
+		for env in envelopes {
+			let ScpStatementPledges::ScpStExternalize(externalize) = &env.statement.pledges else {
+				continue
+			};
+			let value = externalize.commit.value.get_vec();
+			match values.iter_mut().find(|(known, _)| known == value) {
+				Some((_, value_envelopes)) => value_envelopes.push(env),
+				None => values.push((value.clone(), vec![env])),
+			}
+		}
+
+		let mut missing_quorum_sets = 0;
+		for (value, value_envelopes) in values {
+			// only the nodes whose quorum set is known can be part of the quorum
+			let mut quorum: Vec<(&ScpEnvelope, &ScpQuorumSet)> = value_envelopes
+				.into_iter()
+				.filter_map(|env| {
+					let qset = state.quorum_sets.get(&statement_quorum_set_hash(&env.statement));
+					if qset.is_none() {
+						missing_quorum_sets += 1;
+					}
+					qset.map(|qset| (env, qset))
+				})
+				.collect();
+
+			// drop the nodes whose quorum set is not satisfied, until the rest agree
+			loop {
+				let nodes: Vec<&NodeId> =
+					quorum.iter().map(|(env, _)| &env.statement.node_id).collect();
+				let size = quorum.len();
+				quorum.retain(|(_, qset)| is_slice_satisfied(qset, &nodes));
+				if quorum.len() == size {
+					break
+				}
+			}
+
+			if !quorum.is_empty() {
+				return Ok(ExternalizedSlot {
+					slot,
+					value,
+					envelopes: quorum.into_iter().map(|(env, _)| env.clone()).collect(),
+				})
+			}
+		}
+
+		Err(Error::InvalidExternalize(
+			slot,
+			format!(
+				"no quorum among {} envelopes; {missing_quorum_sets} quorum sets are unknown",
+				envelopes.len()
+			),
+		))
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::mock_peer::signed_externalize as externalize;
+	use substrate_stellar_sdk::{
+		compound_types::LimitedVarArray, network::TEST_NETWORK, SecretKey,
+	};
+
+	fn quorum_set(threshold: u32, secrets: &[&SecretKey]) -> ScpQuorumSet {
+		ScpQuorumSet {
+			threshold,
+			validators: LimitedVarArray::new(
+				secrets.iter().map(|secret| secret.get_public().clone()).collect(),
+			)
+			.expect("should create the validators"),
+			inner_sets: LimitedVarArray::new(vec![]).expect("should create the inner sets"),
+		}
+	}
+
+	fn secrets() -> [SecretKey; 3] {
+		[[1; 32], [2; 32], [3; 32]].map(SecretKey::from_binary)
+	}
+
+	#[test]
+	fn envelope_signature_is_verified() {
+		let [secret, other, _] = secrets();
+		let qset = quorum_set(1, &[&secret]);
+
+		let env = externalize(&secret, 10, 7, &qset);
+		assert!(verify_scp_envelope(TEST_NETWORK.get_id(), &env));
+
+		// the signature is bound to the network
+		let mut other_network = *TEST_NETWORK.get_id();
+		other_network[0] ^= 1;
+		assert!(!verify_scp_envelope(&other_network, &env));
+
+		// a statement that was changed after signing
+		let mut forged = env.clone();
+		forged.statement.slot_index = 11;
+		assert!(!verify_scp_envelope(TEST_NETWORK.get_id(), &forged));
+
+		// someone else's node id
+		let mut forged = env;
+		forged.statement.node_id = other.get_public().clone();
+		assert!(!verify_scp_envelope(TEST_NETWORK.get_id(), &forged));
+	}
+
+	#[test]
+	fn quorum_sets_are_requested_once_and_kept_only_if_requested() {
+		let [secret, other, _] = secrets();
+		let qset = quorum_set(1, &[&secret]);
+		let tracker = ScpTracker::default();
+
+		let env = externalize(&secret, 10, 7, &qset);
+		assert_eq!(tracker.on_envelope(&env), Some(quorum_set_hash(&qset)));
+		assert_eq!(tracker.on_envelope(&env), None);
+
+		assert!(!tracker.on_quorum_set(&quorum_set(1, &[&other])));
+		assert!(tracker.on_quorum_set(&qset));
+		assert_eq!(tracker.quorum_set(&quorum_set_hash(&qset)), Some(qset));
+	}
+
+	#[test]
+	fn unanswered_quorum_set_requests_are_retried() {
+		let [secret, _, _] = secrets();
+		let qset = quorum_set(1, &[&secret]);
+		// every request goes unanswered for too long right away
+		let tracker = ScpTracker::with_request_timeout(Duration::ZERO);
+
+		let env = externalize(&secret, 10, 7, &qset);
+		let hash = quorum_set_hash(&qset);
+		assert_eq!(tracker.on_envelope(&env), Some(hash));
+		assert_eq!(tracker.on_envelope(&env), Some(hash));
+
+		assert!(tracker.on_quorum_set(&qset));
+		assert!(tracker.state().requested.is_empty());
+		// a quorum set that was received already is not requested again
+		assert_eq!(tracker.on_envelope(&env), None);
+	}
+
+	#[test]
+	fn oldest_quorum_sets_are_dropped() {
+		let [secret, _, _] = secrets();
+		let tracker = ScpTracker::default();
+
+		// quorum sets that differ in their threshold only
+		let qsets: Vec<ScpQuorumSet> = (1..=QUORUM_SETS_CACHE_SIZE as u32 + 1)
+			.map(|threshold| quorum_set(threshold, &[&secret]))
+			.collect();
+		for (slot, qset) in qsets.iter().enumerate() {
+			tracker.on_envelope(&externalize(&secret, slot as u64, 7, qset));
+			assert!(tracker.on_quorum_set(qset));
+		}
+
+		assert_eq!(tracker.state().quorum_sets.len(), QUORUM_SETS_CACHE_SIZE);
+		assert_eq!(tracker.quorum_set(&quorum_set_hash(&qsets[0])), None);
+		let newest = qsets.last().expect("should have quorum sets");
+		assert_eq!(tracker.quorum_set(&quorum_set_hash(newest)), Some(newest.clone()));
+	}
+
+	#[test]
+	fn externalize_is_valid_only_with_a_quorum() {
+		let secrets = secrets();
+		let [a, b, c] = &secrets;
+		// each node needs 2 of the 3
+		let qset = quorum_set(2, &[a, b, c]);
+		let tracker = ScpTracker::default();
+
+		tracker.on_envelope(&externalize(a, 10, 7, &qset));
+		// the quorum set is unknown yet
+		assert!(tracker.validate_externalize(10).is_err());
+
+		tracker.on_quorum_set(&qset);
+		// a single node is not enough
+		assert!(tracker.validate_externalize(10).is_err());
+
+		// a node that externalized a different value does not count
+		tracker.on_envelope(&externalize(b, 10, 8, &qset));
+		assert!(tracker.validate_externalize(10).is_err());
+
+		tracker.on_envelope(&externalize(c, 10, 7, &qset));
+		let externalized = tracker.validate_externalize(10).expect("should have a quorum");
+		assert_eq!(externalized.value, vec![7; 32]);
+		assert_eq!(externalized.envelopes.len(), 2);
+
+		assert!(tracker.validate_externalize(11).is_err());
+	}
+}
@@ -0,0 +1,305 @@
+use crate::connection::{xdr_converter::parse_message_type, Error};
+use std::{
+	ops::RangeInclusive,
+	sync::{Arc, Mutex},
+};
+use substrate_stellar_sdk::{
+	types::{
+		FeeBumpTransactionInnerTx, MessageType, MuxedAccount, StellarMessage, TransactionEnvelope,
+	},
+	PublicKey, XdrCodec,
+};
+use tokio::sync::{mpsc, mpsc::error::TrySendError};
+use tracing::warn;
+
+/// Selects the messages that a subscriber receives.
+/// A message passes when it meets every criterion that was set; a message that a criterion
+/// does not apply to does not pass, e.g. only SCP messages pass a slot range.
+/// Without any criterion, every message passes.
+#[derive(Clone, Debug, Default)]
+pub struct MessageFilter {
+	message_types: Option<Vec<MessageType>>,
+	slots: Option<RangeInclusive<u64>>,
+	signers: Option<Vec<PublicKey>>,
+	tx_source_accounts: Option<Vec<PublicKey>>,
+}
+
+impl MessageFilter {
+	/// Returns a filter that lets every message pass
+	pub fn all() -> Self {
+		Self::default()
+	}
+
+	/// Lets only messages of the given types pass
+	pub fn message_types(mut self, message_types: impl IntoIterator<Item = MessageType>) -> Self {
+		self.message_types = Some(message_types.into_iter().collect());
+		self
+	}
+
+	/// Lets only SCP messages of the given slots pass
+	pub fn slots(mut self, slots: RangeInclusive<u64>) -> Self {
+		self.slots = Some(slots);
+		self
+	}
+
+	/// Lets only SCP messages pass, whose statement is from one of the given nodes
+	pub fn signers(mut self, node_ids: impl IntoIterator<Item = PublicKey>) -> Self {
+		self.signers = Some(node_ids.into_iter().collect());
+		self
+	}
+
+	/// Lets only transactions pass, whose source is one of the given accounts.
+	/// For a fee bump transaction, both the fee source and the source of the inner transaction
+	/// count.
+	pub fn tx_source_accounts(mut self, accounts: impl IntoIterator<Item = PublicKey>) -> Self {
+		self.tx_source_accounts = Some(accounts.into_iter().collect());
+		self
+	}
+
+	/// Returns true if the message passes the filter
+	///
+	/// # Arguments
+	/// * `msg` - the message to check
+	/// * `msg_type` - the `MessageType` of the message, if known
+	pub fn matches(&self, msg: &StellarMessage, msg_type: Option<MessageType>) -> bool {
+		if let Some(message_types) = &self.message_types {
+			match msg_type.or_else(|| message_type(msg)) {
+				Some(msg_type) if message_types.contains(&msg_type) => {},
+				_ => return false,
+			}
+		}
+
+		if let Some(slots) = &self.slots {
+			match msg {
+				StellarMessage::ScpMessage(env) if slots.contains(&env.statement.slot_index) => {},
+				_ => return false,
+			}
+		}
+
+		if let Some(signers) = &self.signers {
+			match msg {
+				StellarMessage::ScpMessage(env) if signers.contains(&env.statement.node_id) => {},
+				_ => return false,
+			}
+		}
+
+		if let Some(accounts) = &self.tx_source_accounts {
+			match msg {
+				StellarMessage::Transaction(env)
+					if tx_source_accounts(env).iter().any(|source| accounts.contains(source)) => {},
+				_ => return false,
+			}
+		}
+
+		true
+	}
+
+	fn needs_message_type(&self) -> bool {
+		self.message_types.is_some()
+	}
+}
+
+/// Returns the `MessageType` of the message.
+fn message_type(msg: &StellarMessage) -> Option<MessageType> {
+	let msg_type = match msg {
+		StellarMessage::ErrorMsg(_) => MessageType::ErrorMsg,
+		StellarMessage::Hello(_) => MessageType::Hello,
+		StellarMessage::Auth(_) => MessageType::Auth,
+		StellarMessage::DontHave(_) => MessageType::DontHave,
+		StellarMessage::GetPeers => MessageType::GetPeers,
+		StellarMessage::Peers(_) => MessageType::Peers,
+		StellarMessage::GetTxSet(_) => MessageType::GetTxSet,
+		StellarMessage::TxSet(_) => MessageType::TxSet,
+		StellarMessage::GeneralizedTxSet(_) => MessageType::GeneralizedTxSet,
+		StellarMessage::Transaction(_) => MessageType::Transaction,
+		StellarMessage::GetScpQuorumset(_) => MessageType::GetScpQuorumset,
+		StellarMessage::ScpQuorumset(_) => MessageType::ScpQuorumset,
+		StellarMessage::ScpMessage(_) => MessageType::ScpMessage,
+		StellarMessage::GetScpState(_) => MessageType::GetScpState,
+		StellarMessage::SendMore(_) => MessageType::SendMore,
+		StellarMessage::SendMoreExtended(_) => MessageType::SendMoreExtended,
+		StellarMessage::FloodAdvert(_) => MessageType::FloodAdvert,
+		StellarMessage::FloodDemand(_) => MessageType::FloodDemand,
+		// e.g. the survey messages; the type is the first 4 bytes of the xdr
+		_ => return parse_message_type(&msg.to_xdr()[0..4]).ok(),
+	};
+	Some(msg_type)
+}
+
+pub(crate) fn tx_source_accounts(env: &TransactionEnvelope) -> Vec<PublicKey> {
+	match env {
+		TransactionEnvelope::EnvelopeTypeTxV0(env) =>
+			vec![PublicKey::PublicKeyTypeEd25519(env.tx.source_account_ed25519)],
+		TransactionEnvelope::EnvelopeTypeTx(env) => vec![account_id(&env.tx.source_account)],
+		TransactionEnvelope::EnvelopeTypeTxFeeBump(env) => {
+			let FeeBumpTransactionInnerTx::EnvelopeTypeTx(inner_env) = &env.tx.inner_tx;
+			vec![account_id(&env.tx.fee_source), account_id(&inner_env.tx.source_account)]
+		},
+	}
+}
+
+fn account_id(account: &MuxedAccount) -> PublicKey {
+	match account {
+		MuxedAccount::KeyTypeEd25519(key) => PublicKey::PublicKeyTypeEd25519(*key),
+		MuxedAccount::KeyTypeMuxedEd25519(muxed) => PublicKey::PublicKeyTypeEd25519(muxed.ed25519),
+	}
+}
+
+struct Subscriber {
+	filter: MessageFilter,
+	sender: mpsc::Sender<StellarMessage>,
+}
+
+/// The consumers that share a connection, each with its own filter and channel
+#[derive(Clone, Default)]
+pub(crate) struct Subscriptions {
+	subscribers: Arc<Mutex<Vec<Subscriber>>>,
+}
+
+impl Subscriptions {
+	/// Returns the receiver of the messages that pass the filter.
+	/// Dropping the receiver ends the subscription.
+	/// Returns an error if the `capacity` is 0.
+	pub fn subscribe(
+		&self,
+		filter: MessageFilter,
+		capacity: usize,
+	) -> Result<mpsc::Receiver<StellarMessage>, Error> {
+		if capacity == 0 {
+			return Err(Error::ConfigError(
+				"The capacity of a subscription must not be 0".to_string(),
+			))
+		}
+
+		let (sender, receiver) = mpsc::channel(capacity);
+		self.subscribers
+			.lock()
+			.expect("should not be poisoned")
+			.push(Subscriber { filter, sender });
+		Ok(receiver)
+	}
+
+	/// Passes the message to every subscriber whose filter it passes.
+	/// A subscriber that cannot keep up misses the message, so that it does not hold up the others.
+	pub fn publish(&self, msg: &StellarMessage) {
+		let mut subscribers = self.subscribers.lock().expect("should not be poisoned");
+		if subscribers.is_empty() {
+			return
+		}
+
+		let msg_type = subscribers
+			.iter()
+			.any(|subscriber| subscriber.filter.needs_message_type())
+			.then(|| message_type(msg))
+			.flatten();
+
+		subscribers.retain(|subscriber| {
+			if !subscriber.filter.matches(msg, msg_type) {
+				return !subscriber.sender.is_closed()
+			}
+
+			match subscriber.sender.try_send(msg.clone()) {
+				Ok(_) => true,
+				Err(TrySendError::Full(_)) => {
+					warn!("publish(): subscriber is full; it misses a {msg_type:?} message");
+					true
+				},
+				// the subscriber is gone
+				Err(TrySendError::Closed(_)) => false,
+			}
+		});
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn filter_without_criteria_lets_everything_pass() {
+		let filter = MessageFilter::all();
+
+		assert!(filter.matches(&StellarMessage::GetScpState(1), None));
+		assert!(filter.matches(&StellarMessage::GetTxSet([0; 32]), None));
+	}
+
+	#[test]
+	fn filter_by_message_types() {
+		let filter = MessageFilter::all().message_types([MessageType::GetScpState]);
+
+		assert!(filter.matches(&StellarMessage::GetScpState(1), None));
+		assert!(!filter.matches(&StellarMessage::GetTxSet([0; 32]), None));
+
+		// a known message type is not computed again
+		assert!(filter.matches(&StellarMessage::GetTxSet([0; 32]), Some(MessageType::GetScpState)));
+	}
+
+	#[test]
+	fn filter_for_scp_messages_rejects_other_messages() {
+		let filter = MessageFilter::all().slots(1..=10);
+		assert!(!filter.matches(&StellarMessage::GetScpState(1), None));
+
+		let filter = MessageFilter::all().signers([PublicKey::PublicKeyTypeEd25519([0; 32])]);
+		assert!(!filter.matches(&StellarMessage::GetScpState(1), None));
+
+		let filter =
+			MessageFilter::all().tx_source_accounts([PublicKey::PublicKeyTypeEd25519([0; 32])]);
+		assert!(!filter.matches(&StellarMessage::GetScpState(1), None));
+	}
+
+	#[test]
+	fn message_type_of_every_message() {
+		assert_eq!(message_type(&StellarMessage::GetScpState(1)), Some(MessageType::GetScpState));
+		assert_eq!(message_type(&StellarMessage::GetTxSet([0; 32])), Some(MessageType::GetTxSet));
+		assert_eq!(message_type(&StellarMessage::GetPeers), Some(MessageType::GetPeers));
+	}
+
+	#[test]
+	fn subscription_without_capacity_is_rejected() {
+		let subscriptions = Subscriptions::default();
+
+		assert!(subscriptions.subscribe(MessageFilter::all(), 0).is_err());
+		assert!(subscriptions.subscribers.lock().unwrap().is_empty());
+	}
+
+	#[tokio::test]
+	async fn subscribers_receive_only_their_messages() {
+		let subscriptions = Subscriptions::default();
+		let mut scp_states = subscriptions
+			.subscribe(MessageFilter::all().message_types([MessageType::GetScpState]), 10)
+			.expect("should subscribe");
+		let mut everything =
+			subscriptions.subscribe(MessageFilter::all(), 10).expect("should subscribe");
+
+		subscriptions.publish(&StellarMessage::GetScpState(1));
+		subscriptions.publish(&StellarMessage::GetTxSet([0; 32]));
+
+		assert_eq!(scp_states.try_recv().ok(), Some(StellarMessage::GetScpState(1)));
+		assert!(scp_states.try_recv().is_err());
+
+		assert_eq!(everything.try_recv().ok(), Some(StellarMessage::GetScpState(1)));
+		assert_eq!(everything.try_recv().ok(), Some(StellarMessage::GetTxSet([0; 32])));
+	}
+
+	#[tokio::test]
+	async fn full_or_closed_subscribers_do_not_hold_up_others() {
+		let subscriptions = Subscriptions::default();
+		let mut slow = subscriptions.subscribe(MessageFilter::all(), 1).expect("should subscribe");
+		let gone = subscriptions.subscribe(MessageFilter::all(), 10).expect("should subscribe");
+		let mut fast = subscriptions.subscribe(MessageFilter::all(), 10).expect("should subscribe");
+		drop(gone);
+
+		subscriptions.publish(&StellarMessage::GetScpState(1));
+		subscriptions.publish(&StellarMessage::GetScpState(2));
+
+		// the subscriber that is gone was removed
+		assert_eq!(subscriptions.subscribers.lock().unwrap().len(), 2);
+
+		assert_eq!(fast.try_recv().ok(), Some(StellarMessage::GetScpState(1)));
+		assert_eq!(fast.try_recv().ok(), Some(StellarMessage::GetScpState(2)));
+
+		// the slow subscriber missed the second message
+		assert_eq!(slow.try_recv().ok(), Some(StellarMessage::GetScpState(1)));
+		assert!(slow.try_recv().is_err());
+	}
+}
@@ -0,0 +1,477 @@
+use crate::connection::Error;
+use serde::{Deserialize, Serialize, Serializer};
+use std::{
+	fmt::{Debug, Formatter},
+	io,
+	net::IpAddr,
+	time::Duration,
+};
+use tokio::{
+	io::{AsyncReadExt, AsyncWriteExt},
+	net::TcpStream,
+};
+
+const SOCKS5_VERSION: u8 = 5;
+const SOCKS5_NO_AUTH: u8 = 0;
+const SOCKS5_USERNAME_PASSWORD: u8 = 2;
+const SOCKS5_AUTH_VERSION: u8 = 1;
+const SOCKS5_CONNECT: u8 = 1;
+const SOCKS5_IPV4: u8 = 1;
+const SOCKS5_DOMAIN_NAME: u8 = 3;
+const SOCKS5_IPV6: u8 = 4;
+const SOCKS5_SUCCEEDED: u8 = 0;
+
+/// The longest response head accepted from an HTTP proxy
+const MAX_HTTP_RESPONSE_HEAD_LEN: usize = 8 * 1024;
+
+/// The username and password for a proxy that requires them
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProxyCredentials {
+	pub username: String,
+	#[serde(serialize_with = "redact")]
+	pub password: String,
+}
+
+/// Serializes a secret as `****`, so that it does not end up in logs or dumped configs
+fn redact<S: Serializer>(_secret: &str, serializer: S) -> Result<S::Ok, S::Error> {
+	serializer.serialize_str("****")
+}
+
+impl Debug for ProxyCredentials {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("ProxyCredentials")
+			.field("username", &self.username)
+			// do not expose the password
+			.field("password", &"****")
+			.finish()
+	}
+}
+
+/// Where the proxy is, and how to authenticate to it
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProxyConfig {
+	pub address: String,
+	pub port: u16,
+	#[serde(default)]
+	pub credentials: Option<ProxyCredentials>,
+}
+
+impl ProxyConfig {
+	pub fn new(address: &str, port: u16) -> Self {
+		ProxyConfig { address: address.to_string(), port, credentials: None }
+	}
+
+	pub fn with_credentials(mut self, username: &str, password: &str) -> Self {
+		self.credentials = Some(ProxyCredentials {
+			username: username.to_string(),
+			password: password.to_string(),
+		});
+		self
+	}
+}
+
+/// How the stream to the Stellar Node is opened.
+/// Once it is open, the handshake and the messages are the same for every transport.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Transport {
+	/// a TCP connection straight to the Stellar Node
+	#[default]
+	Direct,
+	/// a tunnel through a SOCKS5 proxy. The proxy resolves the address of the Stellar Node.
+	Socks5(ProxyConfig),
+	/// a tunnel through an HTTP proxy, opened with the `CONNECT` method
+	HttpConnect(ProxyConfig),
+}
+
+impl Transport {
+	/// Returns the stream to the Stellar Node at `address`:`port`.
+	/// Opening a tunnel through a proxy fails after `timeout`.
+	pub(crate) async fn connect(
+		&self,
+		address: &str,
+		port: u32,
+		timeout: Duration,
+	) -> Result<TcpStream, Error> {
+		let proxy = match self {
+			Transport::Direct =>
+				return TcpStream::connect(format!("{address}:{port}"))
+					.await
+					.map_err(|e| Error::ConnectionFailed(e.to_string())),
+			Transport::Socks5(proxy) | Transport::HttpConnect(proxy) => proxy,
+		};
+
+		let port = u16::try_from(port)
+			.map_err(|_| Error::ConnectionFailed(format!("Port {port} is out of range")))?;
+
+		let tunnel = async {
+			let mut stream = TcpStream::connect((proxy.address.as_str(), proxy.port)).await?;
+			match self {
+				Transport::Socks5(_) =>
+					socks5_connect(&mut stream, address, port, proxy.credentials.as_ref()).await?,
+				_ => http_connect(&mut stream, address, port, proxy.credentials.as_ref()).await?,
+			}
+			Ok::<_, io::Error>(stream)
+		};
+
+		match tokio::time::timeout(timeout, tunnel).await {
+			Ok(Ok(stream)) => Ok(stream),
+			Ok(Err(e)) => Err(Error::ConnectionFailed(format!(
+				"Tunnel to {address}:{port} through proxy {}:{}: {e}",
+				proxy.address, proxy.port
+			))),
+			Err(_) => Err(Error::ConnectionFailed(format!(
+				"Tunnel to {address}:{port} through proxy {}:{}: timed out",
+				proxy.address, proxy.port
+			))),
+		}
+	}
+
+	pub(crate) fn is_proxy(&self) -> bool {
+		!matches!(self, Transport::Direct)
+	}
+}
+
+/// Asks the SOCKS5 proxy to connect the stream to `address`:`port`, as in RFC 1928 and RFC 1929
+async fn socks5_connect(
+	stream: &mut TcpStream,
+	address: &str,
+	port: u16,
+	credentials: Option<&ProxyCredentials>,
+) -> io::Result<()> {
+	let greeting = match credentials {
+		Some(_) => vec![SOCKS5_VERSION, 2, SOCKS5_NO_AUTH, SOCKS5_USERNAME_PASSWORD],
+		None => vec![SOCKS5_VERSION, 1, SOCKS5_NO_AUTH],
+	};
+	stream.write_all(&greeting).await?;
+
+	let mut reply = [0; 2];
+	stream.read_exact(&mut reply).await?;
+	if reply[0] != SOCKS5_VERSION {
+		return Err(io::Error::other("not a SOCKS5 proxy"))
+	}
+
+	match (reply[1], credentials) {
+		(SOCKS5_NO_AUTH, _) => {},
+		(SOCKS5_USERNAME_PASSWORD, Some(credentials)) => {
+			let mut auth = vec![SOCKS5_AUTH_VERSION];
+			auth.extend(length_prefixed(credentials.username.as_bytes())?);
+			auth.extend(length_prefixed(credentials.password.as_bytes())?);
+			stream.write_all(&auth).await?;
+
+			let mut reply = [0; 2];
+			stream.read_exact(&mut reply).await?;
+			if reply[1] != SOCKS5_SUCCEEDED {
+				return Err(io::Error::other("proxy rejected the credentials"))
+			}
+		},
+		(method, _) =>
+			return Err(io::Error::other(format!("unsupported authentication method {method}"))),
+	}
+
+	let mut request = vec![SOCKS5_VERSION, SOCKS5_CONNECT, 0];
+	match address.parse::<IpAddr>() {
+		Ok(IpAddr::V4(ip)) => {
+			request.push(SOCKS5_IPV4);
+			request.extend(ip.octets());
+		},
+		Ok(IpAddr::V6(ip)) => {
+			request.push(SOCKS5_IPV6);
+			request.extend(ip.octets());
+		},
+		Err(_) => {
+			request.push(SOCKS5_DOMAIN_NAME);
+			request.extend(length_prefixed(address.as_bytes())?);
+		},
+	}
+	request.extend(port.to_be_bytes());
+	stream.write_all(&request).await?;
+
+	let mut reply = [0; 4];
+	stream.read_exact(&mut reply).await?;
+	if reply[1] != SOCKS5_SUCCEEDED {
+		return Err(io::Error::other(format!("proxy failed to connect with reply {}", reply[1])))
+	}
+
+	// the address that the proxy bound to is of no use to us; skip it and its port
+	let bound_address_len = match reply[3] {
+		SOCKS5_IPV4 => 4,
+		SOCKS5_IPV6 => 16,
+		SOCKS5_DOMAIN_NAME => usize::from(stream.read_u8().await?),
+		other => return Err(io::Error::other(format!("unknown address type {other}"))),
+	};
+	let mut bound_address = vec![0; bound_address_len + 2];
+	stream.read_exact(&mut bound_address).await?;
+
+	Ok(())
+}
+
+fn length_prefixed(bytes: &[u8]) -> io::Result<Vec<u8>> {
+	let len = u8::try_from(bytes.len()).map_err(|_| {
+		io::Error::other(format!("{} bytes do not fit in a SOCKS5 field", bytes.len()))
+	})?;
+
+	let mut field = vec![len];
+	field.extend_from_slice(bytes);
+	Ok(field)
+}
+
+/// Asks the HTTP proxy to connect the stream to `address`:`port` with the `CONNECT` method
+async fn http_connect(
+	stream: &mut TcpStream,
+	address: &str,
+	port: u16,
+	credentials: Option<&ProxyCredentials>,
+) -> io::Result<()> {
+	let authority = match address.parse::<IpAddr>() {
+		Ok(IpAddr::V6(_)) => format!("[{address}]:{port}"),
+		_ => format!("{address}:{port}"),
+	};
+
+	let mut request = format!("CONNECT {authority} HTTP/1.1\r\nHost: {authority}\r\n");
+	if let Some(credentials) = credentials {
+		let token = base64::encode(format!("{}:{}", credentials.username, credentials.password));
+		request.push_str(&format!("Proxy-Authorization: Basic {token}\r\n"));
+	}
+	request.push_str("\r\n");
+	stream.write_all(request.as_bytes()).await?;
+
+	// read byte by byte, to leave whatever follows the head to the overlay
+	let mut head = vec![];
+	while !head.ends_with(b"\r\n\r\n") {
+		if head.len() >= MAX_HTTP_RESPONSE_HEAD_LEN {
+			return Err(io::Error::other("proxy response is too long"))
+		}
+		head.push(stream.read_u8().await?);
+	}
+
+	let head = String::from_utf8_lossy(&head);
+	let status_line = head.lines().next().unwrap_or_default();
+	match status_line.split_whitespace().nth(1) {
+		Some(status) if status.starts_with('2') => Ok(()),
+		_ => Err(io::Error::other(format!("proxy refused the tunnel: {status_line}"))),
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::{
+		mock_peer::{test_node_info, MockPeerScript, MockStellarPeer},
+		StellarOverlayConnection,
+	};
+	use std::sync::{Arc, Mutex};
+	use substrate_stellar_sdk::types::StellarMessage;
+	use tokio::net::TcpListener;
+
+	type Targets = Arc<Mutex<Vec<String>>>;
+
+	/// Starts a proxy on localhost, standing in for the proxy of a restricted network.
+	/// Every client is served by `serve`, which returns the target to tunnel to, if any.
+	/// Returns the port of the proxy, and the targets it tunnelled to.
+	async fn start_proxy<F, Fut>(serve: F) -> (u16, Targets)
+	where
+		F: Fn(TcpStream) -> Fut + Send + Sync + 'static,
+		Fut: std::future::Future<Output = io::Result<Option<(TcpStream, String)>>> + Send,
+	{
+		let listener = TcpListener::bind("127.0.0.1:0").await.expect("should bind");
+		let port = listener.local_addr().expect("should have an address").port();
+		let targets = Targets::default();
+
+		let tunnelled = targets.clone();
+		let serve = Arc::new(serve);
+		tokio::spawn(async move {
+			while let Ok((client, _)) = listener.accept().await {
+				let serve = serve.clone();
+				let tunnelled = tunnelled.clone();
+				tokio::spawn(async move {
+					if let Ok(Some((mut client, target))) = serve(client).await {
+						let Ok(mut target_stream) = TcpStream::connect(&target).await else {
+							return
+						};
+						tunnelled.lock().expect("should not be poisoned").push(target);
+						let _ =
+							tokio::io::copy_bidirectional(&mut client, &mut target_stream).await;
+					}
+				});
+			}
+		});
+
+		(port, targets)
+	}
+
+	/// A SOCKS5 proxy that only accepts the given credentials
+	async fn start_socks5_proxy(username: &'static str, password: &'static str) -> (u16, Targets) {
+		start_proxy(move |mut client| async move {
+			let mut greeting = [0; 2];
+			client.read_exact(&mut greeting).await?;
+			let mut methods = vec![0; usize::from(greeting[1])];
+			client.read_exact(&mut methods).await?;
+			client.write_all(&[SOCKS5_VERSION, SOCKS5_USERNAME_PASSWORD]).await?;
+
+			let _version = client.read_u8().await?;
+			let mut received_username = vec![0; usize::from(client.read_u8().await?)];
+			client.read_exact(&mut received_username).await?;
+			let mut received_password = vec![0; usize::from(client.read_u8().await?)];
+			client.read_exact(&mut received_password).await?;
+			if received_username != username.as_bytes() || received_password != password.as_bytes()
+			{
+				client.write_all(&[SOCKS5_AUTH_VERSION, 1]).await?;
+				return Ok(None)
+			}
+			client.write_all(&[SOCKS5_AUTH_VERSION, SOCKS5_SUCCEEDED]).await?;
+
+			let mut request = [0; 4];
+			client.read_exact(&mut request).await?;
+			let host = match request[3] {
+				SOCKS5_IPV4 => {
+					let mut ip = [0; 4];
+					client.read_exact(&mut ip).await?;
+					IpAddr::from(ip).to_string()
+				},
+				_ => {
+					let mut name = vec![0; usize::from(client.read_u8().await?)];
+					client.read_exact(&mut name).await?;
+					String::from_utf8_lossy(&name).to_string()
+				},
+			};
+			let port = client.read_u16().await?;
+
+			client
+				.write_all(&[SOCKS5_VERSION, SOCKS5_SUCCEEDED, 0, SOCKS5_IPV4, 0, 0, 0, 0, 0, 0])
+				.await?;
+			Ok(Some((client, format!("{host}:{port}"))))
+		})
+		.await
+	}
+
+	/// An HTTP proxy that answers every `CONNECT` with the status
+	async fn start_http_proxy(status: &'static str) -> (u16, Targets) {
+		start_proxy(move |mut client| async move {
+			let mut head = vec![];
+			while !head.ends_with(b"\r\n\r\n") {
+				head.push(client.read_u8().await?);
+			}
+			let head = String::from_utf8_lossy(&head).to_string();
+			let target = head.split_whitespace().nth(1).unwrap_or_default().to_string();
+
+			client.write_all(format!("HTTP/1.1 {status}\r\n\r\n").as_bytes()).await?;
+			Ok(status.starts_with('2').then_some((client, target)))
+		})
+		.await
+	}
+
+	async fn connect_through(
+		mock_peer: &MockStellarPeer,
+		transport: Transport,
+	) -> Result<StellarOverlayConnection, Error> {
+		StellarOverlayConnection::connect(
+			test_node_info(),
+			mock_peer.conn_info().with_transport(transport),
+		)
+		.await
+	}
+
+	#[tokio::test(flavor = "multi_thread")]
+	async fn overlay_is_reached_through_socks5_proxy() {
+		let mock_peer = MockStellarPeer::start(test_node_info(), MockPeerScript::new())
+			.await
+			.expect("should start the mock peer");
+		let (proxy_port, targets) = start_socks5_proxy("vault", "secret").await;
+
+		let transport = Transport::Socks5(
+			ProxyConfig::new("127.0.0.1", proxy_port).with_credentials("vault", "secret"),
+		);
+		let mut overlay_conn =
+			connect_through(&mock_peer, transport).await.expect("should connect through the proxy");
+
+		let msg = overlay_conn.listen().await.expect("should be alive");
+		assert!(matches!(msg, Some(StellarMessage::Hello(_))), "expected Hello, got {msg:?}");
+		assert_eq!(
+			*targets.lock().expect("should not be poisoned"),
+			vec![format!("127.0.0.1:{}", mock_peer.local_addr().port())]
+		);
+	}
+
+	#[tokio::test(flavor = "multi_thread")]
+	async fn socks5_proxy_rejects_wrong_credentials() {
+		let mock_peer = MockStellarPeer::start(test_node_info(), MockPeerScript::new())
+			.await
+			.expect("should start the mock peer");
+		let (proxy_port, targets) = start_socks5_proxy("vault", "secret").await;
+
+		let transport = Transport::Socks5(
+			ProxyConfig::new("127.0.0.1", proxy_port).with_credentials("vault", "wrong"),
+		);
+		let result = connect_through(&mock_peer, transport).await;
+
+		assert!(matches!(result, Err(Error::ConnectionFailed(_))));
+		assert!(targets.lock().expect("should not be poisoned").is_empty());
+	}
+
+	#[test]
+	fn proxy_password_is_not_serialized() {
+		let conn_info = crate::mock_peer::test_conn_info(11625).with_transport(Transport::Socks5(
+			ProxyConfig::new("10.0.0.1", 1080).with_credentials("vault", "secret"),
+		));
+
+		let serialized = serde_json::to_string(&conn_info).expect("should serialize");
+		assert!(serialized.contains("\"username\":\"vault\""));
+		assert!(!serialized.contains("secret"));
+		assert!(!format!("{conn_info:?}").contains("secret"));
+	}
+
+	#[tokio::test(flavor = "multi_thread")]
+	async fn overlay_is_reached_through_http_proxy() {
+		let mock_peer = MockStellarPeer::start(test_node_info(), MockPeerScript::new())
+			.await
+			.expect("should start the mock peer");
+		let (proxy_port, targets) = start_http_proxy("200 Connection established").await;
+
+		let transport = Transport::HttpConnect(ProxyConfig::new("127.0.0.1", proxy_port));
+		let mut overlay_conn =
+			connect_through(&mock_peer, transport).await.expect("should connect through the proxy");
+
+		let msg = overlay_conn.listen().await.expect("should be alive");
+		assert!(matches!(msg, Some(StellarMessage::Hello(_))), "expected Hello, got {msg:?}");
+		assert_eq!(targets.lock().expect("should not be poisoned").len(), 1);
+	}
+
+	#[tokio::test(flavor = "multi_thread")]
+	async fn http_proxy_refusal_fails_the_connection() {
+		let mock_peer = MockStellarPeer::start(test_node_info(), MockPeerScript::new())
+			.await
+			.expect("should start the mock peer");
+		let (proxy_port, _) = start_http_proxy("403 Forbidden").await;
+
+		let transport = Transport::HttpConnect(ProxyConfig::new("127.0.0.1", proxy_port));
+		let result = connect_through(&mock_peer, transport).await;
+
+		assert!(
+			matches!(&result, Err(Error::ConnectionFailed(msg)) if msg.contains("403")),
+			"expected a refusal, got {:?}",
+			result.err()
+		);
+	}
+
+	#[test]
+	fn transport_in_config() {
+		let transport: Transport = serde_json::from_str(
+			r#"{ "type": "socks5", "address": "10.0.0.1", "port": 1080,
+				 "credentials": { "username": "vault", "password": "secret" } }"#,
+		)
+		.expect("should return a Transport");
+		assert_eq!(
+			transport,
+			Transport::Socks5(
+				ProxyConfig::new("10.0.0.1", 1080).with_credentials("vault", "secret")
+			)
+		);
+		assert!(!format!("{transport:?}").contains("secret"));
+
+		let transport: Transport =
+			serde_json::from_str(r#"{ "type": "http_connect", "address": "proxy", "port": 3128 }"#)
+				.expect("should return a Transport");
+		assert_eq!(transport, Transport::HttpConnect(ProxyConfig::new("proxy", 3128)));
+	}
+}
@@ -1,6 +1,4 @@
-use crate::sdk::types::{
-	AuthenticatedMessage, AuthenticatedMessageV0, HmacSha256Mac, MessageType, StellarMessage,
-};
+use crate::sdk::types::{AuthenticatedMessageV0, HmacSha256Mac, MessageType, StellarMessage};
 use std::fmt::Debug;
 use substrate_stellar_sdk::{parse_stellar_type, StellarSdkError, XdrCodec};
 
@@ -39,17 +37,18 @@ pub(crate) fn get_xdr_message_length(data: &[u8]) -> usize {
 	usize::try_from(len).unwrap_or(0)
 }
 
-/// Returns xdr of the authenticated message
-pub(crate) fn from_authenticated_message(message: &AuthenticatedMessage) -> Result<Vec<u8>, Error> {
-	message_to_bytes(message)
-}
-
 /// Parses the xdr message into `AuthenticatedMessageV0`.
 /// When successful, returns a tuple of the message and the `MessageType`.
 pub(crate) fn parse_authenticated_message(
 	xdr_message: &[u8],
 ) -> Result<(AuthenticatedMessageV0, MessageType), Error> {
 	let xdr_msg_len = xdr_message.len();
+	// the version, sequence and message type in front, and the mac at the end
+	if xdr_msg_len < 16 + 32 {
+		return Err(Error::DecodeError(format!(
+			"Authenticated message of {xdr_msg_len} bytes is too short"
+		)));
+	}
 
 	let msg_vers = parse_message_version(&xdr_message[0..4])?;
 	if msg_vers != 0 {
@@ -84,29 +83,10 @@ fn parse_hmac(xdr_message: &[u8]) -> Result<HmacSha256Mac, Error> {
 	parse_stellar_type!(xdr_message, HmacSha256Mac).map_err(|e| e.into())
 }
 
-fn parse_message_type(xdr_message: &[u8]) -> Result<MessageType, Error> {
+pub(crate) fn parse_message_type(xdr_message: &[u8]) -> Result<MessageType, Error> {
 	parse_stellar_type!(xdr_message, MessageType).map_err(|e| e.into())
 }
 
-/// Returns XDR format of the message or
-/// an error when the message length exceeds the max of u32
-fn message_to_bytes<T: XdrCodec>(message: &T) -> Result<Vec<u8>, Error> {
-	let mut message_xdr = message.to_xdr();
-
-	// get the bytes of the message xdr's length.
-	let message_len_bytes = u32::try_from(message_xdr.len())
-		.map(|len| len.to_be_bytes())
-		.map_err(|_| Error::UsizeToU32ExceedMax)?;
-
-	let mut buffer: Vec<u8> = vec![];
-	// first 4 bytes are for the length
-	buffer.extend_from_slice(&message_len_bytes);
-	// the message
-	buffer.append(&mut message_xdr);
-
-	Ok(buffer)
-}
-
 pub fn log_decode_error<T: Debug>(source: &str, error: T) -> Error {
 	tracing::error!("decode error: {:?}", error);
 	Error::DecodeError(source.to_string())
@@ -143,6 +123,12 @@ mod test {
 		assert!(parse_authenticated_message(&msg).is_ok());
 	}
 
+	#[test]
+	fn parse_authenticated_message_too_short() {
+		assert!(parse_authenticated_message(&[0; 15]).is_err());
+		assert!(parse_authenticated_message(&[0; 47]).is_err());
+	}
+
 	#[test]
 	fn message_not_complete_check() {
 		let xdr_no_next_msg = base64::decode_config(
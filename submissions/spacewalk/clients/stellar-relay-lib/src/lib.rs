@@ -1,14 +1,34 @@
 mod config;
 // mod connection;
 mod connection;
+mod listener;
+/// Metrics of the overlay connections, labelled with the address of the Stellar Node.
+/// They are only recorded with the `metrics` feature.
+pub mod metrics;
+#[cfg(any(test, feature = "testing-utils"))]
+pub mod mock_peer;
 pub mod node;
 mod overlay;
+mod overlay_pool;
+mod peer_book;
+mod requester;
 #[cfg(test)]
 mod tests;
 
 pub use crate::connection::{
-	handshake::HandshakeState, helper, xdr_converter, ConnectionInfo, Error,
+	handshake::HandshakeState, helper, quorum_set_hash, read_recording, tx_flood_hash,
+	verify_scp_envelope, xdr_converter, ConnectionEvent, ConnectionInfo, Error, ExternalizedSlot,
+	FlowControlConfig, FrameDirection, FrameRecorder, MessageFilter, ProxyConfig, ProxyCredentials,
+	ReconnectPolicy, RecordedFrame, Transport, TxWatch,
 };
-pub use config::{connect_to_stellar_overlay_network, StellarOverlayConfig};
+pub use config::{
+	connect_to_stellar_overlay_network, connect_to_stellar_overlay_network_with_reconnect,
+	connect_to_stellar_overlay_pool, listen_for_stellar_overlay_peers,
+	replay_stellar_overlay_recording, ConnectionEndpoint, StellarOverlayConfig,
+};
+pub use listener::StellarOverlayListener;
 pub use overlay::StellarOverlayConnection;
+pub use overlay_pool::StellarOverlayPool;
+pub use peer_book::{PeerBook, PeerRecord};
+pub use requester::{RequestPolicy, StellarOverlayRequester};
 pub use substrate_stellar_sdk as sdk;
@@ -0,0 +1,109 @@
+use std::net::SocketAddr;
+use tokio::net::TcpListener;
+use tracing::{info, warn};
+
+use crate::{
+	connection::{ConnectionInfo, Connector},
+	node::NodeInfo,
+	Error, StellarOverlayConnection,
+};
+
+/// Used to accept connections that Stellar Nodes open to us
+pub struct StellarOverlayListener {
+	local_node_info: NodeInfo,
+	/// the config shared by every accepted connection
+	conn_info: ConnectionInfo,
+	tcp_listener: TcpListener,
+}
+
+impl StellarOverlayListener {
+	/// Returns a `StellarOverlayListener` when binding to the address of the `ConnectionInfo` is
+	/// successful.
+	pub async fn bind(local_node_info: NodeInfo, conn_info: ConnectionInfo) -> Result<Self, Error> {
+		let tcp_listener = TcpListener::bind(conn_info.address())
+			.await
+			.map_err(|e| Error::ConnectionFailed(e.to_string()))?;
+
+		info!("bind(): listening for Stellar Nodes on {}", conn_info.address());
+
+		Ok(StellarOverlayListener { local_node_info, conn_info, tcp_listener })
+	}
+
+	pub fn local_addr(&self) -> Result<SocketAddr, Error> {
+		self.tcp_listener.local_addr().map_err(|e| Error::ConnectionFailed(e.to_string()))
+	}
+
+	/// Waits for the next Stellar Node to connect to us.
+	/// Returns an `StellarOverlayConnection` once the handshake with the Stellar Node is completed,
+	/// or an error if the Stellar Node does not complete it in time.
+	pub async fn accept(&self) -> Result<StellarOverlayConnection, Error> {
+		let (tcp_stream, peer_addr) =
+			self.tcp_listener.accept().await.map_err(|e| Error::ConnectionFailed(e.to_string()))?;
+
+		info!("accept(): accepted connection from {peer_addr}");
+
+		let connector =
+			Connector::accept(self.local_node_info.clone(), self.conn_info.clone(), tcp_stream)?;
+
+		let mut conn = StellarOverlayConnection::run(connector, None);
+		if let Err(e) = conn.wait_for_handshake(self.conn_info.timeout()).await {
+			warn!("accept(): no handshake with {peer_addr}: {e:?}");
+			conn.stop();
+			return Err(e)
+		}
+
+		Ok(conn)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::mock_peer::{test_conn_info, test_node_info};
+	use rand::Rng;
+	use substrate_stellar_sdk::{types::StellarMessage, SecretKey};
+
+	async fn expect_hello(conn: &mut StellarOverlayConnection) {
+		let msg = conn.listen().await.expect("should be alive").expect("should return a message");
+		assert!(matches!(msg, StellarMessage::Hello(_)), "expected Hello, got {msg:?}");
+	}
+
+	#[tokio::test(flavor = "multi_thread")]
+	async fn listener_completes_handshake_with_outbound_connection() {
+		let listener = StellarOverlayListener::bind(test_node_info(), test_conn_info(0))
+			.await
+			.expect("should bind to a free port");
+		let port = listener.local_addr().expect("should return the bound address").port();
+
+		let (accepted, connected) = tokio::join!(
+			listener.accept(),
+			StellarOverlayConnection::connect(test_node_info(), test_conn_info(u32::from(port)))
+		);
+		let mut accepted = accepted.expect("should accept the connection");
+		let mut connected = connected.expect("should connect to the listener");
+
+		// both sides pass the hello message of the other side to the user
+		expect_hello(&mut accepted).await;
+		expect_hello(&mut connected).await;
+
+		assert!(accepted.is_alive());
+		assert!(connected.is_alive());
+	}
+
+	#[tokio::test(flavor = "multi_thread")]
+	async fn listener_rejects_connection_without_handshake() {
+		let secret_key = SecretKey::from_binary(rand::thread_rng().gen());
+		let conn_info =
+			ConnectionInfo::new_with_timeout("127.0.0.1", 0, secret_key, 0, false, true, false, 1);
+		let listener = StellarOverlayListener::bind(test_node_info(), conn_info)
+			.await
+			.expect("should bind to a free port");
+		let port = listener.local_addr().expect("should return the bound address").port();
+
+		// connects, but never sends a hello message
+		let (accepted, _stream) =
+			tokio::join!(listener.accept(), tokio::net::TcpStream::connect(("127.0.0.1", port)));
+
+		assert!(accepted.is_err());
+	}
+}
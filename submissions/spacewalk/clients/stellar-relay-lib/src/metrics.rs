@@ -0,0 +1,166 @@
+use std::time::Duration;
+use substrate_stellar_sdk::types::{MessageType, StellarMessage};
+
+#[cfg(feature = "metrics")]
+pub use enabled::register_overlay_metrics;
+
+#[cfg(feature = "metrics")]
+mod enabled {
+	use lazy_static::lazy_static;
+	use prometheus::{HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry};
+
+	pub(super) const PEER_LABEL: &str = "peer";
+	pub(super) const MESSAGE_TYPE_LABEL: &str = "message_type";
+
+	lazy_static! {
+		pub(super) static ref BYTES_READ: IntCounterVec = IntCounterVec::new(
+			Opts::new("stellar_overlay_bytes_read", "Bytes read from the Stellar Node"),
+			&[PEER_LABEL]
+		)
+		.expect("Failed to create prometheus metric");
+		pub(super) static ref MESSAGES_RECEIVED: IntCounterVec = IntCounterVec::new(
+			Opts::new(
+				"stellar_overlay_messages_received",
+				"Messages received from the Stellar Node"
+			),
+			&[PEER_LABEL, MESSAGE_TYPE_LABEL]
+		)
+		.expect("Failed to create prometheus metric");
+		pub(super) static ref HMAC_FAILURES: IntCounterVec = IntCounterVec::new(
+			Opts::new("stellar_overlay_hmac_failures", "Messages with an invalid HMAC"),
+			&[PEER_LABEL]
+		)
+		.expect("Failed to create prometheus metric");
+		pub(super) static ref SEQUENCE_MISMATCHES: IntCounterVec = IntCounterVec::new(
+			Opts::new(
+				"stellar_overlay_sequence_mismatches",
+				"Messages with an unexpected sequence"
+			),
+			&[PEER_LABEL]
+		)
+		.expect("Failed to create prometheus metric");
+		pub(super) static ref SEND_MORE_MESSAGES: IntCounterVec = IntCounterVec::new(
+			Opts::new(
+				"stellar_overlay_send_more_messages",
+				"Message credits granted to the Stellar Node with SendMore"
+			),
+			&[PEER_LABEL]
+		)
+		.expect("Failed to create prometheus metric");
+		pub(super) static ref SEND_MORE_BYTES: IntCounterVec = IntCounterVec::new(
+			Opts::new(
+				"stellar_overlay_send_more_bytes",
+				"Byte credits granted to the Stellar Node with SendMoreExtended"
+			),
+			&[PEER_LABEL]
+		)
+		.expect("Failed to create prometheus metric");
+		pub(super) static ref HANDSHAKE_DURATION: HistogramVec = HistogramVec::new(
+			HistogramOpts::new(
+				"stellar_overlay_handshake_duration_seconds",
+				"Time from connecting to the Stellar Node until the handshake is completed"
+			),
+			&[PEER_LABEL]
+		)
+		.expect("Failed to create prometheus metric");
+	}
+
+	/// Registers the overlay metrics to the registry of the service
+	pub fn register_overlay_metrics(registry: &Registry) -> Result<(), prometheus::Error> {
+		registry.register(Box::new(BYTES_READ.clone()))?;
+		registry.register(Box::new(MESSAGES_RECEIVED.clone()))?;
+		registry.register(Box::new(HMAC_FAILURES.clone()))?;
+		registry.register(Box::new(SEQUENCE_MISMATCHES.clone()))?;
+		registry.register(Box::new(SEND_MORE_MESSAGES.clone()))?;
+		registry.register(Box::new(SEND_MORE_BYTES.clone()))?;
+		registry.register(Box::new(HANDSHAKE_DURATION.clone()))?;
+		Ok(())
+	}
+}
+
+#[allow(unused_variables)]
+pub(crate) fn record_bytes_read(peer: &str, len: usize) {
+	#[cfg(feature = "metrics")]
+	enabled::BYTES_READ.with_label_values(&[peer]).inc_by(len as u64);
+}
+
+#[allow(unused_variables)]
+pub(crate) fn record_message_received(peer: &str, msg_type: MessageType) {
+	#[cfg(feature = "metrics")]
+	enabled::MESSAGES_RECEIVED.with_label_values(&[peer, &format!("{msg_type:?}")]).inc();
+}
+
+#[allow(unused_variables)]
+pub(crate) fn record_hmac_failure(peer: &str) {
+	#[cfg(feature = "metrics")]
+	enabled::HMAC_FAILURES.with_label_values(&[peer]).inc();
+}
+
+#[allow(unused_variables)]
+pub(crate) fn record_sequence_mismatch(peer: &str) {
+	#[cfg(feature = "metrics")]
+	enabled::SEQUENCE_MISMATCHES.with_label_values(&[peer]).inc();
+}
+
+/// Counts the credits of a `SendMore` or `SendMoreExtended` sent to the Stellar Node
+#[allow(unused_variables)]
+pub(crate) fn record_send_more(peer: &str, msg: &StellarMessage) {
+	#[cfg(feature = "metrics")]
+	match msg {
+		StellarMessage::SendMore(send_more) => enabled::SEND_MORE_MESSAGES
+			.with_label_values(&[peer])
+			.inc_by(u64::from(send_more.num_messages)),
+		StellarMessage::SendMoreExtended(send_more) => {
+			enabled::SEND_MORE_MESSAGES
+				.with_label_values(&[peer])
+				.inc_by(u64::from(send_more.num_messages));
+			enabled::SEND_MORE_BYTES
+				.with_label_values(&[peer])
+				.inc_by(u64::from(send_more.num_bytes));
+		},
+		_ => {},
+	}
+}
+
+#[allow(unused_variables)]
+pub(crate) fn record_handshake_duration(peer: &str, duration: Duration) {
+	#[cfg(feature = "metrics")]
+	enabled::HANDSHAKE_DURATION.with_label_values(&[peer]).observe(duration.as_secs_f64());
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod test {
+	use super::*;
+	use substrate_stellar_sdk::types::{SendMore, SendMoreExtended};
+
+	#[test]
+	fn send_more_credits_are_counted() {
+		let peer = "send_more_credits_are_counted:11625";
+
+		record_send_more(peer, &StellarMessage::SendMore(SendMore { num_messages: 40 }));
+		record_send_more(
+			peer,
+			&StellarMessage::SendMoreExtended(SendMoreExtended {
+				num_messages: 10,
+				num_bytes: 5000,
+			}),
+		);
+		// not a SendMore
+		record_send_more(peer, &StellarMessage::GetScpState(1));
+
+		assert_eq!(enabled::SEND_MORE_MESSAGES.with_label_values(&[peer]).get(), 50);
+		assert_eq!(enabled::SEND_MORE_BYTES.with_label_values(&[peer]).get(), 5000);
+	}
+
+	#[test]
+	fn overlay_metrics_are_registered() {
+		let registry = prometheus::Registry::new();
+		register_overlay_metrics(&registry).expect("should register the metrics");
+
+		record_message_received("overlay_metrics_are_registered:11625", MessageType::ScpMessage);
+		assert!(registry
+			.gather()
+			.iter()
+			.any(|family| family.get_name() == "stellar_overlay_messages_received"));
+	}
+}
@@ -0,0 +1,492 @@
+use std::{
+	net::SocketAddr,
+	sync::{Arc, Mutex},
+};
+use substrate_stellar_sdk::{
+	compound_types::{LimitedString, UnlimitedVarOpaque},
+	types::{
+		DontHave, EnvelopeType, ErrorCode, MessageType, ScpBallot, ScpEnvelope, ScpQuorumSet,
+		ScpStatement, ScpStatementExternalize, ScpStatementPledges, Signature, StellarMessage,
+		Uint256,
+	},
+	SecretKey, XdrCodec,
+};
+use tokio::{
+	net::{TcpListener, TcpStream},
+	task::JoinSet,
+};
+use tracing::{error, info, warn};
+
+use crate::{
+	config::{ConnectionEndpoint, NodeInfoCfg},
+	connection::{quorum_set_hash, ConnectionInfo, Connector},
+	node::NodeInfo,
+	Error, StellarOverlayConfig,
+};
+
+type Responder = Arc<dyn Fn(&StellarMessage) -> Vec<StellarMessage> + Send + Sync>;
+
+/// What the `MockStellarPeer` sends to the peers connected to it
+#[derive(Clone, Default)]
+pub struct MockPeerScript {
+	/// sent as soon as the handshake is completed
+	after_handshake: Vec<StellarMessage>,
+	/// every responder is asked for the replies to a received message
+	responders: Vec<Responder>,
+}
+
+impl MockPeerScript {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Sends the messages as soon as the handshake with the peer is completed,
+	/// e.g. `ScpMessage`s or an `ErrorMsg`.
+	pub fn send_after_handshake(mut self, msgs: impl IntoIterator<Item = StellarMessage>) -> Self {
+		self.after_handshake.extend(msgs);
+		self
+	}
+
+	/// Replies to a `GetTxSet` of the given hash with the tx set.
+	/// The `tx_set` is either a `StellarMessage::TxSet` or a `StellarMessage::GeneralizedTxSet`.
+	pub fn reply_to_get_tx_set(self, tx_set_hash: Uint256, tx_set: StellarMessage) -> Self {
+		self.reply_with(move |msg| match msg {
+			StellarMessage::GetTxSet(hash) if *hash == tx_set_hash => vec![tx_set.clone()],
+			_ => vec![],
+		})
+	}
+
+	/// Replies to every `GetScpState` with the envelopes.
+	pub fn reply_to_get_scp_state(self, envelopes: Vec<ScpEnvelope>) -> Self {
+		self.reply_with(move |msg| match msg {
+			StellarMessage::GetScpState(_) =>
+				envelopes.iter().cloned().map(StellarMessage::ScpMessage).collect(),
+			_ => vec![],
+		})
+	}
+
+	/// Replies to a received message with whatever the `responder` returns.
+	/// Returning an empty list means no reply.
+	pub fn reply_with(
+		mut self,
+		responder: impl Fn(&StellarMessage) -> Vec<StellarMessage> + Send + Sync + 'static,
+	) -> Self {
+		self.responders.push(Arc::new(responder));
+		self
+	}
+
+	fn replies_to(&self, msg: &StellarMessage) -> Vec<StellarMessage> {
+		let replies: Vec<_> = self.responders.iter().flat_map(|responder| responder(msg)).collect();
+
+		match msg {
+			// like stellar-core, let the peer know that we do not have the tx set
+			StellarMessage::GetTxSet(hash) if replies.is_empty() =>
+				vec![StellarMessage::DontHave(DontHave {
+					type_: MessageType::TxSet,
+					req_hash: *hash,
+				})],
+			_ => replies,
+		}
+	}
+}
+
+/// Returns the `NodeInfo` of a node on the test network, for the mock peer and its peers
+pub fn test_node_info() -> NodeInfo {
+	NodeInfo::new(&NodeInfoCfg {
+		ledger_version: 22,
+		overlay_version: 35,
+		overlay_min_version: 33,
+		version_str: b"stellar-relay-lib test".to_vec(),
+		is_pub_net: false,
+	})
+}
+
+/// Returns the `ConnectionInfo` for connecting to the given port of localhost, with a random
+/// secret key
+pub fn test_conn_info(port: u32) -> ConnectionInfo {
+	let secret_key = SecretKey::from_binary(rand::random());
+	ConnectionInfo::new_with_timeout("127.0.0.1", port, secret_key, 0, false, true, false, 10)
+}
+
+/// Returns an externalize envelope of the value, signed by the validator for the network of
+/// `test_node_info()`
+pub fn signed_externalize(
+	validator: &SecretKey,
+	slot: u64,
+	value: u8,
+	qset: &ScpQuorumSet,
+) -> ScpEnvelope {
+	let statement = ScpStatement {
+		node_id: validator.get_public().clone(),
+		slot_index: slot,
+		pledges: ScpStatementPledges::ScpStExternalize(ScpStatementExternalize {
+			commit: ScpBallot {
+				counter: 1,
+				value: UnlimitedVarOpaque::new(vec![value; 32]).expect("should create a value"),
+			},
+			n_h: 1,
+			commit_quorum_set_hash: quorum_set_hash(qset),
+		}),
+	};
+
+	let mut raw_data = test_node_info().network_id.to_vec();
+	raw_data.append(&mut EnvelopeType::EnvelopeTypeScp.to_xdr());
+	raw_data.append(&mut statement.to_xdr());
+	let signature = Signature::new(validator.create_signature(raw_data).to_vec())
+		.expect("should create a signature");
+
+	ScpEnvelope { statement, signature }
+}
+
+/// Returns an `ErrorMsg`, as stellar-core sends it
+pub fn error_message(code: ErrorCode, msg: &str) -> StellarMessage {
+	StellarMessage::ErrorMsg(substrate_stellar_sdk::types::Error {
+		code,
+		msg: LimitedString::new(msg.as_bytes().to_vec())
+			.expect("should return a valid LimitedString"),
+	})
+}
+
+/// A stellar-core peer running in-process, to test against localhost instead of a Stellar Node.
+/// It completes the handshake of every peer that connects to it, and then follows its
+/// `MockPeerScript`.
+pub struct MockStellarPeer {
+	local_addr: SocketAddr,
+	/// the messages that the connected peers sent, apart from the handshake
+	received_messages: Arc<Mutex<Vec<StellarMessage>>>,
+	/// accepts the peers; aborting it disconnects every peer
+	accept_task: tokio::task::JoinHandle<()>,
+}
+
+impl MockStellarPeer {
+	/// Listens on a free port of localhost, and serves every peer that connects with the `script`.
+	pub async fn start(local_node_info: NodeInfo, script: MockPeerScript) -> Result<Self, Error> {
+		let secret_key = SecretKey::from_binary(rand::random());
+		let conn_info =
+			ConnectionInfo::new_with_timeout("127.0.0.1", 0, secret_key, 0, true, true, true, 10);
+
+		let tcp_listener = TcpListener::bind(conn_info.address())
+			.await
+			.map_err(|e| Error::ConnectionFailed(e.to_string()))?;
+		let local_addr =
+			tcp_listener.local_addr().map_err(|e| Error::ConnectionFailed(e.to_string()))?;
+
+		info!("start(): mock Stellar peer listening on {local_addr}");
+
+		let received_messages = Arc::new(Mutex::new(vec![]));
+		let accept_task = tokio::spawn(accept_peers(
+			tcp_listener,
+			local_node_info,
+			conn_info,
+			Arc::new(script),
+			received_messages.clone(),
+		));
+
+		Ok(MockStellarPeer { local_addr, received_messages, accept_task })
+	}
+
+	pub fn local_addr(&self) -> SocketAddr {
+		self.local_addr
+	}
+
+	/// Returns the `ConnectionInfo` for connecting to this mock peer
+	pub fn conn_info(&self) -> ConnectionInfo {
+		test_conn_info(u32::from(self.local_addr.port()))
+	}
+
+	/// Returns the config with this mock peer as its only endpoint
+	pub fn overlay_config(&self, mut cfg: StellarOverlayConfig) -> StellarOverlayConfig {
+		cfg.connection_info.endpoints = vec![ConnectionEndpoint {
+			address: self.local_addr.ip().to_string().into_bytes(),
+			port: u32::from(self.local_addr.port()),
+		}];
+		cfg
+	}
+
+	/// Returns the messages that the connected peers sent so far, apart from the handshake
+	pub fn received_messages(&self) -> Vec<StellarMessage> {
+		self.received_messages.lock().expect("should not be poisoned").clone()
+	}
+
+	pub fn stop(&self) {
+		self.accept_task.abort();
+	}
+}
+
+impl Drop for MockStellarPeer {
+	fn drop(&mut self) {
+		self.stop();
+	}
+}
+
+async fn accept_peers(
+	tcp_listener: TcpListener,
+	local_node_info: NodeInfo,
+	conn_info: ConnectionInfo,
+	script: Arc<MockPeerScript>,
+	received_messages: Arc<Mutex<Vec<StellarMessage>>>,
+) {
+	// dropping the set, when this task is aborted, disconnects every peer
+	let mut peers = JoinSet::new();
+
+	loop {
+		let (tcp_stream, peer_addr) = match tcp_listener.accept().await {
+			Ok(accepted) => accepted,
+			Err(e) => {
+				error!("accept_peers(): failed to accept a peer: {e:?}");
+				continue
+			},
+		};
+		info!("accept_peers(): {peer_addr} connected");
+
+		let local_node_info = local_node_info.clone();
+		let conn_info = conn_info.clone();
+		let script = script.clone();
+		let received_messages = received_messages.clone();
+		peers.spawn(async move {
+			if let Err(e) =
+				serve_peer(local_node_info, conn_info, tcp_stream, script, received_messages).await
+			{
+				warn!("accept_peers(): stopped serving {peer_addr}: {e:?}");
+			}
+		});
+	}
+}
+
+/// Completes the handshake with the peer, and follows the script afterwards
+async fn serve_peer(
+	local_node_info: NodeInfo,
+	conn_info: ConnectionInfo,
+	tcp_stream: TcpStream,
+	script: Arc<MockPeerScript>,
+	received_messages: Arc<Mutex<Vec<StellarMessage>>>,
+) -> Result<(), Error> {
+	let mut connector = Connector::accept(local_node_info, conn_info, tcp_stream)?;
+	let mut sent_after_handshake = false;
+
+	loop {
+		// the peer disconnected
+		let Ok(frame) = connector.read_frame().await else { return Ok(()) };
+
+		if let Some(msg) = connector.process_raw_message(&frame).await? {
+			if !matches!(msg, StellarMessage::Hello(_)) {
+				for reply in script.replies_to(&msg) {
+					connector.send_to_node(reply).await?;
+				}
+				received_messages.lock().expect("should not be poisoned").push(msg);
+			}
+		}
+
+		if connector.is_handshake_completed() && !sent_after_handshake {
+			sent_after_handshake = true;
+			for msg in script.after_handshake.iter().cloned() {
+				connector.send_to_node(msg).await?;
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::{tx_flood_hash, ConnectionEvent, StellarOverlayConnection};
+	use std::time::Duration;
+	use substrate_stellar_sdk::{
+		compound_types::LimitedVarArray,
+		types::{FloodDemand, Preconditions},
+		PublicKey, Transaction,
+	};
+
+	async fn connect_to(mock_peer: &MockStellarPeer) -> StellarOverlayConnection {
+		let mut overlay_conn =
+			StellarOverlayConnection::connect(test_node_info(), mock_peer.conn_info())
+				.await
+				.expect("should connect to the mock peer");
+
+		let msg = overlay_conn.listen().await.expect("should be alive");
+		assert!(matches!(msg, Some(StellarMessage::Hello(_))), "expected Hello, got {msg:?}");
+
+		overlay_conn
+	}
+
+	async fn next_message(overlay_conn: &mut StellarOverlayConnection) -> StellarMessage {
+		overlay_conn.listen().await.expect("should be alive").expect("should return a message")
+	}
+
+	#[tokio::test(flavor = "multi_thread")]
+	async fn mock_peer_sends_scripted_messages_after_handshake() {
+		let script = MockPeerScript::new()
+			.send_after_handshake(vec![error_message(ErrorCode::ErrLoad, "too busy")])
+			.reply_with(|msg| match msg {
+				StellarMessage::GetScpQuorumset(_) => vec![StellarMessage::GetScpState(8)],
+				_ => vec![],
+			});
+		let mock_peer = MockStellarPeer::start(test_node_info(), script)
+			.await
+			.expect("should start the mock peer");
+
+		let mut overlay_conn = connect_to(&mock_peer).await;
+		let mut events = overlay_conn.take_event_receiver().expect("should return the receiver");
+
+		assert!(matches!(events.recv().await, Some(ConnectionEvent::HandshakeCompleted { .. })));
+		assert!(matches!(
+			events.recv().await,
+			Some(ConnectionEvent::PeerError { code: ErrorCode::ErrLoad, .. })
+		));
+		assert_eq!(
+			next_message(&mut overlay_conn).await,
+			error_message(ErrorCode::ErrLoad, "too busy")
+		);
+
+		// an `ErrLoad` leaves the connection open
+		overlay_conn
+			.send_to_node(StellarMessage::GetScpQuorumset([2; 32]))
+			.await
+			.expect("should send");
+		assert_eq!(next_message(&mut overlay_conn).await, StellarMessage::GetScpState(8));
+		assert!(events.try_recv().is_err());
+	}
+
+	#[tokio::test(flavor = "multi_thread")]
+	async fn config_errors_close_the_connection() {
+		let script = MockPeerScript::new()
+			.send_after_handshake(vec![error_message(ErrorCode::ErrConf, "wrong network")]);
+		let mock_peer = MockStellarPeer::start(test_node_info(), script)
+			.await
+			.expect("should start the mock peer");
+
+		let mut overlay_conn = connect_to(&mock_peer).await;
+		let mut events = overlay_conn.take_event_receiver().expect("should return the receiver");
+
+		assert!(matches!(events.recv().await, Some(ConnectionEvent::HandshakeCompleted { .. })));
+		assert!(matches!(
+			events.recv().await,
+			Some(ConnectionEvent::PeerError { code: ErrorCode::ErrConf, .. })
+		));
+		assert!(matches!(events.recv().await, Some(ConnectionEvent::Closed { .. })));
+
+		assert!(matches!(overlay_conn.listen().await, Ok(None) | Err(Error::Disconnected)));
+	}
+
+	#[tokio::test(flavor = "multi_thread")]
+	async fn mock_peer_replies_to_requests() {
+		let tx_set_hash = [1; 32];
+		// any message will do as the reply
+		let script = MockPeerScript::new()
+			.reply_to_get_tx_set(tx_set_hash, StellarMessage::GetScpState(7))
+			.reply_with(|msg| match msg {
+				StellarMessage::GetScpQuorumset(_) => vec![StellarMessage::GetScpState(8)],
+				_ => vec![],
+			});
+		let mock_peer = MockStellarPeer::start(test_node_info(), script)
+			.await
+			.expect("should start the mock peer");
+
+		let mut overlay_conn = connect_to(&mock_peer).await;
+
+		overlay_conn
+			.send_to_node(StellarMessage::GetTxSet(tx_set_hash))
+			.await
+			.expect("should send");
+		assert_eq!(next_message(&mut overlay_conn).await, StellarMessage::GetScpState(7));
+
+		overlay_conn
+			.send_to_node(StellarMessage::GetScpQuorumset([2; 32]))
+			.await
+			.expect("should send");
+		assert_eq!(next_message(&mut overlay_conn).await, StellarMessage::GetScpState(8));
+
+		// unknown tx sets are answered with a `DontHave`
+		overlay_conn.send_to_node(StellarMessage::GetTxSet([3; 32])).await.expect("should send");
+		assert_eq!(
+			next_message(&mut overlay_conn).await,
+			StellarMessage::DontHave(DontHave { type_: MessageType::TxSet, req_hash: [3; 32] })
+		);
+
+		let received = mock_peer.received_messages();
+		assert!(received.contains(&StellarMessage::GetTxSet(tx_set_hash)));
+		assert!(received.contains(&StellarMessage::GetScpQuorumset([2; 32])));
+	}
+
+	#[tokio::test(flavor = "multi_thread")]
+	async fn submitted_transaction_is_sent_on_demand() {
+		// like stellar-core, demand every advertised transaction
+		let script = MockPeerScript::new().reply_with(|msg| match msg {
+			StellarMessage::FloodAdvert(advert) => vec![StellarMessage::FloodDemand(FloodDemand {
+				tx_hashes: advert.tx_hashes.clone(),
+			})],
+			_ => vec![],
+		});
+		let mock_peer = MockStellarPeer::start(test_node_info(), script)
+			.await
+			.expect("should start the mock peer");
+
+		let mut overlay_conn = connect_to(&mock_peer).await;
+
+		let source = PublicKey::PublicKeyTypeEd25519([1; 32]);
+		let envelope = Transaction::new(source, 1, None, Preconditions::PrecondNone, None)
+			.expect("should create a transaction")
+			.into_transaction_envelope();
+
+		let hash = overlay_conn
+			.submit_transaction_via_overlay(envelope.clone())
+			.await
+			.expect("should advertise the transaction");
+		assert_eq!(hash, tx_flood_hash(&envelope));
+
+		// the demand is passed to the user as well
+		assert!(matches!(next_message(&mut overlay_conn).await, StellarMessage::FloodDemand(_)));
+
+		while !mock_peer
+			.received_messages()
+			.contains(&StellarMessage::Transaction(envelope.clone()))
+		{
+			tokio::time::sleep(Duration::from_millis(50)).await;
+		}
+	}
+
+	#[tokio::test(flavor = "multi_thread")]
+	async fn forged_envelopes_are_dropped_and_quorum_sets_are_requested() {
+		let validator = SecretKey::from_binary([1; 32]);
+		let qset = ScpQuorumSet {
+			threshold: 1,
+			validators: LimitedVarArray::new(vec![validator.get_public().clone()])
+				.expect("should create the validators"),
+			inner_sets: LimitedVarArray::new(vec![]).expect("should create the inner sets"),
+		};
+
+		let valid = signed_externalize(&validator, 10, 7, &qset);
+		let mut forged = valid.clone();
+		forged.statement.slot_index = 11;
+
+		let reply = StellarMessage::ScpQuorumset(qset.clone());
+		let script = MockPeerScript::new()
+			.send_after_handshake(vec![
+				StellarMessage::ScpMessage(forged),
+				StellarMessage::ScpMessage(valid.clone()),
+			])
+			.reply_with(move |msg| match msg {
+				StellarMessage::GetScpQuorumset(_) => vec![reply.clone()],
+				_ => vec![],
+			});
+		let mock_peer = MockStellarPeer::start(test_node_info(), script)
+			.await
+			.expect("should start the mock peer");
+
+		let mut overlay_conn = connect_to(&mock_peer).await;
+
+		// the forged envelope never reaches the user
+		assert_eq!(next_message(&mut overlay_conn).await, StellarMessage::ScpMessage(valid));
+		assert_eq!(
+			next_message(&mut overlay_conn).await,
+			StellarMessage::ScpQuorumset(qset.clone())
+		);
+		assert!(mock_peer
+			.received_messages()
+			.contains(&StellarMessage::GetScpQuorumset(quorum_set_hash(&qset))));
+
+		let externalized = overlay_conn.validate_externalize(10).expect("should have a quorum");
+		assert_eq!(externalized.envelopes.len(), 1);
+		assert!(overlay_conn.validate_externalize(11).is_err());
+	}
+}
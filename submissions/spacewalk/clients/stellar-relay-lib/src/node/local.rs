@@ -14,6 +14,11 @@ impl LocalInfo {
 		LocalInfo { sequence: 0, nonce: generate_random_nonce(), node, port: 11625 }
 	}
 
+	pub fn with_port(mut self, port: u32) -> Self {
+		self.port = port;
+		self
+	}
+
 	pub fn sequence(&self) -> u64 {
 		self.sequence
 	}
@@ -1,4 +1,8 @@
-use substrate_stellar_sdk::types::StellarMessage;
+use std::time::Duration;
+use substrate_stellar_sdk::{
+	types::{ScpEnvelope, StellarMessage, TransactionEnvelope, Uint256},
+	TransactionSetType,
+};
 use tokio::sync::{
 	mpsc,
 	mpsc::{error::SendError, Sender},
@@ -6,15 +10,27 @@ use tokio::sync::{
 use tracing::{error, info};
 
 use crate::{
-	connection::{poll_messages_from_stellar, ConnectionInfo, Connector},
+	connection::{
+		flood_adverts, poll_messages_from_stellar, replay_messages_from_recording, ConnectionEvent,
+		ConnectionInfo, Connector, ExternalizedSlot, MessageFilter, ReconnectPolicy, Reconnector,
+		RecordedFrame, ScpTracker, Subscriptions, TxFlooding, TxWatch,
+	},
 	node::NodeInfo,
-	Error,
+	Error, StellarOverlayRequester,
 };
 
 /// Used to send/receive messages to/from Stellar Node
 pub struct StellarOverlayConnection {
 	sender: Sender<StellarMessage>,
 	receiver: mpsc::Receiver<StellarMessage>,
+	/// receives the lifecycle events of the connection, until the user takes it
+	event_receiver: Option<mpsc::Receiver<ConnectionEvent>>,
+	/// the consumers that receive a filtered copy of the messages
+	subscriptions: Subscriptions,
+	/// the transactions to demand and to serve
+	tx_flooding: TxFlooding,
+	/// the quorum sets and the externalized slots of the verified envelopes
+	scp_tracker: ScpTracker,
 }
 
 impl StellarOverlayConnection {
@@ -33,12 +49,44 @@ impl StellarOverlayConnection {
 	) -> Result<Self, Error> {
 		info!("connect(): connecting to {conn_info:?}");
 
+		let connector = Connector::start(local_node_info, conn_info).await?;
+
+		Ok(Self::run(connector, None))
+	}
+
+	/// Returns an `StellarOverlayConnection` when a connection to Stellar Node is successful.
+	/// Whenever the connection is lost afterwards, it is restarted based on the given policy.
+	/// Check the `ConnectionEvent`s for the reconnection attempts.
+	pub async fn connect_with_reconnect_policy(
+		local_node_info: NodeInfo,
+		conn_info: ConnectionInfo,
+		policy: ReconnectPolicy,
+	) -> Result<Self, Error> {
+		info!("connect_with_reconnect_policy(): connecting to {conn_info:?} with {policy:?}");
+
+		let connector = Connector::start(local_node_info.clone(), conn_info.clone()).await?;
+		let reconnector = Reconnector::new(local_node_info, conn_info, policy);
+
+		Ok(Self::run(connector, Some(reconnector)))
+	}
+
+	/// Starts polling messages of the given `Connector` and returns the `StellarOverlayConnection`
+	/// to communicate with it.
+	pub(crate) fn run(connector: Connector, reconnector: Option<Reconnector>) -> Self {
 		// this is a channel to communicate with the user/caller.
-		let (send_to_user_sender, send_to_user_receiver) = mpsc::channel::<StellarMessage>(1024);
+		let (send_to_user_sender, send_to_user_receiver) = mpsc::channel::<StellarMessage>(
+			connector.flow_controller.config().user_channel_capacity,
+		);
 
 		let (send_to_node_sender, send_to_node_receiver) = mpsc::channel::<StellarMessage>(1024);
 
-		let connector = Connector::start(local_node_info, conn_info).await?;
+		let (event_sender, event_receiver) = mpsc::channel::<ConnectionEvent>(1024);
+
+		let subscriptions = Subscriptions::default();
+
+		let tx_flooding = TxFlooding::default();
+
+		let scp_tracker = ScpTracker::default();
 
 		#[cfg(tokio_unstable)]
 		tokio::task::Builder::new()
@@ -47,6 +95,11 @@ impl StellarOverlayConnection {
 				connector,
 				send_to_user_sender,
 				send_to_node_receiver,
+				event_sender,
+				reconnector,
+				subscriptions.clone(),
+				tx_flooding.clone(),
+				scp_tracker.clone(),
 			))
 			.expect("Failed to spawn poll_messages_from_stellar");
 
@@ -55,14 +108,183 @@ impl StellarOverlayConnection {
 			connector,
 			send_to_user_sender,
 			send_to_node_receiver,
+			event_sender,
+			reconnector,
+			subscriptions.clone(),
+			tx_flooding.clone(),
+			scp_tracker.clone(),
+		));
+
+		StellarOverlayConnection {
+			sender: send_to_node_sender,
+			receiver: send_to_user_receiver,
+			event_receiver: Some(event_receiver),
+			subscriptions,
+			tx_flooding,
+			scp_tracker,
+		}
+	}
+
+	/// Returns an `StellarOverlayConnection` that passes the messages of a recorded session to the
+	/// user, as if they came from the Stellar Node. No connection to a Stellar Node is made, and
+	/// messages sent to it are dropped.
+	/// Once the recording ends, `listen()` returns `Ok(None)`.
+	pub fn replay(
+		local_node_info: NodeInfo,
+		conn_info: ConnectionInfo,
+		frames: Vec<RecordedFrame>,
+	) -> Result<Self, Error> {
+		info!("replay(): replaying {} frames", frames.len());
+
+		let connector = Connector::replay(local_node_info, conn_info)?;
+
+		let (send_to_user_sender, send_to_user_receiver) = mpsc::channel::<StellarMessage>(1024);
+
+		let (send_to_node_sender, send_to_node_receiver) = mpsc::channel::<StellarMessage>(1024);
+
+		let (event_sender, event_receiver) = mpsc::channel::<ConnectionEvent>(1024);
+
+		let subscriptions = Subscriptions::default();
+
+		let scp_tracker = ScpTracker::default();
+
+		#[cfg(tokio_unstable)]
+		tokio::task::Builder::new()
+			.name("Replay Stellar Messages")
+			.spawn(replay_messages_from_recording(
+				connector,
+				frames,
+				send_to_user_sender,
+				send_to_node_receiver,
+				event_sender,
+				subscriptions.clone(),
+				scp_tracker.clone(),
+			))
+			.expect("Failed to spawn replay_messages_from_recording");
+
+		#[cfg(not(tokio_unstable))]
+		tokio::spawn(replay_messages_from_recording(
+			connector,
+			frames,
+			send_to_user_sender,
+			send_to_node_receiver,
+			event_sender,
+			subscriptions.clone(),
+			scp_tracker.clone(),
 		));
 
 		Ok(StellarOverlayConnection {
 			sender: send_to_node_sender,
 			receiver: send_to_user_receiver,
+			event_receiver: Some(event_receiver),
+			subscriptions,
+			// nothing is demanded or served in a replay
+			tx_flooding: TxFlooding::default(),
+			scp_tracker,
 		})
 	}
 
+	/// Waits until the handshake with the Stellar Node is completed.
+	/// The events received until then are not passed to the user.
+	pub(crate) async fn wait_for_handshake(&mut self, timeout: Duration) -> Result<(), Error> {
+		let Some(event_receiver) = self.event_receiver.as_mut() else {
+			return Err(Error::ConnectionFailed("the events were already taken".to_string()))
+		};
+
+		let wait = async {
+			while let Some(event) = event_receiver.recv().await {
+				match event {
+					ConnectionEvent::HandshakeCompleted { .. } => return Ok(()),
+					ConnectionEvent::Closed { reason } =>
+						return Err(Error::ConnectionFailed(reason)),
+					other => info!("wait_for_handshake(): {other:?}"),
+				}
+			}
+			Err(Error::Disconnected)
+		};
+
+		match tokio::time::timeout(timeout, wait).await {
+			Ok(result) => result,
+			Err(_) => Err(Error::Timeout),
+		}
+	}
+
+	/// Returns the receiver of the `ConnectionEvent`s, to listen to them separately from the
+	/// messages of the Stellar Node. Returns None if it was already taken.
+	pub fn take_event_receiver(&mut self) -> Option<mpsc::Receiver<ConnectionEvent>> {
+		self.event_receiver.take()
+	}
+
+	/// Returns a receiver of its own, for the messages that pass the `filter`. This way, several
+	/// consumers can share the connection. Dropping the receiver ends the subscription.
+	/// The messages are still passed to `listen()` as well.
+	///
+	/// # Arguments
+	/// * `filter` - selects the messages to receive
+	/// * `capacity` - the size of the channel; when it is full, the subscriber misses messages.
+	///   Must not be 0.
+	pub fn subscribe(
+		&self,
+		filter: MessageFilter,
+		capacity: usize,
+	) -> Result<mpsc::Receiver<StellarMessage>, Error> {
+		self.subscriptions.subscribe(filter, capacity)
+	}
+
+	/// Returns a requester that sends requests for data to the Stellar Node and waits for the
+	/// matching reply. Unlike the connection, it can be cloned and used while listening.
+	pub fn requester(&self) -> StellarOverlayRequester {
+		StellarOverlayRequester::for_node(self.sender.clone(), self.subscriptions.clone())
+	}
+
+	/// Asks the Stellar Node for the tx set of the given hash, and returns it once it arrives.
+	/// See `StellarOverlayRequester::get_tx_set`.
+	pub async fn get_tx_set(&self, tx_set_hash: Uint256) -> Result<TransactionSetType, Error> {
+		self.requester().get_tx_set(tx_set_hash).await
+	}
+
+	/// Asks the Stellar Node for the envelopes of the given slot, and returns them once they
+	/// arrive. See `StellarOverlayRequester::get_scp_state`.
+	pub async fn get_scp_state(&self, slot: u32) -> Result<Vec<ScpEnvelope>, Error> {
+		self.requester().get_scp_state(slot).await
+	}
+
+	/// Returns the value that the slot externalized, once the nodes that externalized it form a
+	/// quorum of their quorum sets. Only envelopes with a valid signature are taken into account,
+	/// and the quorum sets are requested from the Stellar Node as the envelopes arrive.
+	/// Requires the `ConnectionInfo` to receive scp messages.
+	pub fn validate_externalize(&self, slot: u64) -> Result<ExternalizedSlot, Error> {
+		self.scp_tracker.validate_externalize(slot)
+	}
+
+	/// Demands the transactions that the Stellar Node advertises, and passes the ones that match
+	/// the `watch` to the user. Advertised transactions are not demanded by default.
+	/// Requires the `ConnectionInfo` to receive tx messages.
+	pub fn watch_transactions(&self, watch: TxWatch) {
+		self.tx_flooding.watch(Some(watch));
+	}
+
+	/// Stops demanding the transactions that the Stellar Node advertises
+	pub fn stop_watching_transactions(&self) {
+		self.tx_flooding.watch(None);
+	}
+
+	/// Submits the transaction to the network through the Stellar Node instead of Horizon:
+	/// it is advertised with a `FloodAdvert`, and sent once the Stellar Node demands it.
+	/// Returns the hash it was advertised with.
+	pub async fn submit_transaction_via_overlay(
+		&self,
+		envelope: TransactionEnvelope,
+	) -> Result<Uint256, Error> {
+		let hash = self.tx_flooding.submit(envelope);
+		info!("submit_transaction_via_overlay(): advertising transaction {}", hex::encode(hash));
+
+		for advert in flood_adverts(&[hash])? {
+			self.sender.send(advert).await?;
+		}
+		Ok(hash)
+	}
+
 	/// Listens for upcoming messages from Stellar Node via a receiver.
 	/// The sender pair can be found in [fn
 	/// poll_messages_from_stellar](../src/connection/connector/message_reader.rs)
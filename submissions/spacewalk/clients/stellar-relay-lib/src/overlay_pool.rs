@@ -0,0 +1,808 @@
+use rand::seq::SliceRandom;
+use sha2::{Digest, Sha256};
+use std::{
+	collections::{HashMap, HashSet},
+	time::Duration,
+};
+use substrate_stellar_sdk::{
+	types::{PeerAddress, ScpEnvelope, StellarMessage, Uint256},
+	TransactionSetType, XdrCodec,
+};
+use tokio::{
+	sync::{
+		mpsc,
+		mpsc::{error::SendError, Sender},
+	},
+	time::{interval, sleep, Instant},
+};
+use tracing::{error, info, warn};
+
+use crate::{
+	connection::{helper::RecentHashes, ConnectionEvent, ConnectionInfo, Subscriptions},
+	node::NodeInfo,
+	peer_book::{PeerBook, PEER_BOOK_SAVE_INTERVAL},
+	requester::PeerRequest,
+	Error, StellarOverlayConnection, StellarOverlayRequester,
+};
+
+/// The number of message hashes remembered, to filter out duplicates sent by different peers.
+const DEDUP_CACHE_SIZE: usize = 4096;
+
+/// The waiting time before connecting to a replacement of a disconnected peer.
+const PEER_RECONNECT_DELAY_IN_SECS: u64 = 5;
+
+/// The rank of a candidate that is not in the peer book; it comes after all others.
+const UNRANKED: (u32, bool, u64) = (u32::MAX, true, u64::MAX);
+
+type PeerId = u64;
+type MessageHash = [u8; 32];
+
+/// Used to send/receive messages to/from multiple Stellar Nodes at once.
+///
+/// Messages of all peers are merged into one receiver; duplicate `ScpMessage`s and tx sets are
+/// only passed to the user once. Requests for data (`GetTxSet`, `GetScpState`,
+/// `GetScpQuorumset`) are sent to the healthiest peer, everything else is sent to all peers.
+/// A disconnected peer is replaced by another endpoint, without interrupting the user.
+/// With a `PeerBook`, the endpoints grow with the peers that the Stellar Nodes tell us about.
+pub struct StellarOverlayPool {
+	sender: Sender<StellarMessage>,
+	receiver: mpsc::Receiver<StellarMessage>,
+	/// asks the supervisor for a peer to send a request to
+	peer_request_sender: Sender<PeerRequest>,
+	/// receive the replies to the requests, including duplicates
+	subscriptions: Subscriptions,
+}
+
+impl StellarOverlayPool {
+	pub fn sender(&self) -> Sender<StellarMessage> {
+		self.sender.clone()
+	}
+
+	pub async fn send_to_node(&self, msg: StellarMessage) -> Result<(), SendError<StellarMessage>> {
+		self.sender.send(msg).await
+	}
+
+	/// Returns an `StellarOverlayPool` when at least one connection to a Stellar Node is
+	/// successful.
+	///
+	/// # Arguments
+	/// * `local_node_info` - the info of this node, shared by all connections
+	/// * `conn_infos` - the candidate endpoints to connect to
+	/// * `pool_size` - the number of peers to keep connected simultaneously
+	pub async fn connect(
+		local_node_info: NodeInfo,
+		conn_infos: Vec<ConnectionInfo>,
+		pool_size: usize,
+	) -> Result<Self, Error> {
+		Self::start(local_node_info, conn_infos, pool_size, None).await
+	}
+
+	/// Like `connect`, but the given endpoints are only the seeds: the peers known to the peer book
+	/// are candidates too, and so are the peers that the Stellar Nodes send us in `Peers`
+	/// messages. The candidates that were the most stable and the fastest are tried first.
+	/// The peer book is updated with the outcome of every connection.
+	pub async fn connect_with_peer_book(
+		local_node_info: NodeInfo,
+		seeds: Vec<ConnectionInfo>,
+		pool_size: usize,
+		peer_book: PeerBook,
+	) -> Result<Self, Error> {
+		Self::start(local_node_info, seeds, pool_size, Some(peer_book)).await
+	}
+
+	async fn start(
+		local_node_info: NodeInfo,
+		conn_infos: Vec<ConnectionInfo>,
+		pool_size: usize,
+		mut peer_book: Option<PeerBook>,
+	) -> Result<Self, Error> {
+		let Some(candidate_template) = conn_infos.first().cloned() else {
+			return Err(Error::ConfigError(
+				"No endpoints found in config for connecting to overlay".to_string(),
+			))
+		};
+
+		let candidates = match peer_book.as_mut() {
+			Some(peer_book) => ranked_candidates(&candidate_template, conn_infos, peer_book),
+			None => {
+				let mut candidates = conn_infos;
+				candidates.shuffle(&mut rand::thread_rng());
+				candidates
+			},
+		};
+
+		// with a peer book, the candidates grow once the peers are asked for more
+		let pool_size = match peer_book {
+			Some(_) => pool_size.max(1),
+			None => pool_size.clamp(1, candidates.len()),
+		};
+		info!("connect(): connecting to {pool_size} peers out of {} endpoints", candidates.len());
+
+		let mut connections = vec![];
+		let mut last_error = None;
+		for conn_info in candidates.iter() {
+			if connections.len() == pool_size {
+				break
+			}
+
+			let started = Instant::now();
+			let result = StellarOverlayConnection::connect(
+				local_node_info.clone(),
+				conn_info.with_recording_of_endpoint(),
+			)
+			.await;
+			record_connection(&mut peer_book, conn_info, &result, started.elapsed());
+
+			match result {
+				Ok(conn) => connections.push((conn_info.clone(), conn)),
+				Err(e) => {
+					warn!("connect(): failed to connect to {}: {e:?}", conn_info.address());
+					last_error = Some(e);
+				},
+			}
+		}
+
+		if connections.is_empty() {
+			return Err(last_error.unwrap_or(Error::Disconnected))
+		}
+
+		// this is a channel to communicate with the user/caller.
+		let (send_to_user_sender, send_to_user_receiver) = mpsc::channel::<StellarMessage>(1024);
+
+		let (send_to_node_sender, send_to_node_receiver) = mpsc::channel::<StellarMessage>(1024);
+
+		let (peer_event_sender, peer_event_receiver) = mpsc::channel::<PeerEvent>(1024);
+
+		let (peer_request_sender, peer_request_receiver) = mpsc::channel::<PeerRequest>(1024);
+
+		let subscriptions = Subscriptions::default();
+
+		let mut supervisor = PoolSupervisor {
+			local_node_info,
+			candidates,
+			pool_size,
+			peers: HashMap::new(),
+			pending_connections: vec![],
+			consecutive_failures: 0,
+			next_peer_id: 0,
+			seen_messages: RecentHashes::new(DEDUP_CACHE_SIZE),
+			peer_event_sender,
+			subscriptions: subscriptions.clone(),
+			peer_book,
+			candidate_template,
+		};
+
+		for (conn_info, conn) in connections {
+			supervisor.add_peer(conn_info, conn);
+		}
+
+		// fill up the missing slots in the background
+		for _ in supervisor.peers.len()..pool_size {
+			supervisor.spawn_connect(Duration::ZERO);
+		}
+
+		#[cfg(tokio_unstable)]
+		tokio::task::Builder::new()
+			.name("Supervise Stellar Overlay Pool")
+			.spawn(supervisor.run(
+				send_to_user_sender,
+				send_to_node_receiver,
+				peer_event_receiver,
+				peer_request_receiver,
+			))
+			.expect("Failed to spawn the overlay pool supervisor");
+
+		#[cfg(not(tokio_unstable))]
+		tokio::spawn(supervisor.run(
+			send_to_user_sender,
+			send_to_node_receiver,
+			peer_event_receiver,
+			peer_request_receiver,
+		));
+
+		Ok(StellarOverlayPool {
+			sender: send_to_node_sender,
+			receiver: send_to_user_receiver,
+			peer_request_sender,
+			subscriptions,
+		})
+	}
+
+	/// Returns a requester that sends requests for data to the healthiest peer and waits for the
+	/// matching reply. A request that failed is sent to a peer that was not asked yet.
+	pub fn requester(&self) -> StellarOverlayRequester {
+		StellarOverlayRequester::for_pool(
+			self.peer_request_sender.clone(),
+			self.subscriptions.clone(),
+		)
+	}
+
+	/// Asks the peers for the tx set of the given hash, and returns it once it arrives.
+	/// See `StellarOverlayRequester::get_tx_set`.
+	pub async fn get_tx_set(&self, tx_set_hash: Uint256) -> Result<TransactionSetType, Error> {
+		self.requester().get_tx_set(tx_set_hash).await
+	}
+
+	/// Asks the peers for the envelopes of the given slot, and returns them once they arrive.
+	/// See `StellarOverlayRequester::get_scp_state`.
+	pub async fn get_scp_state(&self, slot: u32) -> Result<Vec<ScpEnvelope>, Error> {
+		self.requester().get_scp_state(slot).await
+	}
+
+	/// Listens for upcoming messages from all connected Stellar Nodes via a receiver.
+	pub async fn listen(&mut self) -> Result<Option<StellarMessage>, Error> {
+		if !self.is_alive() {
+			error!("listen(): sender half of overlay pool has closed.");
+			return Err(Error::Disconnected)
+		}
+
+		Ok(self.receiver.recv().await)
+	}
+
+	pub fn is_alive(&mut self) -> bool {
+		let is_closed = self.sender.is_closed();
+
+		if is_closed {
+			self.stop();
+		}
+
+		!is_closed
+	}
+
+	pub fn stop(&mut self) {
+		info!("stop(): closing all connections of the overlay pool");
+		self.receiver.close();
+	}
+}
+
+impl Drop for StellarOverlayPool {
+	fn drop(&mut self) {
+		self.stop();
+	}
+}
+
+/// Events sent by the tasks of each peer to the supervisor
+enum PeerEvent {
+	/// the result of connecting, and how long it took
+	Connected(ConnectionInfo, Result<StellarOverlayConnection, Error>, Duration),
+	Message(PeerId, StellarMessage),
+	Disconnected(PeerId),
+}
+
+struct Peer {
+	address: String,
+	conn_info: ConnectionInfo,
+	sender: Sender<StellarMessage>,
+	last_message_time: Option<Instant>,
+}
+
+/// Owns the senders of all peers and decides where messages go.
+struct PoolSupervisor {
+	local_node_info: NodeInfo,
+	candidates: Vec<ConnectionInfo>,
+	pool_size: usize,
+
+	peers: HashMap<PeerId, Peer>,
+	/// the addresses of the connection attempts that are still in progress
+	pending_connections: Vec<String>,
+	/// number of connection attempts that failed in a row
+	consecutive_failures: usize,
+	next_peer_id: PeerId,
+
+	seen_messages: RecentHashes,
+	peer_event_sender: mpsc::Sender<PeerEvent>,
+	/// the replies to requests are passed here before filtering out duplicates, since the
+	/// request may have been sent after the first copy was passed to the user.
+	subscriptions: Subscriptions,
+
+	/// keeps track of the candidates, if the pool discovers peers
+	peer_book: Option<PeerBook>,
+	/// the info that connections to discovered peers are made with
+	candidate_template: ConnectionInfo,
+}
+
+impl PoolSupervisor {
+	async fn run(
+		mut self,
+		send_to_user_sender: mpsc::Sender<StellarMessage>,
+		mut send_to_node_receiver: mpsc::Receiver<StellarMessage>,
+		mut peer_event_receiver: mpsc::Receiver<PeerEvent>,
+		mut peer_request_receiver: mpsc::Receiver<PeerRequest>,
+	) {
+		info!("run(): overlay pool supervisor started.");
+
+		let mut save_interval = interval(PEER_BOOK_SAVE_INTERVAL);
+		loop {
+			tokio::select! {
+				msg = send_to_node_receiver.recv() => match msg {
+					Some(msg) => self.route_to_peers(msg).await,
+					// the user has dropped the pool
+					None => break,
+				},
+				Some(event) = peer_event_receiver.recv() => {
+					if !self.handle_peer_event(event, &send_to_user_sender).await {
+						break
+					}
+				},
+				Some(request) = peer_request_receiver.recv() => {
+					let peer = self
+						.healthiest_peer_except(&request.tried_peers)
+						.map(|peer| (peer.address.clone(), peer.sender.clone()));
+					// the requester may have given up already
+					let _ = request.reply.send(peer);
+				},
+				_ = save_interval.tick() => {
+					if let Some(peer_book) = self.peer_book.as_mut() {
+						peer_book.save_in_background();
+					}
+				},
+			}
+
+			if send_to_user_sender.is_closed() {
+				info!("run(): closing supervisor during disconnection");
+				break
+			}
+		}
+
+		// dropping the senders of each peer will stop its connection
+		self.peers.clear();
+		send_to_node_receiver.close();
+
+		if let Some(peer_book) = self.peer_book.as_mut() {
+			peer_book.save_in_background();
+		}
+
+		info!("run(): overlay pool supervisor stopped.");
+	}
+
+	/// Returns false if the pool cannot continue anymore
+	async fn handle_peer_event(
+		&mut self,
+		event: PeerEvent,
+		send_to_user_sender: &mpsc::Sender<StellarMessage>,
+	) -> bool {
+		match event {
+			PeerEvent::Connected(conn_info, result, latency) => {
+				if let Some(index) = self
+					.pending_connections
+					.iter()
+					.position(|address| *address == conn_info.address())
+				{
+					self.pending_connections.swap_remove(index);
+				}
+				record_connection(&mut self.peer_book, &conn_info, &result, latency);
+				match result {
+					Ok(conn) => {
+						self.consecutive_failures = 0;
+						self.add_peer(conn_info, conn);
+					},
+					Err(e) => {
+						warn!(
+							"handle_peer_event(): failed to connect to {}: {e:?}",
+							conn_info.address()
+						);
+						self.consecutive_failures += 1;
+						self.spawn_connect(Duration::from_secs(PEER_RECONNECT_DELAY_IN_SECS));
+					},
+				}
+			},
+			PeerEvent::Message(peer_id, msg) => {
+				if let Some(peer) = self.peers.get_mut(&peer_id) {
+					peer.last_message_time = Some(Instant::now());
+				}
+
+				match &msg {
+					StellarMessage::Hello(hello) =>
+						if let (Some(peer_book), Some(peer)) =
+							(self.peer_book.as_mut(), self.peers.get(&peer_id))
+						{
+							let (address, port) = peer.conn_info.endpoint();
+							peer_book.record_overlay_version(address, port, hello.overlay_version);
+						},
+					StellarMessage::Peers(peers) => self.add_discovered_peers(peers.get_vec()),
+					_ => {},
+				}
+
+				self.subscriptions.publish(&msg);
+
+				if let Some(hash) = dedup_hash(&msg) {
+					if !self.seen_messages.insert(hash) {
+						return true
+					}
+				}
+
+				if let Err(e) = send_to_user_sender.send(msg).await {
+					warn!(
+						"handle_peer_event(): Error occurred during sending message to user: {e:?}"
+					);
+				}
+			},
+			PeerEvent::Disconnected(peer_id) => {
+				if let Some(peer) = self.peers.remove(&peer_id) {
+					warn!("handle_peer_event(): peer {} disconnected; replacing it", peer.address);
+					if let Some(peer_book) = self.peer_book.as_mut() {
+						let (address, port) = peer.conn_info.endpoint();
+						peer_book.record_failure(address, port);
+					}
+				}
+				self.spawn_connect(Duration::from_secs(PEER_RECONNECT_DELAY_IN_SECS));
+			},
+		}
+
+		// every candidate failed, and there is no one left to talk to.
+		if self.peers.is_empty() && self.consecutive_failures >= self.candidates.len() {
+			error!("handle_peer_event(): failed to connect to any of the endpoints");
+			return false
+		}
+
+		true
+	}
+
+	/// Sends requests for data to the healthiest peer; everything else to all peers.
+	async fn route_to_peers(&self, msg: StellarMessage) {
+		match msg {
+			StellarMessage::GetTxSet(_) |
+			StellarMessage::GetScpState(_) |
+			StellarMessage::GetScpQuorumset(_) => match self.healthiest_peer() {
+				Some(peer) =>
+					if let Err(e) = peer.sender.send(msg).await {
+						warn!(
+							"route_to_peers(): failed to send message to {}: {e:?}",
+							peer.address
+						);
+					},
+				None => warn!("route_to_peers(): no connected peer to send the message to"),
+			},
+			msg =>
+				for peer in self.peers.values() {
+					if let Err(e) = peer.sender.send(msg.clone()).await {
+						warn!(
+							"route_to_peers(): failed to send message to {}: {e:?}",
+							peer.address
+						);
+					}
+				},
+		}
+	}
+
+	/// The peer that most recently sent us a message
+	fn healthiest_peer(&self) -> Option<&Peer> {
+		self.peers.values().max_by_key(|peer| peer.last_message_time)
+	}
+
+	/// The healthiest peer that is not one of the given addresses.
+	/// Falls back to the healthiest of all, if every peer was given.
+	fn healthiest_peer_except(&self, addresses: &[String]) -> Option<&Peer> {
+		self.peers
+			.values()
+			.filter(|peer| !addresses.contains(&peer.address))
+			.max_by_key(|peer| peer.last_message_time)
+			.or_else(|| self.healthiest_peer())
+	}
+
+	fn add_peer(&mut self, conn_info: ConnectionInfo, conn: StellarOverlayConnection) {
+		let peer_id = self.next_peer_id;
+		self.next_peer_id += 1;
+
+		info!("add_peer(): connected to {}", conn_info.address());
+
+		// ask for more peers, to discover candidates
+		if self.peer_book.is_some() {
+			if let Err(e) = conn.sender().try_send(StellarMessage::GetPeers) {
+				warn!("add_peer(): failed to ask {} for peers: {e:?}", conn_info.address());
+			}
+		}
+
+		self.peers.insert(
+			peer_id,
+			Peer {
+				address: conn_info.address(),
+				conn_info,
+				sender: conn.sender(),
+				last_message_time: None,
+			},
+		);
+
+		tokio::spawn(forward_peer_messages(peer_id, conn, self.peer_event_sender.clone()));
+	}
+
+	/// Adds the peers that a Stellar Node told us about to the candidates
+	fn add_discovered_peers(&mut self, peers: &[PeerAddress]) {
+		let Some(peer_book) = self.peer_book.as_mut() else { return };
+
+		let added = peer_book.add_discovered(peers);
+		if !added.is_empty() {
+			// the peers that the book dropped to make room are no candidates anymore
+			self.candidates.retain(|conn_info| {
+				let (address, port) = conn_info.endpoint();
+				peer_book.get(address, port).is_some()
+			});
+		}
+		for (address, port) in added {
+			self.candidates.push(self.candidate_template.with_endpoint(&address, port));
+		}
+
+		// fill up the slots that had no candidate left
+		for _ in self.peers.len() + self.pending_connections.len()..self.pool_size {
+			self.spawn_connect(Duration::ZERO);
+		}
+	}
+
+	/// Connects to an endpoint that is not yet in use, after the given delay.
+	/// With a peer book, that is the best ranked endpoint; otherwise a random one.
+	fn spawn_connect(&mut self, delay: Duration) {
+		if self.peers.len() + self.pending_connections.len() >= self.pool_size {
+			return
+		}
+
+		let in_use: HashSet<String> = self
+			.peers
+			.values()
+			.map(|peer| peer.address.clone())
+			.chain(self.pending_connections.iter().cloned())
+			.collect();
+		let unused: Vec<&ConnectionInfo> =
+			self.candidates.iter().filter(|info| !in_use.contains(&info.address())).collect();
+
+		let mut rng = rand::thread_rng();
+		let best_ranked = self.peer_book.as_ref().and_then(|peer_book| {
+			unused.iter().min_by_key(|info| {
+				let (address, port) = info.endpoint();
+				peer_book.get(address, port).map_or(UNRANKED, |peer| peer.rank())
+			})
+		});
+		let conn_info = match best_ranked.or_else(|| unused.choose(&mut rng)) {
+			Some(conn_info) => (*conn_info).clone(),
+			// the slot is filled once more peers are discovered
+			None if self.peer_book.is_some() => return,
+			None => match self.candidates.choose(&mut rng) {
+				Some(conn_info) => conn_info.clone(),
+				None => return,
+			},
+		};
+
+		self.pending_connections.push(conn_info.address());
+		let local_node_info = self.local_node_info.clone();
+		let peer_event_sender = self.peer_event_sender.clone();
+		tokio::spawn(async move {
+			sleep(delay).await;
+			let started = Instant::now();
+			let result = StellarOverlayConnection::connect(
+				local_node_info,
+				conn_info.with_recording_of_endpoint(),
+			)
+			.await;
+			let _ = peer_event_sender
+				.send(PeerEvent::Connected(conn_info, result, started.elapsed()))
+				.await;
+		});
+	}
+}
+
+/// Returns the candidates to connect to, best ranked first: the seeds, and the peers of the book.
+fn ranked_candidates(
+	template: &ConnectionInfo,
+	seeds: Vec<ConnectionInfo>,
+	peer_book: &mut PeerBook,
+) -> Vec<ConnectionInfo> {
+	for seed in seeds.iter() {
+		let (address, port) = seed.endpoint();
+		peer_book.add(address, port);
+	}
+
+	peer_book
+		.ranked()
+		.into_iter()
+		.map(|peer| {
+			seeds
+				.iter()
+				.find(|seed| seed.endpoint() == (peer.address.as_str(), peer.port))
+				.cloned()
+				.unwrap_or_else(|| template.with_endpoint(&peer.address, peer.port))
+		})
+		.collect()
+}
+
+/// Records the outcome of connecting to the peer in the peer book, if there is one
+fn record_connection(
+	peer_book: &mut Option<PeerBook>,
+	conn_info: &ConnectionInfo,
+	result: &Result<StellarOverlayConnection, Error>,
+	latency: Duration,
+) {
+	let Some(peer_book) = peer_book.as_mut() else { return };
+
+	let (address, port) = conn_info.endpoint();
+	match result {
+		Ok(_) => peer_book.record_success(address, port, latency),
+		Err(_) => peer_book.record_failure(address, port),
+	}
+}
+
+/// Passes the messages of one peer to the supervisor, until the peer disconnects.
+async fn forward_peer_messages(
+	peer_id: PeerId,
+	mut conn: StellarOverlayConnection,
+	peer_event_sender: mpsc::Sender<PeerEvent>,
+) {
+	let mut event_receiver = conn.take_event_receiver();
+	loop {
+		tokio::select! {
+			result = conn.listen() => match result {
+				// the peer is still connected; only a `Closed` event ends the connection
+				Ok(Some(StellarMessage::ErrorMsg(e))) =>
+					warn!("forward_peer_messages(): peer sent an error message: {e:?}"),
+				Ok(Some(msg)) =>
+					if peer_event_sender.send(PeerEvent::Message(peer_id, msg)).await.is_err() {
+						// the supervisor has stopped
+						break
+					},
+				Ok(None) | Err(_) => break,
+			},
+			Some(event) = next_event(&mut event_receiver) => match event {
+				ConnectionEvent::PeerError { code, msg } =>
+					warn!("forward_peer_messages(): peer sent an error: {code:?}: {msg}"),
+				ConnectionEvent::Closed { reason } => {
+					warn!("forward_peer_messages(): connection to peer closed: {reason}");
+					break
+				},
+				_ => {},
+			},
+		}
+	}
+
+	conn.stop();
+	let _ = peer_event_sender.send(PeerEvent::Disconnected(peer_id)).await;
+}
+
+/// Returns the next event of the connection, or None once there are no more events.
+async fn next_event(
+	event_receiver: &mut Option<mpsc::Receiver<ConnectionEvent>>,
+) -> Option<ConnectionEvent> {
+	let event = event_receiver.as_mut()?.recv().await;
+	if event.is_none() {
+		*event_receiver = None;
+	}
+	event
+}
+
+/// Returns the hash of messages that are likely to be received from more than one peer.
+fn dedup_hash(msg: &StellarMessage) -> Option<MessageHash> {
+	match msg {
+		StellarMessage::ScpMessage(_) |
+		StellarMessage::TxSet(_) |
+		StellarMessage::GeneralizedTxSet(_) => Some(Sha256::digest(msg.to_xdr()).into()),
+		_ => None,
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::mock_peer::{test_node_info, MockPeerScript, MockStellarPeer};
+	use substrate_stellar_sdk::{
+		compound_types::{LimitedVarArray, UnlimitedVarArray},
+		types::{PeerAddressIp, TransactionSet},
+	};
+	use tempdir::TempDir;
+
+	#[test]
+	fn dedup_hash_only_for_flooded_data() {
+		let hash: Uint256 = [3; 32];
+		assert!(dedup_hash(&StellarMessage::GetTxSet(hash)).is_none());
+		assert!(dedup_hash(&StellarMessage::GetScpState(1)).is_none());
+	}
+
+	#[tokio::test(flavor = "multi_thread")]
+	async fn get_tx_set_asks_another_peer_after_dont_have() {
+		let set = TransactionSet {
+			previous_ledger_hash: [1; 32],
+			txes: UnlimitedVarArray::new(vec![]).expect("should return an empty array"),
+		};
+		let tx_set_hash = TransactionSetType::TransactionSet(set.clone())
+			.get_tx_set_hash()
+			.expect("should return a hash");
+
+		let with_tx_set = MockStellarPeer::start(
+			test_node_info(),
+			MockPeerScript::new().reply_to_get_tx_set(tx_set_hash, StellarMessage::TxSet(set)),
+		)
+		.await
+		.expect("should start the mock peer");
+		// replies with a `DontHave`
+		let without_tx_set = MockStellarPeer::start(test_node_info(), MockPeerScript::new())
+			.await
+			.expect("should start the mock peer");
+
+		let mut pool = StellarOverlayPool::connect(
+			test_node_info(),
+			vec![with_tx_set.conn_info(), without_tx_set.conn_info()],
+			2,
+		)
+		.await
+		.expect("should connect to the mock peers");
+
+		// whichever peer is asked first, the tx set is found
+		let tx_set = pool.get_tx_set(tx_set_hash).await.expect("should return the tx set");
+		assert_eq!(tx_set.get_tx_set_hash().ok(), Some(tx_set_hash));
+
+		let unknown_hash: Uint256 = [2; 32];
+		assert!(matches!(pool.get_tx_set(unknown_hash).await, Err(Error::DontHave(..))));
+		assert!(without_tx_set
+			.received_messages()
+			.contains(&StellarMessage::GetTxSet(unknown_hash)));
+		assert!(with_tx_set.received_messages().contains(&StellarMessage::GetTxSet(unknown_hash)));
+
+		pool.stop();
+	}
+
+	#[tokio::test(flavor = "multi_thread")]
+	async fn pool_connects_to_discovered_peers() {
+		let discovered = MockStellarPeer::start(test_node_info(), MockPeerScript::new())
+			.await
+			.expect("should start the mock peer");
+		let discovered_port = u32::from(discovered.local_addr().port());
+
+		let seed = MockStellarPeer::start(
+			test_node_info(),
+			MockPeerScript::new().reply_with(move |msg| match msg {
+				StellarMessage::GetPeers => vec![StellarMessage::Peers(
+					LimitedVarArray::new(vec![PeerAddress {
+						ip: PeerAddressIp::IPv4([127, 0, 0, 1]),
+						port: discovered_port,
+						num_failures: 0,
+					}])
+					.expect("should return the peers"),
+				)],
+				_ => vec![],
+			}),
+		)
+		.await
+		.expect("should start the mock peer");
+
+		let dir = TempDir::new("overlay_pool").expect("should create a temp dir");
+		let path = dir.path().join("peers.json");
+		let peer_book = PeerBook::load(&path)
+			.expect("should start with an empty book")
+			// the mock peers listen on localhost
+			.with_unroutable_addresses();
+
+		let mut pool = StellarOverlayPool::connect_with_peer_book(
+			test_node_info(),
+			vec![seed.conn_info()],
+			2,
+			peer_book,
+		)
+		.await
+		.expect("should connect to the seed");
+
+		// the discovered peer fills the second slot
+		tokio::time::timeout(Duration::from_secs(10), async {
+			while discovered.received_messages().is_empty() {
+				sleep(Duration::from_millis(50)).await;
+			}
+		})
+		.await
+		.expect("should connect to the discovered peer");
+
+		// the book is saved once the pool stops
+		pool.stop();
+		drop(pool);
+		let seed_port = u32::from(seed.local_addr().port());
+		let peer_book = tokio::time::timeout(Duration::from_secs(10), async {
+			loop {
+				let peer_book = PeerBook::load(&path).expect("should load the book");
+				let seed_reached = peer_book
+					.get("127.0.0.1", seed_port)
+					.map_or(false, |seed| seed.last_success.is_some());
+				if seed_reached && peer_book.get("127.0.0.1", discovered_port).is_some() {
+					break peer_book
+				}
+				sleep(Duration::from_millis(50)).await;
+			}
+		})
+		.await
+		.expect("should save the book");
+		assert_eq!(peer_book.len(), 2);
+	}
+}
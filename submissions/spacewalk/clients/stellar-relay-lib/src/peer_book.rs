@@ -0,0 +1,368 @@
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::HashMap,
+	net::{Ipv4Addr, Ipv6Addr},
+	path::{Path, PathBuf},
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+use substrate_stellar_sdk::types::{PeerAddress, PeerAddressIp};
+use tracing::{debug, warn};
+
+use crate::{connection::helper::time_now, Error};
+
+/// The maximum number of peers kept in the book. The least stable ones are dropped first.
+const MAX_PEERS_IN_BOOK: usize = 1000;
+
+/// How much a new latency measurement counts, against the previous ones
+const LATENCY_WEIGHT_PERCENT: u64 = 30;
+
+/// How often the overlay pool saves the changes of its peer book
+pub(crate) const PEER_BOOK_SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// What we know about a peer of the overlay network
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerRecord {
+	pub address: String,
+	pub port: u32,
+	/// when connecting to the peer last succeeded, in milliseconds since the unix epoch
+	pub last_success: Option<u64>,
+	/// the number of failed connections or disconnections since the last success
+	pub failures: u32,
+	/// the overlay version the peer sent in its `Hello`
+	pub overlay_version: Option<u32>,
+	/// the time it took to connect to the peer, averaged over the connections
+	pub latency_in_millis: Option<u64>,
+}
+
+impl PeerRecord {
+	fn new(address: String, port: u32) -> Self {
+		PeerRecord {
+			address,
+			port,
+			last_success: None,
+			failures: 0,
+			overlay_version: None,
+			latency_in_millis: None,
+		}
+	}
+
+	fn key(&self) -> String {
+		peer_key(&self.address, self.port)
+	}
+
+	/// Lower is better: stable peers first, then peers that were reached before, then the fastest.
+	pub(crate) fn rank(&self) -> (u32, bool, u64) {
+		(self.failures, self.last_success.is_none(), self.latency_in_millis.unwrap_or(u64::MAX))
+	}
+}
+
+fn peer_key(address: &str, port: u32) -> String {
+	format!("{address}:{port}")
+}
+
+/// The peers of the overlay network known so far, starting with the seed endpoints of the config
+/// and growing with the `Peers` messages of the Stellar Nodes.
+/// If it has a path, it is saved there, to start from it the next time. The changes are saved
+/// with `save()`, and when the book is dropped.
+#[derive(Debug, Default)]
+pub struct PeerBook {
+	path: Option<PathBuf>,
+	peers: HashMap<String, PeerRecord>,
+	/// whether the peers changed since the book was saved
+	has_changes: bool,
+	/// whether discovered peers at private or otherwise unroutable addresses are added too
+	allow_unroutable: bool,
+	/// lets one save at a time write the file
+	save_lock: Arc<Mutex<()>>,
+}
+
+impl PeerBook {
+	/// Returns the peer book saved at `path`, or an empty one if there is no file yet
+	pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+		let path = path.as_ref();
+		let peers: Vec<PeerRecord> = match std::fs::read_to_string(path) {
+			Ok(content) => serde_json::from_str(&content)
+				.map_err(|e| Error::ConfigError(format!("Peer book {}: {e:?}", path.display())))?,
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+			Err(e) =>
+				return Err(Error::ConfigError(format!("Peer book {}: {e:?}", path.display()))),
+		};
+
+		debug!("load(): {} peers found in {}", peers.len(), path.display());
+		Ok(PeerBook {
+			path: Some(path.to_path_buf()),
+			peers: peers.into_iter().map(|peer| (peer.key(), peer)).collect(),
+			has_changes: false,
+			allow_unroutable: false,
+			save_lock: Default::default(),
+		})
+	}
+
+	/// Returns a peer book that is not saved anywhere
+	pub fn in_memory() -> Self {
+		Self::default()
+	}
+
+	/// Adds the discovered peers at private or otherwise unroutable addresses too, e.g. for a
+	/// local test network. These are skipped by default.
+	pub fn with_unroutable_addresses(mut self) -> Self {
+		self.allow_unroutable = true;
+		self
+	}
+
+	pub fn len(&self) -> usize {
+		self.peers.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.peers.is_empty()
+	}
+
+	pub fn get(&self, address: &str, port: u32) -> Option<&PeerRecord> {
+		self.peers.get(&peer_key(address, port))
+	}
+
+	/// Adds the peer if it is not known yet. Returns true if it was added.
+	pub fn add(&mut self, address: &str, port: u32) -> bool {
+		let key = peer_key(address, port);
+		if self.peers.contains_key(&key) {
+			return false
+		}
+
+		self.peers.insert(key, PeerRecord::new(address.to_string(), port));
+		self.prune();
+		self.has_changes = true;
+		true
+	}
+
+	/// Adds the peers of a `Peers` message. Returns the ones that were not known yet.
+	/// Peers at unroutable addresses are skipped, unless the book allows them.
+	pub fn add_discovered(&mut self, peers: &[PeerAddress]) -> Vec<(String, u32)> {
+		let allow_unroutable = self.allow_unroutable;
+		let added: Vec<_> = peers
+			.iter()
+			.filter(|peer| allow_unroutable || (peer.port != 0 && is_routable(&peer.ip)))
+			.filter_map(|peer| {
+				let address = peer_address_to_string(&peer.ip);
+				self.add(&address, peer.port).then_some((address, peer.port))
+			})
+			.collect();
+
+		if !added.is_empty() {
+			debug!("add_discovered(): {} new peers", added.len());
+		}
+		added
+	}
+
+	pub fn record_success(&mut self, address: &str, port: u32, latency: Duration) {
+		self.add(address, port);
+		if let Some(peer) = self.peers.get_mut(&peer_key(address, port)) {
+			let latency = u64::try_from(latency.as_millis()).unwrap_or(u64::MAX);
+			peer.latency_in_millis = Some(match peer.latency_in_millis {
+				Some(previous) =>
+					(previous * (100 - LATENCY_WEIGHT_PERCENT) + latency * LATENCY_WEIGHT_PERCENT) /
+						100,
+				None => latency,
+			});
+			peer.last_success = Some(time_now());
+			peer.failures = 0;
+		}
+		self.has_changes = true;
+	}
+
+	/// Counts a failed connection, or a lost one
+	pub fn record_failure(&mut self, address: &str, port: u32) {
+		self.add(address, port);
+		if let Some(peer) = self.peers.get_mut(&peer_key(address, port)) {
+			peer.failures = peer.failures.saturating_add(1);
+		}
+		self.has_changes = true;
+	}
+
+	pub fn record_overlay_version(&mut self, address: &str, port: u32, overlay_version: u32) {
+		if let Some(peer) = self.peers.get_mut(&peer_key(address, port)) {
+			peer.overlay_version = Some(overlay_version);
+			self.has_changes = true;
+		}
+	}
+
+	/// Returns the peers, best first
+	pub fn ranked(&self) -> Vec<&PeerRecord> {
+		let mut peers: Vec<_> = self.peers.values().collect();
+		peers.sort_by_key(|peer| (peer.rank(), peer.key()));
+		peers
+	}
+
+	/// Drops the least stable peers, once the book is full
+	fn prune(&mut self) {
+		if self.peers.len() <= MAX_PEERS_IN_BOOK {
+			return
+		}
+
+		let dropped: Vec<String> =
+			self.ranked().iter().skip(MAX_PEERS_IN_BOOK).map(|peer| peer.key()).collect();
+		for key in dropped {
+			self.peers.remove(&key);
+		}
+	}
+
+	/// Writes the changes of the book to its file, if it has one.
+	/// A failure is only logged, since the book can be rebuilt from the seeds.
+	pub fn save(&mut self) {
+		if let Some(write) = self.take_changes() {
+			write();
+		}
+	}
+
+	/// Like `save`, but the file is written on a blocking thread, to not hold up the runtime
+	pub(crate) fn save_in_background(&mut self) {
+		if let Some(write) = self.take_changes() {
+			tokio::task::spawn_blocking(write);
+		}
+	}
+
+	/// Returns what writes the book to its file, if it has one and there are changes to write
+	fn take_changes(&mut self) -> Option<impl FnOnce() + Send + 'static> {
+		let path = self.path.clone()?;
+		if !std::mem::take(&mut self.has_changes) {
+			return None
+		}
+
+		let content = serde_json::to_vec_pretty(&self.ranked()).map_err(|e| e.to_string());
+		let save_lock = self.save_lock.clone();
+		Some(move || {
+			let _lock = save_lock.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+			let result = content.and_then(|content| {
+				// write to a temporary file first, so that a crash does not leave half a book
+				let tmp_path = path.with_extension("tmp");
+				std::fs::write(&tmp_path, content).map_err(|e| e.to_string())?;
+				std::fs::rename(&tmp_path, &path).map_err(|e| e.to_string())
+			});
+
+			if let Err(e) = result {
+				warn!("save(): failed to save peer book to {}: {e}", path.display());
+			}
+		})
+	}
+}
+
+impl Drop for PeerBook {
+	fn drop(&mut self) {
+		self.save();
+	}
+}
+
+/// Returns true if the address can be reached from the internet
+fn is_routable(ip: &PeerAddressIp) -> bool {
+	match ip {
+		PeerAddressIp::IPv4(ip) => {
+			let ip = Ipv4Addr::from(*ip);
+			!(ip.is_private() ||
+				ip.is_loopback() ||
+				ip.is_link_local() ||
+				ip.is_unspecified() ||
+				ip.is_broadcast() ||
+				ip.is_documentation() ||
+				ip.is_multicast())
+		},
+		PeerAddressIp::IPv6(ip) => {
+			let ip = Ipv6Addr::from(*ip);
+			let first_segment = ip.segments()[0];
+			// unique local addresses are in fc00::/7, link local ones in fe80::/10
+			!(ip.is_loopback() ||
+				ip.is_unspecified() ||
+				ip.is_multicast() ||
+				first_segment & 0xfe00 == 0xfc00 ||
+				first_segment & 0xffc0 == 0xfe80)
+		},
+	}
+}
+
+fn peer_address_to_string(ip: &PeerAddressIp) -> String {
+	match ip {
+		PeerAddressIp::IPv4(ip) => Ipv4Addr::from(*ip).to_string(),
+		PeerAddressIp::IPv6(ip) => Ipv6Addr::from(*ip).to_string(),
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use tempdir::TempDir;
+
+	fn peer_address(ip: [u8; 4], port: u32) -> PeerAddress {
+		PeerAddress { ip: PeerAddressIp::IPv4(ip), port, num_failures: 0 }
+	}
+
+	#[test]
+	fn discovered_peers_are_added_once() {
+		let mut book = PeerBook::in_memory();
+		book.add("1.2.3.4", 11625);
+
+		let added = book.add_discovered(&[
+			peer_address([1, 2, 3, 4], 11625),
+			peer_address([5, 6, 7, 8], 11625),
+			peer_address([5, 6, 7, 8], 11625),
+		]);
+
+		assert_eq!(added, vec![("5.6.7.8".to_string(), 11625)]);
+		assert_eq!(book.len(), 2);
+	}
+
+	#[test]
+	fn discovered_peers_at_unroutable_addresses_are_skipped() {
+		let unroutable = [
+			peer_address([10, 0, 0, 1], 11625),
+			peer_address([192, 168, 1, 1], 11625),
+			peer_address([127, 0, 0, 1], 11625),
+			peer_address([0, 0, 0, 0], 11625),
+			peer_address([5, 6, 7, 8], 0),
+		];
+
+		let mut book = PeerBook::in_memory();
+		assert!(book.add_discovered(&unroutable).is_empty());
+		assert_eq!(book.add_discovered(&[peer_address([5, 6, 7, 8], 11625)]).len(), 1);
+
+		let mut book = PeerBook::in_memory().with_unroutable_addresses();
+		assert_eq!(book.add_discovered(&unroutable).len(), unroutable.len());
+	}
+
+	#[test]
+	fn peers_are_ranked_by_stability_and_latency() {
+		let mut book = PeerBook::in_memory();
+		book.add("1.1.1.1", 11625);
+		book.record_success("2.2.2.2", 11625, Duration::from_millis(300));
+		book.record_success("3.3.3.3", 11625, Duration::from_millis(100));
+		book.record_failure("4.4.4.4", 11625);
+
+		let ranked: Vec<_> = book.ranked().iter().map(|peer| peer.address.clone()).collect();
+		assert_eq!(ranked, vec!["3.3.3.3", "2.2.2.2", "1.1.1.1", "4.4.4.4"]);
+
+		// a success clears the failures
+		book.record_success("4.4.4.4", 11625, Duration::from_millis(200));
+		let ranked: Vec<_> = book.ranked().iter().map(|peer| peer.address.clone()).collect();
+		assert_eq!(ranked, vec!["3.3.3.3", "4.4.4.4", "2.2.2.2", "1.1.1.1"]);
+	}
+
+	#[test]
+	fn peer_book_is_saved_and_loaded() {
+		let dir = TempDir::new("peer_book").expect("should create a temp dir");
+		let path = dir.path().join("peers.json");
+
+		let mut book = PeerBook::load(&path).expect("should start with an empty book");
+		assert!(book.is_empty());
+
+		book.record_success("1.2.3.4", 11625, Duration::from_millis(50));
+		book.record_overlay_version("1.2.3.4", 11625, 33);
+		// nothing is written until the book is saved
+		assert!(PeerBook::load(&path).expect("should load the book").is_empty());
+		book.save();
+
+		let book = PeerBook::load(&path).expect("should load the book");
+		let peer = book.get("1.2.3.4", 11625).expect("should return the peer");
+		assert_eq!(peer.latency_in_millis, Some(50));
+		assert_eq!(peer.overlay_version, Some(33));
+		assert_eq!(peer.failures, 0);
+	}
+}
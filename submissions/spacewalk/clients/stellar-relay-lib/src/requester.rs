@@ -0,0 +1,338 @@
+use std::time::Duration;
+use substrate_stellar_sdk::{
+	types::{MessageType, ScpEnvelope, StellarMessage, Uint256},
+	TransactionSetType,
+};
+use tokio::{
+	sync::{mpsc, oneshot},
+	time::{timeout_at, Instant},
+};
+use tracing::{debug, warn};
+
+use crate::{
+	connection::{MessageFilter, Subscriptions},
+	Error,
+};
+
+/// The size of the channel that receives the replies of one request
+const REPLY_CAPACITY: usize = 1024;
+
+/// Decides how long a request to the Stellar Node is waited for, and how often it is sent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestPolicy {
+	/// how long to wait for the reply of a single attempt
+	pub timeout: Duration,
+	/// the number of attempts before giving up. In a pool, every attempt goes to another peer
+	pub max_attempts: u32,
+	/// `get_scp_state` returns once no new envelope of the slot arrived for this long
+	pub scp_state_quiet_period: Duration,
+}
+
+impl Default for RequestPolicy {
+	fn default() -> Self {
+		RequestPolicy {
+			timeout: Duration::from_secs(10),
+			max_attempts: 3,
+			scp_state_quiet_period: Duration::from_millis(500),
+		}
+	}
+}
+
+/// Asks the `StellarOverlayPool` for a peer to send a request to.
+pub(crate) struct PeerRequest {
+	/// the addresses of the peers that were already asked, and should be avoided
+	pub tried_peers: Vec<String>,
+	/// receives the address and the sender of the peer, or None if no peer is connected
+	pub reply: oneshot::Sender<Option<(String, mpsc::Sender<StellarMessage>)>>,
+}
+
+/// Where the requests go to
+#[derive(Clone)]
+enum Route {
+	/// the Stellar Node of a `StellarOverlayConnection`
+	Node(mpsc::Sender<StellarMessage>),
+	/// a peer of a `StellarOverlayPool`, chosen per attempt
+	Pool(mpsc::Sender<PeerRequest>),
+}
+
+/// Sends requests for data to the Stellar Node and waits for the matching reply,
+/// instead of leaving the user to pick the reply out of `listen()`.
+/// It is cheap to clone, so it can be used while another task is listening.
+#[derive(Clone)]
+pub struct StellarOverlayRequester {
+	route: Route,
+	subscriptions: Subscriptions,
+	policy: RequestPolicy,
+}
+
+impl StellarOverlayRequester {
+	pub(crate) fn for_node(
+		sender: mpsc::Sender<StellarMessage>,
+		subscriptions: Subscriptions,
+	) -> Self {
+		StellarOverlayRequester {
+			route: Route::Node(sender),
+			subscriptions,
+			policy: RequestPolicy::default(),
+		}
+	}
+
+	pub(crate) fn for_pool(
+		peer_request_sender: mpsc::Sender<PeerRequest>,
+		subscriptions: Subscriptions,
+	) -> Self {
+		StellarOverlayRequester {
+			route: Route::Pool(peer_request_sender),
+			subscriptions,
+			policy: RequestPolicy::default(),
+		}
+	}
+
+	pub fn with_policy(mut self, policy: RequestPolicy) -> Self {
+		self.policy = policy;
+		self
+	}
+
+	/// Returns the tx set of the given hash.
+	/// Returns `Error::DontHave` if the (last) peer asked does not have it,
+	/// or `Error::Timeout` if it did not reply in time.
+	/// A `DontHave` is only retried in a pool, where another peer may have the tx set.
+	pub async fn get_tx_set(&self, tx_set_hash: Uint256) -> Result<TransactionSetType, Error> {
+		let hash_str = hex::encode(tx_set_hash);
+		let filter = MessageFilter::all().message_types([
+			MessageType::TxSet,
+			MessageType::GeneralizedTxSet,
+			MessageType::DontHave,
+		]);
+
+		let mut tried_peers = vec![];
+		let mut last_error = Error::Timeout;
+		for attempt in 1..=self.policy.max_attempts {
+			// subscribe before sending, to not miss a quick reply
+			let mut replies = self.subscriptions.subscribe(filter.clone(), REPLY_CAPACITY)?;
+			let peer = self.send(StellarMessage::GetTxSet(tx_set_hash), &mut tried_peers).await?;
+
+			match wait_for_tx_set(&mut replies, tx_set_hash, self.policy.timeout).await {
+				Ok(tx_set) => return Ok(tx_set),
+				// asking the same Stellar Node again will not change its answer
+				Err(e @ Error::DontHave(..)) if matches!(self.route, Route::Node(_)) =>
+					return Err(e),
+				Err(e) => {
+					warn!("get_tx_set(): attempt {attempt} for tx set {hash_str} at {peer}: {e:?}");
+					last_error = e;
+				},
+			}
+		}
+
+		Err(last_error)
+	}
+
+	/// Returns the envelopes of the given slot.
+	/// Returns `Error::Timeout` if no envelope of the slot arrived in time.
+	pub async fn get_scp_state(&self, slot: u32) -> Result<Vec<ScpEnvelope>, Error> {
+		let slot_index = u64::from(slot);
+		let filter = MessageFilter::all().slots(slot_index..=slot_index);
+
+		let mut tried_peers = vec![];
+		for attempt in 1..=self.policy.max_attempts {
+			let mut replies = self.subscriptions.subscribe(filter.clone(), REPLY_CAPACITY)?;
+			let peer = self.send(StellarMessage::GetScpState(slot), &mut tried_peers).await?;
+
+			match wait_for_envelopes(&mut replies, &self.policy).await {
+				Ok(envelopes) => return Ok(envelopes),
+				Err(e) =>
+					warn!("get_scp_state(): attempt {attempt} for slot {slot} at {peer}: {e:?}"),
+			}
+		}
+
+		Err(Error::Timeout)
+	}
+
+	/// Sends the request and returns the address of the peer it was sent to
+	async fn send(
+		&self,
+		msg: StellarMessage,
+		tried_peers: &mut Vec<String>,
+	) -> Result<String, Error> {
+		match &self.route {
+			Route::Node(sender) => {
+				sender.send(msg).await.map_err(|_| Error::Disconnected)?;
+				Ok("the Stellar Node".to_string())
+			},
+			Route::Pool(peer_request_sender) => {
+				let (reply, peer_receiver) = oneshot::channel();
+				peer_request_sender
+					.send(PeerRequest { tried_peers: tried_peers.clone(), reply })
+					.await
+					.map_err(|_| Error::Disconnected)?;
+
+				let (address, sender) =
+					peer_receiver.await.ok().flatten().ok_or(Error::Disconnected)?;
+				debug!("send(): sending request to {address}");
+				sender.send(msg).await.map_err(|_| Error::Disconnected)?;
+
+				tried_peers.push(address.clone());
+				Ok(address)
+			},
+		}
+	}
+}
+
+/// Waits for the tx set of the given hash, or for a `DontHave` of it
+async fn wait_for_tx_set(
+	replies: &mut mpsc::Receiver<StellarMessage>,
+	tx_set_hash: Uint256,
+	wait: Duration,
+) -> Result<TransactionSetType, Error> {
+	let deadline = Instant::now() + wait;
+
+	loop {
+		let msg = timeout_at(deadline, replies.recv())
+			.await
+			.map_err(|_| Error::Timeout)?
+			.ok_or(Error::Disconnected)?;
+
+		let tx_set = match msg {
+			StellarMessage::TxSet(set) => TransactionSetType::TransactionSet(set),
+			StellarMessage::GeneralizedTxSet(set) =>
+				TransactionSetType::GeneralizedTransactionSet(set),
+			StellarMessage::DontHave(dont_have) if dont_have.req_hash == tx_set_hash =>
+				return Err(Error::DontHave(dont_have.type_, hex::encode(tx_set_hash))),
+			// a reply to another request
+			_ => continue,
+		};
+
+		if tx_set.get_tx_set_hash().ok() == Some(tx_set_hash) {
+			return Ok(tx_set)
+		}
+	}
+}
+
+/// Collects envelopes until none arrived for the quiet period.
+/// Returns `Error::Timeout` if not even one arrived before the timeout.
+async fn wait_for_envelopes(
+	replies: &mut mpsc::Receiver<StellarMessage>,
+	policy: &RequestPolicy,
+) -> Result<Vec<ScpEnvelope>, Error> {
+	let deadline = Instant::now() + policy.timeout;
+	let mut envelopes: Vec<ScpEnvelope> = vec![];
+
+	loop {
+		let wait_until = if envelopes.is_empty() {
+			deadline
+		} else {
+			deadline.min(Instant::now() + policy.scp_state_quiet_period)
+		};
+
+		match timeout_at(wait_until, replies.recv()).await {
+			// peers of a pool may send the same envelope
+			Ok(Some(StellarMessage::ScpMessage(env))) =>
+				if !envelopes.contains(&env) {
+					envelopes.push(env);
+				},
+			Ok(Some(_)) => {},
+			Ok(None) => return Err(Error::Disconnected),
+			Err(_) if envelopes.is_empty() => return Err(Error::Timeout),
+			Err(_) => return Ok(envelopes),
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use substrate_stellar_sdk::{
+		compound_types::UnlimitedVarArray,
+		types::{DontHave, TransactionSet},
+	};
+
+	fn tx_set(previous_ledger_hash: Uint256) -> (Uint256, StellarMessage) {
+		let set = TransactionSet {
+			previous_ledger_hash,
+			txes: UnlimitedVarArray::new(vec![]).expect("should return an empty array"),
+		};
+		let hash = TransactionSetType::TransactionSet(set.clone())
+			.get_tx_set_hash()
+			.expect("should return a hash");
+
+		(hash, StellarMessage::TxSet(set))
+	}
+
+	fn requester() -> (StellarOverlayRequester, mpsc::Receiver<StellarMessage>, Subscriptions) {
+		let (sender, receiver) = mpsc::channel(10);
+		let subscriptions = Subscriptions::default();
+		let requester = StellarOverlayRequester::for_node(sender, subscriptions.clone())
+			.with_policy(RequestPolicy {
+				timeout: Duration::from_millis(200),
+				max_attempts: 2,
+				scp_state_quiet_period: Duration::from_millis(50),
+			});
+
+		(requester, receiver, subscriptions)
+	}
+
+	#[tokio::test]
+	async fn get_tx_set_skips_replies_to_other_requests() {
+		let (requester, mut node, subscriptions) = requester();
+		let (hash, msg) = tx_set([1; 32]);
+		let (_, other_msg) = tx_set([2; 32]);
+
+		tokio::spawn(async move {
+			assert_eq!(node.recv().await, Some(StellarMessage::GetTxSet(hash)));
+			subscriptions.publish(&other_msg);
+			subscriptions.publish(&StellarMessage::DontHave(DontHave {
+				type_: MessageType::TxSet,
+				req_hash: [9; 32],
+			}));
+			subscriptions.publish(&msg);
+		});
+
+		let tx_set = requester.get_tx_set(hash).await.expect("should return the tx set");
+		assert_eq!(tx_set.get_tx_set_hash().ok(), Some(hash));
+	}
+
+	#[tokio::test]
+	async fn get_tx_set_returns_dont_have_of_the_node() {
+		let (requester, mut node, subscriptions) = requester();
+		let hash = [3; 32];
+
+		let requests = tokio::spawn(async move {
+			let mut requests = 0;
+			while let Some(StellarMessage::GetTxSet(req_hash)) = node.recv().await {
+				requests += 1;
+				subscriptions.publish(&StellarMessage::DontHave(DontHave {
+					type_: MessageType::TxSet,
+					req_hash,
+				}));
+			}
+			requests
+		});
+
+		assert!(matches!(
+			requester.get_tx_set(hash).await,
+			Err(Error::DontHave(MessageType::TxSet, _))
+		));
+
+		// the same node is not asked again
+		drop(requester);
+		assert_eq!(requests.await.expect("should count the requests"), 1);
+	}
+
+	#[tokio::test]
+	async fn get_scp_state_times_out_without_envelopes() {
+		let (requester, mut node, _subscriptions) = requester();
+
+		let requests = tokio::spawn(async move {
+			let mut requests = 0;
+			while node.recv().await.is_some() {
+				requests += 1;
+			}
+			requests
+		});
+
+		assert!(matches!(requester.get_scp_state(10).await, Err(Error::Timeout)));
+
+		drop(requester);
+		assert_eq!(requests.await.expect("should count the requests"), 2);
+	}
+}
@@ -1,13 +1,14 @@
 use crate::{
 	connection::ConnectionInfo, node::NodeInfo, StellarOverlayConfig, StellarOverlayConnection,
+	StellarOverlayPool,
 };
-use async_std::sync::Mutex;
 use serial_test::serial;
 use std::{sync::Arc, thread::sleep, time::Duration};
 use substrate_stellar_sdk::{
 	types::{ScpStatementExternalize, ScpStatementPledges, StellarMessage},
 	Hash, IntoHash,
 };
+use tokio::sync::Mutex;
 
 use wallet::keys::get_source_secret_key_from_env;
 
@@ -160,3 +161,29 @@ async fn stellar_overlay_disconnect_works() {
 	sleep(Duration::from_secs(15));
 	assert!(!overlay_connection.is_alive());
 }
+
+#[tokio::test(flavor = "multi_thread")]
+#[serial]
+#[ntest::timeout(300_000)] // timeout at 5 minutes
+async fn stellar_overlay_pool_should_receive_scp_messages() {
+	let path = "./resources/config/testnet/stellar_relay_config_sdftest.json";
+	let cfg = StellarOverlayConfig::try_from_path(path).expect("should be able to extract config");
+	let conn_infos = cfg.connection_infos(secret_key(false)).expect("should return all conn infos");
+
+	let mut overlay_pool =
+		StellarOverlayPool::connect(cfg.node_info(), conn_infos, 2).await.expect("should connect");
+
+	loop {
+		if let Some(message) = overlay_pool.listen().await.expect("should return a message") {
+			if let StellarMessage::ScpMessage(_) = message {
+				break
+			}
+		}
+	}
+
+	overlay_pool.stop();
+
+	// let the disconnection call pass for a few seconds, before checking its status.
+	sleep(Duration::from_secs(15));
+	assert!(!overlay_pool.is_alive());
+}
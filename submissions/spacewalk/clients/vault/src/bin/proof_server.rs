@@ -0,0 +1,97 @@
+use std::{fs, net::SocketAddr, sync::Arc};
+
+use clap::Parser;
+use runtime::ShutdownSender;
+use service::{warp, LoggingFormat};
+use stellar_relay_lib::{
+	sdk::{PublicKey, SecretKey},
+	StellarOverlayConfig,
+};
+use vault::oracle::{listen_for_stellar_messages, proof_server_routes, OracleAgent, ScpStore};
+
+/// Runs the oracle of the vault on its own, and serves the proofs that it builds over HTTP, so
+/// that several vaults can share one well connected source of proofs.
+#[derive(Parser, Debug)]
+#[clap(name = "proof-server", version)]
+struct Opts {
+	/// The filepath where the json config for StellarOverlay is located.
+	#[clap(long, env = "STELLAR_OVERLAY_CONFIG_FILEPATH")]
+	stellar_overlay_config_filepath: String,
+
+	/// The Stellar secret key to connect to the overlay with. A random one is used if not given.
+	#[clap(long, env = "STELLAR_SECRET_KEY_FILEPATH")]
+	stellar_secret_key_filepath: Option<String>,
+
+	/// The address to serve the proofs at.
+	#[clap(long, env = "PROOF_SERVER_ADDRESS", default_value = "127.0.0.1:3031")]
+	address: SocketAddr,
+
+	/// The accounts, as `G...`, whose payments are proven as soon as possible.
+	#[clap(long)]
+	watch_account: Vec<String>,
+
+	/// The directory to keep the collected SCP envelopes, tx sets and proofs in.
+	#[clap(long, env = "SCP_STORE_PATH")]
+	scp_store_path: Option<String>,
+
+	/// How many slots behind the latest slot the SCP store keeps; the default is about a day.
+	#[clap(long, env = "SCP_STORE_MAX_AGE_IN_SLOTS", default_value = "17280")]
+	scp_store_max_age_in_slots: u64,
+
+	/// Logging output format.
+	#[clap(long, env = "LOGGING_FORMAT", default_value = "full")]
+	logging_format: LoggingFormat,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+	let opts = Opts::parse();
+	opts.logging_format.init_subscriber();
+
+	let cfg = StellarOverlayConfig::try_from_path(&opts.stellar_overlay_config_filepath)?;
+	let secret_key = match &opts.stellar_secret_key_filepath {
+		Some(path) => fs::read_to_string(path)?.trim().to_string(),
+		None => String::from_utf8(SecretKey::from_binary(rand::random()).to_encoding())?,
+	};
+
+	let watched_accounts = opts
+		.watch_account
+		.iter()
+		.map(|account| {
+			PublicKey::from_encoding(account)
+				.map_err(|e| format!("invalid account to watch {account}: {e:?}"))
+		})
+		.collect::<Result<Vec<_>, _>>()?;
+
+	let scp_store = match &opts.scp_store_path {
+		Some(path) => Some(ScpStore::open(path, opts.scp_store_max_age_in_slots)?),
+		None => None,
+	};
+
+	let shutdown_sender = ShutdownSender::new();
+	let agent = Arc::new(
+		OracleAgent::new_with_scp_store(&cfg, secret_key, shutdown_sender.clone(), scp_store)
+			.await?,
+	);
+	for account in &watched_accounts {
+		agent.watch_account(account).await;
+	}
+
+	// the server stops with the connection to the overlay; the process is meant to be restarted
+	let mut shutdown = shutdown_sender.subscribe();
+	tokio::spawn(listen_for_stellar_messages(agent.clone(), shutdown_sender));
+
+	let (address, server) = warp::serve(proof_server_routes(agent))
+		.try_bind_with_graceful_shutdown(opts.address, async move {
+			let _ = shutdown.recv().await;
+		})?;
+	tracing::info!("main(): serving proofs at http://{address}");
+
+	tokio::select! {
+		_ = server => Err("the connection to the Stellar overlay was lost".into()),
+		_ = tokio::signal::ctrl_c() => {
+			tracing::info!("main(): shutting down");
+			Ok(())
+		},
+	}
+}
@@ -43,6 +43,8 @@ pub enum Error {
 
 	#[error("StdIoError: {0}")]
 	StdIoError(#[from] std::io::Error),
+	#[error("SerdeJsonError: {0}")]
+	SerdeJsonError(#[from] serde_json::Error),
 }
 
 impl From<Error> for service::Error<Error> {
@@ -5,7 +5,7 @@ use sp_runtime::traits::StaticLookup;
 
 use primitives::{derive_shortened_request_id, get_text_memo_from_tx_env, TransactionEnvelopeExt};
 use runtime::{
-	CancelIssueEvent, ExecuteIssueEvent, IssueIdLookup, IssuePallet, IssueRequestsMap,
+	CancelIssueEvent, ExecuteIssueEvent, IssueId, IssueIdLookup, IssuePallet, IssueRequestsMap,
 	RequestIssueEvent, SpacewalkParachain, StellarPublicKeyRaw,
 };
 use service::Error as ServiceError;
@@ -14,7 +14,11 @@ use wallet::{
 	types::FilterWith, LedgerTxEnvMap, Slot, SlotTask, SlotTaskStatus, TransactionResponse,
 };
 
-use crate::{oracle::OracleAgent, tokio_spawn, ArcRwLock, Error, Event};
+use crate::{
+	journal::{RequestJournal, RequestKind, RequestState},
+	oracle::{verify_proof, OracleAgent},
+	tokio_spawn, ArcRwLock, Error, Event,
+};
 
 fn is_vault(p1: &PublicKey, p2_raw: [u8; 32]) -> bool {
 	return *p1.as_binary() == p2_raw;
@@ -45,6 +49,48 @@ pub(crate) async fn initialize_issue_set(
 	Ok(())
 }
 
+/// Puts the journaled payments of the open issues back into the `ledger_env_map`, so that they
+/// are executed without waiting for the wallet to find them again.
+/// The issues that the journal has in flight but are not open anymore are marked as failed.
+pub(crate) async fn resume_journaled_issues(
+	journal: &RequestJournal,
+	issue_set: &ArcRwLock<IssueRequestsMap>,
+	ledger_env_map: &ArcRwLock<LedgerTxEnvMap>,
+) {
+	let issue_set = issue_set.read().await;
+
+	for entry in journal.in_flight() {
+		if entry.kind == RequestKind::Issue && !issue_set.contains_key(&entry.request_id) {
+			tracing::info!(
+				"resume_journaled_issues(): Issue #{:?} is not open anymore, last journaled as {:?}",
+				entry.request_id,
+				entry.state
+			);
+			journal.record(
+				entry.request_id,
+				entry.kind,
+				RequestState::failed("The request is not open on the parachain anymore"),
+			);
+		}
+	}
+
+	let mut ledger_env_map = ledger_env_map.write().await;
+	for issue_id in issue_set.keys() {
+		if let Some((slot, tx_env)) = journal.payment(*issue_id) {
+			tracing::info!(
+				"resume_journaled_issues(): resuming Issue #{issue_id:?} with the payment of slot {slot}"
+			);
+			ledger_env_map.insert(slot, tx_env);
+		}
+	}
+}
+
+fn record_issue(journal: Option<&RequestJournal>, issue_id: IssueId, state: RequestState) {
+	if let Some(journal) = journal {
+		journal.record(issue_id, RequestKind::Issue, state);
+	}
+}
+
 /// Listens for RequestIssueEvent directed at the vault.
 ///
 /// # Arguments
@@ -54,12 +100,14 @@ pub(crate) async fn initialize_issue_set(
 /// * `event_channel` - the channel over which to signal events
 /// * `issues` - a map to save all the new issue requests
 /// * `memos_to_issue_ids` - map of issue memo to issue id
+/// * `journal` - records the progress of the issue requests
 pub async fn listen_for_issue_requests(
 	parachain_rpc: SpacewalkParachain,
 	vault_public_key: PublicKey,
 	event_channel: Sender<Event>,
 	issues: ArcRwLock<IssueRequestsMap>,
 	memos_to_issue_ids: ArcRwLock<IssueIdLookup>,
+	journal: Option<RequestJournal>,
 ) -> Result<(), ServiceError<Error>> {
 	tracing::info!("listen_for_issue_requests(): started");
 	// Use references to prevent 'moved closure' errors
@@ -68,6 +116,7 @@ pub async fn listen_for_issue_requests(
 	let issues = &issues;
 	let memos_to_issue_ids = &memos_to_issue_ids;
 	let event_channel = &event_channel;
+	let journal = journal.as_ref();
 
 	parachain_rpc
 		.on_event::<RequestIssueEvent, _, _, _>(
@@ -89,6 +138,9 @@ pub async fn listen_for_issue_requests(
 						issues.insert(event.issue_id, issue_request);
 						let shortened_request_id = derive_shortened_request_id(&event.issue_id.0);
 						memos_to_issue_ids.insert(shortened_request_id, event.issue_id);
+						if let Some(journal) = journal {
+							journal.record_detected(event.issue_id, RequestKind::Issue);
+						}
 
 						// try to send the event, but ignore the returned result since
 						// the only way it can fail is if the channel is closed
@@ -250,12 +302,14 @@ async fn cleanup_ledger_env_map(
 /// * `oracle_agent` - the agent used to get the proofs
 /// * `ledger_env_map` -  a list of TransactionEnvelopes and its corresponding ledger it belongs to
 /// * `issues` - a map of all issue requests
+/// * `journal` - records the progress of the issue requests
 pub async fn process_issues_requests(
 	parachain_rpc: SpacewalkParachain,
 	oracle_agent: Arc<OracleAgent>,
 	ledger_env_map: ArcRwLock<LedgerTxEnvMap>,
 	issues: ArcRwLock<IssueRequestsMap>,
 	memos_to_issue_ids: ArcRwLock<IssueIdLookup>,
+	journal: Option<RequestJournal>,
 ) -> Result<(), ServiceError<Error>> {
 	tracing::info!("process_issue_requests(): started");
 	// collects all the tasks that are executed or about to be executed.
@@ -282,6 +336,7 @@ pub async fn process_issues_requests(
 					oracle_agent.clone(),
 					*slot,
 					sender,
+					journal.clone(),
 				),
 			);
 		}
@@ -303,6 +358,8 @@ pub async fn process_issues_requests(
 /// * `issues` - a map of all issue requests
 /// * `oracle_agent` - the agent used to get the proofs
 /// * `slot` - the slot of the transaction envelope it belongs to
+/// * `journal` - records the progress of the issue request
+#[allow(clippy::too_many_arguments)]
 pub async fn execute_issue(
 	parachain_rpc: SpacewalkParachain,
 	tx_env: TransactionEnvelope,
@@ -311,7 +368,24 @@ pub async fn execute_issue(
 	oracle_agent: Arc<OracleAgent>,
 	slot: Slot,
 	sender: tokio::sync::oneshot::Sender<SlotTaskStatus>,
+	journal: Option<RequestJournal>,
 ) {
+	let (issue_id, text_memo) = match get_text_memo_from_tx_env(&tx_env) {
+		Some(text_memo) => (
+			memos_to_issue_ids.read().await.get(text_memo).map(|&issue_id| issue_id),
+			Some(text_memo),
+		),
+		_ => (None, None),
+	};
+
+	let journal = journal.as_ref();
+	if let (Some(journal), Some(issue_id)) = (journal, issue_id) {
+		// the task is retried until it succeeds; the payment is journaled once
+		if journal.payment(issue_id).is_none() {
+			journal.record(issue_id, RequestKind::Issue, RequestState::paid(slot, &tx_env));
+		}
+	}
+
 	// Get the proof of the given slot
 	let proof =
 		match oracle_agent.get_proof(slot).await {
@@ -325,6 +399,15 @@ pub async fn execute_issue(
 			},
 		};
 
+	// Do not pay for an extrinsic that the pallet is going to reject
+	if let Err(e) = verify_proof(&parachain_rpc, &proof, &tx_env).await {
+		tracing::error!("Could not execute Issue for slot {slot} due to invalid proof: {e:?}");
+		if let Err(e) = sender.send(SlotTaskStatus::RecoverableError) {
+			tracing::error!("Execute Issue for slot {slot}: Failed to send {e:?} status.");
+		}
+		return;
+	}
+
 	let (envelopes, tx_set) = proof.encode();
 
 	let tx_env_encoded = {
@@ -332,15 +415,9 @@ pub async fn execute_issue(
 		base64::encode(tx_env_xdr)
 	};
 
-	let (issue_id, text_memo) = match get_text_memo_from_tx_env(&tx_env) {
-		Some(text_memo) => (
-			memos_to_issue_ids.read().await.get(text_memo).map(|&issue_id| issue_id),
-			Some(text_memo),
-		),
-		_ => (None, None),
-	};
-
 	if let (Some(issue_id), Some(text_memo)) = (issue_id, text_memo) {
+		record_issue(journal, issue_id, RequestState::ProofBuilt { slot });
+
 		// calls the execute_issue of the `Issue` Pallet
 		match parachain_rpc
 			.execute_issue(
@@ -353,6 +430,7 @@ pub async fn execute_issue(
 		{
 			Ok(_) => {
 				tracing::info!("Successfully executed Issue #{issue_id:?} for slot {slot}");
+				record_issue(journal, issue_id, RequestState::Executed);
 
 				let (mut issues, mut memos_to_issue_ids) =
 					future::join(issues.write(), memos_to_issue_ids.write()).await;
@@ -370,6 +448,7 @@ pub async fn execute_issue(
 				tracing::debug!(
 					"Execute Issue #{issue_id:?} for slot {slot} was completed previously."
 				);
+				record_issue(journal, issue_id, RequestState::Executed);
 				if let Err(e) = sender.send(SlotTaskStatus::Success) {
 					tracing::error!(
 						"Execute Issue #{issue_id:?} for slot {slot}: Failed to send {e:?} status"
@@ -378,6 +457,7 @@ pub async fn execute_issue(
 				return;
 			},
 			Err(e) => {
+				record_issue(journal, issue_id, RequestState::failed(format!("{e:?}")));
 				if let Err(e) = sender.send(SlotTaskStatus::Failed(format!("{:?}", e))) {
 					tracing::error!(
 						"Execute Issue #{issue_id:?} for slot {slot}: Failed to send {e:?} status"
@@ -0,0 +1,468 @@
+use std::{
+	collections::HashMap,
+	fs::{self, File, OpenOptions},
+	io::{ErrorKind, Write},
+	path::{Path, PathBuf},
+	sync::{
+		mpsc::{sync_channel, SyncSender},
+		Arc,
+	},
+	thread,
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+use parking_lot::Mutex;
+use runtime::H256;
+use serde::{Deserialize, Serialize};
+use stellar_relay_lib::sdk::{TransactionEnvelope, XdrCodec};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use wallet::Slot;
+
+use crate::Error;
+
+const JOURNAL_FILE: &str = "requests.jsonl";
+
+/// The kinds of requests that the vault works on
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RequestKind {
+	Issue,
+	Redeem,
+	Replace,
+}
+
+/// The steps a request goes through: detected → paid → proof built → executed or failed
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum RequestState {
+	/// the vault learned about the request
+	Detected,
+	/// The Stellar payment of the request was made (redeem, replace) or received (issue).
+	/// The envelope is kept as base64 XDR, so that the request is executed with it after a
+	/// restart instead of being paid again.
+	Paid {
+		slot: Slot,
+		tx_env: String,
+	},
+	/// the proof of the slot of the payment was built
+	ProofBuilt {
+		slot: Slot,
+	},
+	Executed,
+	Failed {
+		reason: String,
+	},
+}
+
+impl RequestState {
+	pub fn paid(slot: Slot, tx_env: &TransactionEnvelope) -> Self {
+		RequestState::Paid { slot, tx_env: base64::encode(tx_env.to_xdr()) }
+	}
+
+	pub fn failed(reason: impl ToString) -> Self {
+		RequestState::Failed { reason: reason.to_string() }
+	}
+
+	/// Whether nothing is left to do for the request
+	pub fn is_final(&self) -> bool {
+		matches!(self, RequestState::Executed | RequestState::Failed { .. })
+	}
+}
+
+/// A line of the journal
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct JournalEntry {
+	/// seconds since the unix epoch
+	pub timestamp: u64,
+	#[serde(with = "hex_hash")]
+	pub request_id: H256,
+	pub kind: RequestKind,
+	#[serde(flatten)]
+	pub state: RequestState,
+}
+
+impl JournalEntry {
+	/// Returns the slot and the envelope of a `Paid` entry
+	pub fn payment(&self) -> Option<(Slot, TransactionEnvelope)> {
+		let RequestState::Paid { slot, tx_env } = &self.state else { return None };
+
+		match TransactionEnvelope::from_base64_xdr(tx_env.as_bytes()) {
+			Ok(tx_env) => Some((*slot, tx_env)),
+			Err(e) => {
+				tracing::warn!(
+					"payment(): invalid envelope journaled for request #{:?}: {e:?}",
+					self.request_id
+				);
+				None
+			},
+		}
+	}
+}
+
+/// The entries of each request, the oldest first. Executed requests are dropped, and of a failed
+/// request only its payment is kept; the full history stays on disk.
+type Requests = HashMap<H256, Vec<JournalEntry>>;
+
+/// An append-only journal of the steps the vault took for each issue, redeem and replace request.
+/// It is replayed on startup, so that the requests continue where they were left off, and serves as
+/// an audit trail of the actions of the vault.
+/// The entries are written to disk by a thread of their own, in the order they were recorded.
+#[derive(Clone, Debug)]
+pub struct RequestJournal {
+	path: PathBuf,
+	requests: Arc<Mutex<Requests>>,
+	writer: UnboundedSender<JournalOp>,
+}
+
+/// The writes that the `RequestJournal` leaves to its writer thread
+enum JournalOp {
+	Append(Vec<u8>),
+	Flush(SyncSender<()>),
+}
+
+impl RequestJournal {
+	pub fn open(dir: impl Into<PathBuf>) -> Result<Self, Error> {
+		let dir = dir.into();
+		fs::create_dir_all(&dir)?;
+		let path = dir.join(JOURNAL_FILE);
+
+		let mut requests = Requests::new();
+		let (entries, is_complete) = Self::read_entries(&path)?;
+		for entry in entries {
+			Self::apply(&mut requests, entry);
+		}
+
+		let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+		// the last line is cut short if the vault stopped while writing it; end it, so that the
+		// next entry starts on a line of its own
+		if !is_complete {
+			file.write_all(b"\n")?;
+		}
+		tracing::info!("open(): replayed the journal of {} requests at {path:?}", requests.len());
+
+		let (writer, ops) = unbounded_channel();
+		let journal_writer = JournalWriter { file };
+		thread::Builder::new()
+			.name("request-journal-writer".to_string())
+			.spawn(move || journal_writer.run(ops))?;
+
+		Ok(RequestJournal { path, requests: Arc::new(Mutex::new(requests)), writer })
+	}
+
+	/// Returns the entries of the journal, and whether its last line is complete
+	fn read_entries(path: &Path) -> Result<(Vec<JournalEntry>, bool), Error> {
+		let bytes = match fs::read(path) {
+			Ok(bytes) => bytes,
+			Err(e) if e.kind() == ErrorKind::NotFound => return Ok((vec![], true)),
+			Err(e) => return Err(e.into()),
+		};
+
+		let mut entries = vec![];
+		for line in bytes.split(|byte| *byte == b'\n') {
+			if line.iter().all(u8::is_ascii_whitespace) {
+				continue
+			}
+
+			match serde_json::from_slice(line) {
+				Ok(entry) => entries.push(entry),
+				Err(e) => tracing::warn!(
+					"read_entries(): skipping invalid line {:?}: {e:?}",
+					String::from_utf8_lossy(line)
+				),
+			}
+		}
+
+		let is_complete = bytes.last().map_or(true, |byte| *byte == b'\n');
+		Ok((entries, is_complete))
+	}
+
+	fn apply(requests: &mut Requests, entry: JournalEntry) {
+		match entry.state {
+			RequestState::Executed => {
+				requests.remove(&entry.request_id);
+			},
+			// the payment is kept, so that the request is not paid again if it is retried
+			RequestState::Failed { .. } => {
+				let payment = requests.remove(&entry.request_id).and_then(|entries| {
+					entries
+						.into_iter()
+						.rev()
+						.find(|journaled| matches!(journaled.state, RequestState::Paid { .. }))
+				});
+				if let Some(payment) = payment {
+					requests.insert(entry.request_id, vec![payment, entry]);
+				}
+			},
+			_ => requests.entry(entry.request_id).or_default().push(entry),
+		}
+	}
+
+	/// Appends the new state of the request to the journal
+	pub fn record(&self, request_id: H256, kind: RequestKind, state: RequestState) {
+		if let Err(e) = self.try_record(request_id, kind, state) {
+			tracing::warn!("record(): failed to journal request #{request_id:?}: {e:?}");
+		}
+	}
+
+	fn try_record(
+		&self,
+		request_id: H256,
+		kind: RequestKind,
+		state: RequestState,
+	) -> Result<(), Error> {
+		let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+		let entry = JournalEntry { timestamp, request_id, kind, state };
+
+		let mut line = serde_json::to_vec(&entry)?;
+		line.push(b'\n');
+
+		// the lines are sent with the lock held, so that they are written in the order of `requests`
+		let mut requests = self.requests.lock();
+		self.send(JournalOp::Append(line));
+		Self::apply(&mut requests, entry);
+
+		Ok(())
+	}
+
+	fn send(&self, op: JournalOp) {
+		if self.writer.send(op).is_err() {
+			tracing::warn!("send(): the writer of the request journal is gone");
+		}
+	}
+
+	/// Waits until everything recorded so far is written
+	pub fn flush(&self) {
+		let (done_sender, done) = sync_channel(1);
+		self.send(JournalOp::Flush(done_sender));
+		let _ = done.recv();
+	}
+
+	/// Records `Detected` for a request that the journal holds no entries of
+	pub fn record_detected(&self, request_id: H256, kind: RequestKind) {
+		if self.latest(request_id).is_none() {
+			self.record(request_id, kind, RequestState::Detected);
+		}
+	}
+
+	/// Returns the last entry of the request, unless it was executed, or failed before it was paid
+	pub fn latest(&self, request_id: H256) -> Option<JournalEntry> {
+		self.requests.lock().get(&request_id).and_then(|entries| entries.last()).cloned()
+	}
+
+	/// Returns the slot and the envelope of the payment of the request, if one was journaled and the
+	/// request was not executed since
+	pub fn payment(&self, request_id: H256) -> Option<(Slot, TransactionEnvelope)> {
+		self.requests.lock().get(&request_id)?.iter().rev().find_map(JournalEntry::payment)
+	}
+
+	/// Returns the last entry of each request that is neither executed nor failed, the oldest
+	/// first
+	pub fn in_flight(&self) -> Vec<JournalEntry> {
+		let mut in_flight: Vec<JournalEntry> = self
+			.requests
+			.lock()
+			.values()
+			.filter_map(|entries| entries.last())
+			.filter(|entry| !entry.state.is_final())
+			.cloned()
+			.collect();
+		in_flight.sort_by_key(|entry| entry.timestamp);
+		in_flight
+	}
+
+	/// Returns every entry of the request, the oldest first
+	pub fn history(&self, request_id: H256) -> Result<Vec<JournalEntry>, Error> {
+		self.flush();
+		let (entries, _) = Self::read_entries(&self.path)?;
+
+		Ok(entries.into_iter().filter(|entry| entry.request_id == request_id).collect())
+	}
+}
+
+/// Appends the lines of the `RequestJournal` to its file, off the async runtime
+struct JournalWriter {
+	file: File,
+}
+
+impl JournalWriter {
+	fn run(mut self, mut ops: UnboundedReceiver<JournalOp>) {
+		while let Some(op) = ops.blocking_recv() {
+			let mut batch = vec![op];
+			while let Ok(op) = ops.try_recv() {
+				batch.push(op);
+			}
+
+			// the lines of a batch are synced to the disk once
+			let mut appended = false;
+			let mut flushed = vec![];
+			for op in batch {
+				match op {
+					JournalOp::Append(line) => match self.file.write_all(&line) {
+						Ok(()) => appended = true,
+						Err(e) => tracing::warn!("run(): failed to append to the journal: {e:?}"),
+					},
+					JournalOp::Flush(done) => flushed.push(done),
+				}
+			}
+
+			if appended {
+				if let Err(e) = self.file.sync_data() {
+					tracing::warn!("run(): failed to sync the journal: {e:?}");
+				}
+			}
+			for done in flushed {
+				let _ = done.send(());
+			}
+		}
+	}
+}
+
+mod hex_hash {
+	use std::convert::TryInto;
+
+	use runtime::H256;
+	use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+	pub fn serialize<S: Serializer>(hash: &H256, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_str(&hex::encode(hash.0))
+	}
+
+	pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<H256, D::Error> {
+		let hash = String::deserialize(deserializer)?;
+		let bytes: [u8; 32] = hex::decode(&hash)
+			.ok()
+			.and_then(|bytes| bytes.try_into().ok())
+			.ok_or_else(|| D::Error::custom(format!("invalid request id {hash}")))?;
+
+		Ok(H256(bytes))
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use stellar_relay_lib::sdk::{types::Preconditions, PublicKey, Transaction};
+
+	fn journal() -> (tempdir::TempDir, RequestJournal) {
+		let dir = tempdir::TempDir::new("request-journal-").expect("failed to create tempdir");
+		let journal = RequestJournal::open(dir.path()).expect("should open the journal");
+		(dir, journal)
+	}
+
+	fn tx_env() -> TransactionEnvelope {
+		let source = PublicKey::PublicKeyTypeEd25519([1; 32]);
+		Transaction::new(source, 1, None, Preconditions::PrecondNone, None)
+			.expect("should create a transaction")
+			.into_transaction_envelope()
+	}
+
+	#[test]
+	fn requests_continue_after_reopening() {
+		let (dir, journal) = journal();
+		let paid = H256([1; 32]);
+		let executed = H256([2; 32]);
+		let failed = H256([3; 32]);
+		let failed_after_paying = H256([4; 32]);
+
+		journal.record_detected(paid, RequestKind::Redeem);
+		journal.record(paid, RequestKind::Redeem, RequestState::paid(100, &tx_env()));
+		journal.record(paid, RequestKind::Redeem, RequestState::ProofBuilt { slot: 100 });
+
+		journal.record_detected(executed, RequestKind::Issue);
+		journal.record(executed, RequestKind::Issue, RequestState::paid(101, &tx_env()));
+		journal.record(executed, RequestKind::Issue, RequestState::Executed);
+
+		journal.record_detected(failed, RequestKind::Replace);
+		journal.record(failed, RequestKind::Replace, RequestState::failed("Deadline has expired"));
+
+		journal.record_detected(failed_after_paying, RequestKind::Redeem);
+		journal.record(
+			failed_after_paying,
+			RequestKind::Redeem,
+			RequestState::paid(102, &tx_env()),
+		);
+		journal.record(failed_after_paying, RequestKind::Redeem, RequestState::failed("timeout"));
+		journal.flush();
+		drop(journal);
+
+		let journal = RequestJournal::open(dir.path()).expect("should reopen the journal");
+
+		let in_flight = journal.in_flight();
+		assert_eq!(in_flight.len(), 1);
+		assert_eq!(in_flight[0].request_id, paid);
+		assert_eq!(in_flight[0].state, RequestState::ProofBuilt { slot: 100 });
+
+		// the request is executed with the journaled payment instead of being paid again
+		assert_eq!(journal.payment(paid), Some((100, tx_env())));
+		assert_eq!(journal.payment(executed), None);
+		assert_eq!(journal.payment(failed), None);
+		// the failed request may be retried, without paying again
+		assert_eq!(journal.payment(failed_after_paying), Some((102, tx_env())));
+
+		// the requests that are done with are not kept in memory
+		assert!(journal.latest(executed).is_none());
+		assert!(journal.latest(failed).is_none());
+		assert!(journal.latest(H256([5; 32])).is_none());
+	}
+
+	#[test]
+	fn history_keeps_every_step() {
+		let (_dir, journal) = journal();
+		let request_id = H256([1; 32]);
+
+		journal.record_detected(request_id, RequestKind::Redeem);
+		// detecting the request again does not add an entry
+		journal.record_detected(request_id, RequestKind::Redeem);
+		journal.record(request_id, RequestKind::Redeem, RequestState::paid(100, &tx_env()));
+		journal.record(request_id, RequestKind::Redeem, RequestState::ProofBuilt { slot: 100 });
+		journal.record(request_id, RequestKind::Redeem, RequestState::Executed);
+		journal.record_detected(H256([2; 32]), RequestKind::Issue);
+
+		let states: Vec<RequestState> = journal
+			.history(request_id)
+			.expect("should read the history")
+			.into_iter()
+			.map(|entry| entry.state)
+			.collect();
+		assert_eq!(
+			states,
+			vec![
+				RequestState::Detected,
+				RequestState::paid(100, &tx_env()),
+				RequestState::ProofBuilt { slot: 100 },
+				RequestState::Executed,
+			]
+		);
+	}
+
+	#[test]
+	fn invalid_lines_are_skipped() {
+		let (dir, journal) = journal();
+		let request_id = H256([1; 32]);
+		journal.record(request_id, RequestKind::Issue, RequestState::paid(100, &tx_env()));
+		journal.flush();
+		drop(journal);
+
+		// a line that was cut short by a crash
+		let path = dir.path().join(JOURNAL_FILE);
+		let mut file = OpenOptions::new().append(true).open(&path).expect("should open the file");
+		file.write_all(b"{\"timestamp\":1,\"request_id\":").expect("should write");
+		drop(file);
+
+		let journal = RequestJournal::open(dir.path()).expect("should reopen the journal");
+		assert_eq!(journal.payment(request_id), Some((100, tx_env())));
+
+		// the entries after the cut line are read again
+		journal.record(request_id, RequestKind::Issue, RequestState::ProofBuilt { slot: 100 });
+		let history = journal.history(request_id).expect("should read the history");
+		assert_eq!(history.len(), 2);
+
+		let line = serde_json::to_string(&history[0]).unwrap();
+		assert!(line.contains(&format!("\"request_id\":\"{}\"", hex::encode([1u8; 32]))));
+		assert!(line.contains("\"kind\":\"issue\""));
+		assert!(line.contains("\"state\":\"paid\""));
+
+		let line = serde_json::to_string(&journal.latest(request_id).unwrap()).unwrap();
+		assert!(line.contains("\"state\":\"proof_built\""));
+	}
+}
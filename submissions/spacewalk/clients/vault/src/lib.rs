@@ -12,6 +12,7 @@ pub use crate::{cancellation::Event, error::Error};
 
 mod cancellation;
 mod error;
+pub mod journal;
 pub mod metrics;
 pub mod process;
 mod redeem;
@@ -271,6 +271,7 @@ pub fn register_custom_metrics() -> Result<(), RuntimeError> {
 	REGISTRY.register(Box::new(MEAN_SCHEDULED_DURATION.clone()))?;
 	REGISTRY.register(Box::new(RESTART_COUNT.clone()))?;
 	REGISTRY.register(Box::new(LIQUIDATED.clone()))?;
+	stellar_relay_lib::metrics::register_overlay_metrics(&REGISTRY)?;
 
 	Ok(())
 }
@@ -1,20 +1,26 @@
 use std::{sync::Arc, time::Duration};
 
 use primitives::stellar::StellarTypeToBase64String;
-use runtime::ShutdownSender;
+use runtime::{ShutdownSender, StellarPublicKeyRaw};
 use stellar_relay_lib::{
-	connect_to_stellar_overlay_network, sdk::types::StellarMessage, StellarOverlayConfig,
-	StellarOverlayConnection,
+	connect_to_stellar_overlay_network, connect_to_stellar_overlay_pool,
+	sdk::{types::StellarMessage, PublicKey},
+	ConnectionEvent, StellarOverlayConfig, StellarOverlayConnection, StellarOverlayPool,
+	StellarOverlayRequester,
 };
 use tokio::{
-	sync::RwLock,
-	time::{sleep, timeout, Instant},
+	sync::{mpsc, RwLock},
+	time::{timeout, Instant},
 };
 use tracing::error;
 
 use crate::{
 	oracle::{
-		collector::ScpMessageCollector, errors::Error, types::StellarMessageSender, AddTxSet, Proof,
+		collector::ScpMessageCollector,
+		errors::Error,
+		types::{IndexedTransaction, StellarMessageSender, TxHash},
+		AddTxSet, ArchiveProofSource, CollectorProofSource, MirrorHealth, Proof, ProofSource,
+		ScpStore, ValidatorSet,
 	},
 	ArcRwLock,
 };
@@ -24,82 +30,279 @@ use wallet::Slot;
 const STELLAR_RELAY_HEALTH_CHECK_IN_SECS: u64 = 300;
 /// The waiting time for reading messages from the overlay.
 static STELLAR_MESSAGES_TIMEOUT_IN_SECS: u64 = 60;
+/// How long the collector gets to build a proof, before the next proof source is asked
+#[cfg(test)]
+const COLLECTOR_PROOF_TIMEOUT_IN_SECS: u64 = 180;
+#[cfg(not(test))]
+const COLLECTOR_PROOF_TIMEOUT_IN_SECS: u64 = 60;
+/// How long the history archives get to provide a proof.
+/// A proof server waits for both its collector and the archives, so the default of
+/// `PROOF_SERVER_TIMEOUT_SECS` has to stay above the sum of the two.
+const ARCHIVE_PROOF_TIMEOUT_IN_SECS: u64 = 60;
+/// The number of Stellar Nodes to stay connected to, when several are configured
+const OVERLAY_POOL_SIZE: usize = 3;
+
+/// The connection to the overlay: to a single Stellar Node, or to a pool of them when the config
+/// has several endpoints or a peer book
+enum OverlayConnection {
+	Node(StellarOverlayConnection),
+	Pool(StellarOverlayPool),
+}
+
+impl OverlayConnection {
+	async fn connect(
+		config: &StellarOverlayConfig,
+		secret_key_as_string: String,
+	) -> Result<Self, Error> {
+		let conn_info = &config.connection_info;
+		if conn_info.endpoints.len() > 1 || conn_info.peer_book_path.is_some() {
+			let pool = connect_to_stellar_overlay_pool(
+				config.clone(),
+				secret_key_as_string,
+				OVERLAY_POOL_SIZE,
+			)
+			.await?;
+			return Ok(OverlayConnection::Pool(pool))
+		}
+
+		let conn = connect_to_stellar_overlay_network(config.clone(), secret_key_as_string).await?;
+		Ok(OverlayConnection::Node(conn))
+	}
+
+	fn sender(&self) -> StellarMessageSender {
+		match self {
+			OverlayConnection::Node(conn) => conn.sender(),
+			OverlayConnection::Pool(pool) => pool.sender(),
+		}
+	}
+
+	fn requester(&self) -> StellarOverlayRequester {
+		match self {
+			OverlayConnection::Node(conn) => conn.requester(),
+			OverlayConnection::Pool(pool) => pool.requester(),
+		}
+	}
+
+	/// The pool handles the events of its peers itself
+	fn take_event_receiver(&mut self) -> Option<mpsc::Receiver<ConnectionEvent>> {
+		match self {
+			OverlayConnection::Node(conn) => conn.take_event_receiver(),
+			OverlayConnection::Pool(_) => None,
+		}
+	}
+
+	async fn listen(&mut self) -> Result<Option<StellarMessage>, stellar_relay_lib::Error> {
+		match self {
+			OverlayConnection::Node(conn) => conn.listen().await,
+			OverlayConnection::Pool(pool) => pool.listen().await,
+		}
+	}
+
+	fn stop(&mut self) {
+		match self {
+			OverlayConnection::Node(conn) => conn.stop(),
+			OverlayConnection::Pool(pool) => pool.stop(),
+		}
+	}
+}
 
 pub struct OracleAgent {
 	pub collector: ArcRwLock<ScpMessageCollector>,
 	pub is_public_network: bool,
 	/// sends message directly to Stellar Node
 	message_sender: StellarMessageSender,
-	overlay_conn: ArcRwLock<StellarOverlayConnection>,
+	/// requests missing data from Stellar Node, and waits for the reply
+	overlay_requester: StellarOverlayRequester,
+	overlay_conn: ArcRwLock<OverlayConnection>,
 	/// sends an entire Vault shutdown
 	shutdown_sender: ShutdownSender,
+	/// where `get_proof` takes the proofs from, the first one first
+	proof_sources: RwLock<Vec<Box<dyn ProofSource>>>,
 }
 
 impl OracleAgent {
-	// the interval for every build_proof retry
-	const BUILD_PROOF_INTERVAL: u64 = 10;
-
 	pub async fn new(
 		config: &StellarOverlayConfig,
 		secret_key_as_string: String,
 		shutdown_sender: ShutdownSender,
+	) -> Result<Self, Error> {
+		Self::new_with_scp_store(config, secret_key_as_string, shutdown_sender, None).await
+	}
+
+	/// Like `new`, but the collected envelopes and tx sets are also kept in the `store`, and the
+	/// ones kept before a restart are loaded from it.
+	pub async fn new_with_scp_store(
+		config: &StellarOverlayConfig,
+		secret_key_as_string: String,
+		shutdown_sender: ShutdownSender,
+		store: Option<ScpStore>,
 	) -> Result<Self, Error> {
 		let is_public_network = config.is_public_network();
 
-		let collector = Arc::new(RwLock::new(ScpMessageCollector::new(
-			is_public_network,
-			config.stellar_history_archive_urls(),
-		)));
+		let mut collector =
+			ScpMessageCollector::new(is_public_network, config.stellar_history_archive_urls());
+		if let Some(store) = store {
+			collector = collector.with_store(store);
+		}
+		let collector = Arc::new(RwLock::new(collector));
 
-		let overlay_conn =
-			connect_to_stellar_overlay_network(config.clone(), secret_key_as_string).await?;
+		let overlay_conn = OverlayConnection::connect(config, secret_key_as_string).await?;
 		let message_sender = overlay_conn.sender();
+		let overlay_requester = overlay_conn.requester();
 
 		let overlay_conn = Arc::new(RwLock::new(overlay_conn));
 
+		let archive_client = collector.read().await.archive_client();
+		let proof_sources: Vec<Box<dyn ProofSource>> = vec![
+			Box::new(CollectorProofSource::new(
+				collector.clone(),
+				overlay_requester.clone(),
+				Duration::from_secs(COLLECTOR_PROOF_TIMEOUT_IN_SECS),
+			)),
+			Box::new(ArchiveProofSource::new(
+				archive_client,
+				Duration::from_secs(ARCHIVE_PROOF_TIMEOUT_IN_SECS),
+			)),
+		];
+
 		Ok(OracleAgent {
 			collector,
 			is_public_network,
 			message_sender,
+			overlay_requester,
 			overlay_conn,
 			shutdown_sender,
+			proof_sources: RwLock::new(proof_sources),
 		})
 	}
 
 	/// This method returns the proof for a given slot or an error if the proof cannot be provided.
-	/// The agent will try every possible way to get the proof before returning an error.
+	/// The agent asks every one of its proof sources, in order, before returning an error.
 	pub async fn get_proof(&self, slot: Slot) -> Result<Proof, Error> {
-		let collector = self.collector.clone();
-
-		#[cfg(test)]
-		let timeout_seconds = 180;
-
-		#[cfg(not(test))]
-		let timeout_seconds = 60;
-
-		timeout(Duration::from_secs(timeout_seconds), async move {
-			loop {
-				tracing::debug!("get_proof(): attempt to build proof for slot {slot}");
-				let collector = collector.read().await;
-				match collector.build_proof(slot, &self.message_sender).await {
-					None => {
-						drop(collector);
-						// give enough interval for every retry
-						sleep(Duration::from_secs(OracleAgent::BUILD_PROOF_INTERVAL)).await;
-						continue
-					},
-					Some(proof) => {
-						tracing::info!("get_proof(): Successfully build proof for slot {slot}");
-						tracing::trace!("  with proof: {proof:?}");
-						return Ok(proof)
-					},
+		let cached_proof = self.collector.read().await.cached_proof(slot);
+		if let Some(proof) = cached_proof {
+			// the validators may have changed since it was cached
+			match self.check_proof(slot, &proof).await {
+				Ok(()) => {
+					tracing::debug!("get_proof(): found the proof of slot {slot} in the cache");
+					return Ok(proof)
+				},
+				Err(e) => tracing::warn!(
+					"get_proof(): the cached proof of slot {slot} is not valid anymore: {e:?}"
+				),
+			}
+		}
+
+		for source in self.proof_sources.read().await.iter() {
+			let name = source.name();
+			let proof = match timeout(source.timeout(), source.get_proof(slot)).await {
+				Ok(Ok(proof)) => proof,
+				Ok(Err(e)) => {
+					tracing::warn!("get_proof(): {name} has no proof of slot {slot}: {e:?}");
+					continue
+				},
+				Err(_) => {
+					tracing::warn!("get_proof(): {name} timed out on the proof of slot {slot}");
+					continue
+				},
+			};
+
+			if source.is_external() {
+				if let Err(e) = self.check_proof(slot, &proof).await {
+					tracing::error!(
+						"get_proof(): {name} sent an invalid proof of slot {slot}: {e:?}"
+					);
+					continue
 				}
 			}
-		})
-		.await
-		.map_err(|_| {
-			Error::ProofTimeout(format!("Timeout elapsed for building proof of slot {slot}"))
-		})?
+
+			tracing::info!("get_proof(): Successfully got the proof for slot {slot} from {name}");
+			tracing::trace!("  with proof: {proof:?}");
+			return Ok(proof)
+		}
+
+		Err(Error::ProofTimeout(format!("No proof source could provide the proof of slot {slot}")))
+	}
+
+	/// Checks a proof that was cached, or not built by the collector, against the validators. While
+	/// the validators are not read from the chain, enough nodes have to sign it instead.
+	async fn check_proof(&self, slot: Slot, proof: &Proof) -> Result<(), Error> {
+		if proof.slot() != slot {
+			return Err(Error::Other(format!("Got the proof of slot {}", proof.slot())))
+		}
+
+		let collector = self.collector.read().await;
+		match collector.validator_set() {
+			Some(validators) if !validators.is_empty() =>
+				proof.verify_envelopes(&validators, collector.network())?,
+			// the validators are not read from the chain yet
+			_ => proof.verify_envelopes_without_validators(
+				collector.network(),
+				collector.min_externalized_messages(),
+			)?,
+		}
+		Ok(())
+	}
+
+	/// Returns a source of the proofs that the collector builds
+	pub fn collector_proof_source(&self, timeout: Duration) -> CollectorProofSource {
+		CollectorProofSource::new(self.collector.clone(), self.overlay_requester.clone(), timeout)
+	}
+
+	/// Replaces the sources that `get_proof` asks, in order
+	pub async fn set_proof_sources(&self, proof_sources: Vec<Box<dyn ProofSource>>) {
+		*self.proof_sources.write().await = proof_sources;
+	}
+
+	/// Adds a source that `get_proof` asks after the others
+	pub async fn add_proof_source(&self, proof_source: Box<dyn ProofSource>) {
+		self.proof_sources.write().await.push(proof_source);
+	}
+
+	/// Builds the proofs of the slots that mention the account as soon as they are complete, so
+	/// that `get_proof` does not have to wait for them
+	pub async fn watch_account(&self, public_key: &PublicKey) {
+		self.collector.read().await.watch_account(public_key.clone().into_binary());
+	}
+
+	/// Returns the slot and the envelope of a transaction that the overlay sent a tx set of
+	pub async fn find_transaction(&self, hash: &TxHash) -> Option<IndexedTransaction> {
+		self.collector.read().await.find_transaction(hash)
+	}
+
+	/// Returns the transactions with the text memo that the overlay sent tx sets of, so that
+	/// payments to the vault are found even if Horizon is behind
+	pub async fn find_transactions_by_memo(&self, memo: &[u8]) -> Vec<IndexedTransaction> {
+		self.collector.read().await.find_transactions_by_memo(memo)
+	}
+
+	/// Returns the transactions with the text memo that pay the account, out of the tx sets that
+	/// the overlay sent
+	pub async fn find_payments(
+		&self,
+		memo: &[u8],
+		destination: &StellarPublicKeyRaw,
+	) -> Vec<IndexedTransaction> {
+		self.collector.read().await.find_payments(memo, destination)
+	}
+
+	/// Returns the transactions made from the account that the overlay sent tx sets of
+	pub async fn find_transactions_by_source(
+		&self,
+		public_key: &PublicKey,
+	) -> Vec<IndexedTransaction> {
+		let account = public_key.clone().into_binary();
+		self.collector.read().await.find_transactions_by_source(&account)
+	}
+
+	/// Replaces the validators that the proofs are built for
+	pub async fn set_validator_set(&self, validator_set: ValidatorSet) {
+		self.collector.read().await.set_validator_set(validator_set);
+	}
+
+	/// Returns how each of the history archive mirrors has been doing
+	pub async fn archive_mirror_health(&self) -> Vec<MirrorHealth> {
+		self.collector.read().await.archive_client().mirror_health()
 	}
 
 	#[cfg(any(test, feature = "integration"))]
@@ -138,6 +341,41 @@ async fn handle_message(
 	Ok(())
 }
 
+/// Logs what happens with the connection to the Stellar Node,
+/// apart from the messages that the Stellar Node sends.
+/// Returns true if the connection is closed for good.
+fn log_overlay_event(event: ConnectionEvent) -> bool {
+	match event {
+		ConnectionEvent::HandshakeCompleted { remote } =>
+			tracing::info!("log_overlay_event(): connected to Stellar Node {remote:?}"),
+		ConnectionEvent::PeerError { code, msg } =>
+			tracing::warn!("log_overlay_event(): Stellar Node sent an error: {code:?}: {msg}"),
+		ConnectionEvent::Timeout =>
+			tracing::warn!("log_overlay_event(): Stellar Node did not respond in time"),
+		ConnectionEvent::Closed { reason } => {
+			tracing::error!("log_overlay_event(): connection to Stellar Node closed: {reason}");
+			return true
+		},
+		other => tracing::info!("log_overlay_event(): {other:?}"),
+	}
+	false
+}
+
+/// Returns the next event of the connection; never returns if there are no events to receive.
+async fn next_overlay_event(
+	event_receiver: &mut Option<mpsc::Receiver<ConnectionEvent>>,
+) -> ConnectionEvent {
+	if let Some(receiver) = event_receiver.as_mut() {
+		match receiver.recv().await {
+			Some(event) => return event,
+			// the sender is gone; the closing of the connection shows up in `listen()` too
+			None => *event_receiver = None,
+		}
+	}
+
+	std::future::pending().await
+}
+
 pub async fn listen_for_stellar_messages(
 	oracle_agent: Arc<OracleAgent>,
 	shutdown_sender: ShutdownSender,
@@ -145,6 +383,7 @@ pub async fn listen_for_stellar_messages(
 	tracing::info!("listen_for_stellar_messages(): started");
 
 	let mut overlay_conn = oracle_agent.overlay_conn.write().await;
+	let mut event_receiver = overlay_conn.take_event_receiver();
 
 	// log a new message received.
 	let health_check_interval = Duration::from_secs(STELLAR_RELAY_HEALTH_CHECK_IN_SECS);
@@ -157,9 +396,18 @@ pub async fn listen_for_stellar_messages(
 			break
 		}
 
-		let result =
-			timeout(Duration::from_secs(STELLAR_MESSAGES_TIMEOUT_IN_SECS), overlay_conn.listen())
-				.await;
+		let result = tokio::select! {
+			result = timeout(
+				Duration::from_secs(STELLAR_MESSAGES_TIMEOUT_IN_SECS),
+				overlay_conn.listen(),
+			) => result,
+			event = next_overlay_event(&mut event_receiver) => {
+				if log_overlay_event(event) {
+					break
+				}
+				continue
+			},
+		};
 		if let Err(_) = result {
 			error!("listen_for_stellar_messages(): overlay_conn.listen() timed out");
 			break;
@@ -167,11 +415,11 @@ pub async fn listen_for_stellar_messages(
 
 		match result.expect("unreachable") {
 			Ok(None) => {},
+			// the Stellar Node is still connected; only a `Closed` event ends the connection
 			Ok(Some(StellarMessage::ErrorMsg(e))) => {
-				tracing::error!(
+				tracing::warn!(
 					"listen_for_stellar_messages(): received error message from Stellar: {e:?}"
 				);
-				break
 			},
 			Ok(Some(msg)) => {
 				last_valid_message_time = Instant::now();
@@ -222,7 +470,7 @@ pub async fn start_oracle_agent(
 	tokio::spawn(listen_for_stellar_messages(oracle_agent.clone(), shutdown_sender));
 
 	while !oracle_agent.is_stellar_running().await {
-		sleep(Duration::from_millis(500)).await;
+		tokio::time::sleep(Duration::from_millis(500)).await;
 	}
 
 	tracing::info!("start_oracle_agent(): Stellar overlay network is running");
@@ -233,12 +481,106 @@ pub async fn start_oracle_agent(
 mod tests {
 	use super::*;
 	use crate::oracle::{
-		get_random_secret_key, specific_stellar_relay_config, traits::ArchiveStorage,
-		ScpArchiveStorage, TransactionsArchiveStorage,
+		get_random_secret_key, specific_stellar_relay_config,
+		traits::{ArchiveStorage, FileHandler},
+		EnvelopesFileHandler, ScpArchiveStorage, StaticProofSource, TransactionsArchiveStorage,
+		TxSetsFileHandler,
 	};
 	use serial_test::serial;
+	use stellar_relay_lib::{
+		mock_peer::{MockPeerScript, MockStellarPeer},
+		sdk::{compound_types::UnlimitedVarArray, TransactionSetType},
+	};
+	use tokio::time::sleep;
 	use wallet::keys::get_source_secret_key_from_env;
 
+	#[tokio::test(flavor = "multi_thread")]
+	#[ntest::timeout(60_000)]
+	async fn test_collect_envelopes_from_mock_stellar_peer() {
+		let first_slot = 578291;
+		let env_map =
+			EnvelopesFileHandler::get_map_from_archives(first_slot).expect("should return a map");
+		let (slot, envelopes) = env_map.first().expect("should return a tuple");
+
+		let base_config = specific_stellar_relay_config(true, 0);
+		let script = MockPeerScript::new()
+			.send_after_handshake(envelopes.iter().cloned().map(StellarMessage::ScpMessage));
+		let mock_peer = MockStellarPeer::start(base_config.node_info.clone().into(), script)
+			.await
+			.expect("should start the mock peer");
+
+		let shutdown_sender = ShutdownSender::new();
+		let agent = start_oracle_agent(
+			mock_peer.overlay_config(base_config),
+			get_random_secret_key(),
+			shutdown_sender,
+		)
+		.await;
+
+		assert_eq!(agent.collector.read().await.last_slot_index(), *slot);
+
+		// the agent asks for the tx set of the externalized slot
+		while !mock_peer
+			.received_messages()
+			.iter()
+			.any(|msg| matches!(msg, StellarMessage::GetTxSet(_)))
+		{
+			sleep(Duration::from_millis(100)).await;
+		}
+	}
+
+	#[tokio::test(flavor = "multi_thread")]
+	#[ntest::timeout(60_000)]
+	async fn test_get_proof_falls_back_and_rejects_unverified_proofs() {
+		let first_slot = 578291;
+		let env_map =
+			EnvelopesFileHandler::get_map_from_archives(first_slot).expect("should return a map");
+		let (slot, envelopes) = env_map.first().expect("should return a tuple");
+		let tx_set = sample_tx_set();
+		let proof = Proof::new(
+			*slot,
+			UnlimitedVarArray::new(envelopes.clone()).expect("should create the array"),
+			tx_set,
+		);
+
+		let base_config = specific_stellar_relay_config(true, 0);
+		let mock_peer =
+			MockStellarPeer::start(base_config.node_info.clone().into(), MockPeerScript::new())
+				.await
+				.expect("should start the mock peer");
+		let agent = OracleAgent::new(
+			&mock_peer.overlay_config(base_config),
+			get_random_secret_key(),
+			ShutdownSender::new(),
+		)
+		.await
+		.expect("should work");
+
+		// the tx set is of another slot, so the proof of the external source does not pass the check
+		agent
+			.set_proof_sources(vec![
+				Box::new(StaticProofSource { proof: None, is_external: false }),
+				Box::new(StaticProofSource { proof: Some(proof.clone()), is_external: true }),
+			])
+			.await;
+		assert!(matches!(agent.get_proof(*slot).await, Err(Error::ProofTimeout(_))));
+
+		agent
+			.add_proof_source(Box::new(StaticProofSource {
+				proof: Some(proof.clone()),
+				is_external: false,
+			}))
+			.await;
+		assert_eq!(agent.get_proof(*slot).await.expect("should return a proof"), proof);
+	}
+
+	fn sample_tx_set() -> TransactionSetType {
+		let txset_slot = 92910;
+		let txsets_map =
+			TxSetsFileHandler::get_map_from_archives(txset_slot).expect("should return a map");
+		txsets_map.get(&txset_slot).expect("should return a tx set").clone()
+	}
+
 	#[tokio::test(flavor = "multi_thread")]
 	#[ntest::timeout(600_000)] // timeout at 10 minutes
 	#[serial]
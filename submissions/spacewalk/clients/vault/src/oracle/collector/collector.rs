@@ -1,17 +1,24 @@
-use std::{default::Default, sync::Arc};
+use std::{collections::HashSet, convert::TryInto, default::Default, sync::Arc};
 
 use parking_lot::{lock_api::RwLockReadGuard, RawRwLock, RwLock};
 use primitives::stellar::StellarTypeToBase64String;
 
 use stellar_relay_lib::sdk::{
+	compound_types::UnlimitedVarArray,
 	network::{Network, PUBLIC_NETWORK, TEST_NETWORK},
-	types::{GeneralizedTransactionSet, ScpEnvelope, TransactionSet},
-	TransactionSetType,
+	types::{GeneralizedTransactionSet, ScpEnvelope, ScpStatementPledges, TransactionSet},
+	TransactionSetType, XdrCodec,
 };
 use wallet::Slot;
 
-use crate::oracle::types::{
-	EnvelopesMap, LimitedFifoMap, TxSetHash, TxSetHashAndSlotMap, TxSetMap,
+use crate::oracle::{
+	collector::{get_tx_set_hash, Proof, ValidatorSet},
+	types::{
+		constants::{get_min_externalized_messages, SCP_STORE_PRUNE_INTERVAL},
+		EnvelopesMap, IndexedTransaction, LimitedFifoMap, ProofCache, TxHash, TxSetHash,
+		TxSetHashAndSlotMap, TxSetIndex, TxSetMap,
+	},
+	ArchiveClient, ScpStore,
 };
 
 /// Collects all ScpMessages and the TxSets.
@@ -26,6 +33,9 @@ pub struct ScpMessageCollector {
 	/// holds the mapping of the Slot Number(key) and the TransactionSet(value)
 	txset_map: Arc<RwLock<TxSetMap>>,
 
+	/// the slot of every tx set in the `txset_map`, by the hash of the tx set
+	txset_slots: Arc<RwLock<TxSetHashAndSlotMap>>,
+
 	/// Mapping between the txset's hash and its corresponding slot.
 	/// An entry is removed when a `TransactionSet` is found.
 	txset_and_slot_map: Arc<RwLock<TxSetHashAndSlotMap>>,
@@ -35,8 +45,26 @@ pub struct ScpMessageCollector {
 
 	public_network: bool,
 
-	// A (possibly empty) list of URLs to use to fetch the stellar history archive entries.
-	stellar_history_archive_urls: Vec<String>,
+	// fetches the stellar history archive entries from a (possibly empty) list of URLs
+	archive_client: ArchiveClient,
+
+	/// keeps the envelopes and tx sets on disk, to reload them after a restart
+	store: Option<ScpStore>,
+
+	/// the validators registered in the stellar-relay pallet, once they are read from the chain
+	validator_set: Arc<RwLock<Option<ValidatorSet>>>,
+
+	/// the raw public keys of the accounts whose payments are proven ahead of time
+	watched_accounts: Arc<RwLock<HashSet<[u8; 32]>>>,
+
+	/// the slots with a tx set that mentions one of the `watched_accounts`
+	watched_slots: Arc<RwLock<LimitedFifoMap<Slot, ()>>>,
+
+	/// the finished proofs of the `watched_slots`
+	proof_cache: Arc<RwLock<ProofCache>>,
+
+	/// finds the transactions of the collected tx sets
+	tx_index: Arc<RwLock<TxSetIndex>>,
 }
 
 impl ScpMessageCollector {
@@ -45,10 +73,17 @@ impl ScpMessageCollector {
 			envelopes_map: Default::default(),
 			env_from_archive_map: Default::default(),
 			txset_map: Default::default(),
+			txset_slots: Default::default(),
 			txset_and_slot_map: Default::default(),
 			last_slot_index: 0,
 			public_network,
-			stellar_history_archive_urls,
+			archive_client: ArchiveClient::new(stellar_history_archive_urls, public_network),
+			store: None,
+			validator_set: Default::default(),
+			watched_accounts: Default::default(),
+			watched_slots: Default::default(),
+			proof_cache: Default::default(),
+			tx_index: Default::default(),
 		}
 	}
 
@@ -63,17 +98,50 @@ impl ScpMessageCollector {
 				LimitedFifoMap::new().with_limit(size_limit),
 			)),
 			txset_map: Arc::new(RwLock::new(TxSetMap::new().with_limit(size_limit))),
+			txset_slots: Default::default(),
 			txset_and_slot_map: Default::default(),
 			last_slot_index: 0,
 			public_network,
-			stellar_history_archive_urls,
+			archive_client: ArchiveClient::new(stellar_history_archive_urls, public_network),
+			store: None,
+			validator_set: Default::default(),
+			watched_accounts: Default::default(),
+			watched_slots: Arc::new(RwLock::new(LimitedFifoMap::new().with_limit(size_limit))),
+			proof_cache: Arc::new(RwLock::new(ProofCache::new().with_limit(size_limit))),
+			tx_index: Arc::new(RwLock::new(TxSetIndex::new(size_limit))),
+		}
+	}
+
+	/// Writes the envelopes and tx sets through to the `store`, and loads the most recent slots
+	/// that it kept before a restart.
+	pub(crate) fn with_store(mut self, store: ScpStore) -> Self {
+		let limit = self.envelopes_map.read().limit();
+		self.store = Some(store.clone());
+
+		match store.recent_slots(limit) {
+			Ok(slots) => {
+				let loaded = slots.into_iter().filter(|slot| self.load_from_store(*slot)).count();
+				tracing::info!("with_store(): loaded {loaded} slots from the SCP store");
+			},
+			Err(e) => tracing::warn!("with_store(): failed to read the SCP store: {e:?}"),
 		}
+
+		self
 	}
 
 	pub fn envelopes_map_len(&self) -> usize {
 		self.envelopes_map.read().len()
 	}
 
+	/// the number of envelopes of all the slots
+	pub fn envelopes_count(&self) -> usize {
+		self.envelopes_map.read().iter().map(|(_, envelopes)| envelopes.len()).sum()
+	}
+
+	pub fn txset_map_len(&self) -> usize {
+		self.txset_map.read().len()
+	}
+
 	pub fn network(&self) -> &Network {
 		if self.public_network {
 			&PUBLIC_NETWORK
@@ -86,8 +154,36 @@ impl ScpMessageCollector {
 		self.public_network
 	}
 
+	/// Replaces the validators that the envelopes of a proof have to come from
+	pub fn set_validator_set(&self, validator_set: ValidatorSet) {
+		tracing::info!(
+			"set_validator_set(): a proof now needs at least {} envelopes",
+			validator_set.min_externalized_messages()
+		);
+		self.archive_client.set_validator_set(validator_set.clone());
+		*self.validator_set.write() = Some(validator_set);
+	}
+
+	pub fn validator_set(&self) -> Option<ValidatorSet> {
+		self.validator_set.read().clone()
+	}
+
+	/// Returns the minimum amount of envelopes that are required to build a valid proof.
+	/// Until the validators are read from the chain, the hardcoded validator count is used.
+	pub fn min_externalized_messages(&self) -> usize {
+		match self.validator_set.read().as_ref() {
+			Some(validator_set) if !validator_set.is_empty() =>
+				validator_set.min_externalized_messages(),
+			_ => get_min_externalized_messages(self.is_public()),
+		}
+	}
+
 	pub fn stellar_history_archive_urls(&self) -> Vec<String> {
-		self.stellar_history_archive_urls.clone()
+		self.archive_client.urls().to_vec()
+	}
+
+	pub fn archive_client(&self) -> ArchiveClient {
+		self.archive_client.clone()
 	}
 }
 
@@ -117,6 +213,10 @@ impl ScpMessageCollector {
 		self.txset_map.clone()
 	}
 
+	pub(super) fn txset_slots_clone(&self) -> Arc<RwLock<TxSetHashAndSlotMap>> {
+		self.txset_slots.clone()
+	}
+
 	pub(super) fn get_txset_hash_by_slot(&self, slot: &Slot) -> Option<TxSetHash> {
 		self.txset_and_slot_map.read().get_txset_hash_by_slot(slot).cloned()
 	}
@@ -151,8 +251,13 @@ impl ScpMessageCollector {
 		let mut envelopes_map = self.envelopes_map.write();
 
 		if let Some(value) = envelopes_map.get(&slot) {
+			// the envelope may have arrived already, as the reply to a `GetScpState`
+			if value.contains(&scp_envelope) {
+				return
+			}
 			let mut value = value.clone();
 			value.push(scp_envelope);
+			self.store_envelopes(slot, &value);
 			envelopes_map.insert(slot, value);
 		} else {
 			tracing::debug!("Collecting SCPEnvelopes for slot {slot}: success");
@@ -160,7 +265,17 @@ impl ScpMessageCollector {
 				"Collecting SCPEnvelopes for slot {slot}: the scp envelope: {}",
 				scp_envelope.statement.as_base64_encoded_string()
 			);
-			envelopes_map.insert(slot, vec![scp_envelope]);
+			let value = vec![scp_envelope];
+			self.store_envelopes(slot, &value);
+			envelopes_map.insert(slot, value);
+		}
+		drop(envelopes_map);
+		self.trust_externalized_value(slot);
+
+		// the tx set may have arrived before there were enough envelopes, and every envelope
+		// after that makes the proof stronger
+		if self.watched_slots.read().contains(&slot) {
+			self.snapshot_proof(slot);
 		}
 	}
 
@@ -177,10 +292,72 @@ impl ScpMessageCollector {
 
 	pub(super) fn set_last_slot_index(&mut self, slot: Slot) {
 		if slot > self.last_slot_index {
+			if slot / SCP_STORE_PRUNE_INTERVAL != self.last_slot_index / SCP_STORE_PRUNE_INTERVAL {
+				self.prune_store(slot);
+			}
 			self.last_slot_index = slot;
 		}
 	}
 
+	/// Returns the value that enough of the envelopes externalized to trust it: a quorum of the
+	/// validators, or the hardcoded number of envelopes until the validators are known
+	fn quorum_value(&self, envelopes: &[ScpEnvelope]) -> Option<Vec<u8>> {
+		let mut values: Vec<(&Vec<u8>, Vec<&ScpEnvelope>)> = vec![];
+		for env in envelopes {
+			let ScpStatementPledges::ScpStExternalize(stmt) = &env.statement.pledges else {
+				continue
+			};
+			let value = stmt.commit.value.get_vec();
+			match values.iter_mut().find(|(known, _)| *known == value) {
+				Some((_, value_envelopes)) => value_envelopes.push(env),
+				None => values.push((value, vec![env])),
+			}
+		}
+
+		let validator_set = self.validator_set.read();
+		values
+			.into_iter()
+			.find(|(_, value_envelopes)| match validator_set.as_ref() {
+				Some(validator_set) if !validator_set.is_empty() =>
+					validator_set.check_quorum_of(value_envelopes).is_ok(),
+				_ => value_envelopes.len() >= get_min_externalized_messages(self.is_public()),
+			})
+			.map(|(value, _)| value.clone())
+	}
+
+	/// Lets the archive client trust the ledger of the slot, once the envelopes that the overlay
+	/// sent for it reach a quorum
+	fn trust_externalized_value(&self, slot: Slot) {
+		if self.is_envelopes_data_from_archive(&slot) {
+			return
+		}
+
+		let value =
+			self.envelopes_map.read().get(&slot).and_then(|envelopes| self.quorum_value(envelopes));
+		if let Some(value) = value {
+			self.archive_client.trust_externalized_value(slot, value);
+		}
+	}
+
+	/// Writes the envelopes of the slot through to the store, if there is one
+	fn store_envelopes(&self, slot: Slot, envelopes: &[ScpEnvelope]) {
+		if let Some(store) = &self.store {
+			store.save_envelopes(slot, envelopes, self.is_envelopes_data_from_archive(&slot));
+		}
+	}
+
+	/// Writes the tx set of the slot through to the store, if there is one
+	pub(super) fn store_txset(&self, slot: Slot, tx_set: &TransactionSetType) {
+		if let Some(store) = &self.store {
+			store.save_tx_set(slot, tx_set);
+		}
+	}
+
+	/// Adds the tx set of the slot to the `txset_map`, under its hash
+	pub(super) fn insert_txset(&self, slot: Slot, hash: TxSetHash, tx_set: TransactionSetType) {
+		insert_txset(&self.txset_map, &self.txset_slots, slot, hash, tx_set);
+	}
+
 	fn add_txset_type(&self, tx_set: TransactionSetType) -> Result<(), String> {
 		let hash = tx_set
 			.get_tx_set_hash()
@@ -191,27 +368,251 @@ impl ScpMessageCollector {
 			let mut map_write = self.txset_and_slot_map.write();
 			map_write.remove_by_txset_hash(&hash).map(|slot| {
 				tracing::debug!("Collecting TxSet for slot {slot}: txset saved.");
-				self.txset_map.write().insert(slot, tx_set);
+				self.store_txset(slot, &tx_set);
+				self.insert_txset(slot, hash, tx_set);
 				slot
 			})
 		};
 
-		if slot.is_none() {
+		let Some(slot) = slot else {
 			tracing::warn!("Collecting TxSet for slot: tx_set_hash: {hash_str} has no slot.");
 			return Err(format!("TxSetHash {hash_str} has no slot."));
+		};
+		self.index_txset(slot);
+
+		let mentions_watched_account = {
+			let txset_map = self.txset_map.read();
+			let watched_accounts = self.watched_accounts.read();
+			txset_map.get(&slot).map_or(false, |tx_set| tx_set_mentions(tx_set, &watched_accounts))
+		};
+		if mentions_watched_account {
+			self.watched_slots.write().insert(slot, ());
+			self.snapshot_proof(slot);
 		}
 
 		Ok(())
 	}
 }
 
+// finding the transactions of the collected tx sets
+impl ScpMessageCollector {
+	fn index_txset(&self, slot: Slot) {
+		if let Some(tx_set) = self.txset_map.read().get(&slot) {
+			self.tx_index.write().insert(slot, tx_set, self.network());
+		}
+	}
+
+	/// Returns the slot and the tx set of the hash, if it was collected
+	pub fn find_txset(&self, hash: &TxSetHash) -> Option<(Slot, TransactionSetType)> {
+		let slot = *self.txset_slots.read().get_slot_by_txset_hash(hash)?;
+		self.txset_map.read().get(&slot).map(|tx_set| (slot, tx_set.clone()))
+	}
+
+	pub fn find_transaction(&self, hash: &TxHash) -> Option<IndexedTransaction> {
+		self.tx_index.read().get(hash).cloned()
+	}
+
+	/// Returns the transactions made from the account, the oldest first
+	pub fn find_transactions_by_source(&self, account: &[u8; 32]) -> Vec<IndexedTransaction> {
+		self.tx_index.read().by_source(account).into_iter().cloned().collect()
+	}
+
+	/// Returns the transactions with the text memo, the oldest first
+	pub fn find_transactions_by_memo(&self, memo: &[u8]) -> Vec<IndexedTransaction> {
+		self.tx_index.read().by_memo(memo).into_iter().cloned().collect()
+	}
+
+	/// Returns the transactions with the text memo that pay the account, the oldest first
+	pub fn find_payments(&self, memo: &[u8], destination: &[u8; 32]) -> Vec<IndexedTransaction> {
+		self.tx_index.read().by_memo_to(memo, destination).into_iter().cloned().collect()
+	}
+}
+
+// proofs of the slots that mention the watched accounts
+impl ScpMessageCollector {
+	/// Proofs are built ahead of time for the slots whose tx set mentions this account
+	pub fn watch_account(&self, public_key: [u8; 32]) {
+		self.watched_accounts.write().insert(public_key);
+	}
+
+	/// Returns the proof of the slot that was built ahead of time, if there is one
+	pub fn cached_proof(&self, slot: Slot) -> Option<Proof> {
+		if let Some(proof) = self.proof_cache.read().get(&slot) {
+			return Some(proof.clone())
+		}
+
+		let store = self.store.as_ref()?;
+		match store.load_proof(slot) {
+			Ok(proof) => proof,
+			Err(e) => {
+				tracing::warn!("cached_proof(): failed to load the proof of slot {slot}: {e:?}");
+				None
+			},
+		}
+	}
+
+	/// Keeps the proof of the slot, once its tx set is collected and its envelopes reach the
+	/// quorum of the validators. A proof that was kept already is replaced by the one with all
+	/// the envelopes collected so far.
+	fn snapshot_proof(&self, slot: Slot) {
+		let Some(tx_set) = self.txset_map.read().get(&slot).cloned() else { return };
+		let Some(envelopes) = self.envelopes_map.read().get(&slot).cloned() else { return };
+
+		// without the validators, the quorum is unknown
+		let Some(validator_set) =
+			self.validator_set().filter(|validator_set| !validator_set.is_empty())
+		else {
+			return
+		};
+		if let Err(e) = validator_set.check_quorum_of(&envelopes.iter().collect::<Vec<_>>()) {
+			tracing::debug!("snapshot_proof(): the proof of slot {slot} is not ready: {e:?}");
+			return
+		}
+
+		let envelopes = match UnlimitedVarArray::new(envelopes) {
+			Ok(envelopes) => envelopes,
+			Err(e) => {
+				tracing::warn!("snapshot_proof(): invalid envelopes of slot {slot}: {e:?}");
+				return
+			},
+		};
+
+		let proof = Proof::new(slot, envelopes, tx_set);
+		if let Some(store) = &self.store {
+			store.save_proof(&proof);
+		}
+		let mut proof_cache = self.proof_cache.write();
+		if proof_cache.contains(&slot) {
+			tracing::debug!("snapshot_proof(): the proof of slot {slot} is updated");
+		} else {
+			tracing::info!("snapshot_proof(): the proof of slot {slot} is ready");
+		}
+		proof_cache.insert(slot, proof);
+	}
+}
+
+/// Checks whether any of the transactions mentions one of the accounts, as a source, a
+/// destination or a claimant. The encoded transactions are searched for the raw public keys.
+fn tx_set_mentions(tx_set: &TransactionSetType, accounts: &HashSet<[u8; 32]>) -> bool {
+	if accounts.is_empty() {
+		return false
+	}
+
+	tx_set.txes().iter().any(|tx| {
+		tx.to_xdr()
+			.windows(32)
+			.any(|window| window.try_into().map_or(false, |key| accounts.contains(key)))
+	})
+}
+
+/// Adds the tx set to the `txset_map`, and its slot to the `txset_slots`. The slot of the tx set
+/// that the `txset_map` drops to make room is dropped from the `txset_slots` as well.
+pub(super) fn insert_txset(
+	txset_map: &RwLock<TxSetMap>,
+	txset_slots: &RwLock<TxSetHashAndSlotMap>,
+	slot: Slot,
+	hash: TxSetHash,
+	tx_set: TransactionSetType,
+) {
+	let mut txset_map = txset_map.write();
+	let mut txset_slots = txset_slots.write();
+
+	if !txset_map.contains(&slot) && txset_map.len() >= txset_map.limit() {
+		if let Some((oldest_slot, _)) = txset_map.first() {
+			txset_slots.remove_by_slot(oldest_slot);
+		}
+	}
+	txset_slots.remove_by_slot(&slot);
+	txset_slots.insert(hash, slot);
+	txset_map.insert(slot, tx_set);
+}
+
 // delete/remove functions
 impl ScpMessageCollector {
 	/// Clear out data related to this slot.
 	pub(crate) fn remove_data(&self, slot: &Slot) {
-		self.envelopes_map.write().remove(slot);
+		let removed_envelopes = self.envelopes_map.write().remove(slot).is_some();
 		self.env_from_archive_map.write().remove(slot);
-		self.txset_map.write().remove(slot);
+		let removed_txset = self.txset_map.write().remove(slot).is_some();
+		self.txset_slots.write().remove_by_slot(slot);
+
+		if let Some(store) = &self.store {
+			if removed_envelopes || removed_txset {
+				store.remove(*slot);
+			}
+		}
+	}
+
+	/// Removes the slots that are too old from the store
+	fn prune_store(&self, latest_slot: Slot) {
+		if let Some(store) = &self.store {
+			store.prune(latest_slot);
+		}
+	}
+}
+
+// loading from the store
+impl ScpMessageCollector {
+	pub(super) fn store_clone(&self) -> Option<ScpStore> {
+		self.store.clone()
+	}
+
+	/// Loads the envelopes and the tx set of the slot from the store into the maps, if they are
+	/// missing there. Returns true if anything was loaded.
+	pub(super) fn load_from_store(&self, slot: Slot) -> bool {
+		let Some(store) = &self.store else { return false };
+		let mut loaded = false;
+
+		if !self.txset_map.read().contains(&slot) {
+			match store.load_tx_set(slot) {
+				Ok(Some(tx_set)) => match tx_set.get_tx_set_hash() {
+					Ok(hash) => {
+						self.insert_txset(slot, hash, tx_set);
+						self.index_txset(slot);
+						loaded = true;
+					},
+					Err(e) => tracing::warn!(
+						"load_from_store(): failed to get the hash of the tx set of slot {slot}: {e:?}"
+					),
+				},
+				Ok(None) => {},
+				Err(e) => tracing::warn!(
+					"load_from_store(): failed to load the tx set of slot {slot}: {e:?}"
+				),
+			}
+		}
+
+		if !self.envelopes_map.read().contains(&slot) {
+			match store.load_envelopes(slot) {
+				Ok(Some((envelopes, from_archive))) => {
+					// without its tx set, the slot still needs the hash to request it
+					if !self.txset_map.read().contains(&slot) {
+						let txset_hash =
+							envelopes.iter().find_map(|env| match &env.statement.pledges {
+								ScpStatementPledges::ScpStExternalize(stmt) =>
+									get_tx_set_hash(stmt).ok(),
+								_ => None,
+							});
+						if let Some(txset_hash) = txset_hash {
+							self.save_txset_hash_and_slot(txset_hash, slot);
+						}
+					}
+
+					if from_archive {
+						self.env_from_archive_map.write().insert(slot, ());
+					}
+					self.envelopes_map.write().insert(slot, envelopes);
+					self.trust_externalized_value(slot);
+					loaded = true;
+				},
+				Ok(None) => {},
+				Err(e) => tracing::warn!(
+					"load_from_store(): failed to load the envelopes of slot {slot}: {e:?}"
+				),
+			}
+		}
+
+		loaded
 	}
 }
 
@@ -231,14 +632,16 @@ mod test {
 	use stellar_relay_lib::sdk::{
 		network::{PUBLIC_NETWORK, TEST_NETWORK},
 		types::{GeneralizedTransactionSet, TransactionSet},
-		IntoHash, XdrCodec,
+		IntoHash, TransactionSetType, XdrCodec,
 	};
 
 	use crate::oracle::{
-		collector::{collector::AddTxSet, ScpMessageCollector},
+		collector::{collector::AddTxSet, ScpMessageCollector, StellarValidator, ValidatorSet},
+		constants::get_min_externalized_messages,
 		random_stellar_relay_config,
 		traits::FileHandler,
-		EnvelopesFileHandler, TxSetsFileHandler,
+		types::source_account,
+		EnvelopesFileHandler, ScpStore, TxSetsFileHandler,
 	};
 
 	fn open_file(file_name: &str) -> Vec<u8> {
@@ -305,6 +708,25 @@ mod test {
 		assert!(!collector.is_public());
 	}
 
+	#[test]
+	fn min_externalized_messages_follows_the_validator_set() {
+		let collector = ScpMessageCollector::new(true, stellar_history_archive_urls());
+		assert_eq!(collector.min_externalized_messages(), get_min_externalized_messages(true));
+
+		// an empty set is not trusted
+		collector.set_validator_set(ValidatorSet::default());
+		assert_eq!(collector.min_externalized_messages(), get_min_externalized_messages(true));
+
+		let validators = (0..4)
+			.map(|organization_id| StellarValidator {
+				public_key: format!("V{organization_id}").into_bytes(),
+				organization_id,
+			})
+			.collect();
+		collector.set_validator_set(ValidatorSet::new(validators));
+		assert_eq!(collector.min_externalized_messages(), 3);
+	}
+
 	#[test]
 	fn add_scp_envelope_works() {
 		let collector = ScpMessageCollector::new(true, stellar_history_archive_urls());
@@ -340,16 +762,138 @@ mod test {
 		let collector = ScpMessageCollector::new(false, stellar_history_archive_urls());
 
 		let value = sample_txset();
+		let hash = value.clone().into_hash().expect("it should return a hash");
+
+		let slot = 578391;
+		collector.save_txset_hash_and_slot(hash, slot);
+
+		assert!(collector.add_txset(value).is_ok());
+
+		assert!(collector.txset_map.read().contains(&slot));
+		assert_eq!(collector.find_txset(&hash).map(|(found_slot, _)| found_slot), Some(slot));
+
+		collector.remove_data(&slot);
+		assert!(collector.find_txset(&hash).is_none());
+	}
+
+	#[test]
+	fn transactions_of_added_txsets_are_found() {
+		let collector = ScpMessageCollector::new(false, stellar_history_archive_urls());
+
+		let value = sample_txset();
+		let tx_env = value.txs.get_vec()[0].clone();
+		let hash = tx_env.get_hash(collector.network());
+		assert!(collector.find_transaction(&hash).is_none());
 
 		let slot = 578391;
 		collector.save_txset_hash_and_slot(
 			value.clone().into_hash().expect("it should return a hash"),
 			slot,
 		);
+		assert!(collector.add_txset(value).is_ok());
+
+		let indexed = collector.find_transaction(&hash).expect("should find the transaction");
+		assert_eq!(indexed.slot, slot);
+		assert_eq!(indexed.tx_env, tx_env);
+
+		let account = source_account(&tx_env).expect("should have a source account");
+		assert!(collector.find_transactions_by_source(&account).contains(&indexed));
+	}
+
+	#[test]
+	fn collected_data_is_reloaded_from_the_store() {
+		let dir = tempdir::TempDir::new("scp-store-").expect("failed to create tempdir");
+		let store = ScpStore::open(dir.path(), 1000).expect("should open the store");
+
+		let first_slot = 578291;
+		let env_map =
+			EnvelopesFileHandler::get_map_from_archives(first_slot).expect("should return a map");
+		let mut slots = env_map.iter();
+		let (slot, envelopes) = slots.next().expect("should return a tuple");
+		let (slot_without_txset, other_envelopes) = slots.next().expect("should return a tuple");
+
+		let collector = ScpMessageCollector::new(true, stellar_history_archive_urls())
+			.with_store(store.clone());
+		for env in envelopes {
+			collector.add_scp_envelope(*slot, env.clone());
+		}
+		for env in other_envelopes {
+			collector.add_scp_envelope(*slot_without_txset, env.clone());
+		}
 
+		let value = sample_txset();
+		collector.save_txset_hash_and_slot(
+			value.clone().into_hash().expect("it should return a hash"),
+			*slot,
+		);
 		assert!(collector.add_txset(value).is_ok());
+		store.flush();
 
-		assert!(collector.txset_map.read().contains(&slot));
+		// the vault restarted
+		let collector =
+			ScpMessageCollector::new(true, stellar_history_archive_urls()).with_store(store);
+		assert_eq!(collector.envelopes_map().get(slot), Some(envelopes));
+		assert!(collector.txset_map().contains(slot));
+
+		// the tx set that is still missing can be requested again
+		assert_eq!(collector.envelopes_map().get(slot_without_txset), Some(other_envelopes));
+		assert!(collector.get_txset_hash_by_slot(slot_without_txset).is_some());
+	}
+
+	#[test]
+	fn proofs_of_watched_accounts_are_snapshotted() {
+		let first_slot = 578291;
+		let env_map =
+			EnvelopesFileHandler::get_map_from_archives(first_slot).expect("should return a map");
+		let (slot, envelopes) = env_map.first().expect("should return a tuple");
+
+		let tx_set = sample_txset();
+		let account =
+			source_account(&tx_set.txs.get_vec()[0]).expect("should have a source account");
+
+		// every node of the envelopes is a validator of its own organization
+		let validators = ValidatorSet::new(
+			envelopes
+				.iter()
+				.enumerate()
+				.map(|(organization_id, env)| StellarValidator {
+					public_key: env.statement.node_id.to_encoding(),
+					organization_id: organization_id as u128,
+				})
+				.collect(),
+		);
+
+		let unwatched = ScpMessageCollector::new(false, stellar_history_archive_urls());
+		let watched = ScpMessageCollector::new(false, stellar_history_archive_urls());
+		watched.watch_account(account);
+
+		for collector in [&unwatched, &watched] {
+			collector.set_validator_set(validators.clone());
+			// a single envelope is not a quorum yet
+			collector.add_scp_envelope(*slot, envelopes[0].clone());
+			collector.save_txset_hash_and_slot(
+				tx_set.clone().into_hash().expect("it should return a hash"),
+				*slot,
+			);
+			assert!(collector.add_txset(tx_set.clone()).is_ok());
+			assert!(collector.cached_proof(*slot).is_none());
+
+			// the proof is taken again as the envelopes arrive
+			for env in envelopes {
+				collector.add_scp_envelope(*slot, env.clone());
+			}
+		}
+
+		assert!(unwatched.cached_proof(*slot).is_none());
+
+		let proof = watched.cached_proof(*slot).expect("should have a proof");
+		assert_eq!(proof.slot(), *slot);
+		assert_eq!(proof.envelopes(), envelopes);
+		assert_eq!(proof.tx_set(), &TransactionSetType::TransactionSet(tx_set));
+
+		// the proof outlives the data it was built from
+		watched.remove_data(slot);
+		assert_eq!(watched.cached_proof(*slot), Some(proof));
 	}
 
 	#[test]
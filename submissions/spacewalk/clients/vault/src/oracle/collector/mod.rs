@@ -1,9 +1,11 @@
 mod collector;
 mod handler;
 mod proof_builder;
+mod proof_verifier;
 
 pub use collector::*;
 pub use proof_builder::*;
+pub use proof_verifier::*;
 use std::convert::TryInto;
 use stellar_relay_lib::sdk::types::ScpStatementExternalize;
 
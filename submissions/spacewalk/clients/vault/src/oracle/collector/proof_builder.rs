@@ -1,23 +1,23 @@
-use primitives::stellar::types::TransactionHistoryEntryExt;
 use std::{convert::TryFrom, future::Future};
 use tracing::log;
 
-use stellar_relay_lib::sdk::{
-	compound_types::{UnlimitedVarArray, XdrArchive},
-	types::{ScpEnvelope, ScpHistoryEntry, ScpStatementPledges, StellarMessage},
-	InitExt, TransactionSetType, XdrCodec,
+use stellar_relay_lib::{
+	sdk::{
+		compound_types::UnlimitedVarArray,
+		types::{ScpEnvelope, ScpStatementPledges},
+		TransactionSetType, XdrCodec,
+	},
+	StellarOverlayRequester,
 };
 use wallet::Slot;
 
-use crate::oracle::{
-	constants::{get_min_externalized_messages, MAX_SLOTS_TO_REMEMBER},
-	traits::ArchiveStorage,
-	types::StellarMessageSender,
-	ScpArchiveStorage, ScpMessageCollector, TransactionsArchiveStorage,
+use crate::{
+	oracle::{collector::insert_txset, constants::MAX_SLOTS_TO_REMEMBER, ScpMessageCollector},
+	ArcRwLock,
 };
 
 /// The Proof of Transactions that needed to be processed
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Proof {
 	/// the slot (or ledger) where the transaction is found
 	slot: Slot,
@@ -30,6 +30,14 @@ pub struct Proof {
 }
 
 impl Proof {
+	pub(crate) fn new(
+		slot: Slot,
+		envelopes: UnlimitedVarArray<ScpEnvelope>,
+		tx_set: TransactionSetType,
+	) -> Self {
+		Proof { slot, envelopes, tx_set }
+	}
+
 	/// Encodes these Stellar structures to make it easier to send as extrinsic.
 	pub fn encode(&self) -> (String, String) {
 		let envelopes_xdr = self.envelopes.to_xdr();
@@ -58,19 +66,33 @@ impl Proof {
 impl ScpMessageCollector {
 	/// Returns the Proof
 	///
+	/// The collector is only locked for short moments: the missing data is awaited without the
+	/// lock, so that the collector keeps handling the messages of the overlay in the meantime.
+	///
 	/// # Arguments
 	///
+	/// * `collector` - where the envelopes and the txset are collected
 	/// * `slot` - the slot where the txset is  to get.
-	/// * `sender` - used to send messages to Stellar Node
-	pub async fn build_proof(&self, slot: Slot, sender: &StellarMessageSender) -> Option<Proof> {
-		if self.last_slot_index() == 0 {
-			tracing::warn!(
-				"build_proof(): Proof Building for slot {slot}: last_slot_index is still 0, not yet ready to build proofs."
-			);
-			return None;
+	/// * `overlay` - used to request missing data from Stellar Node
+	pub async fn build_proof(
+		collector: &ArcRwLock<ScpMessageCollector>,
+		slot: Slot,
+		overlay: &StellarOverlayRequester,
+	) -> Option<Proof> {
+		{
+			let collector = collector.read().await;
+			if collector.last_slot_index() == 0 {
+				tracing::warn!(
+					"build_proof(): Proof Building for slot {slot}: last_slot_index is still 0, not yet ready to build proofs."
+				);
+				return None;
+			}
+
+			// the slot might have been collected before the last restart
+			collector.load_from_store(slot);
 		}
 
-		let Some(envelopes) = self.get_envelopes(slot, sender).await else {
+		let Some(envelopes) = Self::get_envelopes(collector, slot, overlay).await else {
 			// return early if we don't have enough envelopes
 			tracing::warn!(
 				"build_proof(): Couldn't build proof for slot {slot} due to missing envelopes"
@@ -78,122 +100,185 @@ impl ScpMessageCollector {
 			return None;
 		};
 
-		let tx_set = self.get_txset(slot, sender).await?;
-		Some(Proof { slot, envelopes, tx_set })
+		let tx_set = Self::get_txset(collector, slot, overlay).await?;
+		Some(Proof::new(slot, envelopes, tx_set))
 	}
 
 	/// Returns either a list of ScpEnvelopes
 	///
 	/// # Arguments
 	///
+	/// * `collector` - where the envelopes are collected
 	/// * `slot` - the slot where the needed envelopes are.
-	/// * `sender` - used to send messages to Stellar Node
+	/// * `overlay` - used to request missing data from Stellar Node
 	async fn get_envelopes(
-		&self,
+		collector: &ArcRwLock<ScpMessageCollector>,
 		slot: Slot,
-		sender: &StellarMessageSender,
+		overlay: &StellarOverlayRequester,
 	) -> Option<UnlimitedVarArray<ScpEnvelope>> {
-		if let Some(envelopes) = self.envelopes_map().get(&slot) {
-			// If the data was provided from the archive, no need to check for the minimum
-			// Otherwise, we are still lacking envelopes.
-			if !self.is_envelopes_data_from_archive(&slot) &&
-				envelopes.len() < get_min_externalized_messages(self.is_public())
-			{
-				tracing::warn!(
-					"get_envelopes(): Proof Building for slot {slot}: {:?} envelopes is not enough to build proof",
-					envelopes.len()
-				);
-			} else {
-				return UnlimitedVarArray::new(envelopes.clone()).ok();
-			}
+		if let Some(envelopes) = collector.read().await.collected_envelopes(slot) {
+			return Some(envelopes)
 		}
 
 		// forcefully retrieve envelopes
-		self._get_envelopes(slot, sender).await;
+		Self::_get_envelopes(collector, slot, overlay).await
+	}
+
+	/// Returns the envelopes of the slot, if there are enough of them to build the proof
+	fn collected_envelopes(&self, slot: Slot) -> Option<UnlimitedVarArray<ScpEnvelope>> {
+		let envelopes_map = self.envelopes_map();
+		let envelopes = envelopes_map.get(&slot)?;
 
-		return None;
+		// If the data was provided from the archive, no need to check for the minimum
+		// Otherwise, we are still lacking envelopes.
+		if !self.is_envelopes_data_from_archive(&slot) &&
+			envelopes.len() < self.min_externalized_messages()
+		{
+			tracing::warn!(
+				"collected_envelopes(): Proof Building for slot {slot}: {:?} envelopes is not enough to build proof",
+				envelopes.len()
+			);
+			return None;
+		}
+
+		UnlimitedVarArray::new(envelopes.clone()).ok()
 	}
 
 	/// fetch envelopes not found in the collector
-	async fn _get_envelopes(&self, slot: Slot, sender: &StellarMessageSender) {
-		tracing::debug!("_get_envelopes(): FOR SLOT {slot} check_slot_still_recoverable_from_overlay: LAST SLOT INDEX: {}",self.last_slot_index());
+	async fn _get_envelopes(
+		collector: &ArcRwLock<ScpMessageCollector>,
+		slot: Slot,
+		overlay: &StellarOverlayRequester,
+	) -> Option<UnlimitedVarArray<ScpEnvelope>> {
+		let last_slot_index = collector.read().await.last_slot_index();
+		tracing::debug!("_get_envelopes(): FOR SLOT {slot} check_slot_still_recoverable_from_overlay: LAST SLOT INDEX: {last_slot_index}");
 		// If the current slot is still in the range of 'remembered' slots, retrieve the envelopes
 		// from the overlay network
-		if check_slot_still_recoverable_from_overlay(self.last_slot_index(), slot) {
+		if check_slot_still_recoverable_from_overlay(last_slot_index, slot) {
 			tracing::debug!(
 				"_get_envelopes(): Proof Building for slot {slot}: fetching missing envelopes from Stellar Node..."
 			);
-			self.ask_overlay_for_envelopes(slot, sender).await;
-
-			return;
+			return Self::ask_overlay_for_envelopes(collector, slot, overlay).await;
 		}
 
 		tracing::info!(
 			"_get_envelopes(): Proof Building for slot {slot}: fetching from Archive Node..."
 		);
 
-		self.get_envelopes_from_horizon_archive(slot).await
+		let fetch_from_archive = collector.read().await.get_envelopes_from_horizon_archive(slot);
+		fetch_from_archive.await;
+		None
 	}
 
-	/// fetches envelopes from the stellar node
-	async fn ask_overlay_for_envelopes(&self, slot: Slot, sender: &StellarMessageSender) {
-		// for this slot to be processed, we must put this in our watch list.
-		let Ok(slot) = u32::try_from(slot) else {
+	/// fetches envelopes from the stellar node, and returns them if they are enough to build the
+	/// proof
+	async fn ask_overlay_for_envelopes(
+		collector: &ArcRwLock<ScpMessageCollector>,
+		slot: Slot,
+		overlay: &StellarOverlayRequester,
+	) -> Option<UnlimitedVarArray<ScpEnvelope>> {
+		let Ok(slot_u32) = u32::try_from(slot) else {
 			tracing::error!(
 					"ask_overlay_for_envelopes(): Proof Building for slot {slot:} failed to convert slot value into u32 datatype"
 				);
-			return;
+			return None;
 		};
 
-		if let Err(e) = sender.send(StellarMessage::GetScpState(slot)).await {
-			tracing::error!(
-				"ask_overlay_for_envelopes(): Proof Building for slot {slot}: failed to send `GetScpState` message: {e:?}"
+		tracing::info!("ask_overlay_for_envelopes(): Proof Building for slot {slot}: requesting to StellarNode for messages...");
+		let envelopes = match overlay.get_scp_state(slot_u32).await {
+			Ok(envelopes) => envelopes,
+			Err(e) => {
+				tracing::error!(
+					"ask_overlay_for_envelopes(): Proof Building for slot {slot}: failed to get envelopes: {e:?}"
+				);
+				return None;
+			},
+		};
+
+		collector.read().await.add_envelopes_from_overlay(slot, envelopes)
+	}
+
+	/// Collects the envelopes that the stellar node sent, and returns the envelopes of the slot if
+	/// they are enough to build the proof
+	fn add_envelopes_from_overlay(
+		&self,
+		slot: Slot,
+		envelopes: Vec<ScpEnvelope>,
+	) -> Option<UnlimitedVarArray<ScpEnvelope>> {
+		// same as the envelopes from `listen()`, only the externalized ones are collected
+		for env in envelopes {
+			if let ScpStatementPledges::ScpStExternalize(_) = &env.statement.pledges {
+				self.add_scp_envelope(slot, env);
+			}
+		}
+
+		let envelopes = self.envelopes_map().get(&slot).cloned()?;
+		if envelopes.len() < self.min_externalized_messages() {
+			tracing::warn!(
+				"add_envelopes_from_overlay(): Proof Building for slot {slot}: {:?} envelopes is not enough to build proof",
+				envelopes.len()
 			);
-			return;
+			return None;
 		}
-		tracing::info!("ask_overlay_for_envelopes(): Proof Building for slot {slot}: requesting to StellarNode for messages...");
+
+		UnlimitedVarArray::new(envelopes).ok()
 	}
 
 	/// Returns a TransactionSet if a txset is found; None if the slot does not have a txset
 	///
 	/// # Arguments
 	///
+	/// * `collector` - where the txsets are collected
 	/// * `slot` - the slot from where we get the txset
-	/// * `sender` - used to send messages to Stellar Node
+	/// * `overlay` - used to request missing data from Stellar Node
 	async fn get_txset(
-		&self,
+		collector: &ArcRwLock<ScpMessageCollector>,
 		slot: Slot,
-		sender: &StellarMessageSender,
+		overlay: &StellarOverlayRequester,
 	) -> Option<TransactionSetType> {
-		let txset_map = self.txset_map().clone();
-		let tx_set = txset_map.get(&slot).cloned();
-
-		match tx_set {
-			Some(res) => Some(res),
-			None => {
-				tracing::debug!("get_txset(): FOR SLOT {slot} check_slot_still_recoverable_from_overlay: LAST SLOT INDEX: {}",self.last_slot_index());
-				// If the current slot is still in the range of 'remembered' slots
-				if check_slot_still_recoverable_from_overlay(self.last_slot_index(), slot) {
-					self.ask_overlay_for_txset(slot, sender).await;
-				} else {
-					self.get_txset_from_horizon_archive(slot).await;
-				}
-
-				tracing::warn!("get_txset(): Proof Building for slot {slot}: no txset found");
-				None
-			},
+		let tx_set = collector.read().await.txset_map().get(&slot).cloned();
+		if tx_set.is_some() {
+			return tx_set
+		}
+
+		let last_slot_index = collector.read().await.last_slot_index();
+		tracing::debug!("get_txset(): FOR SLOT {slot} check_slot_still_recoverable_from_overlay: LAST SLOT INDEX: {last_slot_index}");
+		// If the current slot is still in the range of 'remembered' slots
+		if check_slot_still_recoverable_from_overlay(last_slot_index, slot) {
+			if let Some(tx_set) = Self::ask_overlay_for_txset(collector, slot, overlay).await {
+				return Some(tx_set);
+			}
+		} else {
+			let fetch_from_archive = collector.read().await.get_txset_from_horizon_archive(slot);
+			fetch_from_archive.await;
 		}
+
+		tracing::warn!("get_txset(): Proof Building for slot {slot}: no txset found");
+		None
 	}
 
-	/// Send message to overlay network to fetch the missing txset _if_ we already have the txset
-	/// hash for it. If we don't have the hash, we can't fetch it from the overlay network.
-	async fn ask_overlay_for_txset(&self, slot: Slot, sender: &StellarMessageSender) {
+	/// Fetches the missing txset from the overlay network _if_ we already have the txset hash
+	/// for it. If we don't have the hash, we can't fetch it from the overlay network.
+	async fn ask_overlay_for_txset(
+		collector: &ArcRwLock<ScpMessageCollector>,
+		slot: Slot,
+		overlay: &StellarOverlayRequester,
+	) -> Option<TransactionSetType> {
 		// we need the txset hash to create the message.
-		if let Some(txset_hash) = self.get_txset_hash_by_slot(&slot) {
-			tracing::debug!("ask_overlay_for_txset(): Proof Building for slot {slot}: Fetching TxSet from overlay...");
-			if let Err(error) = sender.send(StellarMessage::GetTxSet(txset_hash)).await {
-				tracing::error!("ask_overlay_for_txset(): Proof Building for slot {slot}: failed to send GetTxSet message to overlay {:?}", error);
-			}
+		let txset_hash = collector.read().await.get_txset_hash_by_slot(&slot)?;
+
+		tracing::debug!("ask_overlay_for_txset(): Proof Building for slot {slot}: Fetching TxSet from overlay...");
+		match overlay.get_tx_set(txset_hash).await {
+			Ok(tx_set) => {
+				let collector = collector.read().await;
+				collector.store_txset(slot, &tx_set);
+				collector.insert_txset(slot, txset_hash, tx_set.clone());
+				Some(tx_set)
+			},
+			Err(error) => {
+				tracing::error!("ask_overlay_for_txset(): Proof Building for slot {slot}: failed to get TxSet from overlay {:?}", error);
+				None
+			},
 		}
 	}
 
@@ -207,91 +292,67 @@ impl ScpMessageCollector {
 		tracing::debug!("get_envelopes_from_horizon_archive(): Fetching SCP envelopes from horizon archive for slot {slot}...");
 		let envelopes_map_arc = self.envelopes_map_clone();
 		let env_from_archive_map = self.env_from_archive_map_clone();
+		let store = self.store_clone();
 
-		let archive_urls = self.stellar_history_archive_urls();
+		let archive_client = self.archive_client();
 		async move {
-			if archive_urls.is_empty() {
+			if archive_client.urls().is_empty() {
 				tracing::error!("get_envelopes_from_horizon_archive(): Cannot get envelopes from horizon archive for slot {slot}: no archive URLs configured");
 				return;
 			}
 
-			// We try to get the SCPArchive from each archive URL until we succeed or run out of
-			// URLs
-			for archive_url in archive_urls {
-				let scp_archive_storage = ScpArchiveStorage(archive_url.clone());
-				let scp_archive_result = scp_archive_storage.get_archive(slot).await;
-				if let Err(e) = scp_archive_result {
+			let vec_scp = match archive_client.get_envelopes(slot).await {
+				Ok(envelopes) => envelopes,
+				Err(e) => {
 					tracing::error!(
-						"get_envelopes_from_horizon_archive(): Could not get SCPArchive for slot {slot} from Horizon Archive {archive_url}: {e:?}"
+						"get_envelopes_from_horizon_archive(): Could not get SCP envelopes for slot {slot} from Horizon Archive: {e:?}"
 					);
-					continue;
-				}
-				let scp_archive: XdrArchive<ScpHistoryEntry> =
-					scp_archive_result.expect("Should unwrap SCPArchive");
-
-				let value = scp_archive.get_vec().iter().find(|&scp_entry| {
-					if let ScpHistoryEntry::V0(scp_entry_v0) = scp_entry {
-						Slot::from(scp_entry_v0.ledger_messages.ledger_seq) == slot
-					} else {
-						false
-					}
-				});
-
-				let Some(ScpHistoryEntry::V0(scp_entry_v0)) = value else {
-					tracing::warn!("get_envelopes_from_horizon_archive(): Could not get ScpHistory entry from archive {archive_url} for slot {slot}");
-					continue;
-				};
-
-				let slot_scp_envelopes = scp_entry_v0.clone().ledger_messages.messages;
-				let vec_scp = slot_scp_envelopes.get_vec().clone();
-
-				// Filter out any envelopes that are not externalize or confirm statements
-				let relevant_envelopes = vec_scp
-					.into_iter()
-					.filter(|scp| match scp.statement.pledges {
-						ScpStatementPledges::ScpStExternalize(_) |
-						ScpStatementPledges::ScpStConfirm(_) => true,
-						_ => false,
-					})
-					.collect::<Vec<_>>();
-
-				let externalized_envelopes_count = relevant_envelopes
-					.iter()
-					.filter(|scp| match scp.statement.pledges {
-						ScpStatementPledges::ScpStExternalize(_) => true,
-						_ => false,
-					})
-					.count();
-
-				// Ensure that at least one envelope is externalized
-				if externalized_envelopes_count == 0 {
-					tracing::error!(
-							"get_envelopes_from_horizon_archive(): The contained archive entry fetched from {} for slot {slot} is invalid because it does not contain any externalized envelopes.",
-								scp_archive_storage.0
-						);
-					// remove the file since it's invalid.
-					scp_archive_storage.remove_file(slot);
-					continue;
-				}
-
-				let mut envelopes_map = envelopes_map_arc.write();
-				let mut from_archive_map = env_from_archive_map.write();
-
-				tracing::info!(
-							"get_envelopes_from_horizon_archive(): Adding {} archived SCP envelopes for slot {slot} to envelopes map. {} are externalized",
-							relevant_envelopes.len(),
-							externalized_envelopes_count
-						);
-
-				envelopes_map.insert(slot, relevant_envelopes);
-
-				// indicates that the data was taken from the archive
-				from_archive_map.insert(slot, ());
-
-				// remove the archive file after successfully retrieving envelopes
-				scp_archive_storage.remove_file(slot);
-				break;
+					return;
+				},
+			};
+
+			// Filter out any envelopes that are not externalize or confirm statements
+			let relevant_envelopes = vec_scp
+				.into_iter()
+				.filter(|scp| match scp.statement.pledges {
+					ScpStatementPledges::ScpStExternalize(_) |
+					ScpStatementPledges::ScpStConfirm(_) => true,
+					_ => false,
+				})
+				.collect::<Vec<_>>();
+
+			let externalized_envelopes_count = relevant_envelopes
+				.iter()
+				.filter(|scp| match scp.statement.pledges {
+					ScpStatementPledges::ScpStExternalize(_) => true,
+					_ => false,
+				})
+				.count();
+
+			// Ensure that at least one envelope is externalized
+			if externalized_envelopes_count == 0 {
+				tracing::error!(
+					"get_envelopes_from_horizon_archive(): The contained archive entry for slot {slot} is invalid because it does not contain any externalized envelopes."
+				);
+				return;
+			}
+
+			let mut envelopes_map = envelopes_map_arc.write();
+			let mut from_archive_map = env_from_archive_map.write();
+
+			tracing::info!(
+				"get_envelopes_from_horizon_archive(): Adding {} archived SCP envelopes for slot {slot} to envelopes map. {} are externalized",
+				relevant_envelopes.len(),
+				externalized_envelopes_count
+			);
+
+			if let Some(store) = &store {
+				store.save_envelopes(slot, &relevant_envelopes, true);
 			}
+			envelopes_map.insert(slot, relevant_envelopes);
+
+			// indicates that the data was taken from the archive
+			from_archive_map.insert(slot, ());
 		}
 	}
 
@@ -306,51 +367,31 @@ impl ScpMessageCollector {
 			"get_txset_from_horizon_archive(): Fetching TxSet for slot {slot} from horizon archive"
 		);
 		let txset_map_arc = self.txset_map_clone();
-		let archive_urls = self.stellar_history_archive_urls();
+		let txset_slots_arc = self.txset_slots_clone();
+		let store = self.store_clone();
+		let archive_client = self.archive_client();
 
 		async move {
-			for archive_url in archive_urls {
-				let tx_archive_storage = TransactionsArchiveStorage(archive_url);
-				let transactions_archive = tx_archive_storage.get_archive(slot).await;
-
-				let transactions_archive = match transactions_archive {
-					Ok(value) => value,
-					Err(e) => {
-						tracing::error!(
-							"get_txset_from_horizon_archive(): Could not get TransactionsArchive for slot {slot} from horizon archive: {e:?}"
-						);
-						continue;
-					},
-				};
-
-				let value = transactions_archive
-					.get_vec()
-					.iter()
-					.find(|&entry| Slot::from(entry.ledger_seq) == slot);
-
-				if let Some(target_history_entry) = value {
+			match archive_client.get_tx_set(slot).await {
+				Ok(tx_set_type) => {
 					tracing::info!(
 						"get_txset_from_horizon_archive(): Adding archived tx set for slot {slot}"
 					);
-					let mut tx_set_map = txset_map_arc.write();
-
-					// Assign the transaction set based on the type defined in `ext`
-					let tx_set_type = match target_history_entry.clone().ext {
-						TransactionHistoryEntryExt::V1(generalized_tx_set) =>
-						// If the type of `ext` is `V1` we use the contained generalized tx set
-							TransactionSetType::new(generalized_tx_set),
-						// Otherwise we can use the regular `tx_set` contained in the entry
-						_ => TransactionSetType::new(target_history_entry.tx_set.clone()),
+					let txset_hash = match tx_set_type.get_tx_set_hash() {
+						Ok(txset_hash) => txset_hash,
+						Err(e) => {
+							tracing::warn!("get_txset_from_horizon_archive(): Could not get the hash of the tx set for slot {slot}: {e:?}");
+							return
+						},
 					};
-					tx_set_map.insert(slot, tx_set_type);
-					// remove the archive file after a txset has been found
-					tx_archive_storage.remove_file(slot);
-					break;
-				} else {
-					tracing::warn!(
-						"get_txset_from_horizon_archive(): Could not get TransactionHistory entry from archive for slot {slot}"
-					);
-				}
+					if let Some(store) = &store {
+						store.save_tx_set(slot, &tx_set_type);
+					}
+					insert_txset(&txset_map_arc, &txset_slots_arc, slot, txset_hash, tx_set_type);
+				},
+				Err(e) => tracing::warn!(
+					"get_txset_from_horizon_archive(): Could not get tx set for slot {slot} from horizon archive: {e:?}"
+				),
 			}
 		}
 	}
@@ -372,10 +413,21 @@ mod test {
 		collector::{
 			proof_builder::check_slot_still_recoverable_from_overlay, ScpMessageCollector,
 		},
+		get_random_secret_key, specific_stellar_relay_config,
+		traits::FileHandler,
 		types::constants::MAX_SLOTS_TO_REMEMBER,
+		EnvelopesFileHandler,
+	};
+	use std::{sync::Arc, time::Duration};
+	use stellar_relay_lib::{
+		connect_to_stellar_overlay_network,
+		mock_peer::{MockPeerScript, MockStellarPeer},
+		sdk::types::{ScpStatementPledges, StellarMessage},
+	};
+	use tokio::{
+		sync::RwLock,
+		time::{sleep, timeout},
 	};
-	use stellar_relay_lib::sdk::types::StellarMessage;
-	use tokio::sync::mpsc;
 
 	fn collector(is_mainnet: bool) -> ScpMessageCollector {
 		let archives = if is_mainnet {
@@ -412,21 +464,81 @@ mod test {
 		));
 	}
 
-	#[tokio::test]
+	#[tokio::test(flavor = "multi_thread")]
+	#[ntest::timeout(60_000)]
 	async fn test_ask_overlay_for_envelopes() {
-		let (sender, mut receiver) = mpsc::channel::<StellarMessage>(1024);
-		let collector = collector(false);
-
-		let expected_slot = 50;
-		collector.ask_overlay_for_envelopes(expected_slot, &sender).await;
+		let env_map =
+			EnvelopesFileHandler::get_map_from_archives(578291).expect("should return a map");
+		let (expected_slot, envelopes) = env_map.first().expect("should return a tuple");
+
+		let base_config = specific_stellar_relay_config(true, 0);
+		let script = MockPeerScript::new().reply_to_get_scp_state(envelopes.clone());
+		let mock_peer = MockStellarPeer::start(base_config.node_info.clone().into(), script)
+			.await
+			.expect("should start the mock peer");
+
+		let overlay_conn = connect_to_stellar_overlay_network(
+			mock_peer.overlay_config(base_config),
+			get_random_secret_key(),
+		)
+		.await
+		.expect("should connect to the mock peer");
+
+		let collector = Arc::new(RwLock::new(collector(true)));
+		ScpMessageCollector::ask_overlay_for_envelopes(
+			&collector,
+			*expected_slot,
+			&overlay_conn.requester(),
+		)
+		.await;
+
+		let expected_slot_u32 = u32::try_from(*expected_slot).expect("should fit in a u32");
+		assert!(mock_peer
+			.received_messages()
+			.contains(&StellarMessage::GetScpState(expected_slot_u32)));
+
+		// only the externalized envelopes are collected
+		let externalized = envelopes
+			.iter()
+			.filter(|env| matches!(env.statement.pledges, ScpStatementPledges::ScpStExternalize(_)))
+			.count();
+		let collected = collector.read().await.envelopes_map().get(expected_slot).map(Vec::len);
+		assert_eq!(collected, Some(externalized));
+	}
 
-		match receiver.recv().await.expect("should receive message") {
-			StellarMessage::GetScpState(actual_slot) => {
-				let actual_slot = u64::from(actual_slot);
-				assert_eq!(actual_slot, expected_slot);
-			},
-			msg => panic!("Expected GetScpState message, got {:?}", msg),
+	#[tokio::test(flavor = "multi_thread")]
+	#[ntest::timeout(60_000)]
+	async fn collector_is_not_locked_while_asking_the_overlay() {
+		let base_config = specific_stellar_relay_config(true, 0);
+		// the mock peer never replies to a `GetScpState`
+		let mock_peer =
+			MockStellarPeer::start(base_config.node_info.clone().into(), MockPeerScript::new())
+				.await
+				.expect("should start the mock peer");
+
+		let overlay_conn = connect_to_stellar_overlay_network(
+			mock_peer.overlay_config(base_config),
+			get_random_secret_key(),
+		)
+		.await
+		.expect("should connect to the mock peer");
+
+		let collector = Arc::new(RwLock::new(collector(true)));
+		let requester = overlay_conn.requester();
+		let asking_collector = collector.clone();
+		let asking = tokio::spawn(async move {
+			ScpMessageCollector::ask_overlay_for_envelopes(&asking_collector, 578291, &requester)
+				.await
+		});
+
+		// wait until the request is sent
+		while !mock_peer.received_messages().contains(&StellarMessage::GetScpState(578291)) {
+			sleep(Duration::from_millis(50)).await;
 		}
+
+		// the messages of the overlay can still be collected
+		assert!(timeout(Duration::from_secs(1), collector.write()).await.is_ok());
+		asking.abort();
 	}
 
 	#[tokio::test]
@@ -0,0 +1,480 @@
+use std::collections::{HashMap, HashSet};
+
+use runtime::{StellarRelayPallet, StellarRelayValidator};
+use stellar_relay_lib::{
+	sdk::{
+		network::{Network, PUBLIC_NETWORK, TEST_NETWORK},
+		types::{ScpEnvelope, ScpStatementPledges, Value},
+		TransactionEnvelope,
+	},
+	verify_scp_envelope,
+};
+use wallet::Slot;
+
+use crate::oracle::{collector::Proof, Error};
+
+/// The reasons the stellar-relay pallet rejects a proof for
+#[derive(Debug, Eq, PartialEq, thiserror::Error)]
+pub enum ProofError {
+	#[error("No validators registered")]
+	NoValidatorsRegistered,
+
+	#[error("Empty envelope set")]
+	EmptyEnvelopeSet,
+
+	#[error("Envelope of slot {found} in the proof of slot {expected}")]
+	EnvelopeSlotIndexMismatch { expected: u64, found: u64 },
+
+	#[error("Invalid signature of the envelope of {0}")]
+	InvalidEnvelopeSignature(String),
+
+	#[error("Envelope of {0} neither externalizes nor confirms a value")]
+	InvalidScpPledge(String),
+
+	#[error("Envelope of {0} commits to another value")]
+	ExternalizedValueMismatch(String),
+
+	#[error("Envelope of {0} commits to another n_h")]
+	ExternalizedNHMismatch(String),
+
+	#[error("No externalized message")]
+	MissingExternalizedMessage,
+
+	#[error("Only {signed} out of {total} organizations signed")]
+	InvalidQuorumSetNotEnoughOrganizations { signed: usize, total: usize },
+
+	#[error("Only {signed} out of {total} validators of organization {organization_id} signed")]
+	InvalidQuorumSetNotEnoughValidators { organization_id: u128, signed: usize, total: usize },
+
+	#[error("Only {signed} nodes signed, {required} have to")]
+	NotEnoughSigners { signed: usize, required: usize },
+
+	#[error("Failed to compute the hash of the transaction set")]
+	TransactionSetHashCreationFailed,
+
+	#[error("Transaction set hash does not match the externalized value")]
+	TransactionSetHashMismatch,
+
+	#[error("Transaction not in transaction set")]
+	TransactionNotInTransactionSet,
+}
+
+/// A validator that the stellar-relay pallet accepts envelopes of
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StellarValidator {
+	/// the encoded public key, as in `G...`
+	pub public_key: Vec<u8>,
+	pub organization_id: u128,
+}
+
+/// The validators the proofs are checked against
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ValidatorSet {
+	validators: Vec<StellarValidator>,
+}
+
+impl ValidatorSet {
+	pub fn new(validators: Vec<StellarValidator>) -> Self {
+		ValidatorSet { validators }
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.validators.is_empty()
+	}
+
+	/// Returns the fewest envelopes a proof can have and still reach the quorum: more than half
+	/// of the validators of more than 2/3 of the organizations, taking the smallest organizations
+	pub fn min_externalized_messages(&self) -> usize {
+		let mut required: Vec<usize> = self
+			.organizations(&HashSet::new())
+			.into_values()
+			.map(|(_, total)| total / 2 + 1)
+			.collect();
+		required.sort_unstable();
+
+		let organizations = required.len() * 2 / 3 + 1;
+		required.into_iter().take(organizations).sum()
+	}
+
+	/// Returns how many of the validators of each organization signed, and how many there are
+	fn organizations(&self, signers: &HashSet<Vec<u8>>) -> HashMap<u128, (usize, usize)> {
+		let mut organizations: HashMap<u128, (usize, usize)> = HashMap::new();
+		for validator in &self.validators {
+			let counts = organizations.entry(validator.organization_id).or_default();
+			counts.1 += 1;
+			if signers.contains(&validator.public_key) {
+				counts.0 += 1;
+			}
+		}
+		organizations
+	}
+
+	fn organization_of(&self, public_key: &[u8]) -> Option<u128> {
+		self.validators
+			.iter()
+			.find(|validator| validator.public_key == public_key)
+			.map(|validator| validator.organization_id)
+	}
+
+	/// Checks that more than 2/3 of the organizations signed, and that more than half of the
+	/// validators of each of those organizations signed
+	pub(crate) fn check_quorum(&self, signers: &HashSet<Vec<u8>>) -> Result<(), ProofError> {
+		let organizations = self.organizations(signers);
+
+		let total = organizations.len();
+		let signed = organizations.values().filter(|(signed, _)| *signed > 0).count();
+		if signed * 3 <= total * 2 {
+			return Err(ProofError::InvalidQuorumSetNotEnoughOrganizations { signed, total })
+		}
+
+		for (organization_id, (signed, total)) in organizations {
+			if signed > 0 && signed * 2 <= total {
+				return Err(ProofError::InvalidQuorumSetNotEnoughValidators {
+					organization_id,
+					signed,
+					total,
+				})
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Checks that the nodes of the envelopes reach the quorum of the validators
+	pub(crate) fn check_quorum_of(&self, envelopes: &[&ScpEnvelope]) -> Result<(), ProofError> {
+		let signers = envelopes
+			.iter()
+			.map(|env| env.statement.node_id.to_encoding())
+			.filter(|node_id| self.organization_of(node_id).is_some())
+			.collect();
+		self.check_quorum(&signers)
+	}
+}
+
+impl From<Vec<StellarRelayValidator>> for ValidatorSet {
+	fn from(validators: Vec<StellarRelayValidator>) -> Self {
+		ValidatorSet::new(
+			validators
+				.into_iter()
+				.map(|validator| StellarValidator {
+					public_key: validator.public_key.0,
+					organization_id: validator.organization_id,
+				})
+				.collect(),
+		)
+	}
+}
+
+impl Proof {
+	/// Checks the proof of `tx_env` the way the stellar-relay pallet does, so that an execute
+	/// extrinsic that is bound to fail is not paid for.
+	pub fn verify(
+		&self,
+		tx_env: &TransactionEnvelope,
+		validators: &ValidatorSet,
+		network: &Network,
+	) -> Result<(), ProofError> {
+		self.verify_envelopes(validators, network)?;
+
+		if !self.tx_set().txes().iter().any(|tx| tx == tx_env) {
+			return Err(ProofError::TransactionNotInTransactionSet)
+		}
+
+		Ok(())
+	}
+
+	/// Checks the envelopes and the tx set of the proof, but not what transaction it is for
+	pub fn verify_envelopes(
+		&self,
+		validators: &ValidatorSet,
+		network: &Network,
+	) -> Result<(), ProofError> {
+		if validators.validators.is_empty() {
+			return Err(ProofError::NoValidatorsRegistered)
+		}
+
+		self.check_envelopes(network, |signers| validators.check_quorum(signers))
+	}
+
+	/// Checks the envelopes and the tx set of the proof while the validators are unknown. Instead
+	/// of a quorum of the validators, `min_envelopes` nodes have to sign.
+	pub(crate) fn verify_envelopes_without_validators(
+		&self,
+		network: &Network,
+		min_envelopes: usize,
+	) -> Result<(), ProofError> {
+		self.check_envelopes(network, |signers| check_signer_count(signers, min_envelopes))
+	}
+
+	/// Checks the envelopes with `check_externalized_value`, and that the externalized value is
+	/// the hash of the tx set
+	fn check_envelopes(
+		&self,
+		network: &Network,
+		check_signers: impl FnOnce(&HashSet<Vec<u8>>) -> Result<(), ProofError>,
+	) -> Result<(), ProofError> {
+		let value =
+			check_externalized_value(self.slot(), self.envelopes(), network, check_signers)?;
+
+		let tx_set_hash = self
+			.tx_set()
+			.get_tx_set_hash()
+			.map_err(|_| ProofError::TransactionSetHashCreationFailed)?;
+		if value.get_vec().get(0..32) != Some(&tx_set_hash[..]) {
+			return Err(ProofError::TransactionSetHashMismatch)
+		}
+
+		Ok(())
+	}
+}
+
+/// Checks the signatures of the envelopes of the slot, that they all externalize or confirm the
+/// same value, and whether the nodes that signed are enough with `check_signers`. Returns the
+/// value.
+pub(crate) fn check_externalized_value<'a>(
+	slot: Slot,
+	envelopes: &'a [ScpEnvelope],
+	network: &Network,
+	check_signers: impl FnOnce(&HashSet<Vec<u8>>) -> Result<(), ProofError>,
+) -> Result<&'a Value, ProofError> {
+	if envelopes.is_empty() {
+		return Err(ProofError::EmptyEnvelopeSet)
+	}
+
+	// the value and n_h of the first externalize statement
+	let mut externalized: Option<(&Value, u32)> = None;
+	let mut signers = HashSet::new();
+
+	for env in envelopes {
+		let node_id = env.statement.node_id.to_encoding();
+		let node = String::from_utf8_lossy(&node_id).to_string();
+
+		if env.statement.slot_index != slot {
+			return Err(ProofError::EnvelopeSlotIndexMismatch {
+				expected: slot,
+				found: env.statement.slot_index,
+			})
+		}
+
+		if !verify_scp_envelope(network.get_id(), env) {
+			return Err(ProofError::InvalidEnvelopeSignature(node))
+		}
+
+		let (value, n_h) = match &env.statement.pledges {
+			ScpStatementPledges::ScpStExternalize(stmt) => (&stmt.commit.value, stmt.n_h),
+			ScpStatementPledges::ScpStConfirm(stmt) => (&stmt.ballot.value, stmt.n_h),
+			_ => return Err(ProofError::InvalidScpPledge(node)),
+		};
+
+		match externalized {
+			None => externalized = Some((value, n_h)),
+			Some((externalized_value, _)) if externalized_value != value =>
+				return Err(ProofError::ExternalizedValueMismatch(node)),
+			Some((_, externalized_n_h)) if externalized_n_h != n_h =>
+				return Err(ProofError::ExternalizedNHMismatch(node)),
+			_ => {},
+		}
+
+		signers.insert(node_id);
+	}
+
+	let Some((value, _)) = externalized else { return Err(ProofError::MissingExternalizedMessage) };
+
+	check_signers(&signers)?;
+	Ok(value)
+}
+
+/// Checks that `min_envelopes` nodes signed, for when the validators are unknown
+pub(crate) fn check_signer_count(
+	signers: &HashSet<Vec<u8>>,
+	min_envelopes: usize,
+) -> Result<(), ProofError> {
+	if signers.len() < min_envelopes {
+		return Err(ProofError::NotEnoughSigners { signed: signers.len(), required: min_envelopes })
+	}
+	Ok(())
+}
+
+/// Checks the proof against the validators that are currently registered in the stellar-relay
+/// pallet
+pub async fn verify_proof<P: StellarRelayPallet>(
+	parachain_rpc: &P,
+	proof: &Proof,
+	tx_env: &TransactionEnvelope,
+) -> Result<(), crate::Error> {
+	let network =
+		if parachain_rpc.is_public_network().await { &PUBLIC_NETWORK } else { &TEST_NETWORK };
+	let validators = ValidatorSet::from(parachain_rpc.get_stellar_validators().await?);
+
+	proof.verify(tx_env, &validators, network).map_err(Error::from)?;
+	Ok(())
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::oracle::{traits::FileHandler, EnvelopesFileHandler, TxSetsFileHandler};
+	use stellar_relay_lib::sdk::{compound_types::UnlimitedVarArray, types::ScpEnvelope};
+	use wallet::Slot;
+
+	const SLOT: Slot = 578291;
+
+	fn envelopes() -> Vec<ScpEnvelope> {
+		let env_map =
+			EnvelopesFileHandler::get_map_from_archives(SLOT).expect("should return a map");
+		env_map.get(&SLOT).expect("should have envelopes").clone()
+	}
+
+	/// every node of the envelopes is a validator of its own organization
+	fn validator_set(envelopes: &[ScpEnvelope]) -> ValidatorSet {
+		ValidatorSet::new(
+			envelopes
+				.iter()
+				.enumerate()
+				.map(|(organization_id, env)| StellarValidator {
+					public_key: env.statement.node_id.to_encoding(),
+					organization_id: organization_id as u128,
+				})
+				.collect(),
+		)
+	}
+
+	fn proof(slot: Slot, envelopes: Vec<ScpEnvelope>) -> Proof {
+		let txset_slot = 92910;
+		let txsets_map =
+			TxSetsFileHandler::get_map_from_archives(txset_slot).expect("should return a map");
+		let tx_set = txsets_map.get(&txset_slot).expect("should return a tx set").clone();
+
+		Proof::new(
+			slot,
+			UnlimitedVarArray::new(envelopes).expect("should create the array"),
+			tx_set,
+		)
+	}
+
+	fn verify(proof: &Proof, validators: &ValidatorSet) -> Result<(), ProofError> {
+		let tx_env = proof.tx_set().txes().first().cloned().expect("should have a transaction");
+		proof.verify(&tx_env, validators, &TEST_NETWORK)
+	}
+
+	#[test]
+	fn proofs_without_validators_or_envelopes_are_rejected() {
+		let envelopes = envelopes();
+		let validators = validator_set(&envelopes);
+
+		assert_eq!(
+			verify(&proof(SLOT, envelopes), &ValidatorSet::default()),
+			Err(ProofError::NoValidatorsRegistered)
+		);
+		assert_eq!(verify(&proof(SLOT, vec![]), &validators), Err(ProofError::EmptyEnvelopeSet));
+	}
+
+	#[test]
+	fn envelopes_of_another_slot_are_rejected() {
+		let envelopes = envelopes();
+		let validators = validator_set(&envelopes);
+
+		assert_eq!(
+			verify(&proof(SLOT + 1, envelopes), &validators),
+			Err(ProofError::EnvelopeSlotIndexMismatch { expected: SLOT + 1, found: SLOT })
+		);
+	}
+
+	#[test]
+	fn forged_envelopes_are_rejected() {
+		let mut envelopes = envelopes();
+		let validators = validator_set(&envelopes);
+
+		// the statement of one node signed by another one
+		envelopes[0].statement.node_id = envelopes[1].statement.node_id.clone();
+		let node =
+			String::from_utf8_lossy(&envelopes[0].statement.node_id.to_encoding()).to_string();
+
+		assert_eq!(
+			verify(&proof(SLOT, envelopes), &validators),
+			Err(ProofError::InvalidEnvelopeSignature(node))
+		);
+	}
+
+	#[test]
+	fn without_validators_enough_nodes_have_to_sign() {
+		let envelopes = envelopes();
+		let signed = envelopes.len();
+		let proof = proof(SLOT, envelopes);
+
+		assert_eq!(
+			proof.verify_envelopes_without_validators(&TEST_NETWORK, signed + 1),
+			Err(ProofError::NotEnoughSigners { signed, required: signed + 1 })
+		);
+		// enough nodes signed, but the tx set of the proof is from another slot
+		assert_eq!(
+			proof.verify_envelopes_without_validators(&TEST_NETWORK, signed),
+			Err(ProofError::TransactionSetHashMismatch)
+		);
+	}
+
+	#[test]
+	fn quorum_needs_two_thirds_of_the_organizations_and_half_of_their_validators() {
+		let validator = |public_key: &str, organization_id| StellarValidator {
+			public_key: public_key.as_bytes().to_vec(),
+			organization_id,
+		};
+		let validators = ValidatorSet::new(vec![
+			validator("A1", 0),
+			validator("A2", 0),
+			validator("A3", 0),
+			validator("B1", 1),
+			validator("B2", 1),
+			validator("C1", 2),
+		]);
+		let signers = |public_keys: &[&str]| -> HashSet<Vec<u8>> {
+			public_keys.iter().map(|public_key| public_key.as_bytes().to_vec()).collect()
+		};
+
+		assert_eq!(validators.check_quorum(&signers(&["A1", "A2", "B1", "B2", "C1"])), Ok(()));
+		assert_eq!(
+			validators.check_quorum(&signers(&["A1", "A2", "B1", "B2"])),
+			Err(ProofError::InvalidQuorumSetNotEnoughOrganizations { signed: 2, total: 3 })
+		);
+		assert_eq!(
+			validators.check_quorum(&signers(&["A1", "A2", "B1", "C1"])),
+			Err(ProofError::InvalidQuorumSetNotEnoughValidators {
+				organization_id: 1,
+				signed: 1,
+				total: 2
+			})
+		);
+	}
+
+	#[test]
+	fn min_externalized_messages_takes_the_smallest_organizations() {
+		let validator = |public_key: &str, organization_id| StellarValidator {
+			public_key: public_key.as_bytes().to_vec(),
+			organization_id,
+		};
+		let mut validators = vec![
+			validator("A1", 0),
+			validator("A2", 0),
+			validator("A3", 0),
+			validator("B1", 1),
+			validator("B2", 1),
+			validator("C1", 2),
+		];
+
+		// all 3 organizations have to sign
+		assert_eq!(ValidatorSet::new(validators.clone()).min_externalized_messages(), 5);
+
+		// 3 out of 4 organizations: B, C and D
+		validators.push(validator("D1", 3));
+		let validators = ValidatorSet::new(validators);
+		assert_eq!(validators.min_externalized_messages(), 4);
+
+		let signers: HashSet<Vec<u8>> = ["B1", "B2", "C1", "D1"]
+			.iter()
+			.map(|public_key| public_key.as_bytes().to_vec())
+			.collect();
+		assert_eq!(validators.check_quorum(&signers), Ok(()));
+
+		assert_eq!(ValidatorSet::default().min_externalized_messages(), 0);
+	}
+}
@@ -4,6 +4,8 @@ use tokio::sync::{mpsc, oneshot};
 
 use stellar_relay_lib::sdk::StellarSdkError;
 
+use crate::oracle::collector::ProofError;
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
 	#[error("Stellar SDK Error: {0:?}")]
@@ -38,6 +40,12 @@ pub enum Error {
 
 	#[error("ArchiveResponseError: {0}")]
 	ArchiveResponseError(String),
+
+	#[error("Proof Server Error: {0}")]
+	ProofServerError(String),
+
+	#[error("Invalid Proof: {0}")]
+	InvalidProof(#[from] ProofError),
 }
 
 impl From<StellarSdkError> for Error {
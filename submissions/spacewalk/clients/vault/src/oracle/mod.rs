@@ -1,15 +1,19 @@
 #![allow(dead_code)]
 
 pub use agent::*;
-pub use collector::Proof;
 use collector::*;
+pub use collector::{verify_proof, Proof, ProofError, StellarValidator, ValidatorSet};
 pub use errors::Error;
+pub use proof_source::*;
+pub use server::*;
 pub use storage::*;
 use types::*;
 
 mod agent;
 mod collector;
 mod errors;
+mod proof_source;
+mod server;
 pub mod storage;
 pub mod types;
 
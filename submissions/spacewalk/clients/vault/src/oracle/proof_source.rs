@@ -0,0 +1,236 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use stellar_relay_lib::{
+	sdk::{compound_types::UnlimitedVarArray, types::ScpEnvelope, TransactionSetType},
+	StellarOverlayRequester,
+};
+use tokio::time::sleep;
+use wallet::Slot;
+
+use crate::{
+	oracle::{
+		storage::externalized_envelopes, ArchiveClient, Error, Proof, ProofResponse,
+		ScpMessageCollector,
+	},
+	ArcRwLock,
+};
+
+/// Somewhere the proof of a slot can be taken from.
+/// `OracleAgent::get_proof` asks its sources one after the other, until one has the proof.
+#[async_trait]
+pub trait ProofSource: Send + Sync {
+	/// names the source in the logs
+	fn name(&self) -> String;
+
+	/// how long to wait for the proof before asking the next source
+	fn timeout(&self) -> Duration;
+
+	/// Whether the proofs come from outside of the vault. These are checked against the
+	/// validators before they are used.
+	fn is_external(&self) -> bool {
+		true
+	}
+
+	async fn get_proof(&self, slot: Slot) -> Result<Proof, Error>;
+}
+
+/// Builds the proofs out of what the collector received from the overlay, and asks the Stellar
+/// Node for what is missing
+pub struct CollectorProofSource {
+	collector: ArcRwLock<ScpMessageCollector>,
+	overlay_requester: StellarOverlayRequester,
+	timeout: Duration,
+}
+
+impl CollectorProofSource {
+	// the interval for every build_proof retry
+	const BUILD_PROOF_INTERVAL: Duration = Duration::from_secs(10);
+
+	pub fn new(
+		collector: ArcRwLock<ScpMessageCollector>,
+		overlay_requester: StellarOverlayRequester,
+		timeout: Duration,
+	) -> Self {
+		CollectorProofSource { collector, overlay_requester, timeout }
+	}
+}
+
+#[async_trait]
+impl ProofSource for CollectorProofSource {
+	fn name(&self) -> String {
+		"collector".to_string()
+	}
+
+	fn timeout(&self) -> Duration {
+		self.timeout
+	}
+
+	fn is_external(&self) -> bool {
+		false
+	}
+
+	async fn get_proof(&self, slot: Slot) -> Result<Proof, Error> {
+		loop {
+			tracing::debug!("get_proof(): attempt to build proof for slot {slot}");
+			if let Some(proof) =
+				ScpMessageCollector::build_proof(&self.collector, slot, &self.overlay_requester)
+					.await
+			{
+				return Ok(proof)
+			}
+
+			// give enough interval for every retry
+			sleep(Self::BUILD_PROOF_INTERVAL).await;
+		}
+	}
+}
+
+/// Takes the proofs from the Stellar history archives
+pub struct ArchiveProofSource {
+	archive_client: ArchiveClient,
+	timeout: Duration,
+}
+
+impl ArchiveProofSource {
+	pub fn new(archive_client: ArchiveClient, timeout: Duration) -> Self {
+		ArchiveProofSource { archive_client, timeout }
+	}
+}
+
+#[async_trait]
+impl ProofSource for ArchiveProofSource {
+	fn name(&self) -> String {
+		"history archives".to_string()
+	}
+
+	fn timeout(&self) -> Duration {
+		self.timeout
+	}
+
+	async fn get_proof(&self, slot: Slot) -> Result<Proof, Error> {
+		let envelopes = self.archive_client.get_envelopes(slot).await?;
+		let tx_set = self.archive_client.get_tx_set(slot).await?;
+
+		archived_proof(slot, envelopes, tx_set)
+	}
+}
+
+/// Returns the proof of the archived envelopes, without the nominate and prepare statements that
+/// the archive records as well
+fn archived_proof(
+	slot: Slot,
+	envelopes: Vec<ScpEnvelope>,
+	tx_set: TransactionSetType,
+) -> Result<Proof, Error> {
+	Ok(Proof::new(slot, UnlimitedVarArray::new(externalized_envelopes(envelopes))?, tx_set))
+}
+
+/// Asks a `proof-server` for the proofs
+pub struct RemoteProofSource {
+	url: String,
+	http: reqwest::Client,
+	timeout: Duration,
+}
+
+impl RemoteProofSource {
+	pub fn new(url: String, timeout: Duration) -> Self {
+		RemoteProofSource { url, http: reqwest::Client::new(), timeout }
+	}
+}
+
+#[async_trait]
+impl ProofSource for RemoteProofSource {
+	fn name(&self) -> String {
+		format!("proof server {}", self.url)
+	}
+
+	fn timeout(&self) -> Duration {
+		self.timeout
+	}
+
+	async fn get_proof(&self, slot: Slot) -> Result<Proof, Error> {
+		let url = format!("{}/proof/{slot}", self.url.trim_end_matches('/'));
+		let response = self
+			.http
+			.get(&url)
+			.send()
+			.await
+			.map_err(|e| Error::ProofServerError(format!("Failed to reach {url}: {e}")))?;
+
+		let status = response.status();
+		if !status.is_success() {
+			let body = response.text().await.unwrap_or_default();
+			return Err(Error::ProofServerError(format!("{url} answered {status}: {body}")))
+		}
+
+		let proof: ProofResponse = response
+			.json()
+			.await
+			.map_err(|e| Error::ProofServerError(format!("Invalid answer of {url}: {e}")))?;
+		if proof.slot != slot {
+			return Err(Error::ProofServerError(format!(
+				"{url} answered with the proof of slot {}",
+				proof.slot
+			)))
+		}
+
+		proof.decode()
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::oracle::{traits::FileHandler, ProofError, TxSetsFileHandler};
+	use stellar_relay_lib::{
+		mock_peer::signed_externalize,
+		sdk::{
+			compound_types::LimitedVarArray,
+			network::TEST_NETWORK,
+			types::{ScpNomination, ScpQuorumSet, ScpStatementPledges},
+			SecretKey,
+		},
+	};
+
+	#[test]
+	fn archived_proofs_only_have_externalize_and_confirm_envelopes() {
+		let slot = 578291;
+		let validators: Vec<SecretKey> =
+			(1..=2).map(|seed| SecretKey::from_binary([seed; 32])).collect();
+		let qset = ScpQuorumSet {
+			threshold: 1,
+			validators: LimitedVarArray::new(
+				validators.iter().map(|validator| validator.get_public().clone()).collect(),
+			)
+			.expect("should create the validators"),
+			inner_sets: LimitedVarArray::new(vec![]).expect("should create the inner sets"),
+		};
+
+		// the archive entry records a nomination next to the externalize statements
+		let mut envelopes: Vec<ScpEnvelope> = validators
+			.iter()
+			.map(|validator| signed_externalize(validator, slot, 1, &qset))
+			.collect();
+		let mut nomination = envelopes[0].clone();
+		nomination.statement.pledges = ScpStatementPledges::ScpStNominate(ScpNomination {
+			quorum_set_hash: [0; 32],
+			votes: UnlimitedVarArray::new(vec![]).expect("should create the votes"),
+			accepted: UnlimitedVarArray::new(vec![]).expect("should create the accepted"),
+		});
+		envelopes.push(nomination);
+
+		let txsets_map =
+			TxSetsFileHandler::get_map_from_archives(92910).expect("should return a map");
+		let tx_set = txsets_map.get(&92910).expect("should return a tx set").clone();
+
+		let proof = archived_proof(slot, envelopes, tx_set).expect("should build the proof");
+		assert_eq!(proof.envelopes().len(), validators.len());
+
+		// the envelopes pass; only the tx set, which is of another slot, does not
+		assert_eq!(
+			proof.verify_envelopes_without_validators(&TEST_NETWORK, validators.len()),
+			Err(ProofError::TransactionSetHashMismatch)
+		);
+	}
+}
@@ -0,0 +1,258 @@
+use std::{
+	convert::{Infallible, TryInto},
+	fmt::Debug,
+	sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+use service::warp::{
+	self,
+	http::StatusCode,
+	reply::{self, Response},
+	Filter, Rejection, Reply,
+};
+use stellar_relay_lib::sdk::{
+	compound_types::UnlimitedVarArray, types::ScpEnvelope, TransactionSetType, XdrCodec,
+};
+use wallet::Slot;
+
+use crate::oracle::{Error, OracleAgent, Proof, TxSetHash};
+
+/// The proof of a slot, encoded like `Proof::encode`
+#[derive(Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ProofResponse {
+	pub slot: Slot,
+	/// the base64 XDR of the envelopes
+	pub envelopes: String,
+	/// the base64 XDR of the tx set
+	pub tx_set: String,
+}
+
+impl From<&Proof> for ProofResponse {
+	fn from(proof: &Proof) -> Self {
+		let (envelopes, tx_set) = proof.encode();
+		ProofResponse { slot: proof.slot(), envelopes, tx_set }
+	}
+}
+
+impl ProofResponse {
+	/// Decodes the proof that a proof server sent
+	pub fn decode(&self) -> Result<Proof, Error> {
+		let envelopes = base64::decode(&self.envelopes).map_err(decode_error)?;
+		let envelopes =
+			UnlimitedVarArray::<ScpEnvelope>::from_xdr(envelopes).map_err(decode_error)?;
+
+		let tx_set = base64::decode(&self.tx_set).map_err(decode_error)?;
+		let tx_set = TransactionSetType::from_xdr(tx_set).map_err(decode_error)?;
+
+		Ok(Proof::new(self.slot, envelopes, tx_set))
+	}
+}
+
+fn decode_error<E: Debug>(e: E) -> Error {
+	Error::ProofServerError(format!("Decode Error: {e:?}"))
+}
+
+/// What the collector of the proof server holds
+#[derive(Debug, Eq, PartialEq, Serialize)]
+pub struct StatusResponse {
+	pub is_public_network: bool,
+	pub last_slot_index: Slot,
+	/// how many slots have envelopes
+	pub slots_with_envelopes: usize,
+	/// how many envelopes there are, of all the slots
+	pub envelopes: usize,
+	pub tx_sets: usize,
+	/// the fewest envelopes a proof is built with
+	pub min_externalized_messages: usize,
+}
+
+/// A tx set that the collector holds, as base64 XDR
+#[derive(Debug, Eq, PartialEq, Serialize)]
+pub struct TxSetResponse {
+	pub slot: Slot,
+	pub tx_set: String,
+}
+
+#[derive(Serialize)]
+struct ErrorResponse {
+	error: String,
+}
+
+/// The routes of the proof server:
+/// * `GET /proof/{slot}` - the proof of the slot, see `ProofResponse`
+/// * `GET /status` - see `StatusResponse`
+/// * `GET /txset/{hash}` - the tx set of the hex encoded hash, see `TxSetResponse`
+pub fn proof_server_routes(
+	agent: Arc<OracleAgent>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+	let with_agent = warp::any().map(move || agent.clone());
+
+	let proof =
+		warp::path!("proof" / Slot).and(warp::get()).and(with_agent.clone()).and_then(get_proof);
+	let status =
+		warp::path!("status").and(warp::get()).and(with_agent.clone()).and_then(get_status);
+	let tx_set =
+		warp::path!("txset" / String).and(warp::get()).and(with_agent).and_then(get_tx_set);
+
+	proof.or(status).or(tx_set)
+}
+
+async fn get_proof(slot: Slot, agent: Arc<OracleAgent>) -> Result<Response, Infallible> {
+	match agent.get_proof(slot).await {
+		Ok(proof) => Ok(reply::json(&ProofResponse::from(&proof)).into_response()),
+		Err(e) => {
+			tracing::warn!("get_proof(): failed to get the proof of slot {slot}: {e:?}");
+			let status = match e {
+				Error::ProofTimeout(_) => StatusCode::GATEWAY_TIMEOUT,
+				_ => StatusCode::INTERNAL_SERVER_ERROR,
+			};
+			Ok(error_response(status, format!("No proof of slot {slot}: {e}")))
+		},
+	}
+}
+
+async fn get_status(agent: Arc<OracleAgent>) -> Result<Response, Infallible> {
+	let collector = agent.collector.read().await;
+	let status = StatusResponse {
+		is_public_network: collector.is_public(),
+		last_slot_index: collector.last_slot_index(),
+		slots_with_envelopes: collector.envelopes_map_len(),
+		envelopes: collector.envelopes_count(),
+		tx_sets: collector.txset_map_len(),
+		min_externalized_messages: collector.min_externalized_messages(),
+	};
+	Ok(reply::json(&status).into_response())
+}
+
+async fn get_tx_set(hash: String, agent: Arc<OracleAgent>) -> Result<Response, Infallible> {
+	let tx_set_hash: Option<TxSetHash> =
+		hex::decode(&hash).ok().and_then(|hash| hash.try_into().ok());
+	let Some(tx_set_hash) = tx_set_hash else {
+		return Ok(error_response(
+			StatusCode::BAD_REQUEST,
+			format!("The tx set hash {hash} is not 32 hex encoded bytes"),
+		))
+	};
+
+	match agent.collector.read().await.find_txset(&tx_set_hash) {
+		Some((slot, tx_set)) => {
+			let tx_set = base64::encode(tx_set.to_xdr());
+			Ok(reply::json(&TxSetResponse { slot, tx_set }).into_response())
+		},
+		None => Ok(error_response(StatusCode::NOT_FOUND, format!("No tx set with hash {hash}"))),
+	}
+}
+
+fn error_response(status: StatusCode, error: String) -> Response {
+	reply::with_status(reply::json(&ErrorResponse { error }), status).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::oracle::{
+		get_random_secret_key, specific_stellar_relay_config, start_oracle_agent,
+		traits::FileHandler, EnvelopesFileHandler, StaticProofSource, TxSetsFileHandler,
+	};
+	use runtime::ShutdownSender;
+	use std::time::Duration;
+	use stellar_relay_lib::{
+		mock_peer::{MockPeerScript, MockStellarPeer},
+		sdk::types::StellarMessage,
+	};
+	use tokio::time::sleep;
+
+	fn sample_proof() -> Proof {
+		let slot = 578291;
+		let env_map =
+			EnvelopesFileHandler::get_map_from_archives(slot).expect("should return a map");
+		let envelopes = env_map.get(&slot).expect("should have envelopes").clone();
+
+		let txset_slot = 92910;
+		let txsets_map =
+			TxSetsFileHandler::get_map_from_archives(txset_slot).expect("should return a map");
+		let tx_set = txsets_map.get(&txset_slot).expect("should return a tx set").clone();
+
+		let envelopes = UnlimitedVarArray::new(envelopes).expect("should return an array");
+		Proof::new(slot, envelopes, tx_set)
+	}
+
+	#[test]
+	fn proof_responses_decode_to_the_proof() {
+		let proof = sample_proof();
+
+		let response = ProofResponse::from(&proof);
+		let json = serde_json::to_vec(&response).expect("should serialize");
+		let response: ProofResponse = serde_json::from_slice(&json).expect("should deserialize");
+		assert_eq!(response.decode().expect("should decode"), proof);
+
+		let invalid = ProofResponse { tx_set: "not base64".to_string(), ..response };
+		assert!(matches!(invalid.decode(), Err(Error::ProofServerError(_))));
+	}
+
+	#[tokio::test(flavor = "multi_thread")]
+	#[ntest::timeout(60_000)]
+	async fn proof_server_reports_the_collected_data() {
+		let first_slot = 578291;
+		let env_map =
+			EnvelopesFileHandler::get_map_from_archives(first_slot).expect("should return a map");
+		let (slot, envelopes) = env_map.first().expect("should return a tuple");
+
+		let base_config = specific_stellar_relay_config(true, 0);
+		let script = MockPeerScript::new()
+			.send_after_handshake(envelopes.iter().cloned().map(StellarMessage::ScpMessage));
+		let mock_peer = MockStellarPeer::start(base_config.node_info.clone().into(), script)
+			.await
+			.expect("should start the mock peer");
+
+		let agent = start_oracle_agent(
+			mock_peer.overlay_config(base_config),
+			get_random_secret_key(),
+			ShutdownSender::new(),
+		)
+		.await;
+		let routes = proof_server_routes(agent.clone());
+
+		// the envelopes arrive some time after the agent started
+		loop {
+			let response = warp::test::request().path("/status").reply(&routes).await;
+			assert_eq!(response.status(), StatusCode::OK);
+			let status: serde_json::Value =
+				serde_json::from_slice(response.body()).expect("should be json");
+			assert_eq!(status["is_public_network"], true);
+			if status["last_slot_index"] == *slot {
+				break
+			}
+			sleep(Duration::from_millis(100)).await;
+		}
+
+		let proof = sample_proof();
+		agent
+			.set_proof_sources(vec![Box::new(StaticProofSource {
+				proof: Some(proof.clone()),
+				is_external: false,
+			})])
+			.await;
+		let response =
+			warp::test::request().path(&format!("/proof/{}", proof.slot())).reply(&routes).await;
+		assert_eq!(response.status(), StatusCode::OK);
+		let response: serde_json::Value =
+			serde_json::from_slice(response.body()).expect("should be json");
+		let (envelopes, tx_set) = proof.encode();
+		assert_eq!(response["slot"], proof.slot());
+		assert_eq!(response["envelopes"], envelopes);
+		assert_eq!(response["tx_set"], tx_set);
+
+		let response = warp::test::request().path("/txset/not-a-hash").reply(&routes).await;
+		assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+		let unknown_hash = hex::encode([0u8; 32]);
+		let response =
+			warp::test::request().path(&format!("/txset/{unknown_hash}")).reply(&routes).await;
+		assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+		let response = warp::test::request().method("POST").path("/status").reply(&routes).await;
+		assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+	}
+}
@@ -0,0 +1,854 @@
+use std::{
+	collections::{BTreeMap, HashMap, HashSet, VecDeque},
+	sync::Arc,
+	time::{Duration, Instant},
+};
+
+use futures::future::select_ok;
+use parking_lot::Mutex;
+use primitives::stellar::types::TransactionHistoryEntryExt;
+use sha2::{Digest, Sha256};
+use stellar_relay_lib::sdk::{
+	compound_types::XdrArchive,
+	network::{Network, PUBLIC_NETWORK, TEST_NETWORK},
+	types::{
+		LedgerHeaderHistoryEntry, ScpEnvelope, ScpHistoryEntry, ScpStatementPledges,
+		TransactionHistoryEntry,
+	},
+	TransactionSetType, XdrCodec,
+};
+use wallet::Slot;
+
+use crate::oracle::{
+	collector::{check_externalized_value, check_signer_count},
+	constants::{
+		get_min_externalized_messages, ARCHIVE_CHECKPOINT_CACHE_SIZE, ARCHIVE_MAX_ANCHOR_DISTANCE,
+		ARCHIVE_NODE_LEDGER_BATCH, ARCHIVE_REQUEST_TIMEOUT, ARCHIVE_TRUSTED_LEDGERS_LIMIT,
+	},
+	storage::traits::{gz_decode, last_slot_index_in_batch, ArchiveStorage},
+	Error, LedgerArchiveStorage, ProofError, ScpArchiveStorage, TransactionsArchiveStorage,
+	TxSetHash, ValidatorSet,
+};
+
+/// How a history archive mirror has been doing
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct MirrorHealth {
+	pub url: String,
+	pub successes: u64,
+	pub failures: u64,
+	/// how long the last download took, whether it succeeded or not
+	pub last_latency: Option<Duration>,
+	pub last_error: Option<String>,
+}
+
+/// Keeps the most recently used checkpoints, at most `capacity`
+struct CheckpointCache<T> {
+	capacity: usize,
+	checkpoints: VecDeque<(Slot, Arc<T>)>,
+}
+
+impl<T> CheckpointCache<T> {
+	fn new(capacity: usize) -> Self {
+		CheckpointCache { capacity, checkpoints: VecDeque::new() }
+	}
+
+	fn get(&mut self, checkpoint: Slot) -> Option<Arc<T>> {
+		let index = self.checkpoints.iter().position(|(slot, _)| *slot == checkpoint)?;
+		// the most recently used one is kept the longest
+		let entry = self.checkpoints.remove(index)?;
+		let value = entry.1.clone();
+		self.checkpoints.push_back(entry);
+		Some(value)
+	}
+
+	fn insert(&mut self, checkpoint: Slot, value: Arc<T>) {
+		self.checkpoints.retain(|(slot, _)| *slot != checkpoint);
+		if self.checkpoints.len() >= self.capacity {
+			self.checkpoints.pop_front();
+		}
+		self.checkpoints.push_back((checkpoint, value));
+	}
+
+	fn remove(&mut self, checkpoint: Slot) {
+		self.checkpoints.retain(|(slot, _)| *slot != checkpoint);
+	}
+}
+
+/// The tx set hash of every ledger of a checkpoint, taken from its verified ledger headers
+type LedgerTxSetHashes = HashMap<Slot, TxSetHash>;
+
+/// A checkpoint file, whose entries are verified against the ledger headers up to a slot
+struct VerifiedCheckpoint<T> {
+	archive: XdrArchive<T>,
+	verified_up_to: Slot,
+}
+
+impl<T> VerifiedCheckpoint<T> {
+	fn new(archive: XdrArchive<T>, tx_set_hashes: &LedgerTxSetHashes) -> Self {
+		let verified_up_to = tx_set_hashes.keys().max().copied().unwrap_or_default();
+		VerifiedCheckpoint { archive, verified_up_to }
+	}
+}
+
+/// What a ledger header of the archive has to match, to be trusted
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum LedgerAnchor {
+	/// the value that a quorum externalized for the ledger, as in its `scp_value`
+	Value(Vec<u8>),
+	/// the hash of the ledger, as the trusted ledger after it refers to it
+	Hash(TxSetHash),
+}
+
+/// The ledgers that the ledger headers of the archives are checked against, the newest kept
+#[derive(Default)]
+struct TrustedLedgers {
+	anchors: BTreeMap<Slot, LedgerAnchor>,
+}
+
+impl TrustedLedgers {
+	fn insert(&mut self, slot: Slot, anchor: LedgerAnchor) {
+		// a value says more than a hash; both anchor the same header
+		if let Some(LedgerAnchor::Value(_)) = self.anchors.get(&slot) {
+			return
+		}
+		self.anchors.insert(slot, anchor);
+		while self.anchors.len() > ARCHIVE_TRUSTED_LEDGERS_LIMIT {
+			self.anchors.pop_first();
+		}
+	}
+
+	/// Returns the newest trusted ledger of the checkpoint
+	fn anchor_of_checkpoint(&self, checkpoint: Slot) -> Option<(Slot, LedgerAnchor)> {
+		let first_slot = checkpoint.saturating_sub(ARCHIVE_NODE_LEDGER_BATCH - 1);
+		self.anchors
+			.range(first_slot..=checkpoint)
+			.next_back()
+			.map(|(slot, anchor)| (*slot, anchor.clone()))
+	}
+}
+
+/// Fetches the checkpoint files of the Stellar history archives.
+/// All mirrors are asked at once and the first valid answer wins. The checkpoints are verified
+/// against their ledger headers, and kept decoded so that the other slots of the same checkpoint
+/// do not need another download.
+/// The ledger headers themselves are trusted only up to a ledger that a quorum externalized, or
+/// that a trusted ledger after it refers to. The quorum is either seen live by the overlay, or
+/// found in the SCP checkpoint of the archive.
+#[derive(Clone)]
+pub struct ArchiveClient {
+	urls: Vec<String>,
+	http: reqwest::Client,
+	health: Arc<Mutex<Vec<MirrorHealth>>>,
+	public_network: bool,
+	/// the validators that the envelopes of the archive are checked against, once they are known
+	validator_set: Arc<Mutex<Option<ValidatorSet>>>,
+	trusted_ledgers: Arc<Mutex<TrustedLedgers>>,
+	ledger_checkpoints: Arc<Mutex<CheckpointCache<LedgerTxSetHashes>>>,
+	scp_checkpoints: Arc<Mutex<CheckpointCache<VerifiedCheckpoint<ScpHistoryEntry>>>>,
+	tx_checkpoints: Arc<Mutex<CheckpointCache<VerifiedCheckpoint<TransactionHistoryEntry>>>>,
+}
+
+impl ArchiveClient {
+	pub fn new(urls: Vec<String>, public_network: bool) -> Self {
+		let health = urls
+			.iter()
+			.map(|url| MirrorHealth { url: url.clone(), ..Default::default() })
+			.collect();
+
+		ArchiveClient {
+			urls,
+			http: reqwest::Client::builder()
+				.timeout(ARCHIVE_REQUEST_TIMEOUT)
+				.build()
+				.unwrap_or_else(|e| {
+					tracing::warn!("new(): failed to set the timeout of the archive client: {e:?}");
+					reqwest::Client::new()
+				}),
+			health: Arc::new(Mutex::new(health)),
+			public_network,
+			validator_set: Default::default(),
+			trusted_ledgers: Default::default(),
+			ledger_checkpoints: Arc::new(Mutex::new(CheckpointCache::new(
+				ARCHIVE_CHECKPOINT_CACHE_SIZE,
+			))),
+			scp_checkpoints: Arc::new(Mutex::new(CheckpointCache::new(
+				ARCHIVE_CHECKPOINT_CACHE_SIZE,
+			))),
+			tx_checkpoints: Arc::new(Mutex::new(CheckpointCache::new(
+				ARCHIVE_CHECKPOINT_CACHE_SIZE,
+			))),
+		}
+	}
+
+	pub fn urls(&self) -> &[String] {
+		&self.urls
+	}
+
+	pub fn mirror_health(&self) -> Vec<MirrorHealth> {
+		self.health.lock().clone()
+	}
+
+	fn network(&self) -> &Network {
+		if self.public_network {
+			&PUBLIC_NETWORK
+		} else {
+			&TEST_NETWORK
+		}
+	}
+
+	pub fn set_validator_set(&self, validator_set: ValidatorSet) {
+		*self.validator_set.lock() = Some(validator_set);
+	}
+
+	/// Trusts the value that a quorum externalized for the slot, as the `scp_value` of its ledger
+	/// header
+	pub fn trust_externalized_value(&self, slot: Slot, value: Vec<u8>) {
+		self.trusted_ledgers.lock().insert(slot, LedgerAnchor::Value(value));
+	}
+
+	/// Whether the ledger headers of the checkpoint can be verified up to the slot
+	fn is_anchored(&self, checkpoint: Slot, slot: Slot) -> bool {
+		self.ledger_checkpoints.lock().get(checkpoint).is_some() ||
+			self.trusted_ledgers
+				.lock()
+				.anchor_of_checkpoint(checkpoint)
+				.is_some_and(|(anchored_slot, _)| anchored_slot >= slot)
+	}
+
+	/// Trusts the newest ledger of the checkpoint of the slot whose envelopes in the SCP
+	/// checkpoint reach the quorum, or the fewest envelopes of a proof while the validators are
+	/// unknown
+	async fn trust_archived_envelopes(&self, slot: Slot) -> Result<(), Error> {
+		let archive = self
+			.race(slot, ScpArchiveStorage, |archive| self.archived_value(archive, slot).map(|_| ()))
+			.await?;
+		let (anchored_slot, value) = self.archived_value(&archive, slot)?;
+
+		tracing::debug!(
+			"trust_archived_envelopes(): trusting ledger {anchored_slot} to verify slot {slot}"
+		);
+		self.trusted_ledgers.lock().insert(anchored_slot, LedgerAnchor::Value(value));
+		Ok(())
+	}
+
+	fn archived_value(
+		&self,
+		archive: &XdrArchive<ScpHistoryEntry>,
+		slot: Slot,
+	) -> Result<(Slot, Vec<u8>), Error> {
+		let validator_set =
+			self.validator_set.lock().clone().filter(|validator_set| !validator_set.is_empty());
+		let min_envelopes = get_min_externalized_messages(self.public_network);
+
+		let ledgers = archive.get_vec().iter().filter_map(|entry| match entry {
+			ScpHistoryEntry::V0(entry) => Some((
+				Slot::from(entry.ledger_messages.ledger_seq),
+				entry.ledger_messages.messages.get_vec().as_slice(),
+			)),
+			_ => None,
+		});
+		newest_externalized_value(ledgers, slot, self.network(), |signers| match &validator_set {
+			Some(validator_set) => validator_set.check_quorum(signers),
+			None => check_signer_count(signers, min_envelopes),
+		})
+	}
+
+	/// Returns the envelopes that the archive recorded for the slot
+	pub async fn get_envelopes(&self, slot: Slot) -> Result<Vec<ScpEnvelope>, Error> {
+		let checkpoint = last_slot_index_in_batch(slot);
+
+		let cached = self
+			.scp_checkpoints
+			.lock()
+			.get(checkpoint)
+			.filter(|cached| slot <= cached.verified_up_to);
+		let checkpoint_file = match cached {
+			Some(checkpoint_file) => checkpoint_file,
+			None => {
+				let tx_set_hashes = self.get_ledger_tx_set_hashes(slot).await?;
+				let archive = self
+					.race(slot, ScpArchiveStorage, |archive| {
+						verify_scp_archive(archive, slot, &tx_set_hashes)
+					})
+					.await?;
+				let checkpoint_file = Arc::new(VerifiedCheckpoint::new(archive, &tx_set_hashes));
+				self.scp_checkpoints.lock().insert(checkpoint, checkpoint_file.clone());
+				checkpoint_file
+			},
+		};
+
+		checkpoint_file
+			.archive
+			.get_vec()
+			.iter()
+			.find_map(|entry| match entry {
+				ScpHistoryEntry::V0(entry)
+					if Slot::from(entry.ledger_messages.ledger_seq) == slot =>
+					Some(entry.ledger_messages.messages.get_vec().clone()),
+				_ => None,
+			})
+			.ok_or_else(|| Error::ArchiveError(format!("No SCP history entry for slot {slot}")))
+	}
+
+	/// Returns the tx set that the archive recorded for the slot
+	pub async fn get_tx_set(&self, slot: Slot) -> Result<TransactionSetType, Error> {
+		let checkpoint = last_slot_index_in_batch(slot);
+
+		let cached = self
+			.tx_checkpoints
+			.lock()
+			.get(checkpoint)
+			.filter(|cached| slot <= cached.verified_up_to);
+		let checkpoint_file = match cached {
+			Some(checkpoint_file) => checkpoint_file,
+			None => {
+				let tx_set_hashes = self.get_ledger_tx_set_hashes(slot).await?;
+				let archive = self
+					.race(slot, TransactionsArchiveStorage, |archive| {
+						verify_transactions_archive(archive, slot, &tx_set_hashes)
+					})
+					.await?;
+				let checkpoint_file = Arc::new(VerifiedCheckpoint::new(archive, &tx_set_hashes));
+				self.tx_checkpoints.lock().insert(checkpoint, checkpoint_file.clone());
+				checkpoint_file
+			},
+		};
+
+		checkpoint_file
+			.archive
+			.get_vec()
+			.iter()
+			.find(|entry| Slot::from(entry.ledger_seq) == slot)
+			.map(tx_set_of)
+			.ok_or_else(|| {
+				Error::ArchiveError(format!("No transaction history entry for slot {slot}"))
+			})
+	}
+
+	/// Returns the tx set hashes of the checkpoint of the slot, up to its newest trusted ledger.
+	/// Without a trusted ledger from the slot on, the envelopes of the SCP checkpoint are checked
+	/// for one. Failing that, the checkpoints after it are fetched until one has a trusted
+	/// ledger, since each checkpoint anchors the one before it.
+	async fn get_ledger_tx_set_hashes(&self, slot: Slot) -> Result<Arc<LedgerTxSetHashes>, Error> {
+		let checkpoint = last_slot_index_in_batch(slot);
+
+		// the cached headers end at the ledger they were trusted up to, a newer one may be trusted
+		// by now
+		let cached = self.ledger_checkpoints.lock().get(checkpoint);
+		if let Some(tx_set_hashes) = cached {
+			if tx_set_hashes.contains_key(&slot) {
+				return Ok(tx_set_hashes)
+			}
+			self.ledger_checkpoints.lock().remove(checkpoint);
+		}
+
+		if !self.is_anchored(checkpoint, slot) {
+			if let Err(e) = self.trust_archived_envelopes(slot).await {
+				tracing::warn!(
+					"get_ledger_tx_set_hashes(): the archived envelopes of slot {slot} are not trusted: {e:?}"
+				);
+			}
+		}
+
+		let mut anchored_checkpoint = checkpoint;
+		while !self.is_anchored(anchored_checkpoint, slot) {
+			if anchored_checkpoint - checkpoint >=
+				ARCHIVE_MAX_ANCHOR_DISTANCE * ARCHIVE_NODE_LEDGER_BATCH
+			{
+				return Err(Error::ArchiveError(format!(
+					"No trusted ledger to verify the ledger headers of slot {slot} against"
+				)))
+			}
+			anchored_checkpoint += ARCHIVE_NODE_LEDGER_BATCH;
+		}
+
+		loop {
+			let tx_set_hashes = self.get_anchored_ledger_checkpoint(anchored_checkpoint).await?;
+			if anchored_checkpoint == checkpoint {
+				return if tx_set_hashes.contains_key(&slot) {
+					Ok(tx_set_hashes)
+				} else {
+					Err(Error::ArchiveError(format!(
+						"The ledger header of slot {slot} is newer than the trusted ledgers"
+					)))
+				}
+			}
+			anchored_checkpoint -= ARCHIVE_NODE_LEDGER_BATCH;
+		}
+	}
+
+	/// Returns the tx set hashes of the checkpoint, whose newest trusted ledger is known
+	async fn get_anchored_ledger_checkpoint(
+		&self,
+		checkpoint: Slot,
+	) -> Result<Arc<LedgerTxSetHashes>, Error> {
+		let cached = self.ledger_checkpoints.lock().get(checkpoint);
+		if let Some(tx_set_hashes) = cached {
+			return Ok(tx_set_hashes)
+		}
+
+		let anchor = self.trusted_ledgers.lock().anchor_of_checkpoint(checkpoint);
+		let Some((anchored_slot, anchor)) = anchor else {
+			return Err(Error::ArchiveError(format!(
+				"No trusted ledger in the checkpoint of slot {checkpoint}"
+			)))
+		};
+
+		let archive = self
+			.race(checkpoint, LedgerArchiveStorage, |archive| {
+				verify_ledger_archive(archive, anchored_slot, &anchor)
+			})
+			.await?;
+
+		let headers = archive.get_vec();
+		// the ledger before the checkpoint is the one its first header refers to
+		if let Some(first) = headers.first() {
+			let previous_slot = Slot::from(first.header.ledger_seq).saturating_sub(1);
+			self.trusted_ledgers
+				.lock()
+				.insert(previous_slot, LedgerAnchor::Hash(first.header.previous_ledger_hash));
+		}
+
+		let tx_set_hashes = Arc::new(
+			headers
+				.iter()
+				.filter(|entry| Slot::from(entry.header.ledger_seq) <= anchored_slot)
+				.map(|entry| {
+					(Slot::from(entry.header.ledger_seq), entry.header.scp_value.tx_set_hash)
+				})
+				.collect::<LedgerTxSetHashes>(),
+		);
+		self.ledger_checkpoints.lock().insert(checkpoint, tx_set_hashes.clone());
+		Ok(tx_set_hashes)
+	}
+
+	/// Downloads the checkpoint of the slot from all mirrors at once, and returns the first one
+	/// that passes `verify`
+	async fn race<S, V>(
+		&self,
+		slot: Slot,
+		storage: fn(String) -> S,
+		verify: V,
+	) -> Result<XdrArchive<S::T>, Error>
+	where
+		S: ArchiveStorage,
+		V: Fn(&XdrArchive<S::T>) -> Result<(), Error>,
+	{
+		if self.urls.is_empty() {
+			return Err(Error::ArchiveError("No archive URLs configured".to_string()))
+		}
+
+		let verify = &verify;
+		let downloads = self.urls.iter().enumerate().map(|(index, url)| {
+			let storage = storage(url.clone());
+			Box::pin(async move {
+				let started = Instant::now();
+				let result = self
+					.download(&storage, slot)
+					.await
+					.and_then(|archive| verify(&archive).map(|_| archive));
+				self.record(index, started.elapsed(), &result);
+
+				result.map_err(|e| {
+					tracing::warn!(
+						"race(): failed to get the {} checkpoint of slot {slot} from {url}: {e:?}",
+						S::PREFIX_URL
+					);
+					e
+				})
+			})
+		});
+
+		match select_ok(downloads).await {
+			Ok((archive, _)) => Ok(archive),
+			Err(e) => {
+				tracing::error!(
+					"race(): no mirror has a valid {} checkpoint of slot {slot}. Mirror health: {:?}",
+					S::PREFIX_URL,
+					self.mirror_health()
+				);
+				Err(e)
+			},
+		}
+	}
+
+	async fn download<S: ArchiveStorage>(
+		&self,
+		storage: &S,
+		slot: Slot,
+	) -> Result<XdrArchive<S::T>, Error> {
+		let (url, _) = storage.get_url_and_file_name(slot);
+		let response =
+			self.http.get(&url).send().await.map_err(|e| Error::ArchiveError(e.to_string()))?;
+		if response.status().is_server_error() | response.status().is_client_error() {
+			return Err(Error::ArchiveResponseError(format!("{response:?}")))
+		}
+
+		let content = response.bytes().await.map_err(|e| Error::ArchiveError(e.to_string()))?;
+		S::decode_xdr(gz_decode(&content)?)
+	}
+
+	fn record<T>(&self, index: usize, latency: Duration, result: &Result<T, Error>) {
+		let mut health = self.health.lock();
+		let Some(mirror) = health.get_mut(index) else { return };
+
+		mirror.last_latency = Some(latency);
+		match result {
+			Ok(_) => {
+				mirror.successes += 1;
+				mirror.last_error = None;
+			},
+			Err(e) => {
+				mirror.failures += 1;
+				mirror.last_error = Some(e.to_string());
+			},
+		}
+	}
+}
+
+fn tx_set_of(entry: &TransactionHistoryEntry) -> TransactionSetType {
+	match entry.ext.clone() {
+		TransactionHistoryEntryExt::V1(generalized_tx_set) =>
+			TransactionSetType::new(generalized_tx_set),
+		_ => TransactionSetType::new(entry.tx_set.clone()),
+	}
+}
+
+/// Checks that every header hashes to its recorded hash, that the headers form a chain, and that
+/// the header of the `anchored_slot` matches the trusted `anchor`
+fn verify_ledger_archive(
+	archive: &XdrArchive<LedgerHeaderHistoryEntry>,
+	anchored_slot: Slot,
+	anchor: &LedgerAnchor,
+) -> Result<(), Error> {
+	let mut previous_hash = None;
+	let mut is_anchored = false;
+	for entry in archive.get_vec() {
+		let ledger = entry.header.ledger_seq;
+
+		if Slot::from(ledger) == anchored_slot {
+			let matches_anchor = match anchor {
+				LedgerAnchor::Value(value) => entry.header.scp_value.to_xdr() == *value,
+				LedgerAnchor::Hash(hash) => entry.hash == *hash,
+			};
+			if !matches_anchor {
+				return Err(Error::ArchiveError(format!(
+					"Ledger header {ledger} does not match the trusted ledger"
+				)))
+			}
+			is_anchored = true;
+		}
+
+		let hash: TxSetHash = Sha256::digest(entry.header.to_xdr()).into();
+		if hash != entry.hash {
+			return Err(Error::ArchiveError(format!(
+				"Ledger header {ledger} does not match its hash"
+			)))
+		}
+
+		if previous_hash
+			.is_some_and(|previous_hash| previous_hash != entry.header.previous_ledger_hash)
+		{
+			return Err(Error::ArchiveError(format!(
+				"Ledger header {ledger} does not follow the previous ledger"
+			)))
+		}
+		previous_hash = Some(entry.hash);
+	}
+
+	if !is_anchored {
+		return Err(Error::ArchiveError(format!("No ledger header of slot {anchored_slot}")))
+	}
+	Ok(())
+}
+
+/// Returns the externalize and confirm envelopes, which are the ones of a proof. The archive
+/// records the nominate and prepare statements as well.
+pub(crate) fn externalized_envelopes(
+	envelopes: impl IntoIterator<Item = ScpEnvelope>,
+) -> Vec<ScpEnvelope> {
+	envelopes
+		.into_iter()
+		.filter(|env| {
+			matches!(
+				env.statement.pledges,
+				ScpStatementPledges::ScpStExternalize(_) | ScpStatementPledges::ScpStConfirm(_)
+			)
+		})
+		.collect()
+}
+
+/// Returns the newest of the ledgers from the slot on, whose externalize and confirm envelopes
+/// pass `check_signers`, with the value they externalized
+fn newest_externalized_value<'a>(
+	ledgers: impl Iterator<Item = (Slot, &'a [ScpEnvelope])>,
+	slot: Slot,
+	network: &Network,
+	check_signers: impl Fn(&HashSet<Vec<u8>>) -> Result<(), ProofError>,
+) -> Result<(Slot, Vec<u8>), Error> {
+	let mut ledgers: Vec<_> = ledgers.filter(|(ledger, _)| *ledger >= slot).collect();
+	ledgers.sort_unstable_by_key(|(ledger, _)| std::cmp::Reverse(*ledger));
+
+	for (ledger, envelopes) in ledgers {
+		let envelopes = externalized_envelopes(envelopes.iter().cloned());
+
+		match check_externalized_value(ledger, &envelopes, network, &check_signers) {
+			Ok(value) => return Ok((ledger, value.get_vec().clone())),
+			Err(e) => tracing::debug!(
+				"newest_externalized_value(): the archived envelopes of slot {ledger} are not trusted: {e:?}"
+			),
+		}
+	}
+
+	Err(Error::ArchiveError(format!("No archived envelopes from slot {slot} on are trusted")))
+}
+
+/// Checks that the externalized values commit to the tx sets of the ledger headers. The entries
+/// of the slots after the trusted ledger headers are skipped, unless it is the requested slot.
+fn verify_scp_archive(
+	archive: &XdrArchive<ScpHistoryEntry>,
+	slot: Slot,
+	tx_set_hashes: &LedgerTxSetHashes,
+) -> Result<(), Error> {
+	for entry in archive.get_vec() {
+		let ScpHistoryEntry::V0(entry) = entry else { continue };
+		let entry_slot = Slot::from(entry.ledger_messages.ledger_seq);
+		let Some(tx_set_hash) = verified_tx_set_hash(tx_set_hashes, entry_slot, slot)? else {
+			continue
+		};
+
+		for env in entry.ledger_messages.messages.get_vec() {
+			if let ScpStatementPledges::ScpStExternalize(stmt) = &env.statement.pledges {
+				if stmt.commit.value.get_vec().get(0..32) != Some(&tx_set_hash[..]) {
+					return Err(Error::ArchiveError(format!(
+						"Externalized value of slot {entry_slot} does not match its ledger header"
+					)))
+				}
+			}
+		}
+	}
+	Ok(())
+}
+
+/// Checks that the tx sets hash to the ones of the ledger headers. The entries of the slots after
+/// the trusted ledger headers are skipped, unless it is the requested slot.
+fn verify_transactions_archive(
+	archive: &XdrArchive<TransactionHistoryEntry>,
+	slot: Slot,
+	tx_set_hashes: &LedgerTxSetHashes,
+) -> Result<(), Error> {
+	for entry in archive.get_vec() {
+		let entry_slot = Slot::from(entry.ledger_seq);
+		let Some(tx_set_hash) = verified_tx_set_hash(tx_set_hashes, entry_slot, slot)? else {
+			continue
+		};
+
+		if tx_set_of(entry).get_tx_set_hash()? != *tx_set_hash {
+			return Err(Error::ArchiveError(format!(
+				"Tx set of slot {entry_slot} does not match its ledger header"
+			)))
+		}
+	}
+	Ok(())
+}
+
+/// Returns the tx set hash of the ledger header of the entry, or `None` to skip an entry that
+/// is not the requested slot
+fn verified_tx_set_hash(
+	tx_set_hashes: &LedgerTxSetHashes,
+	entry_slot: Slot,
+	slot: Slot,
+) -> Result<Option<&TxSetHash>, Error> {
+	match tx_set_hashes.get(&entry_slot) {
+		Some(tx_set_hash) => Ok(Some(tx_set_hash)),
+		None if entry_slot == slot =>
+			Err(Error::ArchiveError(format!("No trusted ledger header for slot {slot}"))),
+		None => Ok(None),
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::oracle::StellarValidator;
+	use stellar_relay_lib::{
+		mock_peer::signed_externalize,
+		sdk::{
+			compound_types::{LimitedVarArray, UnlimitedVarArray},
+			types::{ScpNomination, ScpQuorumSet},
+			SecretKey,
+		},
+	};
+
+	/// Externalize envelopes of the value, signed by each of the validators
+	fn externalized(validators: &[SecretKey], slot: Slot, value: u8) -> Vec<ScpEnvelope> {
+		let qset = ScpQuorumSet {
+			threshold: 1,
+			validators: LimitedVarArray::new(
+				validators.iter().map(|validator| validator.get_public().clone()).collect(),
+			)
+			.expect("should create the validators"),
+			inner_sets: LimitedVarArray::new(vec![]).expect("should create the inner sets"),
+		};
+		validators
+			.iter()
+			.map(|validator| signed_externalize(validator, slot, value, &qset))
+			.collect()
+	}
+
+	#[test]
+	fn the_least_recently_used_checkpoint_is_evicted() {
+		let mut cache = CheckpointCache::new(2);
+		cache.insert(63, Arc::new("a"));
+		cache.insert(127, Arc::new("b"));
+
+		// using 63 keeps it over 127
+		assert_eq!(cache.get(63).as_deref(), Some(&"a"));
+		cache.insert(191, Arc::new("c"));
+
+		assert_eq!(cache.get(127), None);
+		assert_eq!(cache.get(63).as_deref(), Some(&"a"));
+		assert_eq!(cache.get(191).as_deref(), Some(&"c"));
+
+		// inserting the same checkpoint again replaces it
+		cache.insert(191, Arc::new("d"));
+		assert_eq!(cache.checkpoints.len(), 2);
+		assert_eq!(cache.get(191).as_deref(), Some(&"d"));
+	}
+
+	#[test]
+	fn the_newest_trusted_ledger_of_a_checkpoint_is_the_anchor() {
+		let mut trusted_ledgers = TrustedLedgers::default();
+		trusted_ledgers.insert(70, LedgerAnchor::Value(vec![1]));
+		trusted_ledgers.insert(100, LedgerAnchor::Hash([2; 32]));
+		trusted_ledgers.insert(130, LedgerAnchor::Value(vec![3]));
+
+		assert_eq!(trusted_ledgers.anchor_of_checkpoint(63), None);
+		assert_eq!(
+			trusted_ledgers.anchor_of_checkpoint(127),
+			Some((100, LedgerAnchor::Hash([2; 32])))
+		);
+
+		// the value of a ledger is not replaced by its hash
+		trusted_ledgers.insert(130, LedgerAnchor::Hash([3; 32]));
+		assert_eq!(
+			trusted_ledgers.anchor_of_checkpoint(191),
+			Some((130, LedgerAnchor::Value(vec![3])))
+		);
+	}
+
+	#[test]
+	fn old_ledgers_are_anchored_by_their_archived_envelopes() {
+		let validators: Vec<SecretKey> =
+			(1..=3).map(|seed| SecretKey::from_binary([seed; 32])).collect();
+		let validator_set = ValidatorSet::new(
+			validators
+				.iter()
+				.enumerate()
+				.map(|(organization_id, validator)| StellarValidator {
+					public_key: validator.get_public().to_encoding(),
+					organization_id: organization_id as u128,
+				})
+				.collect(),
+		);
+
+		// the overlay saw a ledger long after the requested one
+		let slot = 44041116;
+		let client = ArchiveClient::new(vec![], false);
+		client.trust_externalized_value(slot + 1_000_000, vec![0; 32]);
+		assert!(!client.is_anchored(last_slot_index_in_batch(slot), slot));
+
+		// the archive records the nominations too
+		let mut with_nomination = externalized(&validators, slot + 1, 2);
+		let mut nomination = with_nomination[0].clone();
+		nomination.statement.pledges = ScpStatementPledges::ScpStNominate(ScpNomination {
+			quorum_set_hash: [0; 32],
+			votes: UnlimitedVarArray::new(vec![]).expect("should create the votes"),
+			accepted: UnlimitedVarArray::new(vec![]).expect("should create the accepted"),
+		});
+		with_nomination.push(nomination);
+
+		let ledgers = vec![
+			(slot - 1, externalized(&validators, slot - 1, 0)),
+			(slot, externalized(&validators, slot, 1)),
+			(slot + 1, with_nomination),
+			// one organization is not a quorum
+			(slot + 2, externalized(&validators[..1], slot + 2, 3)),
+		];
+		let ledgers = || ledgers.iter().map(|(ledger, envelopes)| (*ledger, envelopes.as_slice()));
+
+		let anchor = newest_externalized_value(ledgers(), slot, &TEST_NETWORK, |signers| {
+			validator_set.check_quorum(signers)
+		})
+		.expect("should find a trusted ledger");
+		assert_eq!(anchor, (slot + 1, vec![2; 32]));
+
+		// without the validators, the fewest envelopes of a proof are enough
+		let min_envelopes = get_min_externalized_messages(false);
+		let anchor = newest_externalized_value(ledgers(), slot, &TEST_NETWORK, |signers| {
+			check_signer_count(signers, min_envelopes)
+		})
+		.expect("should find a trusted ledger");
+		assert_eq!(anchor, (slot + 1, vec![2; 32]));
+
+		// ledgers before the requested one do not help
+		assert!(newest_externalized_value(ledgers(), slot + 2, &TEST_NETWORK, |signers| {
+			validator_set.check_quorum(signers)
+		})
+		.is_err());
+	}
+
+	#[test]
+	fn entries_after_an_anchor_in_the_middle_of_a_checkpoint_are_skipped() {
+		let anchored_slot = 100;
+		let tx_set_hashes: LedgerTxSetHashes =
+			(64..=anchored_slot).map(|slot| (slot, [slot as u8; 32])).collect();
+
+		// the entries of the requested slot and the ones before it are verified
+		assert_eq!(verified_tx_set_hash(&tx_set_hashes, 90, 90).ok(), Some(Some(&[90; 32])));
+		assert_eq!(verified_tx_set_hash(&tx_set_hashes, 80, 90).ok(), Some(Some(&[80; 32])));
+		// the ones after the anchor are skipped
+		assert_eq!(verified_tx_set_hash(&tx_set_hashes, 110, 90).ok(), Some(None));
+		// unless the slot after the anchor is requested
+		assert!(verified_tx_set_hash(&tx_set_hashes, 110, 110).is_err());
+	}
+
+	#[tokio::test]
+	async fn ledgers_without_a_trusted_ledger_are_anchored_by_the_archive() {
+		let unreachable = "http://127.0.0.1:1".to_string();
+		let client = ArchiveClient::new(vec![unreachable], true);
+
+		assert!(client.get_envelopes(578291).await.is_err());
+		// only the SCP checkpoint was asked for, to find a trusted ledger in
+		assert_eq!(client.mirror_health()[0].failures, 1);
+		assert!(client.ledger_checkpoints.lock().checkpoints.is_empty());
+	}
+
+	#[tokio::test]
+	async fn failing_mirrors_are_reported() {
+		let unreachable = "http://127.0.0.1:1".to_string();
+		let client = ArchiveClient::new(vec![unreachable.clone(), unreachable], true);
+		client.trust_externalized_value(578291, vec![0; 32]);
+
+		assert!(client.get_envelopes(578291).await.is_err());
+
+		let health = client.mirror_health();
+		assert_eq!(health.len(), 2);
+		for mirror in health {
+			assert_eq!(mirror.successes, 0);
+			assert_eq!(mirror.failures, 1);
+			assert!(mirror.last_error.is_some());
+			assert!(mirror.last_latency.is_some());
+		}
+
+		// nothing is cached for a checkpoint that could not be fetched
+		assert!(client.ledger_checkpoints.lock().checkpoints.is_empty());
+	}
+
+	#[tokio::test]
+	async fn no_mirrors_is_an_error() {
+		let client = ArchiveClient::new(vec![], true);
+		assert!(client.get_tx_set(578291).await.is_err());
+		assert!(client.mirror_health().is_empty());
+	}
+}
@@ -2,7 +2,7 @@ use std::{fmt::Write, str::Split};
 
 use stellar_relay_lib::sdk::{
 	compound_types::UnlimitedVarArray,
-	types::{ScpEnvelope, ScpHistoryEntry, TransactionHistoryEntry},
+	types::{LedgerHeaderHistoryEntry, ScpEnvelope, ScpHistoryEntry, TransactionHistoryEntry},
 	TransactionSetType, XdrCodec,
 };
 
@@ -12,7 +12,7 @@ use crate::oracle::{
 };
 use wallet::Slot;
 
-use super::{ScpArchiveStorage, TransactionsArchiveStorage};
+use super::{LedgerArchiveStorage, ScpArchiveStorage, TransactionsArchiveStorage};
 
 impl FileHandler<EnvelopesMap> for EnvelopesFileHandler {
 	#[cfg(test)]
@@ -144,6 +144,16 @@ impl ArchiveStorage for TransactionsArchiveStorage {
 	}
 }
 
+impl ArchiveStorage for LedgerArchiveStorage {
+	type T = LedgerHeaderHistoryEntry;
+	const PREFIX_URL: &'static str = "ledger";
+	const PREFIX_FILENAME: &'static str = "ledger-";
+
+	fn stellar_history_base_url(&self) -> String {
+		self.0.clone()
+	}
+}
+
 #[cfg(test)]
 mod test {
 	use std::{
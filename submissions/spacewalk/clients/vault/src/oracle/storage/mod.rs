@@ -1,6 +1,12 @@
+mod archive_client;
 pub mod impls;
+mod scp_store;
 pub mod traits;
 
+pub(crate) use archive_client::externalized_envelopes;
+pub use archive_client::{ArchiveClient, MirrorHealth};
+pub use scp_store::ScpStore;
+
 /// A handler to save the envelopes map to a file.
 pub struct EnvelopesFileHandler;
 
@@ -10,3 +16,5 @@ pub struct TxSetsFileHandler;
 pub struct ScpArchiveStorage(pub(crate) String);
 
 pub struct TransactionsArchiveStorage(pub(crate) String);
+
+pub struct LedgerArchiveStorage(pub(crate) String);
@@ -0,0 +1,481 @@
+use std::{
+	collections::{BTreeSet, HashSet},
+	fs,
+	fs::File,
+	io::{ErrorKind, Write},
+	path::{Path, PathBuf},
+	sync::mpsc::{sync_channel, SyncSender},
+	thread,
+};
+
+use stellar_relay_lib::sdk::{
+	compound_types::UnlimitedVarArray, types::ScpEnvelope, TransactionSetType, XdrCodec,
+};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use wallet::Slot;
+
+use crate::oracle::{Error, Proof, SerializedData};
+
+const ENVELOPES_DIR: &str = "envelopes";
+const TX_SETS_DIR: &str = "tx_sets";
+const PROOFS_DIR: &str = "proofs";
+
+/// Once more slots than this became too old since the last pruning, the directories are listed
+/// instead of removing the files slot by slot
+const MAX_SLOTS_PRUNED_BY_RANGE: Slot = 1000;
+
+/// Keeps the envelopes, tx sets and finished proofs that the collector gathered on disk, one file
+/// per slot, so that they survive the restarts of the vault.
+/// The files are written by a thread of their own, in the order they were saved; a slot that is
+/// saved again before its previous write started is written only once.
+#[derive(Clone, Debug)]
+pub struct ScpStore {
+	path: PathBuf,
+	writer: UnboundedSender<StoreOp>,
+}
+
+/// The writes that the `ScpStore` leaves to its writer thread
+enum StoreOp {
+	Envelopes {
+		slot: Slot,
+		envelopes: Vec<ScpEnvelope>,
+		from_archive: bool,
+	},
+	TxSet {
+		slot: Slot,
+		tx_set: TransactionSetType,
+	},
+	Proof(Proof),
+	Remove(Slot),
+	Prune(Slot),
+	/// answers once the writes before it are done
+	Flush(SyncSender<()>),
+}
+
+fn slot_file(path: &Path, dir: &str, slot: Slot) -> PathBuf {
+	path.join(dir).join(slot.to_string())
+}
+
+/// Replaces the file in one step, so that a restart never leaves half of it behind
+fn write_file(path: &Path, data: &[u8]) -> Result<(), Error> {
+	let tmp_path = path.with_extension("tmp");
+	let mut file = File::create(&tmp_path)?;
+	file.write_all(data)?;
+	file.sync_all()?;
+
+	fs::rename(tmp_path, path)?;
+	Ok(())
+}
+
+fn read_file(path: &Path) -> Result<Option<Vec<u8>>, Error> {
+	match fs::read(path) {
+		Ok(bytes) => Ok(Some(bytes)),
+		Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+		Err(e) => Err(e.into()),
+	}
+}
+
+/// Returns true if the file was there
+fn remove_file(path: &Path) -> Result<bool, Error> {
+	match fs::remove_file(path) {
+		Ok(()) => Ok(true),
+		Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+		Err(e) => Err(e.into()),
+	}
+}
+
+/// Returns the slots that have a file in the directory, in ascending order
+fn slots(path: &Path, dir: &str) -> Result<BTreeSet<Slot>, Error> {
+	let mut slots = BTreeSet::new();
+	for entry in fs::read_dir(path.join(dir))? {
+		// the leftovers of an interrupted write do not parse
+		if let Some(slot) = entry?.file_name().to_str().and_then(|name| name.parse().ok()) {
+			slots.insert(slot);
+		}
+	}
+	Ok(slots)
+}
+
+impl ScpStore {
+	pub fn open(path: impl Into<PathBuf>, max_age_in_slots: Slot) -> Result<Self, Error> {
+		let path = path.into();
+		fs::create_dir_all(path.join(ENVELOPES_DIR))?;
+		fs::create_dir_all(path.join(TX_SETS_DIR))?;
+		fs::create_dir_all(path.join(PROOFS_DIR))?;
+
+		let (writer, ops) = unbounded_channel();
+		let store_writer = StoreWriter { path: path.clone(), max_age_in_slots, pruned_below: None };
+		thread::Builder::new()
+			.name("scp-store-writer".to_string())
+			.spawn(move || store_writer.run(ops))?;
+
+		Ok(ScpStore { path, writer })
+	}
+
+	fn file(&self, dir: &str, slot: Slot) -> PathBuf {
+		slot_file(&self.path, dir, slot)
+	}
+
+	fn send(&self, op: StoreOp) {
+		if self.writer.send(op).is_err() {
+			tracing::warn!("send(): the writer of the SCP store is gone");
+		}
+	}
+
+	/// Saves the envelopes of the slot, replacing the ones saved before.
+	/// `from_archive` marks the envelopes that were taken from an archive.
+	pub fn save_envelopes(&self, slot: Slot, envelopes: &[ScpEnvelope], from_archive: bool) {
+		self.send(StoreOp::Envelopes { slot, envelopes: envelopes.to_vec(), from_archive });
+	}
+
+	pub fn save_tx_set(&self, slot: Slot, tx_set: &TransactionSetType) {
+		self.send(StoreOp::TxSet { slot, tx_set: tx_set.clone() });
+	}
+
+	pub fn save_proof(&self, proof: &Proof) {
+		self.send(StoreOp::Proof(proof.clone()));
+	}
+
+	/// Waits until everything saved so far is written
+	pub fn flush(&self) {
+		let (done_sender, done) = sync_channel(1);
+		self.send(StoreOp::Flush(done_sender));
+		let _ = done.recv();
+	}
+
+	/// Returns the envelopes of the slot, and whether they were taken from an archive
+	pub fn load_envelopes(&self, slot: Slot) -> Result<Option<(Vec<ScpEnvelope>, bool)>, Error> {
+		let Some(bytes) = read_file(&self.file(ENVELOPES_DIR, slot))? else { return Ok(None) };
+
+		let (from_archive, envelopes): (bool, SerializedData) = bincode::deserialize(&bytes)?;
+		let envelopes = UnlimitedVarArray::<ScpEnvelope>::from_xdr(envelopes)
+			.map_err(|e| Error::Other(format!("Decode Error: {e:?}")))?;
+		Ok(Some((envelopes.get_vec().to_vec(), from_archive)))
+	}
+
+	pub fn load_tx_set(&self, slot: Slot) -> Result<Option<TransactionSetType>, Error> {
+		let Some(bytes) = read_file(&self.file(TX_SETS_DIR, slot))? else { return Ok(None) };
+
+		TransactionSetType::from_xdr(bytes)
+			.map(Some)
+			.map_err(|e| Error::Other(format!("Decode Error: {e:?}")))
+	}
+
+	pub fn load_proof(&self, slot: Slot) -> Result<Option<Proof>, Error> {
+		let Some(bytes) = read_file(&self.file(PROOFS_DIR, slot))? else { return Ok(None) };
+
+		let (envelopes, tx_set): (SerializedData, SerializedData) = bincode::deserialize(&bytes)?;
+		let envelopes = UnlimitedVarArray::<ScpEnvelope>::from_xdr(envelopes)
+			.map_err(|e| Error::Other(format!("Decode Error: {e:?}")))?;
+		let tx_set = TransactionSetType::from_xdr(tx_set)
+			.map_err(|e| Error::Other(format!("Decode Error: {e:?}")))?;
+		Ok(Some(Proof::new(slot, envelopes, tx_set)))
+	}
+
+	/// Returns the most recent slots with envelopes or a tx set, at most `limit`, in ascending
+	/// order
+	pub fn recent_slots(&self, limit: usize) -> Result<Vec<Slot>, Error> {
+		let mut slots_with_data = slots(&self.path, ENVELOPES_DIR)?;
+		slots_with_data.append(&mut slots(&self.path, TX_SETS_DIR)?);
+
+		let mut recent_slots: Vec<Slot> = slots_with_data.into_iter().rev().take(limit).collect();
+		recent_slots.reverse();
+		Ok(recent_slots)
+	}
+
+	/// Removes the envelopes and the tx set of the slot, but not its proof
+	pub fn remove(&self, slot: Slot) {
+		self.send(StoreOp::Remove(slot));
+	}
+
+	/// Removes the slots that are more than `max_age_in_slots` behind the `latest_slot`, and
+	/// does not write them anymore.
+	pub fn prune(&self, latest_slot: Slot) {
+		self.send(StoreOp::Prune(latest_slot));
+	}
+}
+
+/// Writes the files of the `ScpStore`, off the async runtime
+struct StoreWriter {
+	path: PathBuf,
+	/// the slots further behind the latest slot than this are pruned
+	max_age_in_slots: Slot,
+	/// the slots below this were pruned already
+	pruned_below: Option<Slot>,
+}
+
+impl StoreWriter {
+	fn run(mut self, mut ops: UnboundedReceiver<StoreOp>) {
+		while let Some(op) = ops.blocking_recv() {
+			let mut batch = vec![op];
+			while let Ok(op) = ops.try_recv() {
+				batch.push(op);
+			}
+
+			// a slot that is saved again or removed later in the batch is not written now
+			let mut replaced = HashSet::new();
+			let mut is_replaced = vec![false; batch.len()];
+			for (index, op) in batch.iter().enumerate().rev() {
+				match op {
+					StoreOp::Envelopes { slot, .. } =>
+						is_replaced[index] = !replaced.insert((ENVELOPES_DIR, *slot)),
+					StoreOp::TxSet { slot, .. } =>
+						is_replaced[index] = !replaced.insert((TX_SETS_DIR, *slot)),
+					StoreOp::Remove(slot) => {
+						replaced.insert((ENVELOPES_DIR, *slot));
+						replaced.insert((TX_SETS_DIR, *slot));
+					},
+					_ => {},
+				}
+			}
+
+			for (op, is_replaced) in batch.into_iter().zip(is_replaced) {
+				if !is_replaced {
+					self.apply(op);
+				}
+			}
+		}
+	}
+
+	fn is_pruned(&self, slot: Slot) -> bool {
+		self.pruned_below.map_or(false, |pruned_below| slot < pruned_below)
+	}
+
+	fn apply(&mut self, op: StoreOp) {
+		match op {
+			StoreOp::Envelopes { slot, envelopes, from_archive } => {
+				if self.is_pruned(slot) {
+					return
+				}
+				if let Err(e) = self.save_envelopes(slot, envelopes, from_archive) {
+					tracing::warn!(
+						"save_envelopes(): failed to store the envelopes of slot {slot}: {e:?}"
+					);
+				}
+			},
+			StoreOp::TxSet { slot, tx_set } => {
+				if self.is_pruned(slot) {
+					return
+				}
+				if let Err(e) =
+					write_file(&slot_file(&self.path, TX_SETS_DIR, slot), &tx_set.to_xdr())
+				{
+					tracing::warn!(
+						"save_tx_set(): failed to store the tx set of slot {slot}: {e:?}"
+					);
+				}
+			},
+			StoreOp::Proof(proof) => {
+				let slot = proof.slot();
+				if self.is_pruned(slot) {
+					return
+				}
+				if let Err(e) = self.save_proof(&proof) {
+					tracing::warn!("save_proof(): failed to store the proof of slot {slot}: {e:?}");
+				}
+			},
+			StoreOp::Remove(slot) =>
+				for dir in [ENVELOPES_DIR, TX_SETS_DIR] {
+					if let Err(e) = remove_file(&slot_file(&self.path, dir, slot)) {
+						tracing::warn!(
+							"remove(): failed to remove the {dir} of slot {slot} from the store: {e:?}"
+						);
+					}
+				},
+			StoreOp::Prune(latest_slot) => match self.prune(latest_slot) {
+				Ok(removed) =>
+					tracing::debug!("prune(): removed {removed} files from the SCP store"),
+				Err(e) => tracing::warn!("prune(): failed to prune the SCP store: {e:?}"),
+			},
+			StoreOp::Flush(done) => {
+				let _ = done.send(());
+			},
+		}
+	}
+
+	fn save_envelopes(
+		&self,
+		slot: Slot,
+		envelopes: Vec<ScpEnvelope>,
+		from_archive: bool,
+	) -> Result<(), Error> {
+		let envelopes = UnlimitedVarArray::new(envelopes)?;
+		let data = bincode::serialize(&(from_archive, envelopes.to_xdr()))?;
+
+		write_file(&slot_file(&self.path, ENVELOPES_DIR, slot), &data)
+	}
+
+	fn save_proof(&self, proof: &Proof) -> Result<(), Error> {
+		let envelopes = UnlimitedVarArray::new(proof.envelopes().clone())?;
+		let data = bincode::serialize(&(envelopes.to_xdr(), proof.tx_set().to_xdr()))?;
+
+		write_file(&slot_file(&self.path, PROOFS_DIR, proof.slot()), &data)
+	}
+
+	/// Removes the slots that are more than `max_age_in_slots` behind the `latest_slot`.
+	/// Returns how many files were removed.
+	fn prune(&mut self, latest_slot: Slot) -> Result<usize, Error> {
+		let oldest_slot = latest_slot.saturating_sub(self.max_age_in_slots);
+		let pruned_below = match self.pruned_below {
+			Some(pruned_below) if oldest_slot <= pruned_below => return Ok(0),
+			pruned_below => pruned_below,
+		};
+
+		let mut removed = 0;
+		for dir in [ENVELOPES_DIR, TX_SETS_DIR, PROOFS_DIR] {
+			match pruned_below {
+				// only the slots that became too old since then are left
+				Some(pruned_below) if oldest_slot - pruned_below <= MAX_SLOTS_PRUNED_BY_RANGE =>
+					for slot in pruned_below..oldest_slot {
+						if remove_file(&slot_file(&self.path, dir, slot))? {
+							removed += 1;
+						}
+					},
+				_ =>
+					for slot in
+						slots(&self.path, dir)?.into_iter().take_while(|slot| *slot < oldest_slot)
+					{
+						fs::remove_file(slot_file(&self.path, dir, slot))?;
+						removed += 1;
+					},
+			}
+		}
+
+		self.pruned_below = Some(oldest_slot);
+		Ok(removed)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::oracle::{traits::FileHandler, EnvelopesFileHandler, TxSetsFileHandler};
+
+	fn store(max_age_in_slots: Slot) -> (tempdir::TempDir, ScpStore) {
+		let dir = tempdir::TempDir::new("scp-store-").expect("failed to create tempdir");
+		let store = ScpStore::open(dir.path(), max_age_in_slots).expect("should open the store");
+		(dir, store)
+	}
+
+	#[test]
+	fn envelopes_and_tx_sets_are_saved_and_loaded() {
+		let (_dir, store) = store(100);
+
+		let slot = 578291;
+		let env_map =
+			EnvelopesFileHandler::get_map_from_archives(slot).expect("should return a map");
+		let envelopes = env_map.get(&slot).expect("should have envelopes").clone();
+
+		let txset_slot = 92910;
+		let txsets_map =
+			TxSetsFileHandler::get_map_from_archives(txset_slot).expect("should return a map");
+		let tx_set = txsets_map.get(&txset_slot).expect("should return a tx set").clone();
+
+		assert!(store.load_envelopes(slot).expect("should read the store").is_none());
+
+		store.save_envelopes(slot, &envelopes[..1], false);
+		store.save_envelopes(slot, &envelopes, true);
+		store.save_tx_set(slot, &tx_set);
+		store.flush();
+
+		let (loaded, from_archive) =
+			store.load_envelopes(slot).expect("should read the store").expect("should be saved");
+		assert_eq!(loaded, envelopes);
+		assert!(from_archive);
+		assert_eq!(store.load_tx_set(slot).expect("should read the store"), Some(tx_set));
+
+		// the store is the same once opened again
+		let reopened = ScpStore::open(store.path.clone(), 100).expect("should open the store");
+		assert_eq!(reopened.recent_slots(10).expect("should list the slots"), vec![slot]);
+
+		store.remove(slot);
+		store.flush();
+		assert!(store.load_envelopes(slot).expect("should read the store").is_none());
+		assert!(store.load_tx_set(slot).expect("should read the store").is_none());
+	}
+
+	#[test]
+	fn proofs_are_saved_and_loaded() {
+		let (_dir, store) = store(100);
+
+		let slot = 578291;
+		let env_map =
+			EnvelopesFileHandler::get_map_from_archives(slot).expect("should return a map");
+		let envelopes = env_map.get(&slot).expect("should have envelopes").clone();
+
+		let txset_slot = 92910;
+		let txsets_map =
+			TxSetsFileHandler::get_map_from_archives(txset_slot).expect("should return a map");
+		let tx_set = txsets_map.get(&txset_slot).expect("should return a tx set").clone();
+
+		assert!(store.load_proof(slot).expect("should read the store").is_none());
+
+		let envelopes = UnlimitedVarArray::new(envelopes).expect("should return an array");
+		let proof = Proof::new(slot, envelopes, tx_set);
+		store.save_proof(&proof);
+		store.flush();
+		assert_eq!(store.load_proof(slot).expect("should read the store"), Some(proof));
+
+		// the proofs are kept until they are too old
+		store.remove(slot);
+		store.flush();
+		assert!(store.load_proof(slot).expect("should read the store").is_some());
+		store.prune(slot + 101);
+		store.flush();
+		assert!(store.load_proof(slot).expect("should read the store").is_none());
+	}
+
+	#[test]
+	fn old_slots_are_pruned() {
+		let (_dir, store) = store(10);
+
+		let slot = 578291;
+		let env_map =
+			EnvelopesFileHandler::get_map_from_archives(slot).expect("should return a map");
+		let envelopes = env_map.get(&slot).expect("should have envelopes").clone();
+
+		for slot in 100..120 {
+			store.save_envelopes(slot, &envelopes, false);
+		}
+		store.flush();
+		// a leftover of an interrupted write
+		fs::write(store.file(ENVELOPES_DIR, 1).with_extension("tmp"), [0])
+			.expect("should write the file");
+
+		assert_eq!(store.recent_slots(3).expect("should list the slots"), vec![117, 118, 119]);
+
+		store.prune(119);
+		store.flush();
+		let slots = store.recent_slots(100).expect("should list the slots");
+		assert_eq!(slots, (109..120).collect::<Vec<_>>());
+
+		// the slots that are pruned are not written anymore
+		store.save_envelopes(105, &envelopes, true);
+		store.save_envelopes(130, &envelopes, false);
+		store.prune(130);
+		store.flush();
+		assert_eq!(store.recent_slots(100).expect("should list the slots"), vec![130]);
+	}
+
+	#[test]
+	fn a_slot_saved_again_is_written_once() {
+		let (_dir, store) = store(100);
+
+		let slot = 578291;
+		let env_map =
+			EnvelopesFileHandler::get_map_from_archives(slot).expect("should return a map");
+		let envelopes = env_map.get(&slot).expect("should have envelopes").clone();
+
+		for len in 1..=envelopes.len() {
+			store.save_envelopes(slot, &envelopes[..len], false);
+		}
+		store.save_envelopes(slot + 1, &envelopes, false);
+		store.remove(slot + 1);
+		store.flush();
+
+		let (loaded, _) =
+			store.load_envelopes(slot).expect("should read the store").expect("should be saved");
+		assert_eq!(loaded, envelopes);
+		assert!(store.load_envelopes(slot + 1).expect("should read the store").is_none());
+	}
+}
@@ -108,10 +108,7 @@ pub trait ArchiveStorage {
 
 	fn try_gz_decode_archive_file(path: &str) -> Result<Vec<u8>, Error> {
 		let bytes = Self::read_file_xdr(path)?;
-		let mut gz = GzDecoder::new(&bytes[..]);
-		let mut bytes: Vec<u8> = vec![];
-		gz.read_to_end(&mut bytes)?;
-		Ok(bytes)
+		gz_decode(&bytes)
 	}
 
 	fn get_url_and_file_name(&self, slot_index: Slot) -> (String, String) {
@@ -132,11 +129,7 @@ pub trait ArchiveStorage {
 	}
 
 	fn find_last_slot_index_in_batch(&self, slot_index: Slot) -> Slot {
-		let rest = (slot_index + 1) % ARCHIVE_NODE_LEDGER_BATCH;
-		if rest == 0 {
-			return slot_index;
-		}
-		slot_index + ARCHIVE_NODE_LEDGER_BATCH - rest
+		last_slot_index_in_batch(slot_index)
 	}
 
 	fn remove_file(&self, target_slot: Slot) {
@@ -161,6 +154,22 @@ pub trait ArchiveStorage {
 	}
 }
 
+/// Returns the slot that names the checkpoint file the slot is in
+pub(crate) fn last_slot_index_in_batch(slot_index: Slot) -> Slot {
+	let rest = (slot_index + 1) % ARCHIVE_NODE_LEDGER_BATCH;
+	if rest == 0 {
+		return slot_index;
+	}
+	slot_index + ARCHIVE_NODE_LEDGER_BATCH - rest
+}
+
+pub(crate) fn gz_decode(bytes: &[u8]) -> Result<Vec<u8>, Error> {
+	let mut gz = GzDecoder::new(bytes);
+	let mut bytes: Vec<u8> = vec![];
+	gz.read_to_end(&mut bytes)?;
+	Ok(bytes)
+}
+
 pub(crate) async fn download_file_and_save(url: &str, file_name: &str) -> Result<(), Error> {
 	let response = reqwest::get(url).await.map_err(|e| Error::ArchiveError(e.to_string()))?;
 	if response.status().is_server_error() | response.status().is_client_error() {
@@ -1,5 +1,12 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
 use rand;
 use stellar_relay_lib::sdk::SecretKey;
+use wallet::Slot;
+
+use crate::oracle::{Error, Proof, ProofSource};
+
 pub fn random_stellar_relay_config(is_mainnet: bool) -> stellar_relay_lib::StellarOverlayConfig {
 	let (_, dir) = stellar_relay_config_choices(is_mainnet);
 
@@ -47,3 +54,28 @@ pub fn get_random_secret_key() -> String {
 
 	secret_string.to_string()
 }
+
+/// Always answers with the same proof
+pub struct StaticProofSource {
+	pub proof: Option<Proof>,
+	pub is_external: bool,
+}
+
+#[async_trait]
+impl ProofSource for StaticProofSource {
+	fn name(&self) -> String {
+		format!("static source (external: {})", self.is_external)
+	}
+
+	fn timeout(&self) -> Duration {
+		Duration::from_secs(1)
+	}
+
+	fn is_external(&self) -> bool {
+		self.is_external
+	}
+
+	async fn get_proof(&self, _slot: Slot) -> Result<Proof, Error> {
+		self.proof.clone().ok_or_else(|| Error::Other("no proof".to_string()))
+	}
+}
@@ -1,3 +1,4 @@
+use std::time::Duration;
 use wallet::Slot;
 
 /// This is for `EnvelopesMap`; how many slots is accommodated per file.
@@ -22,7 +23,24 @@ pub const MAX_SLOTS_TO_REMEMBER: Slot = 12;
 
 pub const ARCHIVE_NODE_LEDGER_BATCH: Slot = 64;
 
-/// Returns the minimum amount of SCP messages that are required to build a valid proof per network
+/// How many decoded checkpoint files of each kind the `ArchiveClient` keeps
+pub const ARCHIVE_CHECKPOINT_CACHE_SIZE: usize = 8;
+
+/// How long the `ArchiveClient` waits for a mirror to send a checkpoint file
+pub const ARCHIVE_REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How many trusted ledgers the `ArchiveClient` keeps to verify the ledger headers against
+pub const ARCHIVE_TRUSTED_LEDGERS_LIMIT: usize = 4096;
+
+/// How many checkpoints the `ArchiveClient` goes forward to find one with a trusted ledger, when
+/// the archived envelopes of the checkpoint itself are not trusted
+pub const ARCHIVE_MAX_ANCHOR_DISTANCE: Slot = 64;
+
+/// How often, in slots, the slots that are too old are removed from the `ScpStore`
+pub const SCP_STORE_PRUNE_INTERVAL: Slot = 100;
+
+/// Returns the minimum amount of SCP messages that are required to build a valid proof per network.
+/// Only used until the validators registered in the stellar-relay pallet are known.
 pub fn get_min_externalized_messages(public_network: bool) -> usize {
 	let validator_count =
 		if public_network { VALIDATOR_COUNT_PUBLIC_NETWORK } else { VALIDATOR_COUNT_TEST_NETWORK };
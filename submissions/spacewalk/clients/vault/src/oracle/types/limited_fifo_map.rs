@@ -1,6 +1,6 @@
 #![allow(non_snake_case)]
 
-use crate::oracle::constants::DEFAULT_MAX_ITEMS_IN_QUEUE;
+use crate::oracle::{constants::DEFAULT_MAX_ITEMS_IN_QUEUE, Proof};
 use itertools::Itertools;
 use std::{collections::VecDeque, fmt::Debug};
 use stellar_relay_lib::sdk::{types::ScpEnvelope, TransactionSetType};
@@ -16,6 +16,9 @@ pub(crate) type EnvelopesMap = LimitedFifoMap<Slot, Vec<ScpEnvelope>>;
 /// The txset here can either be the `TransactionSet` or `GeneralizedTransactionSet`
 pub(crate) type TxSetMap = LimitedFifoMap<Slot, TransactionSetType>;
 
+/// This map uses the slot as the key and the proof that was built ahead of time as the value.
+pub(crate) type ProofCache = LimitedFifoMap<Slot, Proof>;
+
 #[derive(Debug, Clone)]
 pub struct LimitedFifoMap<K, T> {
 	limit: usize,
@@ -1,8 +1,10 @@
 pub mod constants;
 mod double_sided_map;
 mod limited_fifo_map;
+mod tx_set_index;
 mod types;
 
 pub use double_sided_map::*;
 pub use limited_fifo_map::*;
+pub use tx_set_index::*;
 pub use types::*;
@@ -0,0 +1,282 @@
+use std::collections::{HashMap, VecDeque};
+
+use primitives::{get_text_memo_from_tx_env, TextMemo};
+use stellar_relay_lib::sdk::{
+	network::Network,
+	types::{FeeBumpTransactionInnerTx, MuxedAccount, Operation, OperationBody},
+	TransactionEnvelope, TransactionSetType,
+};
+use wallet::Slot;
+
+use crate::oracle::{constants::DEFAULT_MAX_ITEMS_IN_QUEUE, types::TxHash};
+
+/// A transaction of a tx set that the collector received
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct IndexedTransaction {
+	/// the slot (or ledger) of the tx set
+	pub slot: Slot,
+	pub hash: TxHash,
+	pub tx_env: TransactionEnvelope,
+}
+
+/// Finds the transactions of the most recent tx sets by their hash, their source account, the
+/// accounts they pay or their text memo, without asking Horizon.
+#[derive(Clone, Debug)]
+pub struct TxSetIndex {
+	/// how many slots are indexed before the oldest one is dropped
+	limit: usize,
+	/// the indexed slots, the oldest first
+	slots: VecDeque<Slot>,
+	transactions: HashMap<TxHash, IndexedTransaction>,
+	by_slot: HashMap<Slot, Vec<TxHash>>,
+	by_source: HashMap<[u8; 32], Vec<TxHash>>,
+	by_destination: HashMap<[u8; 32], Vec<TxHash>>,
+	by_memo: HashMap<TextMemo, Vec<TxHash>>,
+}
+
+impl Default for TxSetIndex {
+	fn default() -> Self {
+		TxSetIndex::new(DEFAULT_MAX_ITEMS_IN_QUEUE)
+	}
+}
+
+impl TxSetIndex {
+	pub fn new(limit: usize) -> Self {
+		TxSetIndex {
+			limit: limit.max(1),
+			slots: VecDeque::new(),
+			transactions: HashMap::new(),
+			by_slot: HashMap::new(),
+			by_source: HashMap::new(),
+			by_destination: HashMap::new(),
+			by_memo: HashMap::new(),
+		}
+	}
+
+	/// the number of indexed slots
+	pub fn len(&self) -> usize {
+		self.slots.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.slots.is_empty()
+	}
+
+	pub fn contains_slot(&self, slot: &Slot) -> bool {
+		self.by_slot.contains_key(slot)
+	}
+
+	/// Indexes the transactions of the tx set of the slot. The hashes depend on the `network`.
+	pub fn insert(&mut self, slot: Slot, tx_set: &TransactionSetType, network: &Network) {
+		if self.contains_slot(&slot) {
+			return
+		}
+
+		if self.slots.len() >= self.limit {
+			if let Some(oldest_slot) = self.slots.pop_front() {
+				self.remove_slot(oldest_slot);
+			}
+		}
+
+		let mut hashes = vec![];
+		for tx_env in tx_set.txes() {
+			let hash = tx_env.get_hash(network);
+
+			if let Some(source) = source_account(&tx_env) {
+				self.by_source.entry(source).or_default().push(hash);
+			}
+			for destination in payment_destinations(&tx_env) {
+				self.by_destination.entry(destination).or_default().push(hash);
+			}
+			if let Some(memo) = get_text_memo_from_tx_env(&tx_env) {
+				self.by_memo.entry(memo.clone()).or_default().push(hash);
+			}
+
+			self.transactions.insert(hash, IndexedTransaction { slot, hash, tx_env });
+			hashes.push(hash);
+		}
+
+		self.by_slot.insert(slot, hashes);
+		self.slots.push_back(slot);
+	}
+
+	pub fn get(&self, hash: &TxHash) -> Option<&IndexedTransaction> {
+		self.transactions.get(hash)
+	}
+
+	/// Returns the transactions of the account, the oldest first
+	pub fn by_source(&self, account: &[u8; 32]) -> Vec<&IndexedTransaction> {
+		self.lookup(self.by_source.get(account))
+	}
+
+	/// Returns the transactions that pay the account, the oldest first
+	pub fn by_destination(&self, account: &[u8; 32]) -> Vec<&IndexedTransaction> {
+		self.lookup(self.by_destination.get(account))
+	}
+
+	/// Returns the transactions with the text memo, the oldest first
+	pub fn by_memo(&self, memo: &[u8]) -> Vec<&IndexedTransaction> {
+		self.lookup(self.by_memo.get(memo))
+	}
+
+	/// Returns the transactions with the text memo that pay the account, the oldest first
+	pub fn by_memo_to(&self, memo: &[u8], account: &[u8; 32]) -> Vec<&IndexedTransaction> {
+		let Some(to_account) = self.by_destination.get(account) else { return vec![] };
+		self.by_memo(memo).into_iter().filter(|tx| to_account.contains(&tx.hash)).collect()
+	}
+
+	fn lookup(&self, hashes: Option<&Vec<TxHash>>) -> Vec<&IndexedTransaction> {
+		hashes.into_iter().flatten().filter_map(|hash| self.transactions.get(hash)).collect()
+	}
+
+	fn remove_slot(&mut self, slot: Slot) {
+		let Some(hashes) = self.by_slot.remove(&slot) else { return };
+
+		for hash in &hashes {
+			let Some(tx) = self.transactions.remove(hash) else { continue };
+
+			if let Some(source) = source_account(&tx.tx_env) {
+				remove_hash(&mut self.by_source, &source, hash);
+			}
+			for destination in payment_destinations(&tx.tx_env) {
+				remove_hash(&mut self.by_destination, &destination, hash);
+			}
+			if let Some(memo) = get_text_memo_from_tx_env(&tx.tx_env) {
+				remove_hash(&mut self.by_memo, memo, hash);
+			}
+		}
+	}
+}
+
+fn remove_hash<K>(map: &mut HashMap<K, Vec<TxHash>>, key: &K, hash: &TxHash)
+where
+	K: Eq + std::hash::Hash,
+{
+	if let Some(hashes) = map.get_mut(key) {
+		hashes.retain(|h| h != hash);
+		if hashes.is_empty() {
+			map.remove(key);
+		}
+	}
+}
+
+/// Returns the raw public key of the account that the payments of the transaction are made from
+pub(crate) fn source_account(tx_env: &TransactionEnvelope) -> Option<[u8; 32]> {
+	let source_account = match tx_env {
+		TransactionEnvelope::EnvelopeTypeTxV0(env) => return Some(env.tx.source_account_ed25519),
+		TransactionEnvelope::EnvelopeTypeTx(env) => &env.tx.source_account,
+		TransactionEnvelope::EnvelopeTypeTxFeeBump(env) => match &env.tx.inner_tx {
+			FeeBumpTransactionInnerTx::EnvelopeTypeTx(inner) => &inner.tx.source_account,
+			_ => return None,
+		},
+		_ => return None,
+	};
+
+	match source_account {
+		MuxedAccount::KeyTypeEd25519(key) => Some(*key),
+		MuxedAccount::KeyTypeMuxedEd25519(account) => Some(account.ed25519),
+	}
+}
+
+/// Returns the raw public keys of the accounts that the payments of the transaction go to, each
+/// once
+pub(crate) fn payment_destinations(tx_env: &TransactionEnvelope) -> Vec<[u8; 32]> {
+	let operations: &[Operation] = match tx_env {
+		TransactionEnvelope::EnvelopeTypeTxV0(env) => env.tx.operations.get_vec(),
+		TransactionEnvelope::EnvelopeTypeTx(env) => env.tx.operations.get_vec(),
+		TransactionEnvelope::EnvelopeTypeTxFeeBump(env) => match &env.tx.inner_tx {
+			FeeBumpTransactionInnerTx::EnvelopeTypeTx(inner) => inner.tx.operations.get_vec(),
+			_ => return vec![],
+		},
+		_ => return vec![],
+	};
+
+	let mut destinations = vec![];
+	for operation in operations {
+		let destination = match &operation.body {
+			OperationBody::Payment(op) => &op.destination,
+			OperationBody::PathPaymentStrictReceive(op) => &op.destination,
+			OperationBody::PathPaymentStrictSend(op) => &op.destination,
+			_ => continue,
+		};
+		let destination = match destination {
+			MuxedAccount::KeyTypeEd25519(key) => *key,
+			MuxedAccount::KeyTypeMuxedEd25519(account) => account.ed25519,
+		};
+		if !destinations.contains(&destination) {
+			destinations.push(destination);
+		}
+	}
+	destinations
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use stellar_relay_lib::sdk::network::PUBLIC_NETWORK;
+
+	use crate::oracle::{traits::FileHandler, TxSetsFileHandler};
+
+	fn sample_tx_sets() -> Vec<(Slot, TransactionSetType)> {
+		let txset_slot = 92910;
+		let txsets_map =
+			TxSetsFileHandler::get_map_from_archives(txset_slot).expect("should return a map");
+		txsets_map.iter().map(|(slot, tx_set)| (*slot, tx_set.clone())).collect()
+	}
+
+	#[test]
+	fn transactions_are_found_by_hash_source_destination_and_memo() {
+		let mut index = TxSetIndex::default();
+		let (slot, tx_set) = sample_tx_sets()
+			.into_iter()
+			.find(|(_, tx_set)| !tx_set.txes().is_empty())
+			.expect("should have a tx set with transactions");
+		index.insert(slot, &tx_set, &PUBLIC_NETWORK);
+
+		for tx_env in tx_set.txes() {
+			let hash = tx_env.get_hash(&PUBLIC_NETWORK);
+			let indexed = index.get(&hash).expect("should be indexed");
+			assert_eq!(indexed.slot, slot);
+			assert_eq!(indexed.tx_env, tx_env);
+
+			if let Some(source) = source_account(&tx_env) {
+				assert!(index.by_source(&source).contains(&indexed));
+			}
+			for destination in payment_destinations(&tx_env) {
+				assert!(index.by_destination(&destination).contains(&indexed));
+			}
+			if let Some(memo) = get_text_memo_from_tx_env(&tx_env) {
+				assert!(index.by_memo(memo).contains(&indexed));
+				for destination in payment_destinations(&tx_env) {
+					assert!(index.by_memo_to(memo, &destination).contains(&indexed));
+				}
+				assert!(!index.by_memo_to(memo, &[0; 32]).contains(&indexed));
+			}
+		}
+
+		assert!(index.get(&[0; 32]).is_none());
+		assert!(index.by_memo(b"not a memo of the sample").is_empty());
+	}
+
+	#[test]
+	fn the_oldest_slots_are_dropped() {
+		let tx_sets = sample_tx_sets();
+		assert!(tx_sets.len() > 2, "should have enough tx sets");
+
+		let mut index = TxSetIndex::new(2);
+		for (slot, tx_set) in &tx_sets {
+			index.insert(*slot, tx_set, &PUBLIC_NETWORK);
+		}
+		assert_eq!(index.len(), 2);
+
+		let (oldest_slot, oldest_tx_set) = &tx_sets[0];
+		assert!(!index.contains_slot(oldest_slot));
+		for tx_env in oldest_tx_set.txes() {
+			let hash = tx_env.get_hash(&PUBLIC_NETWORK);
+			assert!(index.get(&hash).is_none());
+		}
+
+		let (latest_slot, _) = tx_sets.last().expect("should have a tx set");
+		assert!(index.contains_slot(latest_slot));
+	}
+}
@@ -35,6 +35,7 @@ pub async fn listen_for_redeem_requests(
 				// these:
 				let parachain_rpc = parachain_rpc.clone();
 				let oracle_agent_clone = oracle_agent.clone();
+				let journal = vault_id_manager.journal();
 				// Spawn a new task so that we handle these events concurrently
 				spawn_cancelable(shutdown_tx.subscribe(), async move {
 					tracing::info!(
@@ -47,7 +48,9 @@ pub async fn listen_for_redeem_requests(
 							parachain_rpc.get_redeem_request(event.redeem_id).await?,
 							payment_margin,
 						)?;
-						request.pay_and_execute(parachain_rpc, vault, oracle_agent_clone).await
+						request
+							.pay_and_execute(parachain_rpc, vault, oracle_agent_clone, journal)
+							.await
 					}
 					.await;
 
@@ -46,6 +46,7 @@ pub async fn listen_for_accept_replace(
 				// these:
 				let parachain_rpc = parachain_rpc.clone();
 				let oracle_agent = oracle_agent.clone();
+				let journal = vault_id_manager.journal();
 				// Spawn a new task so that we handle these events concurrently
 				spawn_cancelable(shutdown_tx.subscribe(), async move {
 					tracing::info!(
@@ -60,7 +61,7 @@ pub async fn listen_for_accept_replace(
 							parachain_rpc.get_replace_request(event.replace_id).await?,
 							payment_margin,
 						)?;
-						request.pay_and_execute(parachain_rpc, vault, oracle_agent).await
+						request.pay_and_execute(parachain_rpc, vault, oracle_agent, journal).await
 					}
 					.await;
 
@@ -1,6 +1,10 @@
 use crate::{
 	error::Error,
-	oracle::OracleAgent,
+	journal::{RequestJournal, RequestKind, RequestState},
+	oracle::{
+		types::{IndexedTransaction, TxHash},
+		OracleAgent,
+	},
 	requests::{
 		helper::{
 			get_all_transactions_of_wallet_async, get_request_for_stellar_tx,
@@ -21,7 +25,7 @@ use governor::{
 use primitives::{derive_shortened_request_id, stellar::TransactionEnvelope, TextMemo};
 use runtime::{PrettyPrint, ShutdownSender, SpacewalkParachain, UtilFuncs};
 use service::{spawn_cancelable, Error as ServiceError};
-use std::{collections::HashMap, sync::Arc, time::Duration};
+use std::{collections::HashMap, future::Future, sync::Arc, time::Duration};
 use tokio::sync::RwLock;
 use wallet::{Slot, StellarWallet, TransactionResponse};
 
@@ -39,6 +43,7 @@ const MAX_EXECUTION_RETRIES: u32 = 3;
 /// * `parachain_rpc` - the parachain RPC handle
 /// * `oracle_agent` - the agent used to get the proofs
 /// * `rate_limiter` - a rate limiter
+/// * `journal` - records the progress of the requests
 async fn spawn_tasks_to_execute_open_requests_async<S, C, MW>(
 	requests: &mut HashMap<TextMemo, Request>,
 	wallet: ArcRwLock<StellarWallet>,
@@ -46,6 +51,7 @@ async fn spawn_tasks_to_execute_open_requests_async<S, C, MW>(
 	parachain_rpc: &SpacewalkParachain,
 	oracle_agent: Arc<OracleAgent>,
 	rate_limiter: Arc<RateLimiter<NotKeyed, S, C, MW>>,
+	journal: Option<RequestJournal>,
 ) where
 	S: DirectStateStore,
 	C: ReasonablyRealtime,
@@ -72,6 +78,7 @@ async fn spawn_tasks_to_execute_open_requests_async<S, C, MW>(
 					shutdown_tx.clone(),
 					parachain_rpc.clone(),
 					oracle_agent.clone(),
+					journal.clone(),
 				);
 
 				// remove request from the hashmap, using the memo
@@ -91,12 +98,14 @@ async fn spawn_tasks_to_execute_open_requests_async<S, C, MW>(
 /// * `shutdown_tx` - for sending and receiving shutdown signals
 /// * `parachain_rpc` - the parachain RPC handle
 /// * `oracle_agent` - the agent used to get the proofs
+/// * `journal` - records the progress of the request
 fn spawn_task_to_execute_open_request(
 	request: Request,
 	transaction: TransactionResponse,
 	shutdown_tx: ShutdownSender,
 	parachain_rpc: SpacewalkParachain,
 	oracle_agent: Arc<OracleAgent>,
+	journal: Option<RequestJournal>,
 ) -> TextMemo {
 	let hash_as_memo = derive_shortened_request_id(&request.hash_inner());
 
@@ -115,15 +124,19 @@ fn spawn_task_to_execute_open_request(
 			);
 		},
 		Ok(tx_envelope) => {
+			let slot = transaction.ledger as Slot;
+			request.record(journal.as_ref(), RequestState::paid(slot, &tx_envelope));
+
 			// start a new task to execute on the parachain
 			spawn_cancelable(
 				shutdown_tx.subscribe(),
 				execute_open_request_async(
 					request,
 					tx_envelope,
-					transaction.ledger as Slot,
+					slot,
 					parachain_rpc,
 					oracle_agent,
+					journal,
 				),
 			);
 		},
@@ -142,13 +155,16 @@ fn spawn_task_to_execute_open_request(
 /// * `slot` - the ledger number of the transaction envelope
 /// * `parachain_rpc` - the parachain RPC handle
 /// * `oracle_agent` - the agent used to get the proofs
+/// * `journal` - records the progress of the request
 async fn execute_open_request_async(
 	request: Request,
 	tx_envelope: TransactionEnvelope,
 	slot: Slot,
 	parachain_rpc: SpacewalkParachain,
 	oracle_agent: Arc<OracleAgent>,
+	journal: Option<RequestJournal>,
 ) {
+	let journal = journal.as_ref();
 	let mut retry_count = 0; // A counter for every execution retry
 
 	while retry_count < MAX_EXECUTION_RETRIES {
@@ -158,6 +174,8 @@ async fn execute_open_request_async(
 
 		match oracle_agent.get_proof(slot).await {
 			Ok(proof) => {
+				request.record(journal, RequestState::ProofBuilt { slot });
+
 				let Err(e) =
 					request.execute(parachain_rpc.clone(), tx_envelope.clone(), proof).await
 				else {
@@ -166,6 +184,7 @@ async fn execute_open_request_async(
 						request.request_type(),
 						request.hash()
 					);
+					request.record(journal, RequestState::Executed);
 
 					break; // There is no need to retry again, so exit from while loop
 				};
@@ -175,6 +194,7 @@ async fn execute_open_request_async(
 					request.request_type(),
 					request.hash()
 				);
+				request.record(journal, RequestState::failed(e));
 				break; // There is also no need to retry on an unrecoverable error.
 			},
 			Err(error) => {
@@ -192,7 +212,160 @@ async fn execute_open_request_async(
             request.request_type(),
             request.hash(),
         );
+		request.record(
+			journal,
+			RequestState::failed(format!("No proof of slot {slot} after {retry_count} retries")),
+		);
+	}
+}
+
+/// Returns the first of the `payments` that pays enough and that `is_successful` confirms.
+/// A tx set also contains the transactions that failed, so paying enough is not enough.
+async fn find_successful_payment<F, Fut>(
+	payments: Vec<IndexedTransaction>,
+	pays_enough: impl Fn(&TransactionEnvelope) -> bool,
+	is_successful: F,
+) -> Option<IndexedTransaction>
+where
+	F: Fn(TxHash) -> Fut,
+	Fut: Future<Output = bool>,
+{
+	for payment in payments {
+		if pays_enough(&payment.tx_env) && is_successful(payment.hash).await {
+			return Some(payment)
+		}
+	}
+	None
+}
+
+/// Executes the open requests whose payment is in the tx sets that the oracle agent collected,
+/// so that they are not searched in the transactions of the wallet. The executed requests are
+/// removed from `requests`.
+///
+/// # Arguments
+///
+/// * `requests` - a list of all open/pending requests
+/// * `wallet` - the vault's wallet; used to confirm that the payments succeeded
+/// * `shutdown_tx` - for sending and receiving shutdown signals
+/// * `parachain_rpc` - the parachain RPC handle
+/// * `oracle_agent` - the agent used to find the payments and get the proofs
+/// * `journal` - records the progress of the requests
+async fn spawn_tasks_to_execute_collected_requests(
+	requests: &mut HashMap<TextMemo, Request>,
+	wallet: &StellarWallet,
+	shutdown_tx: &ShutdownSender,
+	parachain_rpc: &SpacewalkParachain,
+	oracle_agent: &Arc<OracleAgent>,
+	journal: Option<&RequestJournal>,
+) {
+	let is_successful = |hash: TxHash| async move {
+		match wallet.is_transaction_successful(&hex::encode(hash)).await {
+			Ok(successful) => successful,
+			Err(e) => {
+				tracing::warn!(
+					"spawn_tasks_to_execute_collected_requests(): cannot confirm transaction {}: {e:?}",
+					hex::encode(hash)
+				);
+				false
+			},
+		}
+	};
+
+	let mut executed = vec![];
+	for (memo, request) in requests.iter() {
+		let pays_enough = |tx_env: &TransactionEnvelope| {
+			tx_env.get_payment_amount_for_asset_to(request.stellar_address(), request.asset()) >=
+				request.amount()
+		};
+		let payments = oracle_agent.find_payments(memo, &request.stellar_address()).await;
+		let Some(payment) = find_successful_payment(payments, pays_enough, is_successful).await
+		else {
+			continue
+		};
+
+		tracing::info!(
+			"Processing the Stellar payment of slot {} for open {:?} request #{}, found in the collected tx sets",
+			payment.slot,
+			request.request_type(),
+			request.hash()
+		);
+		request.record(journal, RequestState::paid(payment.slot, &payment.tx_env));
+		spawn_cancelable(
+			shutdown_tx.subscribe(),
+			execute_open_request_async(
+				request.clone(),
+				payment.tx_env,
+				payment.slot,
+				parachain_rpc.clone(),
+				oracle_agent.clone(),
+				journal.cloned(),
+			),
+		);
+		executed.push(memo.clone());
 	}
+
+	requests.retain(|memo, _| !executed.contains(memo));
+}
+
+/// Executes the open requests whose payment is in the journal, so that they are neither looked
+/// up on Horizon nor paid again. The executed requests are removed from `requests`.
+/// The requests that the journal has in flight but are not open anymore are marked as failed.
+///
+/// # Arguments
+///
+/// * `requests` - a list of all open/pending requests
+/// * `journal` - records the progress of the requests
+/// * `shutdown_tx` - for sending and receiving shutdown signals
+/// * `parachain_rpc` - the parachain RPC handle
+/// * `oracle_agent` - the agent used to get the proofs
+fn spawn_tasks_to_execute_journaled_requests(
+	requests: &mut HashMap<TextMemo, Request>,
+	journal: &RequestJournal,
+	shutdown_tx: &ShutdownSender,
+	parachain_rpc: &SpacewalkParachain,
+	oracle_agent: &Arc<OracleAgent>,
+) {
+	for entry in journal.in_flight() {
+		if entry.kind == RequestKind::Issue ||
+			requests.values().any(|request| request.hash() == entry.request_id)
+		{
+			continue
+		}
+
+		tracing::info!(
+			"{:?} request #{:?} is not open anymore, last journaled as {:?}",
+			entry.kind,
+			entry.request_id,
+			entry.state
+		);
+		journal.record(
+			entry.request_id,
+			entry.kind,
+			RequestState::failed("The request is not open on the parachain anymore"),
+		);
+	}
+
+	requests.retain(|_, request| {
+		let Some((slot, tx_envelope)) = journal.payment(request.hash()) else { return true };
+
+		tracing::info!(
+			"Resuming the execution of open {:?} request #{} with the journaled payment of slot {slot}",
+			request.request_type(),
+			request.hash()
+		);
+		spawn_cancelable(
+			shutdown_tx.subscribe(),
+			execute_open_request_async(
+				request.clone(),
+				tx_envelope,
+				slot,
+				parachain_rpc.clone(),
+				oracle_agent.clone(),
+				Some(journal.clone()),
+			),
+		);
+		false
+	});
 }
 
 #[async_trait]
@@ -255,7 +428,8 @@ where
 		// error.
 		rate_limiter.until_ready().await;
 
-		match request.pay_and_execute(parachain_rpc, vault, oracle_agent).await {
+		let journal = vault_id_manager.journal();
+		match request.pay_and_execute(parachain_rpc, vault, oracle_agent, journal).await {
 			Ok(_) => tracing::info!(
 				"Successfully executed open {:?} request #{:?}",
 				request.request_type(),
@@ -309,6 +483,30 @@ pub async fn execute_open_requests(
 
 	tracing::info!("execute_open_requests(): Oracle agent is ready.");
 
+	// The journal knows which of the open requests were paid before the restart
+	let journal = vault_id_manager.journal();
+	if let Some(journal) = &journal {
+		spawn_tasks_to_execute_journaled_requests(
+			&mut open_requests,
+			journal,
+			&shutdown_tx,
+			parachain_rpc_ref,
+			&oracle_agent,
+		);
+	}
+
+	// The payments that the overlay sent a tx set of are found without searching the
+	// transactions of the wallet; Horizon only confirms that they succeeded
+	spawn_tasks_to_execute_collected_requests(
+		&mut open_requests,
+		&*wallet.read().await,
+		&shutdown_tx,
+		parachain_rpc_ref,
+		&oracle_agent,
+		journal.as_ref(),
+	)
+	.await;
+
 	// Check if the open requests have a corresponding payment on Stellar
 	// and are just waiting to be executed on the parachain
 	spawn_tasks_to_execute_open_requests_async(
@@ -318,6 +516,7 @@ pub async fn execute_open_requests(
 		parachain_rpc_ref,
 		oracle_agent.clone(),
 		rate_limiter.clone(),
+		journal,
 	)
 	.await;
 
@@ -338,3 +537,52 @@ pub async fn execute_open_requests(
 
 	Ok(())
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::oracle::{traits::FileHandler, TxSetsFileHandler};
+	use stellar_relay_lib::sdk::network::PUBLIC_NETWORK;
+
+	fn sample_payments() -> Vec<IndexedTransaction> {
+		let txsets_map =
+			TxSetsFileHandler::get_map_from_archives(92910).expect("should return a map");
+		let (slot, tx_set) = txsets_map
+			.iter()
+			.find(|(_, tx_set)| tx_set.txes().len() > 1)
+			.expect("should have a tx set with transactions");
+
+		tx_set
+			.txes()
+			.into_iter()
+			.take(2)
+			.map(|tx_env| IndexedTransaction {
+				slot: *slot,
+				hash: tx_env.get_hash(&PUBLIC_NETWORK),
+				tx_env,
+			})
+			.collect()
+	}
+
+	#[tokio::test]
+	async fn failed_payments_are_not_executed() {
+		let payments = sample_payments();
+		let failed = payments[0].hash;
+
+		let is_successful = |hash: TxHash| async move { hash != failed };
+		let payment = find_successful_payment(payments.clone(), |_| true, is_successful).await;
+		assert_eq!(payment, Some(payments[1].clone()));
+
+		let only_failed = vec![payments[0].clone()];
+		let payment = find_successful_payment(only_failed, |_| true, is_successful).await;
+		assert_eq!(payment, None);
+	}
+
+	#[tokio::test]
+	async fn payments_have_to_pay_enough() {
+		let payments = sample_payments();
+
+		let payment = find_successful_payment(payments, |_| false, |_| async { true }).await;
+		assert_eq!(payment, None);
+	}
+}
@@ -1,6 +1,7 @@
 use crate::{
+	journal::{RequestJournal, RequestKind, RequestState},
 	metrics::update_stellar_metrics,
-	oracle::{OracleAgent, Proof},
+	oracle::{verify_proof, OracleAgent, Proof},
 	system::VaultData,
 	Error,
 };
@@ -27,6 +28,15 @@ pub enum RequestType {
 	Replace,
 }
 
+impl From<RequestType> for RequestKind {
+	fn from(request_type: RequestType) -> Self {
+		match request_type {
+			RequestType::Redeem => RequestKind::Redeem,
+			RequestType::Replace => RequestKind::Replace,
+		}
+	}
+}
+
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub struct Request {
@@ -131,7 +141,8 @@ impl Request {
 		})
 	}
 
-	/// Makes the stellar transfer and executes the request
+	/// Makes the stellar transfer and executes the request.
+	/// A payment that the journal holds is not made again; the request is executed with it.
 	pub async fn pay_and_execute<
 		P: ReplacePallet
 			+ StellarRelayPallet
@@ -148,30 +159,90 @@ impl Request {
 		parachain_rpc: P,
 		vault: VaultData,
 		oracle_agent: Arc<OracleAgent>,
+		journal: Option<RequestJournal>,
 	) -> Result<(), Error> {
-		// ensure the deadline has not expired yet
-		if let Some(ref deadline) = self.deadline {
-			if parachain_rpc.get_current_active_block_number().await? >= deadline.parachain {
-				return Err(Error::DeadlineExpired);
-			}
+		let journal = journal.as_ref();
+		if let Some(journal) = journal {
+			journal.record_detected(self.hash, self.request_type.into());
 		}
 
-		let response = self.transfer_stellar_asset(vault.stellar_wallet.clone()).await?;
-		let tx_env = response.to_envelope()?;
+		let result = self.try_pay_and_execute(parachain_rpc, vault, oracle_agent, journal).await;
+		match &result {
+			Ok(_) => self.record(journal, RequestState::Executed),
+			Err(e) => self.record(journal, RequestState::failed(e)),
+		}
+		result
+	}
 
-		let proof = oracle_agent.get_proof(response.ledger as Slot).await?;
+	async fn try_pay_and_execute<
+		P: ReplacePallet
+			+ StellarRelayPallet
+			+ RedeemPallet
+			+ SecurityPallet
+			+ VaultRegistryPallet
+			+ OraclePallet
+			+ UtilFuncs
+			+ Clone
+			+ Send
+			+ Sync,
+	>(
+		&self,
+		parachain_rpc: P,
+		vault: VaultData,
+		oracle_agent: Arc<OracleAgent>,
+		journal: Option<&RequestJournal>,
+	) -> Result<(), Error> {
+		let (slot, tx_env) = match journal.and_then(|journal| journal.payment(self.hash)) {
+			Some(payment) => {
+				tracing::info!(
+					"For {:?} request #{}: the payment was already made, executing it",
+					self.request_type,
+					self.hash
+				);
+				payment
+			},
+			None => {
+				// ensure the deadline has not expired yet
+				if let Some(ref deadline) = self.deadline {
+					if parachain_rpc.get_current_active_block_number().await? >= deadline.parachain
+					{
+						return Err(Error::DeadlineExpired);
+					}
+				}
+
+				let response = self.transfer_stellar_asset(vault.stellar_wallet.clone()).await?;
+				let tx_env = response.to_envelope()?;
+				let slot = response.ledger as Slot;
+				self.record(journal, RequestState::paid(slot, &tx_env));
+
+				(slot, tx_env)
+			},
+		};
+
+		let proof = oracle_agent.get_proof(slot).await?;
+		self.record(journal, RequestState::ProofBuilt { slot });
 
 		let _ = update_stellar_metrics(&vault, &parachain_rpc).await;
 		self.execute(parachain_rpc, tx_env, proof).await
 	}
 
+	/// Appends the new state of the request to the journal, if there is one
+	pub(crate) fn record(&self, journal: Option<&RequestJournal>, state: RequestState) {
+		if let Some(journal) = journal {
+			journal.record(self.hash, self.request_type.into(), state);
+		}
+	}
+
 	/// Executes the request. Upon failure it will retry again.
-	pub(crate) async fn execute<P: ReplacePallet + RedeemPallet>(
+	pub(crate) async fn execute<P: ReplacePallet + RedeemPallet + StellarRelayPallet>(
 		&self,
 		parachain_rpc: P,
 		tx_env: TransactionEnvelope,
 		proof: Proof,
 	) -> Result<(), Error> {
+		// Do not pay for an extrinsic that the pallet is going to reject
+		verify_proof(&parachain_rpc, &proof, &tx_env).await?;
+
 		// select the execute function based on request_type
 		let execute = match self.request_type {
 			RequestType::Redeem => RedeemPallet::execute_redeem,
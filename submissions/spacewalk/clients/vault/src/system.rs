@@ -26,7 +26,7 @@ use runtime::{
 	cli::parse_duration_minutes, AccountId, BlockNumber, CollateralBalancesPallet, CurrencyId,
 	Error as RuntimeError, IssueIdLookup, IssueRequestsMap, PrettyPrint, RegisterVaultEvent,
 	ShutdownSender, SpacewalkParachain, StellarRelayPallet, TryFromSymbol, UpdateActiveBlockEvent,
-	UtilFuncs, VaultCurrencyPair, VaultId, VaultRegistryPallet,
+	UpdateTier1ValidatorSetEvent, UtilFuncs, VaultCurrencyPair, VaultId, VaultRegistryPallet,
 };
 use service::{wait_or_shutdown, Error as ServiceError, MonitoringConfig, Service};
 use stellar_relay_lib::{sdk::PublicKey, StellarOverlayConfig};
@@ -37,8 +37,9 @@ use crate::{
 	error::Error,
 	issue,
 	issue::IssueFilter,
+	journal::RequestJournal,
 	metrics::{monitor_bridge_metrics, poll_metrics, publish_tokio_metrics, PerCurrencyMetrics},
-	oracle::{listen_for_stellar_messages, OracleAgent},
+	oracle::{listen_for_stellar_messages, OracleAgent, RemoteProofSource, ScpStore, ValidatorSet},
 	redeem::listen_for_redeem_requests,
 	replace::{listen_for_accept_replace, listen_for_execute_replace, listen_for_replace_requests},
 	requests::execution::execute_open_requests,
@@ -66,6 +67,7 @@ pub struct VaultIdManager {
 	vault_data: ArcRwLock<HashMap<VaultId, VaultData>>,
 	spacewalk_parachain: SpacewalkParachain,
 	stellar_wallet: ArcRwLock<StellarWallet>,
+	journal: Option<RequestJournal>,
 }
 
 impl VaultIdManager {
@@ -77,9 +79,16 @@ impl VaultIdManager {
 			vault_data: Arc::new(RwLock::new(HashMap::new())),
 			spacewalk_parachain,
 			stellar_wallet,
+			journal: None,
 		}
 	}
 
+	/// Records the progress of the requests of the vaults in the journal
+	pub fn with_journal(mut self, journal: Option<RequestJournal>) -> Self {
+		self.journal = journal;
+		self
+	}
+
 	// used for testing only
 	pub fn from_map(
 		spacewalk_parachain: SpacewalkParachain,
@@ -100,7 +109,12 @@ impl VaultIdManager {
 				)
 			})
 			.collect();
-		Self { vault_data: Arc::new(RwLock::new(vault_data)), spacewalk_parachain, stellar_wallet }
+		Self {
+			vault_data: Arc::new(RwLock::new(vault_data)),
+			spacewalk_parachain,
+			stellar_wallet,
+			journal: None,
+		}
 	}
 
 	async fn add_vault_id(&self, vault_id: VaultId, is_liquidated: bool) -> Result<(), Error> {
@@ -176,6 +190,10 @@ impl VaultIdManager {
 		self.vault_data.read().await.get(vault_id).cloned()
 	}
 
+	pub fn journal(&self) -> Option<RequestJournal> {
+		self.journal.clone()
+	}
+
 	// Get all ACTIVE vaults
 	pub async fn get_entries(&self) -> Vec<VaultData> {
 		self.vault_data
@@ -280,6 +298,32 @@ pub struct VaultServiceConfig {
 	/// Don't try to execute issues.
 	#[clap(long, env = "NO_ISSUE_EXECUTION")]
 	pub no_issue_execution: bool,
+
+	/// The directory to keep the collected SCP envelopes and tx sets in, so that they survive the
+	/// restarts. Without it, they are only kept in memory.
+	#[clap(long, env = "SCP_STORE_PATH")]
+	pub scp_store_path: Option<String>,
+
+	/// How many slots behind the latest slot the SCP store keeps; the default is about a day.
+	#[clap(long, env = "SCP_STORE_MAX_AGE_IN_SLOTS", default_value = "17280")]
+	pub scp_store_max_age_in_slots: u64,
+
+	/// The URLs of the proof servers to ask for the proofs that neither the overlay nor the
+	/// history archives provide, in order. Their proofs are checked before they are used.
+	#[clap(long, env = "PROOF_SERVER_URLS", value_delimiter = ',')]
+	pub proof_server_urls: Vec<String>,
+
+	/// How many seconds a proof server gets to provide a proof. A proof server asks its collector
+	/// and then the history archives, for up to 120 seconds in total (see
+	/// `COLLECTOR_PROOF_TIMEOUT_IN_SECS` and `ARCHIVE_PROOF_TIMEOUT_IN_SECS`), so the default
+	/// waits longer than that.
+	#[clap(long, env = "PROOF_SERVER_TIMEOUT_SECS", default_value = "150")]
+	pub proof_server_timeout_secs: u64,
+
+	/// The directory to keep the journal of the issue, redeem and replace requests in, so that
+	/// their payments and executions continue after a restart. Without it, nothing is journaled.
+	#[clap(long, env = "REQUEST_JOURNAL_PATH")]
+	pub request_journal_path: Option<String>,
 }
 
 async fn active_block_listener(
@@ -304,6 +348,50 @@ async fn active_block_listener(
 	Ok(())
 }
 
+async fn update_validator_set(parachain_rpc: &SpacewalkParachain, oracle_agent: &OracleAgent) {
+	match parachain_rpc.get_stellar_validators().await {
+		Ok(validators) => oracle_agent.set_validator_set(ValidatorSet::from(validators)).await,
+		Err(e) => tracing::error!("update_validator_set(): failed to get the validators: {e:?}"),
+	}
+}
+
+/// Keeps the validators that the oracle builds the proofs for in sync with the stellar-relay
+/// pallet
+async fn validator_set_listener(
+	parachain_rpc: SpacewalkParachain,
+	oracle_agent: Arc<OracleAgent>,
+) -> Result<(), ServiceError<Error>> {
+	tracing::info!("validator_set_listener(): started");
+	update_validator_set(&parachain_rpc, &oracle_agent).await;
+
+	let parachain_rpc = &parachain_rpc;
+	let oracle_agent = &oracle_agent;
+	parachain_rpc
+		.on_event::<UpdateTier1ValidatorSetEvent, _, _, _>(
+			|event| async move {
+				// the new validators only replace the old ones at the enactment height
+				let enactment_block_height = event.new_validators_enactment_block_height;
+				loop {
+					match parachain_rpc.get_current_chain_height().await {
+						Ok(height) if height >= enactment_block_height => break,
+						Ok(_) => {},
+						Err(e) => tracing::warn!(
+							"validator_set_listener(): failed to get the chain height: {e:?}"
+						),
+					}
+					sleep(Duration::from_millis(runtime::MILLISECS_PER_BLOCK)).await;
+				}
+
+				update_validator_set(parachain_rpc, oracle_agent).await;
+			},
+			|err| tracing::error!("Error (UpdateTier1ValidatorSetEvent): {}", err.to_string()),
+		)
+		.await?;
+
+	tracing::info!("validator_set_listener(): ended");
+	Ok(())
+}
+
 pub struct VaultService {
 	spacewalk_parachain: SpacewalkParachain,
 	stellar_wallet: ArcRwLock<StellarWallet>,
@@ -502,13 +590,33 @@ impl VaultService {
 			return Err(ServiceError::IncompatibleNetwork);
 		}
 
-		let oracle_agent =
-			OracleAgent::new(&stellar_overlay_cfg, self.secret_key(), shutdown_sender)
-				.await
-				.map_err(|e| {
-					tracing::error!("Failed to create OracleAgent: {e:?}");
+		let scp_store = match &self.config.scp_store_path {
+			Some(path) =>
+				Some(ScpStore::open(path, self.config.scp_store_max_age_in_slots).map_err(|e| {
+					tracing::error!("Failed to open the SCP store at {path}: {e:?}");
 					ServiceError::OracleError(Error::OracleError(e))
-				})?;
+				})?),
+			None => None,
+		};
+
+		let oracle_agent = OracleAgent::new_with_scp_store(
+			&stellar_overlay_cfg,
+			self.secret_key(),
+			shutdown_sender,
+			scp_store,
+		)
+		.await
+		.map_err(|e| {
+			tracing::error!("Failed to create OracleAgent: {e:?}");
+			ServiceError::OracleError(Error::OracleError(e))
+		})?;
+
+		for url in &self.config.proof_server_urls {
+			let timeout = Duration::from_secs(self.config.proof_server_timeout_secs);
+			oracle_agent
+				.add_proof_source(Box::new(RemoteProofSource::new(url.clone(), timeout)))
+				.await;
+		}
 
 		Ok(Arc::new(oracle_agent))
 	}
@@ -534,6 +642,7 @@ impl VaultService {
 					issue_event_tx,
 					issue_map.clone(),
 					memos_to_issue_ids.clone(),
+					self.vault_id_manager.journal(),
 				)),
 			),
 			(
@@ -562,6 +671,7 @@ impl VaultService {
 						ledger_env_map,
 						issue_map,
 						memos_to_issue_ids,
+						self.vault_id_manager.journal(),
 					),
 				),
 			),
@@ -746,6 +856,11 @@ impl VaultService {
 
 		tasks.append(&mut replace_tasks);
 
+		tasks.push((
+			"Stellar Validator Set Listener",
+			run(validator_set_listener(self.spacewalk_parachain.clone(), oracle_agent.clone())),
+		));
+
 		tasks.push((
 			"Redeem Request Listener",
 			run(listen_for_redeem_requests(
@@ -794,13 +909,19 @@ impl VaultService {
 
 		let stellar_wallet = Arc::new(RwLock::new(stellar_wallet));
 
+		let journal = match &config.request_journal_path {
+			Some(path) => Some(RequestJournal::open(path)?),
+			None => None,
+		};
+
 		Ok(Self {
 			spacewalk_parachain: spacewalk_parachain.clone(),
 			stellar_wallet: stellar_wallet.clone(),
 			config,
 			monitoring_config,
 			shutdown,
-			vault_id_manager: VaultIdManager::new(spacewalk_parachain, stellar_wallet),
+			vault_id_manager: VaultIdManager::new(spacewalk_parachain, stellar_wallet)
+				.with_journal(journal),
 			secret_key,
 			agent: None,
 		})
@@ -860,6 +981,7 @@ impl VaultService {
 
 		let oracle_agent =
 			self.create_oracle_agent(is_public_network, self.shutdown.clone()).await?;
+		oracle_agent.watch_account(&vault_public_key).await;
 		self.agent = Some(oracle_agent.clone());
 
 		// issue handling
@@ -888,6 +1010,9 @@ impl VaultService {
 		);
 
 		let ledger_env_map: ArcRwLock<LedgerTxEnvMap> = Arc::new(RwLock::new(HashMap::new()));
+		if let Some(journal) = self.vault_id_manager.journal() {
+			issue::resume_journaled_issues(&journal, &issue_map, &ledger_env_map).await;
+		}
 
 		tracing::info!("Starting all services...");
 
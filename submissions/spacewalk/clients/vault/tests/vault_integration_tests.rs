@@ -392,6 +392,7 @@ async fn test_cancel_scheduler_succeeds() {
 				issue_cancellation_event_tx.clone(),
 				issue_set.clone(),
 				memos_to_issue_ids.clone(),
+				None,
 			);
 			drop(wallet_read);
 
@@ -588,6 +589,7 @@ async fn test_issue_cancel_succeeds() {
 					issue_event_tx,
 					issue_set.clone(),
 					memos_to_issue_ids.clone(),
+					None,
 				),
 				vault::service::listen_for_issue_cancels(
 					vault_provider.clone(),
@@ -882,6 +884,7 @@ async fn test_automatic_issue_execution_succeeds_on_network(is_public_network: b
 					issue_event_tx,
 					issue_set.clone(),
 					memos_to_issue_ids.clone(),
+					None,
 				),
 				vault::service::process_issues_requests(
 					vault_provider.clone(),
@@ -889,6 +892,7 @@ async fn test_automatic_issue_execution_succeeds_on_network(is_public_network: b
 					slot_tx_env_map.clone(),
 					issue_set.clone(),
 					memos_to_issue_ids.clone(),
+					None,
 				),
 				vault::service::listen_for_new_transactions(
 					wallet_read.public_key(),
@@ -1036,6 +1040,7 @@ async fn test_automatic_issue_execution_succeeds_for_other_vault() {
 					issue_event_tx,
 					issue_set_arc.clone(),
 					memos_to_issue_ids.clone(),
+					None,
 				),
 				vault::service::process_issues_requests(
 					vault2_provider.clone(),
@@ -1043,6 +1048,7 @@ async fn test_automatic_issue_execution_succeeds_for_other_vault() {
 					slot_tx_env_map.clone(),
 					issue_set_arc.clone(),
 					memos_to_issue_ids.clone(),
+					None,
 				),
 				vault::service::listen_for_executed_issues(
 					vault2_provider.clone(),
@@ -123,6 +123,17 @@ impl HorizonClient for reqwest::Client {
 		self.get_from_url(&url).await
 	}
 
+	async fn get_transaction(
+		&self,
+		transaction_hash: &str,
+		is_public_network: bool,
+	) -> Result<TransactionResponse, Error> {
+		let base_url = horizon_url(is_public_network, false);
+		let url = format!("{base_url}/transactions/{transaction_hash}");
+
+		self.get_from_url(&url).await
+	}
+
 	async fn submit_transaction(
 		&self,
 		transaction_envelope: TransactionEnvelope,
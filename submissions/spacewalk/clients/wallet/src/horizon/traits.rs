@@ -40,6 +40,13 @@ pub trait HorizonClient {
 
 	async fn get_fee_stats(&self, is_public_network: bool) -> Result<FeeStats, Error>;
 
+	/// Returns the transaction of the given hex encoded hash, failed or not
+	async fn get_transaction(
+		&self,
+		transaction_hash: &str,
+		is_public_network: bool,
+	) -> Result<TransactionResponse, Error>;
+
 	async fn submit_transaction(
 		&self,
 		transaction: TransactionEnvelope,
@@ -204,6 +204,14 @@ impl StellarWallet {
 
 		Ok(account.sequence)
 	}
+
+	/// Returns whether Horizon knows the transaction of the given hex encoded hash as successful
+	pub async fn is_transaction_successful(&self, transaction_hash: &str) -> Result<bool, Error> {
+		let transaction =
+			self.client.get_transaction(transaction_hash, self.is_public_network).await?;
+
+		Ok(transaction.successful)
+	}
 }
 
 // cache operations